@@ -100,6 +100,12 @@ pub enum BackendError {
     #[error("Alert not found for id: {0}")]
     AlertNotFound(Uuid),
 
+    #[error("Alert rule not found for id: {0}")]
+    AlertRuleNotFound(Uuid),
+
+    #[error("Alert silence not found for id: {0}")]
+    AlertSilenceNotFound(Uuid),
+
     #[error("Alert not found for client id: {0}")]
     ClientHeartbeatAlertNotFound(ClientId),
 
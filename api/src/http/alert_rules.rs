@@ -0,0 +1,195 @@
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    Json,
+};
+use axum_extra::extract::CookieJar;
+use ivynet_database::{
+    alerts::{
+        node::alert_handler::resolve_rule_alert,
+        rule::{AlertRule, AlertRuleDefinition, AlertRuleState},
+    },
+    Account,
+};
+use uuid::Uuid;
+
+use crate::{error::BackendError, http::authorize};
+
+use super::{normalize_node_type, HttpState};
+
+pub const MAX_RULE_FOR_SECS: i64 = 60 * 60 * 24 * 7; // 1 week
+
+/// Get the alert rules of the organization
+#[utoipa::path(
+    get,
+    path = "/alerts/rules",
+    responses(
+        (status = 200, body = [AlertRule]),
+        (status = 404)
+    )
+)]
+pub async fn rules(
+    headers: HeaderMap,
+    State(state): State<HttpState>,
+    jar: CookieJar,
+) -> Result<Json<Vec<AlertRule>>, BackendError> {
+    let account = authorize::verify(&state.pool, &headers, &state.cache, &jar).await?;
+    let rules = AlertRule::get_all_for_org(&state.pool, account.organization_id).await?;
+    Ok(Json(rules))
+}
+
+/// Create an alert rule. A custom alert is raised for every node whose metric compares to the
+/// threshold for `for_secs`, and resolved once it no longer does.
+#[utoipa::path(
+    post,
+    path = "/alerts/rules",
+    request_body = AlertRuleDefinition,
+    responses(
+        (status = 200, body = AlertRule),
+        (status = 404)
+    )
+)]
+pub async fn create_rule(
+    headers: HeaderMap,
+    State(state): State<HttpState>,
+    jar: CookieJar,
+    Json(definition): Json<AlertRuleDefinition>,
+) -> Result<Json<AlertRule>, BackendError> {
+    let account = authorize::verify(&state.pool, &headers, &state.cache, &jar).await?;
+    if !account.role.can_write() {
+        return Err(BackendError::InsufficientPriviledges);
+    }
+    let definition = validate(definition)?;
+    let rule = AlertRule::create(&state.pool, account.organization_id, &definition).await?;
+    Ok(Json(rule))
+}
+
+/// Get an alert rule
+#[utoipa::path(
+    get,
+    path = "/alerts/rules/:rule_id",
+    responses(
+        (status = 200, body = AlertRule),
+        (status = 404)
+    )
+)]
+pub async fn rule(
+    headers: HeaderMap,
+    State(state): State<HttpState>,
+    Path(rule_id): Path<String>,
+    jar: CookieJar,
+) -> Result<Json<AlertRule>, BackendError> {
+    let account = authorize::verify(&state.pool, &headers, &state.cache, &jar).await?;
+    let rule = owned_rule(&state, &account, &rule_id).await?;
+    Ok(Json(rule))
+}
+
+/// Replace an alert rule. Alerts raised by the rule are resolved, and raised again if the new
+/// definition still applies.
+#[utoipa::path(
+    put,
+    path = "/alerts/rules/:rule_id",
+    request_body = AlertRuleDefinition,
+    responses(
+        (status = 200, body = AlertRule),
+        (status = 404)
+    )
+)]
+pub async fn update_rule(
+    headers: HeaderMap,
+    State(state): State<HttpState>,
+    Path(rule_id): Path<String>,
+    jar: CookieJar,
+    Json(definition): Json<AlertRuleDefinition>,
+) -> Result<Json<AlertRule>, BackendError> {
+    let account = authorize::verify(&state.pool, &headers, &state.cache, &jar).await?;
+    if !account.role.can_write() {
+        return Err(BackendError::InsufficientPriviledges);
+    }
+    let rule = owned_rule(&state, &account, &rule_id).await?;
+    let definition = validate(definition)?;
+
+    resolve_rule_alerts(&state, rule.rule_id).await?;
+    let rule = AlertRule::update(&state.pool, rule.rule_id, &definition)
+        .await?
+        .ok_or(BackendError::AlertRuleNotFound(rule.rule_id))?;
+    Ok(Json(rule))
+}
+
+/// Delete an alert rule and resolve the alerts it raised
+#[utoipa::path(
+    delete,
+    path = "/alerts/rules/:rule_id",
+    responses(
+        (status = 200),
+        (status = 404)
+    )
+)]
+pub async fn delete_rule(
+    headers: HeaderMap,
+    State(state): State<HttpState>,
+    Path(rule_id): Path<String>,
+    jar: CookieJar,
+) -> Result<(), BackendError> {
+    let account = authorize::verify(&state.pool, &headers, &state.cache, &jar).await?;
+    if !account.role.can_write() {
+        return Err(BackendError::InsufficientPriviledges);
+    }
+    let rule = owned_rule(&state, &account, &rule_id).await?;
+
+    resolve_rule_alerts(&state, rule.rule_id).await?;
+    AlertRule::delete(&state.pool, rule.rule_id).await?;
+    Ok(())
+}
+
+async fn owned_rule(
+    state: &HttpState,
+    account: &Account,
+    rule_id: &str,
+) -> Result<AlertRule, BackendError> {
+    let rule_id = rule_id.parse::<Uuid>().map_err(|_| BackendError::BadId)?;
+    AlertRule::get(&state.pool, rule_id)
+        .await?
+        .filter(|rule| rule.organization_id == account.organization_id)
+        .ok_or(BackendError::AlertRuleNotFound(rule_id))
+}
+
+/// Resolves every alert raised by the rule and forgets which series were pending, so it starts
+/// over.
+async fn resolve_rule_alerts(state: &HttpState, rule_id: Uuid) -> Result<(), BackendError> {
+    for rule_state in AlertRuleState::get_for_rule(&state.pool, rule_id).await? {
+        resolve_rule_alert(&state.pool, &rule_state).await?;
+    }
+    AlertRuleState::clear_rule(&state.pool, rule_id).await?;
+    Ok(())
+}
+
+fn validate(mut definition: AlertRuleDefinition) -> Result<AlertRuleDefinition, BackendError> {
+    let malformed =
+        |name: &str, reason: String| BackendError::MalformedParameter(name.to_string(), reason);
+
+    if definition.name.trim().is_empty() {
+        return Err(malformed("name", "Name is empty".to_string()));
+    }
+    if definition.metric_name.trim().is_empty() {
+        return Err(malformed("metric_name", "Metric name is empty".to_string()));
+    }
+    if !definition.threshold.is_finite() {
+        return Err(malformed("threshold", "Threshold is not a number".to_string()));
+    }
+    if !(0..=MAX_RULE_FOR_SECS).contains(&definition.for_secs) {
+        return Err(malformed(
+            "for_secs",
+            format!("{} is not between 0 and {MAX_RULE_FOR_SECS}", definition.for_secs),
+        ));
+    }
+    for matcher in &definition.label_matchers {
+        matcher.validate().map_err(|e| malformed("label_matchers", e.to_string()))?;
+    }
+
+    for node_type in definition.scope.node_types.iter_mut() {
+        *node_type = normalize_node_type("scope", node_type)?;
+    }
+
+    Ok(definition)
+}
@@ -0,0 +1,128 @@
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    Json,
+};
+use axum_extra::extract::CookieJar;
+use ivynet_database::alerts::silence::{AlertSilence, SilenceDefinition, MAX_WINDOW_MINUTES};
+use uuid::Uuid;
+
+use crate::{error::BackendError, http::authorize};
+
+use super::{normalize_node_type, HttpState};
+
+/// Get the alert silences of the organization, including the ones that ended
+#[utoipa::path(
+    get,
+    path = "/alerts/silences",
+    responses(
+        (status = 200, body = [AlertSilence]),
+        (status = 404)
+    )
+)]
+pub async fn silences(
+    headers: HeaderMap,
+    State(state): State<HttpState>,
+    jar: CookieJar,
+) -> Result<Json<Vec<AlertSilence>>, BackendError> {
+    let account = authorize::verify(&state.pool, &headers, &state.cache, &jar).await?;
+    let silences = AlertSilence::get_all_for_org(&state.pool, account.organization_id).await?;
+    Ok(Json(silences))
+}
+
+/// Silence the alerts matching the machine, node name, node type and alert type set, from
+/// `starts_at` until `ends_at`. With a schedule, the silence is only in effect during its weekly
+/// window. Silenced alerts are still recorded, but not notified.
+#[utoipa::path(
+    post,
+    path = "/alerts/silences",
+    request_body = SilenceDefinition,
+    responses(
+        (status = 200, body = AlertSilence),
+        (status = 404)
+    )
+)]
+pub async fn create_silence(
+    headers: HeaderMap,
+    State(state): State<HttpState>,
+    jar: CookieJar,
+    Json(definition): Json<SilenceDefinition>,
+) -> Result<Json<AlertSilence>, BackendError> {
+    let account = authorize::verify(&state.pool, &headers, &state.cache, &jar).await?;
+    if !account.role.can_write() {
+        return Err(BackendError::InsufficientPriviledges);
+    }
+    if let Some(machine_id) = definition.machine_id {
+        authorize::verify_machine_ownership(&account, State(state.clone()), machine_id.to_string())
+            .await?;
+    }
+    let definition = validate(definition)?;
+
+    let silence = AlertSilence::create(&state.pool, account.organization_id, &definition).await?;
+    Ok(Json(silence))
+}
+
+/// End an alert silence now
+#[utoipa::path(
+    post,
+    path = "/alerts/silences/:silence_id/expire",
+    responses(
+        (status = 200),
+        (status = 404)
+    )
+)]
+pub async fn expire_silence(
+    headers: HeaderMap,
+    State(state): State<HttpState>,
+    Path(silence_id): Path<String>,
+    jar: CookieJar,
+) -> Result<(), BackendError> {
+    let account = authorize::verify(&state.pool, &headers, &state.cache, &jar).await?;
+    if !account.role.can_write() {
+        return Err(BackendError::InsufficientPriviledges);
+    }
+
+    let silence_id = silence_id.parse::<Uuid>().map_err(|_| BackendError::BadId)?;
+    AlertSilence::get(&state.pool, silence_id)
+        .await?
+        .filter(|silence| silence.organization_id == account.organization_id)
+        .ok_or(BackendError::AlertSilenceNotFound(silence_id))?;
+
+    AlertSilence::expire(&state.pool, silence_id).await?;
+    Ok(())
+}
+
+fn validate(mut definition: SilenceDefinition) -> Result<SilenceDefinition, BackendError> {
+    let malformed =
+        |name: &str, reason: String| BackendError::MalformedParameter(name.to_string(), reason);
+
+    match (definition.ends_at, &definition.schedule) {
+        (Some(ends_at), _) if ends_at <= definition.starts_at => {
+            return Err(malformed("ends_at", "Silence ends before it starts".to_string()));
+        }
+        (None, None) => {
+            return Err(malformed("ends_at", "Silence without a schedule never ends".to_string()));
+        }
+        _ => {}
+    }
+    if let Some(schedule) = &definition.schedule {
+        if schedule.start_minute >= 24 * 60 {
+            return Err(malformed("schedule", "Window starts after midnight".to_string()));
+        }
+        if !(1..=MAX_WINDOW_MINUTES).contains(&schedule.duration_minutes) {
+            return Err(malformed(
+                "schedule",
+                format!("Window duration is not between 1 and {MAX_WINDOW_MINUTES} minutes"),
+            ));
+        }
+    }
+    if definition.node_name.as_ref().is_some_and(|node_name| node_name.is_empty()) {
+        return Err(malformed("node_name", "Node name is empty".to_string()));
+    }
+
+    if let Some(node_type) = definition.node_type.as_mut() {
+        *node_type = normalize_node_type("node_type", node_type)?;
+    }
+
+    Ok(definition)
+}
@@ -8,13 +8,14 @@ use chrono::DateTime;
 use ivynet_alerts::{AlertFlags, AlertType};
 use ivynet_database::{
     alerts::{
+        escalation::EscalationPolicy,
         node::{alerts_active::NodeActiveAlert, alerts_historical::NodeHistoryAlert},
         org::{
             alerts_active::OrganizationActiveAlert, alerts_historical::OrganizationHistoryAlert,
         },
     },
     service_settings::ServiceType,
-    NotificationSettings, ServiceSettings,
+    NotificationSettings, PerformanceSettings, ServiceSettings,
 };
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
@@ -24,6 +25,9 @@ use crate::{error::BackendError, http::authorize};
 
 use super::HttpState;
 
+pub const MAX_ESCALATION_DELAY: i64 = 60 * 60 * 24 * 7; // 1 week
+pub const MIN_ESCALATION_REPEAT_INTERVAL: i64 = 60 * 5; // 5 minutes
+
 #[derive(Debug, Clone, Copy, Deserialize, ToSchema, utoipa::IntoParams)]
 pub struct HistoricalAlertParams {
     pub from: i64,
@@ -613,3 +617,157 @@ pub async fn update_multiple_alert_flags(
 
     Ok(())
 }
+
+/// Get the performance score below which EigenDA nodes raise a LowPerformanceScore alert. A
+/// threshold that is not set uses the default of the backend.
+#[utoipa::path(
+    get,
+    path = "/alerts/performance/settings",
+    responses(
+        (status = 200, body = PerformanceSettings),
+        (status = 404)
+    )
+)]
+pub async fn get_performance_settings(
+    headers: HeaderMap,
+    State(state): State<HttpState>,
+    jar: CookieJar,
+) -> Result<Json<PerformanceSettings>, BackendError> {
+    let account = authorize::verify(&state.pool, &headers, &state.cache, &jar).await?;
+    let settings = PerformanceSettings::get(&state.pool, account.organization_id).await?;
+    Ok(Json(settings))
+}
+
+/// Set the performance score, between 0 and 100, below which EigenDA nodes raise a
+/// LowPerformanceScore alert
+#[utoipa::path(
+    post,
+    path = "/alerts/performance/settings",
+    request_body = PerformanceSettings,
+    responses(
+        (status = 200, body = PerformanceSettings),
+        (status = 404)
+    )
+)]
+pub async fn set_performance_settings(
+    headers: HeaderMap,
+    State(state): State<HttpState>,
+    jar: CookieJar,
+    Json(settings): Json<PerformanceSettings>,
+) -> Result<Json<PerformanceSettings>, BackendError> {
+    let account = authorize::verify(&state.pool, &headers, &state.cache, &jar).await?;
+    if !account.role.can_write() {
+        return Err(BackendError::InsufficientPriviledges);
+    }
+
+    if let Some(threshold) = settings.healthy_threshold.filter(|t| !(0.0..=100.0).contains(t)) {
+        return Err(BackendError::MalformedParameter(
+            "healthy_threshold".to_string(),
+            format!("{threshold} is not between 0 and 100"),
+        ));
+    }
+
+    settings.set(&state.pool, account.organization_id).await?;
+    Ok(Json(settings))
+}
+
+/// Get the escalation policy of the organization, if it set one. Without a policy, alerts are
+/// notified once, on every channel.
+#[utoipa::path(
+    get,
+    path = "/alerts/escalation",
+    responses(
+        (status = 200, body = Option<EscalationPolicy>),
+        (status = 404)
+    )
+)]
+pub async fn get_escalation_policy(
+    headers: HeaderMap,
+    State(state): State<HttpState>,
+    jar: CookieJar,
+) -> Result<Json<Option<EscalationPolicy>>, BackendError> {
+    let account = authorize::verify(&state.pool, &headers, &state.cache, &jar).await?;
+    let policy = EscalationPolicy::get(&state.pool, account.organization_id).await?;
+    Ok(Json(policy))
+}
+
+/// Replace the escalation policy of the organization. Each step notifies its channel once a node
+/// or machine alert went unacknowledged for `after_secs`. With a repeat interval, every channel is
+/// notified again that often once all steps were taken.
+#[utoipa::path(
+    post,
+    path = "/alerts/escalation",
+    request_body = EscalationPolicy,
+    responses(
+        (status = 200, body = EscalationPolicy),
+        (status = 404)
+    )
+)]
+pub async fn set_escalation_policy(
+    headers: HeaderMap,
+    State(state): State<HttpState>,
+    jar: CookieJar,
+    Json(policy): Json<EscalationPolicy>,
+) -> Result<Json<EscalationPolicy>, BackendError> {
+    let account = authorize::verify(&state.pool, &headers, &state.cache, &jar).await?;
+    if !account.role.can_write() {
+        return Err(BackendError::InsufficientPriviledges);
+    }
+
+    if policy.steps.is_empty() {
+        return Err(BackendError::MalformedParameter(
+            "steps".to_string(),
+            "Escalation policy has no steps".to_string(),
+        ));
+    }
+    for (i, step) in policy.steps.iter().enumerate() {
+        if !(0..=MAX_ESCALATION_DELAY).contains(&step.after_secs) {
+            return Err(BackendError::MalformedParameter(
+                "after_secs".to_string(),
+                format!("{} is not between 0 and {MAX_ESCALATION_DELAY}", step.after_secs),
+            ));
+        }
+        if policy.steps[..i].iter().any(|other| other.channel == step.channel) {
+            return Err(BackendError::MalformedParameter(
+                "steps".to_string(),
+                format!("{:?} has more than one step", step.channel),
+            ));
+        }
+    }
+    if let Some(interval) = policy.repeat_interval_secs {
+        if !(MIN_ESCALATION_REPEAT_INTERVAL..=MAX_ESCALATION_DELAY).contains(&interval) {
+            return Err(BackendError::MalformedParameter(
+                "repeat_interval_secs".to_string(),
+                format!(
+                    "{interval} is not between {MIN_ESCALATION_REPEAT_INTERVAL} and \
+                     {MAX_ESCALATION_DELAY}"
+                ),
+            ));
+        }
+    }
+
+    policy.set(&state.pool, account.organization_id).await?;
+    Ok(Json(policy))
+}
+
+/// Remove the escalation policy of the organization, so alerts are notified once, on every channel
+#[utoipa::path(
+    delete,
+    path = "/alerts/escalation",
+    responses(
+        (status = 200),
+        (status = 404)
+    )
+)]
+pub async fn delete_escalation_policy(
+    headers: HeaderMap,
+    State(state): State<HttpState>,
+    jar: CookieJar,
+) -> Result<(), BackendError> {
+    let account = authorize::verify(&state.pool, &headers, &state.cache, &jar).await?;
+    if !account.role.can_write() {
+        return Err(BackendError::InsufficientPriviledges);
+    }
+    EscalationPolicy::delete(&state.pool, account.organization_id).await?;
+    Ok(())
+}
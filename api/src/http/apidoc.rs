@@ -11,7 +11,10 @@ use ivynet_database::{
 };
 use utoipa::OpenApi;
 
-use super::{alerts, authorize, client, heartbeat, info, machine, node, organization, pubkey};
+use super::{
+    alert_rules, alert_silences, alerts, authorize, client, heartbeat, info, machine, node,
+    organization, pubkey,
+};
 #[derive(OpenApi)]
 #[openapi(
     paths(
@@ -55,6 +58,8 @@ use super::{alerts, authorize, client, heartbeat, info, machine, node, organizat
         machine::set_name,
         machine::system_metrics,
         machine::set_node_type,
+        machine::queue_command,
+        machine::commands,
         heartbeat::client_active_alerts,
         heartbeat::client_alert_history,
         heartbeat::acknowledge_client_alert,
@@ -64,6 +69,8 @@ use super::{alerts, authorize, client, heartbeat, info, machine, node, organizat
         heartbeat::node_active_alerts,
         heartbeat::node_alert_history,
         heartbeat::acknowledge_node_alert,
+        heartbeat::get_settings,
+        heartbeat::set_settings,
         alerts::node_active_alerts,
         alerts::node_acknowledge_alert,
         alerts::node_alert_history,
@@ -80,6 +87,19 @@ use super::{alerts, authorize, client, heartbeat, info, machine, node, organizat
         alerts::set_notification_service_flags,
         alerts::update_multiple_alert_flags,
         alerts::node_remove_alert,
+        alerts::get_performance_settings,
+        alerts::set_performance_settings,
+        alerts::get_escalation_policy,
+        alerts::set_escalation_policy,
+        alerts::delete_escalation_policy,
+        alert_rules::rules,
+        alert_rules::create_rule,
+        alert_rules::rule,
+        alert_rules::update_rule,
+        alert_rules::delete_rule,
+        alert_silences::silences,
+        alert_silences::create_silence,
+        alert_silences::expire_silence,
     ),
     components(
         schemas(
@@ -101,6 +121,9 @@ use super::{alerts, authorize, client, heartbeat, info, machine, node, organizat
             ivynet_database::metric::Metric,
             ivynet_database::log::ContainerLog,
             ivynet_database::log::LogLevel,
+            ivynet_database::machine_command::MachineCommand,
+            ivynet_database::machine_command::CommandKind,
+            machine::CommandRequest,
             alerts::NotificationServiceSettings,
             alerts::NotificationServiceFlags,
             alerts::AlertFlagUpdate,
@@ -123,6 +146,21 @@ use super::{alerts, authorize, client, heartbeat, info, machine, node, organizat
             ivynet_heartbeat::alerts::MachineHeartbeatAlertHistorical,
             ivynet_heartbeat::alerts::NodeHeartbeatAlert,
             ivynet_heartbeat::alerts::NodeHeartbeatAlertHistorical,
+            ivynet_database::HeartbeatSettings,
+            ivynet_database::PerformanceSettings,
+            ivynet_database::alerts::escalation::EscalationPolicy,
+            ivynet_database::alerts::escalation::EscalationStep,
+            ivynet_database::alerts::escalation::EscalationChannel,
+            ivynet_database::alerts::rule::AlertRule,
+            ivynet_database::alerts::rule::AlertRuleDefinition,
+            ivynet_database::alerts::rule::AlertRuleScope,
+            ivynet_database::alerts::rule::ScopedNode,
+            ivynet_database::alerts::rule::LabelMatcher,
+            ivynet_database::alerts::rule::MatchOp,
+            ivynet_database::alerts::rule::Comparison,
+            ivynet_database::alerts::silence::AlertSilence,
+            ivynet_database::alerts::silence::SilenceDefinition,
+            ivynet_database::alerts::silence::RecurringWindow,
         ),
     ),
     tags(
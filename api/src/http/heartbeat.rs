@@ -1,9 +1,12 @@
+use std::collections::HashMap;
+
 use axum::{
     extract::{Query, State},
     http::HeaderMap,
     Json,
 };
 use axum_extra::extract::CookieJar;
+use ivynet_database::HeartbeatSettings;
 use ivynet_heartbeat::{
     alerts::{
         ClientHeartbeatAlert, ClientHeartbeatAlertHistorical, MachineHeartbeatAlert,
@@ -16,7 +19,12 @@ use utoipa::ToSchema;
 
 use crate::{error::BackendError, http::authorize};
 
-use super::HttpState;
+use super::{normalize_node_type, HttpState};
+
+/// Heartbeats are sent every minute, so shorter TTLs would flag entries as soon as a single
+/// heartbeat is late.
+pub const MIN_HEARTBEAT_TTL: i64 = 60 * 3; // 3 minutes
+pub const MAX_HEARTBEAT_TTL: i64 = 60 * 60 * 24 * 7; // 1 week
 
 #[derive(Debug, Clone, Copy, Deserialize, ToSchema, utoipa::IntoParams)]
 pub struct PaginationParams {
@@ -272,3 +280,65 @@ pub async fn acknowledge_node_alert(
     NodeHeartbeatAlert::resolve(&state.pool, node_id.clone()).await?;
     Ok(Json(()))
 }
+
+/// Get the heartbeat TTLs of the organization. TTLs that are not set use the default of the
+/// backend.
+#[utoipa::path(
+    get,
+    path = "/alerts/heartbeat/settings",
+    responses(
+        (status = 200, body = HeartbeatSettings),
+        (status = 404)
+    )
+)]
+pub async fn get_settings(
+    headers: HeaderMap,
+    State(state): State<HttpState>,
+    jar: CookieJar,
+) -> Result<Json<HeartbeatSettings>, BackendError> {
+    let account = authorize::verify(&state.pool, &headers, &state.cache, &jar).await?;
+    let settings = HeartbeatSettings::get(&state.pool, account.organization_id).await?;
+    Ok(Json(settings))
+}
+
+/// Replace the heartbeat TTLs of the organization, in seconds. Node type TTLs take precedence over
+/// the node TTL for nodes of that type.
+#[utoipa::path(
+    post,
+    path = "/alerts/heartbeat/settings",
+    request_body = HeartbeatSettings,
+    responses(
+        (status = 200, body = HeartbeatSettings),
+        (status = 400),
+        (status = 404)
+    )
+)]
+pub async fn set_settings(
+    headers: HeaderMap,
+    State(state): State<HttpState>,
+    jar: CookieJar,
+    Json(settings): Json<HeartbeatSettings>,
+) -> Result<Json<HeartbeatSettings>, BackendError> {
+    let account = authorize::verify(&state.pool, &headers, &state.cache, &jar).await?;
+    if !account.role.can_write() {
+        return Err(BackendError::InsufficientPriviledges);
+    }
+
+    if let Some(ttl) =
+        settings.ttls().find(|ttl| !(MIN_HEARTBEAT_TTL..=MAX_HEARTBEAT_TTL).contains(ttl))
+    {
+        return Err(BackendError::MalformedParameter(
+            "ttl_secs".to_string(),
+            format!("{ttl} is not between {MIN_HEARTBEAT_TTL} and {MAX_HEARTBEAT_TTL}"),
+        ));
+    }
+
+    let mut node_type_ttl_secs = HashMap::new();
+    for (node_type, ttl) in settings.node_type_ttl_secs {
+        node_type_ttl_secs.insert(normalize_node_type("node_type_ttl_secs", &node_type)?, ttl);
+    }
+    let settings = HeartbeatSettings { node_type_ttl_secs, ..settings };
+
+    settings.set(&state.pool, account.organization_id).await?;
+    Ok(Json(settings))
+}
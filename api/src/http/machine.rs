@@ -5,13 +5,16 @@ use axum::{
     Json,
 };
 use axum_extra::extract::CookieJar;
+use chrono::TimeDelta;
 use ethers::types::{Address, Chain};
 use ivynet_node_type::NodeType;
+use serde::Deserialize;
 use std::{
     collections::HashMap,
     str::FromStr,
     time::{SystemTime, UNIX_EPOCH},
 };
+use utoipa::ToSchema;
 use uuid::Uuid;
 
 use ivynet_database::{
@@ -23,6 +26,7 @@ use ivynet_database::{
         node_data::{self, build_avs_info, AvsInfo},
     },
     log::{ContainerLog, LogLevel},
+    machine_command::{CommandKind, MachineCommand},
     metric::Metric,
 };
 
@@ -30,6 +34,9 @@ use super::{authorize, HttpState};
 
 pub const DEFAULT_LOG_TIME_RANGE: i64 = 60 * 60 * 8; // 8 hours
 pub const MAX_LOG_TIME_RANGE: i64 = 60 * 60 * 24; // 1 Day pagination
+pub const DEFAULT_COMMAND_TTL: i64 = 60 * 60; // 1 hour
+pub const MAX_COMMAND_TTL: i64 = 60 * 60 * 24 * 7; // 1 week
+pub const COMMAND_HISTORY_LIMIT: i64 = 100;
 
 /// Grab information for every machine in the organization
 #[utoipa::path(
@@ -636,3 +643,72 @@ pub async fn delete_avs_node_data(
 
     Ok(())
 }
+
+/// A command to queue for a machine
+#[derive(Deserialize, ToSchema)]
+pub struct CommandRequest {
+    pub command: CommandKind,
+    /// Seconds the command waits for the machine to connect before it expires
+    pub ttl_secs: Option<i64>,
+}
+
+/// Queue a command for a machine. It is sent the next time the machine is connected, and only
+/// carried out if the machine allows the command type.
+#[utoipa::path(
+    post,
+    path = "/machine/:machine_id/commands",
+    request_body = CommandRequest,
+    responses(
+        (status = 200, body = MachineCommand),
+        (status = 404)
+    )
+)]
+pub async fn queue_command(
+    headers: HeaderMap,
+    State(state): State<HttpState>,
+    Path(machine_id): Path<String>,
+    jar: CookieJar,
+    Json(request): Json<CommandRequest>,
+) -> Result<Json<MachineCommand>, BackendError> {
+    let account = authorize::verify(&state.pool, &headers, &state.cache, &jar).await?;
+    if !account.role.can_write() {
+        return Err(BackendError::InsufficientPriviledges);
+    }
+    let machine =
+        authorize::verify_machine_ownership(&account, State(state.clone()), machine_id).await?;
+
+    let ttl = request.ttl_secs.unwrap_or(DEFAULT_COMMAND_TTL).clamp(1, MAX_COMMAND_TTL);
+    let command = MachineCommand::enqueue(
+        &state.pool,
+        machine.machine_id,
+        &request.command,
+        TimeDelta::seconds(ttl),
+    )
+    .await?;
+    Ok(Json(command))
+}
+
+/// Get the most recent commands queued for a machine, with the results it reported
+#[utoipa::path(
+    get,
+    path = "/machine/:machine_id/commands",
+    responses(
+        (status = 200, body = [MachineCommand]),
+        (status = 404)
+    )
+)]
+pub async fn commands(
+    headers: HeaderMap,
+    State(state): State<HttpState>,
+    Path(machine_id): Path<String>,
+    jar: CookieJar,
+) -> Result<Json<Vec<MachineCommand>>, BackendError> {
+    let account = authorize::verify(&state.pool, &headers, &state.cache, &jar).await?;
+    let machine =
+        authorize::verify_machine_ownership(&account, State(state.clone()), machine_id).await?;
+
+    let commands =
+        MachineCommand::get_all_for_machine(&state.pool, machine.machine_id, COMMAND_HISTORY_LIMIT)
+            .await?;
+    Ok(Json(commands))
+}
@@ -1,3 +1,5 @@
+mod alert_rules;
+mod alert_silences;
 mod alerts;
 mod apidoc;
 mod authorize;
@@ -18,6 +20,7 @@ use axum::{
     Router,
 };
 use ivynet_grpc::client::Uri;
+use ivynet_node_type::NodeType;
 use sendgrid::v3::Sender;
 use serde_json::{json, Value};
 use sqlx::PgPool;
@@ -124,6 +127,7 @@ fn create_router() -> Router<HttpState> {
                 .route("/:machine_id/info", get(machine::get_all_node_data))
                 .route("/:machine_id/system_metrics", get(machine::system_metrics))
                 .route("/:machine_id/node_type", put(machine::set_node_type))
+                .route("/:machine_id/commands", get(machine::commands).post(machine::queue_command))
                 .route("/:machine_id", put(machine::update_avs))
                 .route(
                     "/:machine_id",
@@ -158,6 +162,28 @@ fn create_router() -> Router<HttpState> {
                 .route("/notifications/readable", get(alerts::get_alert_flags_human))
                 .route("/notifications/set_flag", post(alerts::update_alert_flag))
                 .route("/notifications/set_flags", post(alerts::update_multiple_alert_flags))
+                .route(
+                    "/escalation",
+                    get(alerts::get_escalation_policy)
+                        .post(alerts::set_escalation_policy)
+                        .delete(alerts::delete_escalation_policy),
+                )
+                .route(
+                    "/performance/settings",
+                    get(alerts::get_performance_settings).post(alerts::set_performance_settings),
+                )
+                .route("/rules", get(alert_rules::rules).post(alert_rules::create_rule))
+                .route(
+                    "/rules/:rule_id",
+                    get(alert_rules::rule)
+                        .put(alert_rules::update_rule)
+                        .delete(alert_rules::delete_rule),
+                )
+                .route(
+                    "/silences",
+                    get(alert_silences::silences).post(alert_silences::create_silence),
+                )
+                .route("/silences/:silence_id/expire", post(alert_silences::expire_silence))
                 .nest(
                     "/heartbeat",
                     Router::new()
@@ -169,7 +195,11 @@ fn create_router() -> Router<HttpState> {
                         .route("/machine/acknowledge", post(heartbeat::acknowledge_machine_alert))
                         .route("/node/active", get(heartbeat::node_active_alerts))
                         .route("/node/history", get(heartbeat::node_alert_history))
-                        .route("/node/acknowledge", post(heartbeat::acknowledge_node_alert)),
+                        .route("/node/acknowledge", post(heartbeat::acknowledge_node_alert))
+                        .route(
+                            "/settings",
+                            get(heartbeat::get_settings).post(heartbeat::set_settings),
+                        ),
                 ),
         )
         .nest(
@@ -193,6 +223,18 @@ fn create_router() -> Router<HttpState> {
         .fallback(handler_404_with_logging)
 }
 
+/// Writes a node type given in a request the way nodes report it, so it matches whatever way it
+/// was written. `field` names the parameter in the error when the node type is unknown.
+fn normalize_node_type(field: &str, node_type: &str) -> Result<String, BackendError> {
+    match NodeType::from(node_type) {
+        NodeType::Unknown => Err(BackendError::MalformedParameter(
+            field.to_string(),
+            format!("Unknown node type {node_type}"),
+        )),
+        parsed => Ok(parsed.to_string()),
+    }
+}
+
 async fn handler_404_with_logging(uri: axum::http::Uri) -> (StatusCode, Json<Value>) {
     println!("404 Not Found for path: {}", uri.path());
     (
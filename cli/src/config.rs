@@ -14,7 +14,7 @@ pub static DEFAULT_CONFIG_PATH: Lazy<PathBuf> = Lazy::new(|| {
     path.join(".ivynet")
 });
 
-use crate::{error::Error, ivy_machine::SystemInformation, metadata::Metadata};
+use crate::{error::Error, identity, ivy_machine::SystemInformation, metadata::Metadata};
 
 #[derive(Parser, Debug, Clone)]
 pub enum ConfigCommands {
@@ -46,8 +46,16 @@ pub enum ConfigSetCommands {
     ServerUrl { server_url: Uri },
     #[command(name = "server_ca", about = "Set backend server certificate")]
     ServerCa { server_ca: String },
-    #[command(name = "identity_key", about = "Set backend connection identity key")]
+    #[command(
+        name = "identity_key",
+        about = "Set backend connection identity key, stored in an encrypted keystore"
+    )]
     IdentityKey { identity_key: String },
+    #[command(
+        name = "identity_password_file",
+        about = "Set the file holding the password of the identity keystore"
+    )]
+    IdentityPasswordFile { path: PathBuf },
 }
 
 #[derive(Parser, Debug, Clone)]
@@ -109,7 +117,12 @@ fn parse_config_setter_commands(
             config.store()?;
         }
         ConfigSetCommands::IdentityKey { identity_key } => {
-            config.backend_info.identity_key = identity_key;
+            let wallet = IvyWallet::from_private_key(identity_key)?;
+            config.set_identity_wallet(&wallet, &identity::new_identity_password(&config)?)?;
+            config.store()?;
+        }
+        ConfigSetCommands::IdentityPasswordFile { path } => {
+            config.backend_info.identity_password_file = Some(path);
             config.store()?;
         }
     }
@@ -153,8 +166,16 @@ fn parse_config_getter_commands(
 pub struct BackendInfo {
     pub server_url: String,
     pub server_ca: String,
-    /// Identification key that node uses for server communications
+    /// Plaintext identification key that node uses for server communications. Only read from
+    /// configs that predate the encrypted keystore; `ivy key migrate-identity` moves it out.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub identity_key: String,
+    /// Keychain name of the encrypted keystore holding the identification key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity_keyfile: Option<String>,
+    /// File holding the password of the identity keystore
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity_password_file: Option<PathBuf>,
 }
 
 // TODO: Change rpc urls to hashmap or remove entirely
@@ -190,6 +211,8 @@ impl Default for IvyConfig {
                 server_url: "https://api1.test.ivynet.dev".into(),
                 server_ca: "".into(),
                 identity_key: "".into(),
+                identity_keyfile: None,
+                identity_password_file: None,
             },
         }
     }
@@ -216,7 +239,6 @@ impl IvyConfig {
     }
 
     pub fn store(&self) -> Result<(), ConfigError> {
-        // TODO: Assert identity key is None on save
         let config_path = self.path.clone().join("ivy-config.toml");
         write_toml(&config_path, self)?;
         Ok(())
@@ -264,8 +286,22 @@ impl IvyConfig {
         self.path.join("ivy-config.toml")
     }
 
+    /// Loads the identity key, decrypting the keystore when there is one and falling back to a
+    /// plaintext key from a config that has not been migrated yet.
     pub fn identity_wallet(&self) -> Result<IvyWallet, Error> {
-        Ok(IvyWallet::from_private_key(self.backend_info.identity_key.clone())?)
+        match &self.backend_info.identity_keyfile {
+            Some(name) => Ok(identity::load_identity(self, name)?),
+            None => Ok(IvyWallet::from_private_key(self.backend_info.identity_key.clone())?),
+        }
+    }
+
+    /// Encrypts `wallet` into the identity keystore and points the config at it. The config is
+    /// not stored.
+    pub fn set_identity_wallet(&mut self, wallet: &IvyWallet, password: &str) -> Result<(), Error> {
+        identity::store_identity(self, wallet, password, identity::IDENTITY_KEY_NAME)?;
+        self.backend_info.identity_keyfile = Some(identity::IDENTITY_KEY_NAME.to_string());
+        self.backend_info.identity_key.clear();
+        Ok(())
     }
 
     pub fn set_server_url(&mut self, url: String) {
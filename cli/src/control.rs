@@ -0,0 +1,476 @@
+use std::{
+    fs::Permissions,
+    io,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
+    net::{
+        unix::{OwnedReadHalf, OwnedWriteHalf},
+        UnixListener, UnixStream,
+    },
+    sync::{mpsc, oneshot},
+};
+use tracing::debug;
+
+use crate::{
+    config::DEFAULT_CONFIG_PATH,
+    telemetry::{
+        stats::{StatsSnapshot, TelemetryStats},
+        ConfiguredAvs, NodeRuntime,
+    },
+    unattended::OutputFormat,
+};
+
+const CONTROL_SOCKET_FILE: &str = "monitor.sock";
+
+/// Unix socket the running monitor daemon accepts control requests on.
+pub fn default_socket_path() -> PathBuf {
+    DEFAULT_CONFIG_PATH.join(CONTROL_SOCKET_FILE)
+}
+
+pub type ControlTx = mpsc::Sender<ControlCommand>;
+pub type ControlRx = mpsc::Receiver<ControlCommand>;
+
+/// A request sent to the daemon as a single line of JSON. Each is answered with a single line
+/// holding a `ControlResponse`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum ControlRequest {
+    AddNode { node: ConfiguredAvs },
+    RemoveNode { assigned_name: String },
+    ListNodes,
+    Status,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum ControlResponse {
+    /// The node that was added or removed
+    Node {
+        node: ConfiguredAvs,
+    },
+    Nodes {
+        nodes: Vec<ConfiguredAvs>,
+    },
+    Status {
+        status: StatusReport,
+    },
+    Error {
+        message: String,
+    },
+}
+
+impl ControlResponse {
+    pub fn error(message: impl ToString) -> Self {
+        Self::Error { message: message.to_string() }
+    }
+}
+
+/// A request forwarded by the control server to the docker stream listener, which owns the set of
+/// monitored nodes and the listeners following them.
+#[derive(Debug)]
+pub struct ControlCommand {
+    pub request: ControlRequest,
+    pub reply: oneshot::Sender<ControlResponse>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ControlError {
+    #[error("The monitor is not running, no control socket at {}", .0.display())]
+    NotRunning(PathBuf),
+
+    #[error("Control socket IO error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Invalid control message: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("The monitor closed the connection")]
+    ConnectionClosed,
+
+    #[error("Request rejected by the monitor: {0}")]
+    Rejected(String),
+
+    #[error("Unexpected response from the monitor")]
+    UnexpectedResponse,
+}
+
+/// What the daemon has done for each monitored node, for `ivy status`. Times are unix seconds.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StatusReport {
+    pub nodes: Vec<NodeStatus>,
+    pub dispatch: DispatchStatus,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NodeStatus {
+    pub assigned_name: String,
+    pub container_name: String,
+    pub avs_type: String,
+    pub last_scrape: Option<u64>,
+    pub scrape_errors: u64,
+    pub last_dispatch: Option<u64>,
+    pub dispatch_errors: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DispatchStatus {
+    pub last_success: Option<u64>,
+    pub successes: u64,
+    pub errors: u64,
+    pub spool_depth: usize,
+}
+
+impl StatusReport {
+    pub fn new(nodes: &[ConfiguredAvs], snapshot: &StatsSnapshot) -> Self {
+        let nodes = nodes
+            .iter()
+            .map(|avs| {
+                let stats = snapshot.nodes.get(&avs.assigned_name).cloned().unwrap_or_default();
+                NodeStatus {
+                    assigned_name: avs.assigned_name.clone(),
+                    container_name: avs.container_name.clone(),
+                    avs_type: avs.avs_type.clone(),
+                    last_scrape: stats.last_scrape.map(unix_seconds),
+                    scrape_errors: stats.scrape_errors,
+                    last_dispatch: stats.last_dispatch.map(unix_seconds),
+                    dispatch_errors: stats.dispatch_errors,
+                }
+            })
+            .collect();
+        let dispatch = &snapshot.dispatch;
+        Self {
+            nodes,
+            dispatch: DispatchStatus {
+                last_success: dispatch.last_success.map(unix_seconds),
+                successes: dispatch.successes,
+                errors: dispatch.errors,
+                spool_depth: dispatch.spool_depth,
+            },
+        }
+    }
+
+    fn text(&self) -> String {
+        let now = unix_seconds(SystemTime::now());
+        let mut lines: Vec<String> = self
+            .nodes
+            .iter()
+            .map(|node| {
+                format!(
+                    "{} ({}, container {}): last scrape {}, {} scrape errors, last dispatch {}, {} dispatch errors",
+                    node.assigned_name,
+                    node.avs_type,
+                    node.container_name,
+                    ago(node.last_scrape, now),
+                    node.scrape_errors,
+                    ago(node.last_dispatch, now),
+                    node.dispatch_errors,
+                )
+            })
+            .collect();
+        if lines.is_empty() {
+            lines.push("No nodes monitored".to_string());
+        }
+        lines.push(format!(
+            "Dispatch: last success {}, {} sent, {} errors, {} spooled",
+            ago(self.dispatch.last_success, now),
+            self.dispatch.successes,
+            self.dispatch.errors,
+            self.dispatch.spool_depth,
+        ));
+        lines.join("\n")
+    }
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+fn ago(time: Option<u64>, now: u64) -> String {
+    match time {
+        Some(time) => format!("{}s ago", now.saturating_sub(time)),
+        None => "never".to_string(),
+    }
+}
+
+/// Binds the control socket, replacing one left behind by a daemon that did not shut down
+/// cleanly. The socket is only accessible to the user running the daemon.
+pub fn bind(path: &Path) -> io::Result<UnixListener> {
+    if path.exists() {
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("another monitor is listening on {}", path.display()),
+            ));
+        }
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    std::fs::set_permissions(path, Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// Serves control requests until the listener fails. Node changes are forwarded to the docker
+/// stream listener through `control_tx`; status is put together from its nodes and `stats`.
+pub async fn serve(
+    listener: UnixListener,
+    control_tx: ControlTx,
+    stats: TelemetryStats,
+) -> Result<(), io::Error> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let control_tx = control_tx.clone();
+        let stats = stats.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, &control_tx, &stats).await {
+                debug!("Control connection error: {}", e);
+            }
+        });
+    }
+}
+
+async fn handle_connection(
+    stream: UnixStream,
+    control_tx: &ControlTx,
+    stats: &TelemetryStats,
+) -> Result<(), ControlError> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        let response = match serde_json::from_str::<ControlRequest>(&line) {
+            Ok(ControlRequest::Status) => {
+                match forward(ControlRequest::ListNodes, control_tx).await {
+                    ControlResponse::Nodes { nodes } => ControlResponse::Status {
+                        status: StatusReport::new(&nodes, &stats.snapshot()),
+                    },
+                    other => other,
+                }
+            }
+            Ok(request) => forward(request, control_tx).await,
+            Err(e) => ControlResponse::error(format!("Invalid request: {e}")),
+        };
+        write_line(&mut writer, &response).await?;
+    }
+    Ok(())
+}
+
+async fn forward(request: ControlRequest, control_tx: &ControlTx) -> ControlResponse {
+    let (reply, reply_rx) = oneshot::channel();
+    if control_tx.send(ControlCommand { request, reply }).await.is_err() {
+        return ControlResponse::error("The monitor is shutting down");
+    }
+    reply_rx.await.unwrap_or_else(|_| ControlResponse::error("The monitor dropped the request"))
+}
+
+async fn write_line<T: Serialize>(writer: &mut OwnedWriteHalf, message: &T) -> io::Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    writer.write_all(&line).await
+}
+
+/// Connection to the control socket of a running monitor.
+#[derive(Debug)]
+pub struct ControlClient {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: OwnedWriteHalf,
+}
+
+impl ControlClient {
+    pub async fn connect(path: &Path) -> Result<Self, ControlError> {
+        let stream = UnixStream::connect(path).await.map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused => {
+                ControlError::NotRunning(path.to_path_buf())
+            }
+            _ => ControlError::Io(e),
+        })?;
+        let (reader, writer) = stream.into_split();
+        Ok(Self { lines: BufReader::new(reader).lines(), writer })
+    }
+
+    /// Sends a request and waits for its response. Error responses are returned as `Rejected`.
+    pub async fn request(
+        &mut self,
+        request: &ControlRequest,
+    ) -> Result<ControlResponse, ControlError> {
+        write_line(&mut self.writer, request).await?;
+        let line = self.lines.next_line().await?.ok_or(ControlError::ConnectionClosed)?;
+        match serde_json::from_str(&line)? {
+            ControlResponse::Error { message } => Err(ControlError::Rejected(message)),
+            response => Ok(response),
+        }
+    }
+}
+
+#[derive(Parser, Debug, Clone)]
+pub enum NodeCommands {
+    #[command(name = "add", about = "Start monitoring a docker container in the running monitor")]
+    Add {
+        /// Name the node is reported under
+        #[arg(long)]
+        name: String,
+        /// Name of the node's container
+        #[arg(long)]
+        container: String,
+        /// Node type, e.g. EigenDA
+        #[arg(long = "type")]
+        node_type: String,
+        /// Metrics port, discovered from the container when not given
+        #[arg(long)]
+        metrics_port: Option<u16>,
+        /// HTTP path of the metrics endpoint, when it is not served on /metrics
+        #[arg(long)]
+        metrics_path: Option<String>,
+    },
+
+    #[command(name = "remove", about = "Stop monitoring a node in the running monitor")]
+    Remove { name: String },
+
+    #[command(name = "list", about = "List the nodes the running monitor follows")]
+    List,
+}
+
+pub async fn parse_node_subcommands(
+    subcmd: NodeCommands,
+    output: OutputFormat,
+) -> Result<(), ControlError> {
+    let mut client = ControlClient::connect(&default_socket_path()).await?;
+    match subcmd {
+        NodeCommands::Add { name, container, node_type, metrics_port, metrics_path } => {
+            let node = ConfiguredAvs {
+                assigned_name: name,
+                container_name: container,
+                avs_type: node_type,
+                metric_port: metrics_port,
+                metrics_path,
+                manifest: None,
+                image: None,
+                runtime: NodeRuntime::Docker,
+            };
+            let ControlResponse::Node { node } =
+                client.request(&ControlRequest::AddNode { node }).await?
+            else {
+                return Err(ControlError::UnexpectedResponse);
+            };
+            output.print(&node, |node| {
+                format!("Monitoring {} under container {}", node.assigned_name, node.container_name)
+            });
+        }
+        NodeCommands::Remove { name } => {
+            let ControlResponse::Node { node } =
+                client.request(&ControlRequest::RemoveNode { assigned_name: name }).await?
+            else {
+                return Err(ControlError::UnexpectedResponse);
+            };
+            output.print(&node, |node| format!("Stopped monitoring {}", node.assigned_name));
+        }
+        NodeCommands::List => {
+            let ControlResponse::Nodes { nodes } =
+                client.request(&ControlRequest::ListNodes).await?
+            else {
+                return Err(ControlError::UnexpectedResponse);
+            };
+            output.print(&nodes, |nodes| {
+                nodes
+                    .iter()
+                    .map(|node| {
+                        format!(
+                            "{} ({}, container {}, metrics port {})",
+                            node.assigned_name,
+                            node.avs_type,
+                            node.container_name,
+                            node.metric_port.map_or("none".to_string(), |port| port.to_string())
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            });
+        }
+    }
+    Ok(())
+}
+
+pub async fn status(output: OutputFormat) -> Result<(), ControlError> {
+    let mut client = ControlClient::connect(&default_socket_path()).await?;
+    let ControlResponse::Status { status } = client.request(&ControlRequest::Status).await? else {
+        return Err(ControlError::UnexpectedResponse);
+    };
+    output.print(&status, StatusReport::text);
+    Ok(())
+}
+
+#[cfg(test)]
+mod control_tests {
+    use super::*;
+
+    fn node(assigned_name: &str) -> ConfiguredAvs {
+        ConfiguredAvs {
+            assigned_name: assigned_name.to_string(),
+            container_name: format!("{assigned_name}-container"),
+            avs_type: "EigenDA".to_string(),
+            metric_port: Some(9092),
+            metrics_path: None,
+            manifest: None,
+            image: None,
+            runtime: NodeRuntime::Docker,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_control_socket_roundtrip() {
+        let path = std::env::temp_dir().join(format!("ivy-control-{}.sock", std::process::id()));
+        let listener = bind(&path).unwrap();
+        // A second daemon must not take over the socket
+        assert_eq!(bind(&path).unwrap_err().kind(), io::ErrorKind::AddrInUse);
+
+        let stats = TelemetryStats::new();
+        stats.record_scrape_error(&node("da"));
+        let (control_tx, mut control_rx) = mpsc::channel(8);
+        tokio::spawn(serve(listener, control_tx, stats));
+
+        // Stands in for the docker stream listener
+        tokio::spawn(async move {
+            let mut nodes = vec![node("da")];
+            while let Some(ControlCommand { request, reply }) = control_rx.recv().await {
+                let response = match request {
+                    ControlRequest::AddNode { node } => {
+                        nodes.push(node.clone());
+                        ControlResponse::Node { node }
+                    }
+                    ControlRequest::ListNodes => ControlResponse::Nodes { nodes: nodes.clone() },
+                    _ => ControlResponse::error("unsupported"),
+                };
+                let _ = reply.send(response);
+            }
+        });
+
+        let mut client = ControlClient::connect(&path).await.unwrap();
+        let added = client.request(&ControlRequest::AddNode { node: node("lagrange") }).await;
+        assert!(
+            matches!(added.unwrap(), ControlResponse::Node { node } if node == self::node("lagrange"))
+        );
+
+        let ControlResponse::Status { status } =
+            client.request(&ControlRequest::Status).await.unwrap()
+        else {
+            panic!("Expected a status response");
+        };
+        assert_eq!(status.nodes.len(), 2);
+        assert_eq!(status.nodes[0].scrape_errors, 1);
+        assert_eq!(status.nodes[1].last_scrape, None);
+
+        let removed =
+            client.request(&ControlRequest::RemoveNode { assigned_name: "da".to_string() }).await;
+        assert!(
+            matches!(removed, Err(ControlError::Rejected(message)) if message == "unsupported")
+        );
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(ControlClient::connect(&path).await, Err(ControlError::NotRunning(_))));
+    }
+}
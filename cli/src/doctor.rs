@@ -0,0 +1,421 @@
+use std::{
+    fmt,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use ivynet_docker::dockerapi::{DockerApi, DockerClient};
+use ivynet_grpc::{
+    backend::backend_client::BackendClient,
+    client::create_channel,
+    messages::NodeTypeQueries,
+    tonic::{transport::Channel, Code, Request, Status},
+};
+use serde::Serialize;
+
+use crate::{
+    config::IvyConfig,
+    error::Error,
+    ivy_machine::IvyMachine,
+    monitor::MonitorConfig,
+    telemetry::{metrics_listener::fetch_telemetry_from, ConfiguredAvs, NodeRuntime},
+    unattended::OutputFormat,
+};
+
+/// Clock skew against the backend above which the check warns.
+const WARN_CLOCK_SKEW: Duration = Duration::from_secs(2);
+/// Clock skew against the backend above which the check fails.
+const MAX_CLOCK_SKEW: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+    /// Not run, because a check it depends on failed
+    Skip,
+}
+
+impl fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckStatus::Pass => write!(f, "PASS"),
+            CheckStatus::Warn => write!(f, "WARN"),
+            CheckStatus::Fail => write!(f, "FAIL"),
+            CheckStatus::Skip => write!(f, "SKIP"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+/// Result of every dependency check run by `ivy doctor`.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct DoctorReport {
+    pub checks: Vec<Check>,
+}
+
+impl DoctorReport {
+    fn push(&mut self, name: impl ToString, status: CheckStatus, detail: impl ToString) {
+        self.checks.push(Check { name: name.to_string(), status, detail: detail.to_string() });
+    }
+
+    pub fn failures(&self) -> usize {
+        self.checks.iter().filter(|check| check.status == CheckStatus::Fail).count()
+    }
+
+    fn text(&self) -> String {
+        let mut lines: Vec<String> = self
+            .checks
+            .iter()
+            .map(|check| format!("[{}] {}: {}", check.status, check.name, check.detail))
+            .collect();
+        lines.push(match self.failures() {
+            0 => "All checks passed".to_string(),
+            n => format!("{n} check(s) failed"),
+        });
+        lines.join("\n")
+    }
+}
+
+/// Checks every dependency of the monitor daemon and prints a pass/fail report. Fails when any of
+/// the checks does.
+pub async fn doctor(config: &IvyConfig, output: OutputFormat) -> Result<(), Error> {
+    let mut report = DoctorReport::default();
+
+    let docker = DockerClient::default();
+    let docker_ok = check_docker(&mut report, &docker).await;
+
+    let backend = check_backend_ca(&mut report, config).await;
+    let machine = match IvyMachine::from_config(config) {
+        Ok(machine) if config.backend_info.identity_keyfile.is_none() => {
+            report.push(
+                "identity_key",
+                CheckStatus::Warn,
+                format!(
+                    "Key {:?} is stored in plaintext. Encrypt it with `ivy key migrate-identity`",
+                    machine.pubkey()
+                ),
+            );
+            Some(machine)
+        }
+        Ok(machine) => {
+            report.push(
+                "identity_key",
+                CheckStatus::Pass,
+                format!("Loaded key {:?}", machine.pubkey()),
+            );
+            Some(machine)
+        }
+        Err(e) => {
+            report.push(
+                "identity_key",
+                CheckStatus::Fail,
+                format!("{e}. Register the machine with `ivy register-node`"),
+            );
+            None
+        }
+    };
+    match backend {
+        Some(backend) => check_backend(&mut report, backend, machine.as_ref()).await,
+        None => {
+            report.push("backend", CheckStatus::Skip, "No usable CA certificate");
+            report.push("registration", CheckStatus::Skip, "Backend not checked");
+            report.push("clock_skew", CheckStatus::Skip, "Backend not checked");
+        }
+    }
+
+    let nodes = MonitorConfig::load_from_default_path()
+        .map(|config| config.configured_avses)
+        .unwrap_or_default();
+    check_nodes(&mut report, &docker, docker_ok, &nodes).await;
+
+    let failures = report.failures();
+    output.print(&report, DoctorReport::text);
+    match failures {
+        0 => Ok(()),
+        n => Err(Error::DoctorChecksFailed(n)),
+    }
+}
+
+async fn check_docker(report: &mut DoctorReport, docker: &DockerClient) -> bool {
+    match docker.inner().version().await {
+        Ok(version) => {
+            report.push(
+                "docker",
+                CheckStatus::Pass,
+                format!(
+                    "Docker daemon reachable, version {}",
+                    version.version.unwrap_or_else(|| "unknown".to_string())
+                ),
+            );
+            true
+        }
+        Err(e) => {
+            report.push(
+                "docker",
+                CheckStatus::Fail,
+                format!("Cannot reach the docker socket: {e}"),
+            );
+            false
+        }
+    }
+}
+
+/// Checks the configured CA certificate, returning a backend client when the channel can be
+/// built. Channels connect lazily, so reachability is checked by the first request.
+async fn check_backend_ca(
+    report: &mut DoctorReport,
+    config: &IvyConfig,
+) -> Option<BackendClient<Channel>> {
+    let url = match config.get_server_url() {
+        Ok(url) => url,
+        Err(e) => {
+            report.push("backend_ca", CheckStatus::Skip, format!("Invalid server URL: {e}"));
+            return None;
+        }
+    };
+
+    let ca = config.get_server_ca();
+    let ca = if ca.is_empty() {
+        report.push("backend_ca", CheckStatus::Pass, "Using the system root certificates");
+        None
+    } else {
+        // The channel panics on an unreadable certificate, so it is checked up front
+        match std::fs::read_to_string(&ca) {
+            Ok(pem) if pem.contains("BEGIN CERTIFICATE") => {
+                report.push("backend_ca", CheckStatus::Pass, format!("Loaded {ca}"));
+                Some(ca)
+            }
+            Ok(_) => {
+                report.push("backend_ca", CheckStatus::Fail, format!("{ca} holds no certificate"));
+                return None;
+            }
+            Err(e) => {
+                report.push("backend_ca", CheckStatus::Fail, format!("Cannot read {ca}: {e}"));
+                return None;
+            }
+        }
+    };
+
+    match create_channel(url, ca).await {
+        Ok(channel) => Some(BackendClient::new(channel)),
+        Err(e) => {
+            report.push("backend_ca", CheckStatus::Fail, format!("Cannot set up TLS: {e}"));
+            None
+        }
+    }
+}
+
+/// Pings the backend with a request signed by the identity key. A reply shows the backend is
+/// reachable, the key is registered for this machine, and carries the server time.
+async fn check_backend(
+    report: &mut DoctorReport,
+    mut backend: BackendClient<Channel>,
+    machine: Option<&IvyMachine>,
+) {
+    let Some(machine) = machine else {
+        // Without a key only reachability can be checked
+        let query = Request::new(NodeTypeQueries { node_types: vec![] });
+        match backend.node_type_queries(query).await {
+            Ok(_) => report.push("backend", CheckStatus::Pass, "Backend reachable"),
+            Err(e) => report.push("backend", CheckStatus::Fail, unreachable_detail(&e)),
+        }
+        report.push("registration", CheckStatus::Skip, "No identity key");
+        report.push("clock_skew", CheckStatus::Skip, "No identity key");
+        return;
+    };
+
+    let sent_at = unix_millis(SystemTime::now());
+    let ping = match machine.sign_ping(sent_at) {
+        Ok(ping) => ping,
+        Err(e) => {
+            report.push("backend", CheckStatus::Skip, "Cannot sign requests");
+            report.push("registration", CheckStatus::Fail, format!("Cannot sign requests: {e}"));
+            report.push("clock_skew", CheckStatus::Skip, "Cannot sign requests");
+            return;
+        }
+    };
+    let started = Instant::now();
+    let response = backend.ping(Request::new(ping)).await;
+    let round_trip = started.elapsed();
+
+    match response {
+        Ok(pong) => {
+            report.push(
+                "backend",
+                CheckStatus::Pass,
+                format!("Backend reachable, round trip {} ms", round_trip.as_millis()),
+            );
+            report.push(
+                "registration",
+                CheckStatus::Pass,
+                format!("Key {:?} is registered for machine {}", machine.pubkey(), machine.id()),
+            );
+            // The server read its clock about halfway through the round trip
+            let local_time = sent_at + round_trip.as_millis() as u64 / 2;
+            let skew_ms = pong.into_inner().server_time as i64 - local_time as i64;
+            report.push("clock_skew", clock_skew_status(skew_ms), format!("{skew_ms} ms"));
+        }
+        Err(e) if e.code() == Code::OutOfRange => {
+            // The backend refuses signatures from too far off its clock before it looks at the key
+            report.push("backend", CheckStatus::Pass, "Backend reachable");
+            report.push("registration", CheckStatus::Skip, "Clock too far off to sign requests");
+            report.push("clock_skew", CheckStatus::Fail, e.message());
+        }
+        Err(e) if e.code() == Code::NotFound || e.code() == Code::InvalidArgument => {
+            report.push("backend", CheckStatus::Pass, "Backend reachable");
+            report.push(
+                "registration",
+                CheckStatus::Fail,
+                format!(
+                    "Key {:?} is not registered for machine {}: {}",
+                    machine.pubkey(),
+                    machine.id(),
+                    e.message()
+                ),
+            );
+            report.push("clock_skew", CheckStatus::Skip, "Machine not registered");
+        }
+        Err(e) if e.code() == Code::Unimplemented => {
+            report.push("backend", CheckStatus::Pass, "Backend reachable");
+            report.push("registration", CheckStatus::Skip, "Backend does not support ping");
+            report.push("clock_skew", CheckStatus::Skip, "Backend does not support ping");
+        }
+        Err(e) => {
+            report.push("backend", CheckStatus::Fail, unreachable_detail(&e));
+            report.push("registration", CheckStatus::Skip, "Backend unreachable");
+            report.push("clock_skew", CheckStatus::Skip, "Backend unreachable");
+        }
+    }
+}
+
+fn unreachable_detail(status: &Status) -> String {
+    format!("Cannot reach the backend: {} ({:?})", status.message(), status.code())
+}
+
+fn clock_skew_status(skew_ms: i64) -> CheckStatus {
+    let skew = Duration::from_millis(skew_ms.unsigned_abs());
+    if skew > MAX_CLOCK_SKEW {
+        CheckStatus::Fail
+    } else if skew > WARN_CLOCK_SKEW {
+        CheckStatus::Warn
+    } else {
+        CheckStatus::Pass
+    }
+}
+
+/// Checks that each configured node is running and that its metrics endpoint answers, then that
+/// sidecar port discovery works against the first running docker node.
+async fn check_nodes(
+    report: &mut DoctorReport,
+    docker: &DockerClient,
+    docker_ok: bool,
+    nodes: &[ConfiguredAvs],
+) {
+    if nodes.is_empty() {
+        report.push("nodes", CheckStatus::Warn, "No nodes configured. Run `ivy scan`");
+    }
+
+    let http_client = reqwest::Client::new();
+    let mut sidecar_target = None;
+    for node in nodes {
+        let name = format!("node {}", node.assigned_name);
+        match &node.runtime {
+            NodeRuntime::Docker if !docker_ok => {
+                report.push(name, CheckStatus::Skip, "Docker unreachable");
+                continue;
+            }
+            NodeRuntime::Docker => {
+                match docker.find_container_by_name(&node.container_name).await {
+                    Some(container) => {
+                        sidecar_target.get_or_insert((container, node.container_name.as_str()));
+                    }
+                    None => {
+                        report.push(
+                            name,
+                            CheckStatus::Fail,
+                            format!("Container {} not found", node.container_name),
+                        );
+                        continue;
+                    }
+                }
+            }
+            NodeRuntime::Systemd { .. } => {
+                if !node.node_running().await {
+                    report.push(
+                        name,
+                        CheckStatus::Fail,
+                        format!("Unit {} is not running", node.container_name),
+                    );
+                    continue;
+                }
+            }
+        }
+
+        let Some(port) = node.metric_port else {
+            report.push(name, CheckStatus::Warn, "Running, but no metrics port is configured");
+            continue;
+        };
+        match fetch_telemetry_from(&http_client, &node.container_name, port, node.metrics_path())
+            .await
+        {
+            Ok(metrics) => report.push(
+                name,
+                CheckStatus::Pass,
+                format!("Running, {} metrics on port {}", metrics.len(), port),
+            ),
+            Err(e) => report.push(name, CheckStatus::Fail, e),
+        }
+    }
+
+    match sidecar_target {
+        Some((container, container_name)) => match container.get_sidecar_ports(docker).await {
+            Ok(ports) => report.push(
+                "sidecar_port_discovery",
+                CheckStatus::Pass,
+                format!("Found ports {:?} of container {}", ports, container_name),
+            ),
+            Err(e) => report.push("sidecar_port_discovery", CheckStatus::Fail, e),
+        },
+        None => report.push("sidecar_port_discovery", CheckStatus::Skip, "No running docker node"),
+    }
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+#[cfg(test)]
+mod doctor_tests {
+    use super::*;
+
+    #[test]
+    fn test_clock_skew_status() {
+        assert_eq!(clock_skew_status(0), CheckStatus::Pass);
+        assert_eq!(clock_skew_status(-1_500), CheckStatus::Pass);
+        assert_eq!(clock_skew_status(5_000), CheckStatus::Warn);
+        assert_eq!(clock_skew_status(-31_000), CheckStatus::Fail);
+    }
+
+    #[test]
+    fn test_report_failures() {
+        let mut report = DoctorReport::default();
+        report.push("docker", CheckStatus::Pass, "ok");
+        report.push("nodes", CheckStatus::Warn, "none");
+        assert_eq!(report.failures(), 0);
+        assert!(report.text().ends_with("All checks passed"));
+
+        report.push("backend", CheckStatus::Fail, "down");
+        assert_eq!(report.failures(), 1);
+        assert!(report.text().contains("[FAIL] backend: down"));
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["checks"][2]["status"], "fail");
+    }
+}
@@ -47,12 +47,21 @@ pub enum Error {
     #[error(transparent)]
     KeychainError(#[from] ivynet_signer::keychain::KeychainError),
 
+    #[error(transparent)]
+    Identity(#[from] crate::identity::IdentityError),
+
     #[error("Chain Unimplemented: {0}")]
     ChainUnimplemented(String),
 
     #[error("Invalid server URI")]
     InvalidUri,
 
+    #[error("{0} doctor check(s) failed")]
+    DoctorChecksFailed(usize),
+
+    #[error(transparent)]
+    Unattended(#[from] crate::unattended::UnattendedError),
+
     #[error("Machine Identity Error")]
     MachineIdentityError(#[from] MachineIdentityError),
 }
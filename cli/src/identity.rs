@@ -0,0 +1,263 @@
+use std::{
+    fs::{self, Permissions},
+    io::Write as _,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use dialoguer::Password;
+use ivynet_signer::{
+    keychain::{KeyName, Keychain, KeychainError},
+    IvyWallet, IvyWalletError,
+};
+use tracing::{debug, info};
+
+use crate::{config::IvyConfig, unattended::is_interactive};
+
+/// Environment variable holding the password of the identity keystore.
+pub const PASSWORD_ENV: &str = "IVYNET_IDENTITY_PASSWORD";
+/// Environment variable naming a file that holds the password of the identity keystore.
+pub const PASSWORD_FILE_ENV: &str = "IVYNET_IDENTITY_PASSWORD_FILE";
+
+/// Keychain name of the machine identity keystore, stored as `identity.ecdsa.json`.
+pub const IDENTITY_KEY_NAME: &str = "identity";
+/// Keychain name of a key that is being rotated to, kept until the config points at it.
+pub const PENDING_IDENTITY_KEY_NAME: &str = "identity-pending";
+
+/// Secret Service attributes the password is stored under in the OS keyring.
+const KEYRING_SERVICE: &str = "ivynet";
+const KEYRING_LABEL: &str = "IvyNet machine identity";
+
+#[derive(Debug, thiserror::Error)]
+pub enum IdentityError {
+    #[error(
+        "No password for the identity keystore. Set IVYNET_IDENTITY_PASSWORD, point IVYNET_IDENTITY_PASSWORD_FILE at a file holding it, or store it in the OS keyring"
+    )]
+    NoPassword,
+
+    #[error("Cannot read password file {}: {source}", path.display())]
+    PasswordFile { path: PathBuf, source: std::io::Error },
+
+    #[error("OS keyring error: {0}")]
+    Keyring(String),
+
+    #[error("Cannot decrypt the identity keystore: {0}")]
+    Keystore(#[from] KeychainError),
+
+    #[error(transparent)]
+    Wallet(#[from] IvyWalletError),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Dialoguer(#[from] dialoguer::Error),
+
+    #[error("No plaintext identity key to migrate")]
+    NothingToMigrate,
+}
+
+/// Finds the password of the identity keystore. It is taken from `IVYNET_IDENTITY_PASSWORD`,
+/// then from the file named by `IVYNET_IDENTITY_PASSWORD_FILE` or the config, and last from the OS
+/// keyring.
+pub fn identity_password(config: &IvyConfig) -> Result<String, IdentityError> {
+    if let Ok(password) = std::env::var(PASSWORD_ENV) {
+        return Ok(password);
+    }
+
+    let password_file = std::env::var_os(PASSWORD_FILE_ENV)
+        .map(PathBuf::from)
+        .or_else(|| config.backend_info.identity_password_file.clone());
+    if let Some(path) = password_file {
+        return read_password_file(&path);
+    }
+
+    keyring_lookup(&keyring_account(config))?.ok_or(IdentityError::NoPassword)
+}
+
+/// Password to encrypt a new identity keystore with. When none is configured, a new one is
+/// prompted for and stored in the OS keyring.
+pub fn new_identity_password(config: &IvyConfig) -> Result<String, IdentityError> {
+    match identity_password(config) {
+        Err(IdentityError::NoPassword) if is_interactive() => {
+            let password = Password::new()
+                .with_prompt("Enter a password to encrypt the machine identity key with")
+                .with_confirmation("Repeat the password", "Passwords do not match")
+                .interact()?;
+            keyring_store(&keyring_account(config), &password)?;
+            info!("Stored the identity key password in the OS keyring");
+            Ok(password)
+        }
+        result => result,
+    }
+}
+
+/// Decrypts the identity keystore stored under `name` in the config directory.
+pub fn load_identity(config: &IvyConfig, name: &str) -> Result<IvyWallet, IdentityError> {
+    load_identity_with(config, name, &identity_password(config)?)
+}
+
+fn load_identity_with(
+    config: &IvyConfig,
+    name: &str,
+    password: &str,
+) -> Result<IvyWallet, IdentityError> {
+    let key = Keychain::new(config.get_dir()).load(KeyName::Ecdsa(name.to_string()), password)?;
+    key.get_wallet_owned().ok_or(IdentityError::Keystore(KeychainError::NoKeyFoundError))
+}
+
+/// Encrypts `wallet` into the keystore `name` in the config directory, readable only by the
+/// current user. Returns the path of the keystore.
+pub fn store_identity(
+    config: &IvyConfig,
+    wallet: &IvyWallet,
+    password: &str,
+    name: &str,
+) -> Result<PathBuf, IdentityError> {
+    let path =
+        wallet.encrypt_and_store(&config.get_dir(), keystore_file(name), password.to_string())?;
+    fs::set_permissions(&path, Permissions::from_mode(0o600))?;
+    debug!("Stored identity keystore at {}", path.display());
+    Ok(path)
+}
+
+/// Deletes the keystore `name` from the config directory.
+pub fn remove_identity(config: &IvyConfig, name: &str) -> Result<(), IdentityError> {
+    match fs::remove_file(config.get_dir().join(keystore_file(name))) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Moves a plaintext `identity_key` from the config into an encrypted keystore, returning the
+/// wallet. The config is only rewritten once the keystore decrypts to the same key.
+pub fn migrate_identity(config: &mut IvyConfig) -> Result<IvyWallet, IdentityError> {
+    if config.backend_info.identity_key.is_empty() {
+        return Err(IdentityError::NothingToMigrate);
+    }
+    let wallet = IvyWallet::from_private_key(config.backend_info.identity_key.clone())?;
+    let password = new_identity_password(config)?;
+    store_identity(config, &wallet, &password, IDENTITY_KEY_NAME)?;
+
+    if load_identity_with(config, IDENTITY_KEY_NAME, &password)?.address() != wallet.address() {
+        return Err(IdentityError::Keystore(KeychainError::NoKeyFoundError));
+    }
+
+    config.backend_info.identity_keyfile = Some(IDENTITY_KEY_NAME.to_string());
+    config.backend_info.identity_key.clear();
+    Ok(wallet)
+}
+
+fn keystore_file(name: &str) -> String {
+    format!("{name}.ecdsa.json")
+}
+
+fn read_password_file(path: &Path) -> Result<String, IdentityError> {
+    fs::read_to_string(path)
+        .map(|password| password.trim_end_matches(['\r', '\n']).to_string())
+        .map_err(|source| IdentityError::PasswordFile { path: path.to_path_buf(), source })
+}
+
+fn keyring_account(config: &IvyConfig) -> String {
+    format!("identity-{}", config.machine_id)
+}
+
+/// Looks the password up in the Secret Service through `secret-tool`. A missing tool or entry is
+/// not an error, as the keyring is the last of the password sources.
+fn keyring_lookup(account: &str) -> Result<Option<String>, IdentityError> {
+    let output = match Command::new("secret-tool")
+        .args(["lookup", "service", KEYRING_SERVICE, "account", account])
+        .stderr(Stdio::null())
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) => {
+            debug!("OS keyring not available: {}", e);
+            return Ok(None);
+        }
+    };
+    if !output.status.success() || output.stdout.is_empty() {
+        return Ok(None);
+    }
+    String::from_utf8(output.stdout)
+        .map(Some)
+        .map_err(|_| IdentityError::Keyring("Password in the keyring is not UTF-8".to_string()))
+}
+
+fn keyring_store(account: &str, password: &str) -> Result<(), IdentityError> {
+    let mut child = Command::new("secret-tool")
+        .args(["store", "--label", KEYRING_LABEL, "service", KEYRING_SERVICE, "account", account])
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| {
+            IdentityError::Keyring(format!(
+                "Cannot run secret-tool ({e}). Set {PASSWORD_ENV} or {PASSWORD_FILE_ENV} instead"
+            ))
+        })?;
+    // The password goes through stdin so it never shows up in the process list
+    child.stdin.take().expect("stdin is piped").write_all(password.as_bytes())?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(IdentityError::Keyring(String::from_utf8_lossy(&output.stderr).to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod identity_tests {
+    use serial_test::serial;
+
+    use super::*;
+
+    #[test]
+    #[serial]
+    fn test_store_and_migrate_identity() {
+        let dir = std::env::temp_dir().join(format!("ivy-identity-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        std::env::set_var(PASSWORD_ENV, "correct horse");
+
+        let wallet = IvyWallet::new();
+        let mut config = IvyConfig::new_at_path(dir.clone());
+        config.backend_info.identity_key = wallet.to_private_key();
+
+        let migrated = migrate_identity(&mut config).unwrap();
+        assert_eq!(migrated.address(), wallet.address());
+        assert!(config.backend_info.identity_key.is_empty());
+        assert_eq!(config.backend_info.identity_keyfile.as_deref(), Some(IDENTITY_KEY_NAME));
+        assert_eq!(config.identity_wallet().unwrap().address(), wallet.address());
+        assert!(matches!(migrate_identity(&mut config), Err(IdentityError::NothingToMigrate)));
+
+        // Only the user can read the keystore, and it does not hold the key in plaintext
+        let keystore = dir.join(format!("{IDENTITY_KEY_NAME}.ecdsa.json"));
+        assert_eq!(fs::metadata(&keystore).unwrap().permissions().mode() & 0o777, 0o600);
+        assert!(!fs::read_to_string(&keystore).unwrap().contains(&wallet.to_private_key()));
+
+        std::env::set_var(PASSWORD_ENV, "wrong");
+        assert!(config.identity_wallet().is_err());
+
+        std::env::remove_var(PASSWORD_ENV);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_password_file() {
+        let path = std::env::temp_dir().join(format!("ivy-identity-pw-{}", std::process::id()));
+        fs::write(&path, "from file\n").unwrap();
+
+        let mut config = IvyConfig::default();
+        config.backend_info.identity_password_file = Some(path.clone());
+        assert_eq!(identity_password(&config).unwrap(), "from file");
+
+        // The environment takes precedence
+        std::env::set_var(PASSWORD_ENV, "from env");
+        assert_eq!(identity_password(&config).unwrap(), "from env");
+        std::env::remove_var(PASSWORD_ENV);
+
+        config.backend_info.identity_password_file = Some(path.with_extension("missing"));
+        assert!(matches!(identity_password(&config), Err(IdentityError::PasswordFile { .. })));
+        fs::remove_file(path).unwrap();
+    }
+}
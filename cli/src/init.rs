@@ -1,79 +1,197 @@
+use std::{fmt, fs, path::PathBuf};
+
+use clap::Args;
 use dialoguer::{Input, Password};
 use ivynet_grpc::{
-    backend::backend_client::BackendClient, client::create_channel,
-    messages::RegistrationCredentials, tonic::Request,
+    backend::backend_client::BackendClient,
+    client::create_channel,
+    messages::RegistrationCredentials,
+    tonic::{Code, Request, Status},
 };
 use ivynet_signer::IvyWallet;
+use serde::Serialize;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+    config::IvyConfig,
+    error::Error,
+    identity,
+    unattended::{is_interactive, OutputFormat, UnattendedError},
+};
+
+/// Login of the IvyNet account the machine is registered to.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub email: String,
+    pub password: String,
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials").field("email", &self.email).finish_non_exhaustive()
+    }
+}
+
+/// Credentials for registering without prompts. The password is not accepted as a flag value, as
+/// it would end up in the process list and shell history.
+#[derive(Args, Clone, Debug, Default)]
+pub struct CredentialArgs {
+    /// Email address of the IvyNet account
+    #[arg(long, env = "IVYNET_EMAIL")]
+    pub email: Option<String>,
+    /// Name of the environment variable holding the account password
+    #[arg(long, conflicts_with = "password_file")]
+    pub password_env: Option<String>,
+    /// File holding the account password
+    #[arg(long, env = "IVYNET_PASSWORD_FILE")]
+    pub password_file: Option<PathBuf>,
+}
 
-use crate::{config::IvyConfig, error::Error};
+impl CredentialArgs {
+    /// Credentials given through flags, the environment or a file. `None` when none were given,
+    /// in which case they are prompted for.
+    pub fn credentials(&self) -> Result<Option<Credentials>, UnattendedError> {
+        let Some(email) = self.email.clone() else {
+            if self.password_env.is_some() || self.password_file.is_some() {
+                return Err(UnattendedError::InvalidInput(
+                    "a password was given without --email".to_string(),
+                ));
+            }
+            return Ok(None);
+        };
 
-pub async fn register_node(mut config: IvyConfig) -> Result<(), Error> {
-    set_backend_connection(&mut config).await?;
-    println!("Backend connection set.");
+        let password = match (&self.password_env, &self.password_file) {
+            (Some(var), _) => std::env::var(var).map_err(|_| {
+                UnattendedError::InvalidInput(format!("environment variable {var} is not set"))
+            })?,
+            (None, Some(path)) => fs::read_to_string(path)
+                .map_err(|e| {
+                    UnattendedError::InvalidInput(format!(
+                        "cannot read password file {}: {e}",
+                        path.display()
+                    ))
+                })?
+                .trim_end_matches(['\r', '\n'])
+                .to_string(),
+            (None, None) => {
+                return Err(UnattendedError::InvalidInput(
+                    "--email needs --password-env or --password-file".to_string(),
+                ))
+            }
+        };
+        Ok(Some(Credentials { email, password }))
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RegistrationReport {
+    pub machine_id: Uuid,
+    pub public_key: String,
+}
+
+pub async fn register_node(
+    mut config: IvyConfig,
+    credentials: Option<Credentials>,
+    output: OutputFormat,
+) -> Result<(), Error> {
+    set_backend_connection(&mut config, credentials).await?;
     let wallet = config.identity_wallet()?;
-    let client_key = wallet.address();
 
-    println!("Node registration for key {:?} successful.", client_key);
+    let report = RegistrationReport {
+        machine_id: config.machine_id,
+        public_key: format!("{:?}", wallet.address()),
+    };
+    output.print(&report, |report| {
+        format!("Node registration for key {} successful.", report.public_key)
+    });
 
     Ok(())
 }
 
-pub async fn set_backend_connection(config: &mut IvyConfig) -> Result<(), Error> {
-    let (identity_key, client_key) = match config.identity_wallet() {
-        Ok(key) => (key.to_private_key(), key.address()),
-        _ => {
-            let new_key = IvyWallet::new();
-            (new_key.to_private_key(), new_key.address())
-        }
+/// Registers the machine with the backend and stores its identity key. Credentials are prompted
+/// for, and retried on failure, when none are given. Given credentials are tried once.
+pub async fn set_backend_connection(
+    config: &mut IvyConfig,
+    mut credentials: Option<Credentials>,
+) -> Result<(), Error> {
+    let prompt = credentials.is_none();
+    if prompt && !is_interactive() {
+        return Err(UnattendedError::MissingCredentials.into());
+    }
+
+    // A keystore that cannot be decrypted is an error rather than a reason to replace the key. A
+    // new key needs its keystore password before registering, so an unattended run without one
+    // fails before the backend knows about the key.
+    let (identity_key, password) = match config.identity_wallet() {
+        Ok(key) => (key, None),
+        Err(e) if config.backend_info.identity_keyfile.is_some() => return Err(e),
+        _ => (IvyWallet::new(), Some(identity::new_identity_password(config)?)),
     };
+    let client_key = identity_key.address();
+
+    let mut backend = BackendClient::new(
+        create_channel(config.get_server_url()?, {
+            let ca = config.get_server_ca();
+            if ca.is_empty() {
+                None
+            } else {
+                Some(ca.clone())
+            }
+        })
+        .await?,
+    );
+    let hostname = { String::from_utf8(rustix::system::uname().nodename().to_bytes().to_vec()) }
+        .expect("Cannot fetch hostname from the node");
 
     loop {
-        let email = Input::new()
-            .with_prompt("Provide email address to IvyNet system")
-            .interact_text()
-            .expect("No no email provided");
-        let password = Password::new()
-            .with_prompt("Enter a password to IvyNet system")
-            .interact()
-            .expect("No password provided");
-        let mut backend = BackendClient::new(
-            create_channel(config.get_server_url()?, {
-                let ca = config.get_server_ca();
-                if ca.is_empty() {
-                    None
-                } else {
-                    Some(ca.clone())
-                }
-            })
-            .await?,
-        );
-        let hostname =
-            { String::from_utf8(rustix::system::uname().nodename().to_bytes().to_vec()) }
-                .expect("Cannot fetch hostname from the node");
-        if let Err(e) = backend
+        let Credentials { email, password } = match credentials.take() {
+            Some(credentials) => credentials,
+            None => Credentials {
+                email: Input::new()
+                    .with_prompt("Provide email address to IvyNet system")
+                    .interact_text()?,
+                password: Password::new()
+                    .with_prompt("Enter a password to IvyNet system")
+                    .interact()?,
+            },
+        };
+        match backend
             .register(Request::new(RegistrationCredentials {
                 machine_id: config.machine_id.into(),
                 email,
                 password,
-                hostname,
+                hostname: hostname.clone(),
                 public_key: client_key.as_bytes().to_vec(),
             }))
             .await
         {
-            println!("Error registering node: {:?}", e);
-        } else {
-            break;
+            Ok(_) => break,
+            Err(e) if prompt => println!("Error registering node: {:?}", e),
+            Err(e) => return Err(registration_error(e).into()),
         }
     }
-    println!("Node properly registered with key {:?}", client_key);
-    config.backend_info.identity_key = identity_key;
+    info!("Node properly registered with key {:?}", client_key);
+    if let Some(password) = password {
+        config.set_identity_wallet(&identity_key, &password)?;
+    }
     config.store()?;
     Ok(())
 }
 
+fn registration_error(status: Status) -> UnattendedError {
+    match status.code() {
+        Code::Unavailable | Code::DeadlineExceeded => {
+            UnattendedError::BackendUnavailable(status.message().to_string())
+        }
+        _ => UnattendedError::RegistrationFailed(status.message().to_string()),
+    }
+}
+
 #[cfg(test)]
 pub mod test {
     use super::*;
+    use crate::unattended::EXIT_USAGE;
     use std::{future::Future, path::PathBuf};
     use tokio::fs;
 
@@ -100,4 +218,45 @@ pub mod test {
         })
         .await;
     }
+
+    #[tokio::test]
+    async fn test_credentials_from_env_and_file() {
+        assert_eq!(CredentialArgs::default().credentials().unwrap(), None);
+
+        std::env::set_var("IVY_TEST_CREDENTIALS_PASSWORD", "hunter2");
+        let from_env = CredentialArgs {
+            email: Some("ops@example.com".to_string()),
+            password_env: Some("IVY_TEST_CREDENTIALS_PASSWORD".to_string()),
+            password_file: None,
+        };
+        assert_eq!(
+            from_env.credentials().unwrap(),
+            Some(Credentials {
+                email: "ops@example.com".to_string(),
+                password: "hunter2".to_string()
+            })
+        );
+
+        let from_file = build_test_dir("test_credentials", |test_path| async move {
+            let password_file = test_path.join("password");
+            fs::write(&password_file, "hunter2\n").await.unwrap();
+            CredentialArgs {
+                email: Some("ops@example.com".to_string()),
+                password_env: None,
+                password_file: Some(password_file),
+            }
+            .credentials()
+        })
+        .await;
+        assert_eq!(from_file.unwrap().unwrap().password, "hunter2");
+
+        let missing_password =
+            CredentialArgs { email: Some("ops@example.com".to_string()), ..Default::default() };
+        assert_eq!(missing_password.credentials().unwrap_err().exit_code(), EXIT_USAGE);
+        let missing_env = CredentialArgs {
+            password_env: Some("IVY_TEST_CREDENTIALS_UNSET".to_string()),
+            ..from_env
+        };
+        assert!(missing_env.credentials().is_err());
+    }
 }
@@ -1,12 +1,23 @@
-use ethers::types::Address;
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use ethers::types::{Address, H256};
 use ivynet_grpc::messages::{
-    DiskInformation, MachineData, Metrics, NodeDataV2, SignedClientLog, SignedLog,
-    SignedMachineData, SignedMetrics, SignedNameChange, SignedNodeDataV2,
+    DiskInformation, MachineData, Metrics, NodeDataV2, SignedClientLog, SignedKeyRotation,
+    SignedLog, SignedLogBatch, SignedMachineData, SignedMetrics, SignedNameChange,
+    SignedNodeDataV2, SignedPing, SignedSessionRequest,
 };
 use ivynet_signer::{
-    sign_utils::{
-        sign_client_log, sign_log, sign_machine_data, sign_metrics, sign_name_change,
-        sign_node_data_v2, IvySigningError,
+    sign_utils::{sign_hash, IvySigningError},
+    typed_data::{
+        client_log_digest, key_rotation_digest, log_batch_digest, log_digest, machine_data_digest,
+        metrics_digest, name_change_digest, node_data_digest, ping_digest, session_digest,
+        Envelope, SIGNATURE_VERSION,
     },
     IvyWallet,
 };
@@ -19,22 +30,37 @@ mod version_hash {
 
 use crate::config::IvyConfig;
 
+/// Identity of the machine, signing everything sent to the backend. Clones share the key, so a
+/// reloaded key is used by all of them.
 #[derive(Clone, Debug)]
 pub struct IvyMachine {
     pub id: Uuid,
-    pub signer: IvyWallet,
+    signer: Arc<RwLock<IvyWallet>>,
+    sequence: Sequence,
 }
 
 impl IvyMachine {
     pub fn new(id: Uuid, signer: IvyWallet) -> Self {
-        Self { id, signer }
+        Self { id, signer: Arc::new(RwLock::new(signer)), sequence: Sequence::default() }
     }
 
     pub fn from_config(config: &IvyConfig) -> Result<Self, MachineIdentityError> {
-        let id = config.machine_id;
         let signer =
             config.identity_wallet().map_err(|_| MachineIdentityError::IdentityWalletError)?;
-        Ok(Self { id, signer })
+        Ok(Self::new(config.machine_id, signer))
+    }
+
+    /// Loads the identity key from the config again, as `ivy key rotate-identity` may have
+    /// replaced it while the daemon is running. Returns whether the key changed.
+    pub fn reload_identity(&self) -> Result<bool, MachineIdentityError> {
+        let signer = IvyConfig::load_from_default_path()
+            .map_err(|_| MachineIdentityError::IdentityWalletError)?
+            .identity_wallet()
+            .map_err(|_| MachineIdentityError::IdentityWalletError)?;
+        let mut current = self.signer.write().expect("Write lock failed");
+        let changed = current.address() != signer.address();
+        *current = signer;
+        Ok(changed)
     }
 
     pub fn system_info(&self) -> SystemInformation {
@@ -46,7 +72,7 @@ impl IvyMachine {
     }
 
     pub fn pubkey(&self) -> Address {
-        self.signer.address()
+        self.signer.read().expect("Read lock failed").address()
     }
 
     pub fn sign_metrics(
@@ -54,13 +80,27 @@ impl IvyMachine {
         avs_name: Option<String>,
         metrics: &[Metrics],
     ) -> Result<SignedMetrics, MachineIdentityError> {
-        let signature =
-            sign_metrics(metrics, &self.signer).map_err(MachineIdentityError::SigningError)?;
+        self.sign_collected_metrics(avs_name, metrics, unix_millis(SystemTime::now()))
+    }
+
+    /// Signs metrics that were scraped at `collected_at`, in unix milliseconds.
+    pub fn sign_collected_metrics(
+        &self,
+        avs_name: Option<String>,
+        metrics: &[Metrics],
+        collected_at: u64,
+    ) -> Result<SignedMetrics, MachineIdentityError> {
+        let envelope = self.envelope();
+        let digest = metrics_digest(&envelope, avs_name.as_deref(), metrics, collected_at);
         Ok(SignedMetrics {
             machine_id: self.id.into(),
             avs_name,
             metrics: metrics.to_vec(),
-            signature: signature.into(),
+            signature: self.sign(digest)?,
+            signature_version: SIGNATURE_VERSION,
+            timestamp: envelope.timestamp,
+            sequence: envelope.sequence,
+            collected_at,
         })
     }
 
@@ -68,12 +108,14 @@ impl IvyMachine {
         &self,
         node_data: &NodeDataV2,
     ) -> Result<SignedNodeDataV2, MachineIdentityError> {
-        let signature = sign_node_data_v2(node_data, &self.signer)
-            .map_err(MachineIdentityError::SigningError)?;
+        let envelope = self.envelope();
         Ok(SignedNodeDataV2 {
             machine_id: self.id.into(),
             node_data: Some(node_data.clone()),
-            signature: signature.into(),
+            signature: self.sign(node_data_digest(&envelope, node_data))?,
+            signature_version: SIGNATURE_VERSION,
+            timestamp: envelope.timestamp,
+            sequence: envelope.sequence,
         })
     }
 
@@ -100,11 +142,24 @@ impl IvyMachine {
                 })
                 .collect(),
         };
-        let signature = sign_machine_data(&machine_data, &self.signer)?;
+        self.sign_collected_machine_data(machine_data, unix_millis(SystemTime::now()))
+    }
+
+    /// Signs machine data that was collected earlier, at `collected_at` in unix milliseconds.
+    pub fn sign_collected_machine_data(
+        &self,
+        machine_data: MachineData,
+        collected_at: u64,
+    ) -> Result<SignedMachineData, MachineIdentityError> {
+        let envelope = self.envelope();
         Ok(SignedMachineData {
             machine_id: self.id.into(),
-            signature: signature.into(),
-            machine_data: Some(machine_data.clone()),
+            signature: self.sign(machine_data_digest(&envelope, &machine_data, collected_at))?,
+            machine_data: Some(machine_data),
+            signature_version: SIGNATURE_VERSION,
+            timestamp: envelope.timestamp,
+            sequence: envelope.sequence,
+            collected_at,
         })
     }
 
@@ -113,34 +168,137 @@ impl IvyMachine {
         old_name: &str,
         new_name: &str,
     ) -> Result<SignedNameChange, MachineIdentityError> {
-        let signature = sign_name_change(old_name, new_name, &self.signer)?;
+        let envelope = self.envelope();
         Ok(SignedNameChange {
-            signature: signature.into(),
+            signature: self.sign(name_change_digest(&envelope, old_name, new_name))?,
             machine_id: self.id.into(),
             old_name: old_name.to_string(),
             new_name: new_name.to_string(),
+            signature_version: SIGNATURE_VERSION,
+            timestamp: envelope.timestamp,
+            sequence: envelope.sequence,
         })
     }
 
     pub fn sign_log(&self, avs_name: &str, log: &str) -> Result<SignedLog, MachineIdentityError> {
-        let signature = sign_log(log, &self.signer).map_err(MachineIdentityError::SigningError)?;
+        let envelope = self.envelope();
         Ok(SignedLog {
-            signature: signature.into(),
+            signature: self.sign(log_digest(&envelope, avs_name, log))?,
             machine_id: self.id.into(),
             avs_name: avs_name.to_string(),
             log: log.to_string(),
+            signature_version: SIGNATURE_VERSION,
+            timestamp: envelope.timestamp,
+            sequence: envelope.sequence,
+        })
+    }
+
+    pub fn sign_log_batch(
+        &self,
+        avs_name: &str,
+        logs: &[String],
+    ) -> Result<SignedLogBatch, MachineIdentityError> {
+        let envelope = self.envelope();
+        Ok(SignedLogBatch {
+            signature: self.sign(log_batch_digest(&envelope, avs_name, logs))?,
+            machine_id: self.id.into(),
+            avs_name: avs_name.to_string(),
+            logs: logs.to_vec(),
+            signature_version: SIGNATURE_VERSION,
+            timestamp: envelope.timestamp,
+            sequence: envelope.sequence,
         })
     }
 
     pub fn sign_client_log(&self, log: &str) -> Result<SignedClientLog, MachineIdentityError> {
-        let signature =
-            sign_client_log(log, &self.signer).map_err(MachineIdentityError::SigningError)?;
+        let envelope = self.envelope();
         Ok(SignedClientLog {
-            signature: signature.into(),
+            signature: self.sign(client_log_digest(&envelope, log))?,
             machine_id: self.id.into(),
             log: log.to_string(),
+            signature_version: SIGNATURE_VERSION,
+            timestamp: envelope.timestamp,
+            sequence: envelope.sequence,
+        })
+    }
+
+    /// Signs a ping carrying the client time in unix milliseconds.
+    pub fn sign_ping(&self, timestamp: u64) -> Result<SignedPing, MachineIdentityError> {
+        let envelope = self.envelope_at(timestamp);
+        Ok(SignedPing {
+            signature: self.sign(ping_digest(&envelope))?,
+            machine_id: self.id.into(),
+            timestamp,
+            sequence: envelope.sequence,
+            signature_version: SIGNATURE_VERSION,
+        })
+    }
+
+    /// Signs a rotation to `new_key` with both the current and the new key. The timestamp is in
+    /// unix milliseconds.
+    pub fn sign_key_rotation(
+        &self,
+        new_key: &IvyWallet,
+        timestamp: u64,
+    ) -> Result<SignedKeyRotation, MachineIdentityError> {
+        let envelope = self.envelope_at(timestamp);
+        let new_address = new_key.address();
+        let digest = key_rotation_digest(&envelope, &new_address);
+        Ok(SignedKeyRotation {
+            signature: self.sign(digest)?,
+            machine_id: self.id.into(),
+            new_public_key: new_address.as_bytes().to_vec(),
+            timestamp,
+            new_key_signature: sign_hash(digest, new_key)?.into(),
+            sequence: envelope.sequence,
+            signature_version: SIGNATURE_VERSION,
+        })
+    }
+
+    pub fn sign_session_request(&self) -> Result<SignedSessionRequest, MachineIdentityError> {
+        let envelope = self.envelope();
+        Ok(SignedSessionRequest {
+            signature: self.sign(session_digest(&envelope))?,
+            machine_id: self.id.into(),
+            signature_version: SIGNATURE_VERSION,
+            timestamp: envelope.timestamp,
+            sequence: envelope.sequence,
         })
     }
+
+    fn envelope(&self) -> Envelope {
+        self.envelope_at(unix_millis(SystemTime::now()))
+    }
+
+    fn envelope_at(&self, timestamp: u64) -> Envelope {
+        Envelope { machine_id: self.id, timestamp, sequence: self.sequence.next() }
+    }
+
+    fn sign(&self, digest: H256) -> Result<Vec<u8>, MachineIdentityError> {
+        Ok(sign_hash(digest, &self.signer.read().expect("Read lock failed"))?.into())
+    }
+}
+
+/// Sequence numbers for signed messages. Each number is at least the current time in unix
+/// microseconds and above the previous one, so numbers keep increasing across restarts and stay
+/// close to each other between processes signing for the same machine.
+#[derive(Clone, Debug, Default)]
+struct Sequence(Arc<AtomicU64>);
+
+impl Sequence {
+    fn next(&self) -> u64 {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_micros();
+        let now = now as u64;
+        let previous = self
+            .0
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| Some(now.max(last + 1)))
+            .expect("update always succeeds");
+        now.max(previous + 1)
+    }
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -227,11 +385,13 @@ impl SystemInformation {
 mod ivy_machine_tests {
     use super::*;
     use ethers::types::Signature;
-    use ivynet_signer::sign_utils::{
-        recover_log, recover_metrics, recover_name_change, recover_node_data_v2,
-    };
+    use ivynet_signer::sign_utils::recover_from_hash;
     use uuid::Uuid;
 
+    fn recover(digest: H256, signature: &[u8]) -> Address {
+        recover_from_hash(digest, &Signature::try_from(signature).unwrap()).unwrap()
+    }
+
     #[tokio::test]
     async fn test_sign_metrics() {
         let id = Uuid::new_v4();
@@ -245,11 +405,21 @@ mod ivy_machine_tests {
         assert_eq!(signed_metrics.machine_id, id.as_bytes());
         assert_eq!(signed_metrics.avs_name, avs_name);
         assert_eq!(signed_metrics.metrics, metrics);
-        assert!(!signed_metrics.signature.is_empty());
+        assert_eq!(signed_metrics.signature_version, SIGNATURE_VERSION);
+        assert!(signed_metrics.collected_at <= signed_metrics.timestamp);
 
-        let signature = Signature::try_from(signed_metrics.signature.as_slice()).unwrap();
-        let recovered = recover_metrics(&metrics, &signature).unwrap();
-        assert_eq!(recovered, machine.pubkey());
+        let envelope = Envelope {
+            machine_id: id,
+            timestamp: signed_metrics.timestamp,
+            sequence: signed_metrics.sequence,
+        };
+        let collected_at = signed_metrics.collected_at;
+        let digest = metrics_digest(&envelope, avs_name.as_deref(), &metrics, collected_at);
+        assert_eq!(recover(digest, &signed_metrics.signature), machine.pubkey());
+
+        // The avs name is part of the signed payload
+        let digest = metrics_digest(&envelope, Some("other_avs"), &metrics, collected_at);
+        assert_ne!(recover(digest, &signed_metrics.signature), machine.pubkey());
     }
 
     #[tokio::test]
@@ -263,11 +433,14 @@ mod ivy_machine_tests {
 
         assert_eq!(signed_node_data.machine_id, id.as_bytes());
         assert_eq!(signed_node_data.node_data.unwrap(), node_data);
-        assert!(!signed_node_data.signature.is_empty());
 
-        let signature = Signature::try_from(signed_node_data.signature.as_slice()).unwrap();
-        let recovered = recover_node_data_v2(&node_data, &signature).unwrap();
-        assert_eq!(recovered, machine.pubkey());
+        let envelope = Envelope {
+            machine_id: id,
+            timestamp: signed_node_data.timestamp,
+            sequence: signed_node_data.sequence,
+        };
+        let digest = node_data_digest(&envelope, &node_data);
+        assert_eq!(recover(digest, &signed_node_data.signature), machine.pubkey());
     }
 
     #[tokio::test]
@@ -283,11 +456,14 @@ mod ivy_machine_tests {
         assert_eq!(signed_name_change.machine_id, id.as_bytes());
         assert_eq!(signed_name_change.old_name, old_name.to_string());
         assert_eq!(signed_name_change.new_name, new_name.to_string());
-        assert!(!signed_name_change.signature.is_empty());
 
-        let signature = Signature::try_from(signed_name_change.signature.as_slice()).unwrap();
-        let recovered = recover_name_change(old_name, new_name, &signature).unwrap();
-        assert_eq!(recovered, machine.pubkey());
+        let envelope = Envelope {
+            machine_id: id,
+            timestamp: signed_name_change.timestamp,
+            sequence: signed_name_change.sequence,
+        };
+        let digest = name_change_digest(&envelope, old_name, new_name);
+        assert_eq!(recover(digest, &signed_name_change.signature), machine.pubkey());
     }
 
     #[tokio::test]
@@ -302,10 +478,92 @@ mod ivy_machine_tests {
         assert_eq!(signed_log.machine_id, id.as_bytes());
         assert_eq!(signed_log.avs_name, avs_name.to_string());
         assert_eq!(signed_log.log, log_message.to_string());
-        assert!(!signed_log.signature.is_empty());
 
-        let signature = Signature::try_from(signed_log.signature.as_slice()).unwrap();
-        let recovered = recover_log(log_message, &signature).unwrap();
-        assert_eq!(recovered, machine.pubkey());
+        let envelope = Envelope {
+            machine_id: id,
+            timestamp: signed_log.timestamp,
+            sequence: signed_log.sequence,
+        };
+        let digest = log_digest(&envelope, avs_name, log_message);
+        assert_eq!(recover(digest, &signed_log.signature), machine.pubkey());
+    }
+
+    #[tokio::test]
+    async fn test_sign_log_batch() {
+        let id = Uuid::new_v4();
+        let wallet = IvyWallet::new();
+        let machine = IvyMachine::new(id, wallet);
+        let avs_name = "test_avs";
+        let logs = vec!["first log line".to_string(), "second log line".to_string()];
+        let signed_batch =
+            machine.sign_log_batch(avs_name, &logs).expect("sign_log_batch should succeed");
+
+        assert_eq!(signed_batch.machine_id, id.as_bytes());
+        assert_eq!(signed_batch.avs_name, avs_name.to_string());
+        assert_eq!(signed_batch.logs, logs);
+
+        let envelope = Envelope {
+            machine_id: id,
+            timestamp: signed_batch.timestamp,
+            sequence: signed_batch.sequence,
+        };
+        let digest = log_batch_digest(&envelope, avs_name, &logs);
+        assert_eq!(recover(digest, &signed_batch.signature), machine.pubkey());
+
+        // The avs name is part of the signed payload
+        let digest = log_batch_digest(&envelope, "other_avs", &logs);
+        assert_ne!(recover(digest, &signed_batch.signature), machine.pubkey());
+    }
+
+    #[tokio::test]
+    async fn test_sign_ping() {
+        let id = Uuid::new_v4();
+        let wallet = IvyWallet::new();
+        let machine = IvyMachine::new(id, wallet);
+        let timestamp = 1_700_000_000_000;
+        let signed_ping = machine.sign_ping(timestamp).expect("sign_ping should succeed");
+
+        assert_eq!(signed_ping.machine_id, id.as_bytes());
+        assert_eq!(signed_ping.timestamp, timestamp);
+
+        let envelope = Envelope { machine_id: id, timestamp, sequence: signed_ping.sequence };
+        assert_eq!(recover(ping_digest(&envelope), &signed_ping.signature), machine.pubkey());
+        let later = Envelope { timestamp: timestamp + 1, ..envelope };
+        assert_ne!(recover(ping_digest(&later), &signed_ping.signature), machine.pubkey());
+    }
+
+    #[tokio::test]
+    async fn test_sign_key_rotation() {
+        let id = Uuid::new_v4();
+        let machine = IvyMachine::new(id, IvyWallet::new());
+        let new_key = IvyWallet::new();
+        let timestamp = 1_700_000_000_000;
+        let rotation = machine
+            .sign_key_rotation(&new_key, timestamp)
+            .expect("sign_key_rotation should succeed");
+
+        assert_eq!(rotation.machine_id, id.as_bytes());
+        assert_eq!(rotation.new_public_key, new_key.address().as_bytes());
+
+        let new_address = new_key.address();
+        let envelope = Envelope { machine_id: id, timestamp, sequence: rotation.sequence };
+        let digest = key_rotation_digest(&envelope, &new_address);
+        assert_eq!(recover(digest, &rotation.signature), machine.pubkey());
+        assert_eq!(recover(digest, &rotation.new_key_signature), new_address);
+
+        // A signature for one machine does not rotate another
+        let other = Envelope { machine_id: Uuid::new_v4(), ..envelope };
+        let digest = key_rotation_digest(&other, &new_address);
+        assert_ne!(recover(digest, &rotation.signature), machine.pubkey());
+    }
+
+    #[test]
+    fn test_sequence_increases() {
+        let machine = IvyMachine::new(Uuid::new_v4(), IvyWallet::new());
+        let clone = machine.clone();
+        let first = machine.sign_client_log("one").unwrap().sequence;
+        let second = clone.sign_client_log("two").unwrap().sequence;
+        let third = machine.sign_client_log("three").unwrap().sequence;
+        assert!(first < second && second < third);
     }
 }
@@ -1,10 +1,21 @@
-use std::path::Path;
+use std::{
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-use crate::error::Error;
+use crate::{config::IvyConfig, error::Error, identity, ivy_machine::IvyMachine};
 use clap::Parser;
 use dialoguer::{Input, MultiSelect, Password, Select};
 use ethers::signers::{coins_bip39::English, MnemonicBuilder};
-use ivynet_signer::keychain::{Key, KeyName, KeyType, Keychain};
+use ivynet_grpc::{
+    backend::backend_client::BackendClient,
+    client::create_channel,
+    tonic::{Code, Request},
+};
+use ivynet_signer::{
+    keychain::{Key, KeyName, KeyType, Keychain},
+    IvyWallet,
+};
 use rustix::path::Arg;
 
 #[derive(Parser, Debug, Clone)]
@@ -17,9 +28,21 @@ pub enum KeyCommands {
 
     #[command(name = "get", about = "Get ECDSA/BLS key information")]
     Get,
+
+    #[command(
+        name = "migrate-identity",
+        about = "Move a plaintext machine identity key from the config into an encrypted keystore"
+    )]
+    MigrateIdentity,
+
+    #[command(
+        name = "rotate-identity",
+        about = "Replace the machine identity key with a new one registered to the same machine"
+    )]
+    RotateIdentity,
 }
 
-pub async fn parse_key_subcommands(subcmd: KeyCommands) -> Result<(), Error> {
+pub async fn parse_key_subcommands(subcmd: KeyCommands, config: IvyConfig) -> Result<(), Error> {
     match subcmd {
         KeyCommands::Import => {
             import_key().await?;
@@ -30,10 +53,29 @@ pub async fn parse_key_subcommands(subcmd: KeyCommands) -> Result<(), Error> {
         KeyCommands::Get => {
             get_key().await?;
         }
+        KeyCommands::MigrateIdentity => {
+            migrate_identity(config)?;
+        }
+        KeyCommands::RotateIdentity => {
+            rotate_identity(config).await?;
+        }
     }
     Ok(())
 }
 
+fn migrate_identity(mut config: IvyConfig) -> Result<(), Error> {
+    let wallet = identity::migrate_identity(&mut config)?;
+    config.store()?;
+    println!(
+        "Identity key {:?} moved to {}",
+        wallet.address(),
+        Keychain::new(config.get_dir())
+            .get_path(&KeyName::Ecdsa(identity::IDENTITY_KEY_NAME.to_string()))
+            .display()
+    );
+    Ok(())
+}
+
 pub async fn import_key() -> Result<(), Error> {
     match Select::new()
         .with_prompt("Choose what type of key you would like to import")
@@ -78,6 +120,58 @@ pub async fn import_ecdsa() -> Result<(), Error> {
         }
 }
 
+/// Generates a new identity key, has the backend move the machine's client to it and switches the
+/// config over. Until the config points at the new key it is kept in a pending keystore, so the
+/// key is not lost when the rotation may have gone through.
+async fn rotate_identity(mut config: IvyConfig) -> Result<(), Error> {
+    let machine = IvyMachine::from_config(&config)?;
+    let password = identity::new_identity_password(&config)?;
+    let new_key = IvyWallet::new();
+    let pending = identity::store_identity(
+        &config,
+        &new_key,
+        &password,
+        identity::PENDING_IDENTITY_KEY_NAME,
+    )?;
+
+    let ca = config.get_server_ca();
+    let mut backend = BackendClient::new(
+        create_channel(config.get_server_url()?, (!ca.is_empty()).then_some(ca)).await?,
+    );
+    let timestamp =
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+    let rotation = machine.sign_key_rotation(&new_key, timestamp)?;
+
+    if let Err(status) = backend.rotate_key(Request::new(rotation)).await {
+        match status.code() {
+            Code::InvalidArgument |
+            Code::OutOfRange |
+            Code::PermissionDenied |
+            Code::NotFound |
+            Code::AlreadyExists |
+            Code::FailedPrecondition => {
+                identity::remove_identity(&config, identity::PENDING_IDENTITY_KEY_NAME)?
+            }
+            _ => println!(
+                "The rotation may have reached the backend. The new key is kept at {}",
+                pending.display()
+            ),
+        }
+        return Err(status.into());
+    }
+
+    config.set_identity_wallet(&new_key, &password)?;
+    config.store()?;
+    identity::remove_identity(&config, identity::PENDING_IDENTITY_KEY_NAME)?;
+    println!(
+        "Identity key rotated from {:?} to {:?}. A running monitor switches to the new key once \
+         the backend refuses the old one.",
+        machine.pubkey(),
+        new_key.address()
+    );
+    Ok(())
+}
+
 fn import_from_mnemonic() -> Result<(), Error> {
     let keychain = Keychain::default();
     let mnemonic: String =
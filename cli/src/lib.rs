@@ -1,6 +1,9 @@
 pub mod commands;
 pub mod config;
+pub mod control;
+pub mod doctor;
 pub mod error;
+pub mod identity;
 pub mod init;
 pub mod inspect;
 pub mod ivy_machine;
@@ -9,4 +12,7 @@ pub mod log_forwarder;
 pub mod metadata;
 pub mod monitor;
 pub mod node_source;
+pub mod scan_rules;
+pub mod systemd;
 pub mod telemetry;
+pub mod unattended;
@@ -2,7 +2,10 @@ use std::{fmt, sync::Arc};
 use tokio::sync::Mutex;
 
 use ivynet_grpc::{
-    backend::backend_client::BackendClient, client::create_channel, tonic::transport::Channel,
+    backend::backend_client::BackendClient,
+    capabilities::declare_protocol,
+    client::create_channel,
+    tonic::{transport::Channel, Code, Request},
 };
 use tracing::{Event, Subscriber};
 use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
@@ -34,21 +37,27 @@ where
 {
     fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
         let formatted = format_event(event);
-        let signed_log = match self.machine.sign_client_log(&formatted) {
-            Ok(signed_log) => signed_log,
+        let mut signed_log = match self.machine.sign_client_log(&formatted) {
+            Ok(signed_log) => Request::new(signed_log),
             Err(e) => {
                 eprintln!("Error signing log: {:?}", e);
                 return;
             }
         };
 
+        declare_protocol(&mut signed_log);
         let backend = Arc::clone(&self.backend);
+        let machine = self.machine.clone();
 
         tokio::spawn(async move {
             let mut backend = backend.lock().await;
             // Post no logs to the backend if the backend is not available
             if let Err(e) = backend.client_logs(signed_log).await {
                 println!("Failed to send log: {:?}", e);
+                // The identity key may have been rotated, later logs are signed with the new one
+                if e.code() == Code::NotFound {
+                    let _ = machine.reload_identity();
+                }
             }
         });
     }
@@ -2,14 +2,17 @@ use anyhow::{Error as AnyError, Result};
 use clap::{Parser, Subcommand};
 use cli::{
     config::{self, IvyConfig},
+    control, doctor,
     error::Error,
-    init::{self},
+    init::{self, CredentialArgs},
     key,
     log_forwarder::LogForwardingLayer,
-    monitor,
+    monitor::{self, ScanOptions},
+    scan_rules::ScanRules,
+    unattended::{self, OutputFormat},
 };
 use ivynet_grpc::client::Uri;
-use std::{fs, path::PathBuf, str::FromStr as _};
+use std::{fs, net::SocketAddr, path::PathBuf, process::ExitCode, str::FromStr as _};
 use tracing::info;
 use tracing_subscriber::{self, filter::EnvFilter, prelude::*};
 
@@ -64,20 +67,44 @@ enum Commands {
         subcmd: key::KeyCommands,
     },
     #[command(name = "monitor", about = "Start node monitor daemon")]
-    Monitor,
+    Monitor {
+        /// Serve a local Prometheus /metrics endpoint on this address (e.g. 127.0.0.1:9100)
+        #[arg(long, env = "METRICS_ADDR")]
+        metrics_addr: Option<SocketAddr>,
+    },
 
     #[command(name = "scan", about = "Scanning for existing AVS instances running on the machine")]
     Scan {
         /// For forcing manual container addition even when all other AVS's are already configured
         #[arg(short, long, default_value_t = false)]
         force: bool,
+
+        /// Add every detected AVS without prompting, named after its container
+        #[arg(long, env = "IVYNET_SCAN_ACCEPT_ALL", default_value_t = false)]
+        accept_all: bool,
+
+        /// TOML file of rules deciding which AVSes are added, and how, without prompting
+        #[arg(long, env = "IVYNET_SCAN_RULES")]
+        rules: Option<PathBuf>,
+
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        output: OutputFormat,
+
+        #[command(flatten)]
+        credentials: CredentialArgs,
     },
 
     #[command(
         name = "register-node",
         about = "Register a node with the backend. Requires a correctly configured IvyConfig."
     )]
-    RegisterNode,
+    RegisterNode {
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        output: OutputFormat,
+
+        #[command(flatten)]
+        credentials: CredentialArgs,
+    },
 
     #[command(name = "rename-node", about = "Rename a node")]
     RenameNode {
@@ -86,12 +113,42 @@ enum Commands {
         #[arg(long, short, env = "NEW_NAME")]
         new_name: Option<String>,
     },
+
+    #[command(name = "node", about = "Manage the nodes followed by the running monitor")]
+    Node {
+        #[command(subcommand)]
+        subcmd: control::NodeCommands,
+
+        #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+        output: OutputFormat,
+    },
+
+    #[command(name = "doctor", about = "Check every dependency of the node monitor")]
+    Doctor {
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        output: OutputFormat,
+    },
+
+    #[command(name = "status", about = "Show what the running monitor has scraped and sent")]
+    Status {
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        output: OutputFormat,
+    },
 }
 
 #[tokio::main]
-async fn main() -> Result<(), AnyError> {
+async fn main() -> ExitCode {
     let args = Args::parse();
+    match run(args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e:?}");
+            ExitCode::from(unattended::exit_code(&e))
+        }
+    }
+}
 
+async fn run(args: Args) -> Result<(), AnyError> {
     let config = {
         match IvyConfig::load_from_default_path() {
             Ok(c) => c,
@@ -119,24 +176,43 @@ async fn main() -> Result<(), AnyError> {
         }
         Commands::Key { subcmd } => {
             start_tracing(&config, args.log_level, args.debug_no_deps, false).await?;
-            key::parse_key_subcommands(subcmd).await?
+            key::parse_key_subcommands(subcmd, config).await?
         }
-        Commands::Monitor => {
+        Commands::Monitor { metrics_addr } => {
             start_tracing(&config, args.log_level, args.debug_no_deps, true).await?;
-            monitor::start_monitor(config).await?
+            monitor::start_monitor(config, metrics_addr).await?
         }
-        Commands::Scan { force } => {
+        Commands::Scan { force, accept_all, rules, output, credentials } => {
             start_tracing(&config, args.log_level, args.debug_no_deps, false).await?;
-            monitor::scan(force, config).await?
+            let options = ScanOptions {
+                force,
+                accept_all,
+                rules: rules.map(|path| ScanRules::load(&path)).transpose()?,
+                credentials: credentials.credentials()?,
+                output,
+            };
+            monitor::scan(options, config).await?
         }
-        Commands::RegisterNode => {
+        Commands::RegisterNode { output, credentials } => {
             start_tracing(&config, args.log_level, args.debug_no_deps, false).await?;
-            init::register_node(config).await?
+            init::register_node(config, credentials.credentials()?, output).await?
         }
         Commands::RenameNode { old_name, new_name } => {
             start_tracing(&config, args.log_level, args.debug_no_deps, false).await?;
             monitor::rename_node(&config, old_name, new_name).await?;
         }
+        Commands::Node { subcmd, output } => {
+            start_tracing(&config, args.log_level, args.debug_no_deps, false).await?;
+            control::parse_node_subcommands(subcmd, output).await?
+        }
+        Commands::Doctor { output } => {
+            start_tracing(&config, args.log_level, args.debug_no_deps, false).await?;
+            doctor::doctor(&config, output).await?
+        }
+        Commands::Status { output } => {
+            start_tracing(&config, args.log_level, args.debug_no_deps, false).await?;
+            control::status(output).await?
+        }
     }
 
     Ok(())
@@ -148,7 +224,9 @@ async fn start_tracing(
     debug_no_deps: bool,
     log_forwarding: bool,
 ) -> Result<(), Error> {
-    let registry = tracing_subscriber::registry().with(tracing_subscriber::fmt::layer());
+    // Logs go to stderr, keeping stdout for command output
+    let registry = tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr));
 
     let registry = if debug_no_deps {
         registry.with(EnvFilter::try_from_default_env().unwrap_or_else(|_| {
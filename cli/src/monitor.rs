@@ -1,43 +1,57 @@
 use anyhow::anyhow;
 use dialoguer::{Input, MultiSelect, Select};
+use ethers::types::Address;
 use fs2::FileExt;
 use ivynet_docker::{
-    container::{ContainerId, ContainerImage},
+    container::{ContainerId, ContainerImage, PortDiscovery},
     dockerapi::DockerClient,
 };
 use ivynet_grpc::{
     self,
     backend::backend_client::BackendClient,
     client::create_channel,
-    messages::{NodeTypeQueries, NodeTypeQuery, SignedNameChange},
-    tonic::{transport::Channel, Request},
+    heartbeat::heartbeat_client::HeartbeatClient,
+    messages::{NodeTypeQueries, NodeTypeQuery},
+    tonic::{transport::Channel, Code, Request},
 };
 use ivynet_io::{read_toml, write_toml, IoError};
-use ivynet_signer::sign_utils::sign_name_change;
 use serde::{Deserialize, Serialize};
 use std::{
     collections::{HashMap, HashSet},
     fs::{File, OpenOptions},
+    net::SocketAddr,
     path::{Path, PathBuf},
 };
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 use crate::{
     config::{IvyConfig, DEFAULT_CONFIG_PATH},
-    init::set_backend_connection,
+    init::{set_backend_connection, Credentials},
     ivy_machine::IvyMachine,
-    node_source::NodeSource,
-    telemetry::{listen, metrics_listener::fetch_telemetry_from, ConfiguredAvs},
+    node_source::{DockerNodeSource, NodeSource},
+    scan_rules::ScanRules,
+    systemd::SystemdClient,
+    telemetry::{
+        labels::labelled_nodes, listen, metrics_listener::fetch_telemetry_from, ConfiguredAvs,
+        NodeRuntime, DEFAULT_METRICS_PATH,
+    },
+    unattended::{is_interactive, OutputFormat, UnattendedError},
 };
 
 const MONITOR_CONFIG_FILE: &str = "monitor-config.toml";
 
+/// Node type of scanned nodes the backend does not recognize.
+pub const UNKNOWN_NODE_TYPE: &str = "unknown";
+
+/// A node found by a `NodeSource`. For systemd nodes `container_name` is the unit name,
+/// `docker_image` the binary name and `manifest` the binary's hash.
 #[derive(Clone, Debug)]
 pub struct PotentialAvs {
     pub container_name: String,
     pub docker_image: ContainerImage,
     pub manifest: ContainerId,
     pub ports: Vec<u16>,
+    pub runtime: NodeRuntime,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -58,8 +72,98 @@ pub enum MonitorConfigError {
     AtomicWriteError(std::io::Error),
 }
 
+/// Adoption of containers started after the monitor, without rerunning `ivy scan`.
+#[derive(Clone, Debug, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub struct AutoDiscovery {
+    /// Send unconfigured containers to the backend for a node type on start, and adopt them when
+    /// it is known
+    #[serde(default)]
+    pub enabled: bool,
+    /// Container name or image patterns that are never adopted. `*` matches any run of characters
+    /// and `?` a single one, e.g. `postgres*` or `ghcr.io/acme/*`.
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+impl AutoDiscovery {
+    pub fn allows(&self, container_name: &str, image: &str) -> bool {
+        !self.deny.iter().any(|pattern| {
+            wildcard_match(pattern, container_name) || wildcard_match(pattern, image)
+        })
+    }
+}
+
+/// Commands the backend may push to the monitor over the telemetry stream.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RemoteCommands {
+    /// Command types that are carried out: `set_scrape_interval`, `log_backfill`, `rescan` and
+    /// `update_node`. Any other command is refused.
+    #[serde(default = "RemoteCommands::default_allow")]
+    pub allow: Vec<String>,
+    /// Address commands have to be signed with. When not set, the key the backend announces on
+    /// the first connection is pinned here.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signer: Option<Address>,
+}
+
+impl RemoteCommands {
+    fn default_allow() -> Vec<String> {
+        // Changing node configuration is left for the operator to opt into
+        ["set_scrape_interval", "log_backfill", "rescan"].map(String::from).to_vec()
+    }
+
+    pub fn allows(&self, command: &str) -> bool {
+        self.allow.iter().any(|allowed| allowed == command)
+    }
+}
+
+impl Default for RemoteCommands {
+    fn default() -> Self {
+        Self { allow: Self::default_allow(), signer: None }
+    }
+}
+
+/// Matches `text` against a pattern in which `*` stands for any run of characters and `?` for any
+/// single character.
+pub(crate) fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and the text position it was tried at, for backtracking
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, Default)]
 pub struct MonitorConfig {
+    /// How ports of containers in host network mode are discovered
+    #[serde(default)]
+    pub port_discovery: PortDiscovery,
+    #[serde(default)]
+    pub auto_discovery: AutoDiscovery,
+    #[serde(default)]
+    pub remote_commands: RemoteCommands,
     /// Configured AVSes to monitor
     pub configured_avses: Vec<ConfiguredAvs>,
 }
@@ -104,6 +208,19 @@ impl MonitorConfig {
         Ok(())
     }
 
+    /// Adds or updates a node found without `ivy scan`, through container labels or
+    /// auto-discovery, returning whether the config changed. See [`upsert_discovered_node`].
+    pub fn upsert_node(&mut self, node: ConfiguredAvs) -> bool {
+        upsert_discovered_node(&mut self.configured_avses, node).is_some_and(|(_, changed)| changed)
+    }
+
+    /// Removes the node with the given assigned name, returning it if it was configured.
+    pub fn remove_node(&mut self, assigned_name: &str) -> Option<ConfiguredAvs> {
+        let idx =
+            self.configured_avses.iter().position(|avs| avs.assigned_name == assigned_name)?;
+        Some(self.configured_avses.remove(idx))
+    }
+
     pub fn change_avs_name(
         &mut self,
         old_name: &str,
@@ -118,6 +235,35 @@ impl MonitorConfig {
     }
 }
 
+/// Adds or updates a node found without `ivy scan`, through container labels or auto-discovery.
+/// A node already known for the container keeps its assigned name, as renames have to be signed
+/// and sent to the backend. New nodes whose name is taken are skipped.
+///
+/// Returns the node as it is now in `nodes` and whether that changed anything, or `None` when it
+/// was skipped.
+pub fn upsert_discovered_node(
+    nodes: &mut Vec<ConfiguredAvs>,
+    node: ConfiguredAvs,
+) -> Option<(ConfiguredAvs, bool)> {
+    if let Some(existing) = nodes.iter_mut().find(|avs| avs.container_name == node.container_name) {
+        let updated = ConfiguredAvs { assigned_name: existing.assigned_name.clone(), ..node };
+        let changed = *existing != updated;
+        *existing = updated.clone();
+        return Some((updated, changed));
+    }
+
+    if nodes.iter().any(|avs| avs.assigned_name == node.assigned_name) {
+        warn!(
+            "Name {} of discovered container {} is already in use, skipping",
+            node.assigned_name, node.container_name
+        );
+        return None;
+    }
+    info!("Adding container {} as {}", node.container_name, node.assigned_name);
+    nodes.push(node.clone());
+    Some((node, true))
+}
+
 pub async fn rename_node(
     config: &IvyConfig,
     old_name: Option<String>,
@@ -125,6 +271,10 @@ pub async fn rename_node(
 ) -> Result<(), anyhow::Error> {
     let mut monitor_config = MonitorConfig::load_from_default_path()?;
 
+    if (old_name.is_none() || new_name.is_none()) && !is_interactive() {
+        return Err(UnattendedError::MissingInput("--old-name and --new-name".to_string()).into());
+    }
+
     let old = match old_name {
         Some(old_name) => old_name,
         None => {
@@ -151,9 +301,8 @@ pub async fn rename_node(
             .map_err(|e| anyhow!("Failed to get input: {}", e))?,
     };
 
-    let signature = sign_name_change(&old, &new, &config.identity_wallet()?)?;
+    let name_change = IvyMachine::from_config(config)?.sign_name_change(&old, &new)?;
 
-    let machine_id = config.machine_id;
     let backend_url = config.get_server_url()?;
     let backend_ca = config.get_server_ca();
     let backend_ca = if backend_ca.is_empty() { None } else { Some(backend_ca) };
@@ -162,27 +311,36 @@ pub async fn rename_node(
         create_channel(backend_url, backend_ca).await.expect("Cannot create channel"),
     );
 
-    let name_change_request = Request::new(SignedNameChange {
-        signature: signature.into(),
-        machine_id: machine_id.into(),
-        old_name: old.clone(),
-        new_name: new.clone(),
-    });
-
-    backend_client.name_change(name_change_request).await?;
+    backend_client.name_change(Request::new(name_change)).await?;
 
     monitor_config.change_avs_name(&old, &new)?;
     Ok(())
 }
 
-pub async fn start_monitor(config: IvyConfig) -> Result<(), anyhow::Error> {
+pub async fn start_monitor(
+    config: IvyConfig,
+    metrics_addr: Option<SocketAddr>,
+) -> Result<(), anyhow::Error> {
     if config.identity_wallet().is_err() {
         return Err(anyhow!(
             "No identity wallet found in config. Please configure your machine with ivynet scan"
         ));
     }
 
-    let monitor_config = MonitorConfig::load_from_default_path().unwrap_or_default();
+    let loaded = MonitorConfig::load_from_default_path();
+    let stored = loaded.is_ok();
+    let mut monitor_config = loaded.unwrap_or_default();
+
+    // Containers declaring themselves through labels are configured without prompts. A config
+    // that could not be read is not overwritten, the labels are read again on the next start.
+    let mut changed = false;
+    for node in labelled_nodes(&DockerClient::default()).await {
+        changed |= monitor_config.upsert_node(node);
+    }
+    if changed && stored {
+        monitor_config.store()?;
+    }
+
     if monitor_config.configured_avses.is_empty() {
         return Err(anyhow!("No AVSes configured to monitor"));
     }
@@ -204,20 +362,68 @@ pub async fn start_monitor(config: IvyConfig) -> Result<(), anyhow::Error> {
     let backend_ca = config.get_server_ca();
     let backend_ca = if backend_ca.is_empty() { None } else { Some(backend_ca) };
 
-    let backend_client = BackendClient::new(
-        create_channel(backend_url, backend_ca).await.expect("Cannot create channel"),
-    );
+    let channel = create_channel(backend_url, backend_ca).await.expect("Cannot create channel");
+    let backend_client = BackendClient::new(channel.clone());
+    let heartbeat_client = HeartbeatClient::new(channel);
 
     info!("Starting monitor listener...");
-    listen(backend_client, machine, &monitor_config.configured_avses).await?;
+    listen(backend_client, heartbeat_client, machine, &monitor_config, metrics_addr).await?;
     Ok(())
 }
 
+/// Options of `ivy scan`. Without `accept_all` or `rules` the nodes to add are prompted for.
+#[derive(Clone, Debug, Default)]
+pub struct ScanOptions {
+    /// For forcing manual container addition even when all other AVS's are already configured
+    pub force: bool,
+    /// Adopt every node the backend recognizes, without prompting
+    pub accept_all: bool,
+    pub rules: Option<ScanRules>,
+    /// Used to register the machine when it has no identity yet
+    pub credentials: Option<Credentials>,
+    pub output: OutputFormat,
+}
+
+/// Outcome of a scan, printed for scripts running `ivy scan --output json`.
+#[derive(Debug, Default, Serialize)]
+pub struct ScanReport {
+    pub added: Vec<ConfiguredAvs>,
+    /// Container names of scanned nodes that were not added
+    pub not_added: Vec<String>,
+    /// Number of nodes configured after the scan
+    pub configured: usize,
+}
+
+impl ScanReport {
+    fn text(&self) -> String {
+        if self.added.is_empty() {
+            return "No new AVSes added".to_string();
+        }
+        let added = self
+            .added
+            .iter()
+            .map(|avs| {
+                format!(
+                    "{} ({} under container {})",
+                    avs.assigned_name, avs.avs_type, avs.container_name
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("Added {} AVSes: {}", self.added.len(), added)
+    }
+}
+
 /// Scan function to set up configured AVS cache file. Derives `NodeType` from the name on the
 /// metrics port and node name from the container name list.
-pub async fn scan(force: bool, mut config: IvyConfig) -> Result<(), anyhow::Error> {
+pub async fn scan(options: ScanOptions, mut config: IvyConfig) -> Result<(), anyhow::Error> {
+    let unattended = options.accept_all || options.rules.is_some();
+    if !unattended && !is_interactive() {
+        return Err(UnattendedError::MissingInput("--accept-all or --rules".to_string()).into());
+    }
+
     if config.identity_wallet().is_err() {
-        set_backend_connection(&mut config).await?;
+        set_backend_connection(&mut config, options.credentials.clone()).await?;
     }
     let backend_url = config.get_server_url()?;
     let backend_ca = config.get_server_ca();
@@ -226,32 +432,56 @@ pub async fn scan(force: bool, mut config: IvyConfig) -> Result<(), anyhow::Erro
     let mut backend = BackendClient::new(
         create_channel(backend_url, backend_ca)
             .await
-            .map_err(|e| anyhow!("Failed to create backend channel: {}", e))?,
+            .map_err(|e| UnattendedError::BackendUnavailable(e.to_string()))?,
     );
 
     let mut monitor_config = MonitorConfig::load_from_default_path().unwrap_or_default();
 
-    let docker_client = DockerClient::default();
-    let potential_docker_nodes = docker_client.potential_nodes().await;
+    let docker_source =
+        DockerNodeSource::new(DockerClient::default(), monitor_config.port_discovery);
+    let mut potential_nodes = docker_source.potential_nodes().await;
+    potential_nodes.extend(SystemdClient::default().potential_nodes().await);
 
-    debug!("POTENTIAL: {:#?}", potential_docker_nodes);
+    debug!("POTENTIAL: {:#?}", potential_nodes);
     let (_existing_nodes, new_configured_nodes, leftover_potential_nodes) =
-        find_new_avses(&mut backend, &monitor_config.configured_avses, &potential_docker_nodes)
-            .await?;
+        find_new_avses(&mut backend, &monitor_config.configured_avses, &potential_nodes).await?;
+
+    let selected_avses = if unattended {
+        let rules = options.rules.unwrap_or_default();
+        let selected: Vec<ConfiguredAvs> = new_configured_nodes
+            .iter()
+            .chain(&leftover_potential_nodes)
+            .filter_map(|avs| rules.select(avs, options.accept_all))
+            .collect();
+        check_unique_names(&monitor_config, &selected)?;
+        selected
+    } else {
+        if !options.force && new_configured_nodes.is_empty() {
+            println!("No potential new AVSes found");
+        }
 
-    if !force && new_configured_nodes.is_empty() {
-        println!("No potential new AVSes found");
-    }
+        let mut selected = select_avses(&new_configured_nodes, &leftover_potential_nodes)?;
+        name_avses(&monitor_config, &mut selected)?;
+        selected
+    };
 
-    let selected_avses = select_avses(&new_configured_nodes, &leftover_potential_nodes)?;
-    if selected_avses.is_empty() {
-        println!("No AVSes selected");
-        return Ok(());
+    let report = ScanReport {
+        not_added: new_configured_nodes
+            .iter()
+            .chain(&leftover_potential_nodes)
+            .filter(|avs| !selected_avses.iter().any(|s| s.container_name == avs.container_name))
+            .map(|avs| avs.container_name.clone())
+            .collect(),
+        configured: monitor_config.configured_avses.len() + selected_avses.len(),
+        added: selected_avses,
+    };
+    if !report.added.is_empty() {
+        monitor_config.configured_avses.extend(report.added.iter().cloned());
+        monitor_config.store().map_err(|e| anyhow!("Failed to store config: {}", e))?;
+        info!("New setup stored with {} AVSes configured", report.configured);
     }
 
-    update_monitor_config(&mut monitor_config, selected_avses)?;
-    info!("New setup stored with {} AVSes configured", monitor_config.configured_avses.len());
-
+    options.output.print(&report, ScanReport::text);
     Ok(())
 }
 
@@ -281,7 +511,13 @@ async fn find_new_avses(
 
     let resp = backend
         .node_type_queries(Request::new(NodeTypeQueries { node_types: node_type_queries }))
-        .await?
+        .await
+        .map_err(|e| match e.code() {
+            Code::Unavailable => {
+                UnattendedError::BackendUnavailable(e.message().to_string()).into()
+            }
+            _ => anyhow::Error::from(e),
+        })?
         .into_inner();
 
     // Map of container name to node type
@@ -289,8 +525,10 @@ async fn find_new_avses(
         resp.node_types.into_iter().map(|nt| (nt.container_name, nt.node_type)).collect();
 
     for avs in potential_avses {
-        let node_type =
-            container_node_types.get(&avs.container_name).cloned().unwrap_or("unknown".to_string());
+        let node_type = container_node_types
+            .get(&avs.container_name)
+            .cloned()
+            .unwrap_or(UNKNOWN_NODE_TYPE.to_string());
         let metric_port =
             get_metrics_port(&reqwest::Client::new(), &avs.container_name, &avs.ports).await?;
         let new_avs = ConfiguredAvs {
@@ -298,8 +536,10 @@ async fn find_new_avses(
             container_name: avs.container_name.clone(),
             avs_type: node_type,
             metric_port,
+            metrics_path: None,
             image: Some(avs.docker_image.clone()),
             manifest: Some(avs.manifest.clone()),
+            runtime: avs.runtime.clone(),
         };
 
         // update the existing configured AVS if it exists, otherwise push to new vec
@@ -308,7 +548,7 @@ async fn find_new_avses(
         {
             node.avs_type = new_avs.avs_type;
             node.metric_port = new_avs.metric_port;
-        } else if new_avs.avs_type != "unknown" {
+        } else if new_avs.avs_type != UNKNOWN_NODE_TYPE {
             new_configured_nodes.push(new_avs);
         } else {
             leftover_potential_nodes.push(new_avs);
@@ -324,7 +564,9 @@ async fn get_metrics_port(
     ports: &[u16],
 ) -> Result<Option<u16>, anyhow::Error> {
     for &port in ports {
-        if let Ok(metrics) = fetch_telemetry_from(http_client, container_name, port).await {
+        if let Ok(metrics) =
+            fetch_telemetry_from(http_client, container_name, port, DEFAULT_METRICS_PATH).await
+        {
             if !metrics.is_empty() {
                 return Ok(Some(port));
             }
@@ -395,14 +637,33 @@ fn select_manual_avses(
     Ok(selected.into_iter().map(|idx| potential_avses[idx].clone()).collect())
 }
 
-fn update_monitor_config(
-    config: &mut MonitorConfig,
-    mut new_avses: Vec<ConfiguredAvs>,
+/// Fails if a node to add would take a name that is already configured, or that another new node
+/// takes.
+fn check_unique_names(
+    config: &MonitorConfig,
+    new_avses: &[ConfiguredAvs],
+) -> Result<(), UnattendedError> {
+    let mut seen_names: HashSet<&str> =
+        config.configured_avses.iter().map(|a| a.assigned_name.as_str()).collect();
+    for avs in new_avses {
+        if !seen_names.insert(&avs.assigned_name) {
+            return Err(UnattendedError::NameConflict {
+                name: avs.assigned_name.clone(),
+                container: avs.container_name.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn name_avses(
+    config: &MonitorConfig,
+    new_avses: &mut [ConfiguredAvs],
 ) -> Result<(), anyhow::Error> {
     let mut seen_names: HashSet<String> =
         config.configured_avses.iter().map(|a| a.assigned_name.clone()).collect();
 
-    for avs in &mut new_avses {
+    for avs in new_avses {
         loop {
             let assigned_name: String = dialoguer::Input::new()
                 .with_prompt(format!("Enter a unique name for AVS {}", avs.container_name))
@@ -420,8 +681,5 @@ fn update_monitor_config(
         }
     }
 
-    config.configured_avses.extend(new_avses);
-    config.store().map_err(|e| anyhow!("Failed to store config: {}", e))?;
-
     Ok(())
 }
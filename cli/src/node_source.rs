@@ -1,7 +1,10 @@
-use ivynet_docker::{container::ContainerId, dockerapi::DockerApi};
+use ivynet_docker::{
+    container::{ContainerId, PortDiscovery},
+    dockerapi::DockerApi,
+};
 use ivynet_grpc::async_trait;
 
-use crate::monitor::PotentialAvs;
+use crate::{monitor::PotentialAvs, telemetry::NodeRuntime};
 
 #[async_trait]
 pub trait NodeSource {
@@ -9,10 +12,24 @@ pub trait NodeSource {
     async fn potential_nodes(&self) -> Vec<PotentialAvs>;
 }
 
+/// Node source for AVS nodes run as docker containers.
+#[derive(Clone, Debug)]
+pub struct DockerNodeSource<D: DockerApi> {
+    pub docker: D,
+    /// How ports are found for containers in host network mode.
+    pub port_discovery: PortDiscovery,
+}
+
+impl<D: DockerApi> DockerNodeSource<D> {
+    pub fn new(docker: D, port_discovery: PortDiscovery) -> Self {
+        Self { docker, port_discovery }
+    }
+}
+
 #[async_trait]
-impl<T: DockerApi> NodeSource for T {
+impl<D: DockerApi> NodeSource for DockerNodeSource<D> {
     async fn potential_nodes(&self) -> Vec<PotentialAvs> {
-        let containers = self.list_containers().await;
+        let containers = self.docker.list_containers().await;
 
         let mut potentials = Vec::new();
 
@@ -20,13 +37,13 @@ impl<T: DockerApi> NodeSource for T {
             let (names, image_str, image_id) = match (
                 container.names(),
                 container.image(),
-                container.repo_digest(&self.inner()).await,
+                container.repo_digest(&self.docker.inner()).await,
             ) {
                 (Some(n), Some(i), Some(id)) => (n, i, id),
                 _ => continue,
             };
 
-            let mut ports = container.public_ports(self).await;
+            let mut ports = container.public_ports(&self.docker, self.port_discovery).await;
             ports.sort_unstable();
             ports.dedup();
 
@@ -37,6 +54,7 @@ impl<T: DockerApi> NodeSource for T {
                 docker_image: image_str.into(),
                 manifest: image_hash,
                 ports,
+                runtime: NodeRuntime::Docker,
             });
         }
 
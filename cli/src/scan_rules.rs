@@ -0,0 +1,152 @@
+use std::path::PathBuf;
+
+use ivynet_io::read_toml;
+use serde::Deserialize;
+
+use crate::{
+    monitor::{wildcard_match, UNKNOWN_NODE_TYPE},
+    telemetry::ConfiguredAvs,
+    unattended::UnattendedError,
+};
+
+/// Placeholder in rule names that is replaced with the container name.
+const CONTAINER_PLACEHOLDER: &str = "{container}";
+
+/// Rules deciding which nodes `ivy scan` adopts when run without prompts, read from a TOML file
+/// of `[[rule]]` tables:
+///
+/// ```toml
+/// [[rule]]
+/// container = "postgres*"
+/// skip = true
+///
+/// [[rule]]
+/// image = "ghcr.io/layr-labs/*"
+/// node_type = "EigenDA"
+/// name = "da-{container}"
+/// ```
+///
+/// The first rule matching a node decides whether it is adopted and how it is configured.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ScanRules {
+    #[serde(default, rename = "rule")]
+    pub rules: Vec<ScanRule>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ScanRule {
+    /// Pattern for the container name, or unit name of systemd nodes. `*` matches any run of
+    /// characters and `?` a single one. A rule without patterns matches every node.
+    pub container: Option<String>,
+    /// Pattern for the image, or binary name of systemd nodes.
+    pub image: Option<String>,
+    /// Never adopt matching nodes
+    #[serde(default)]
+    pub skip: bool,
+    /// Node type to configure, in place of the one reported by the backend. Required to adopt
+    /// nodes the backend does not recognize.
+    pub node_type: Option<String>,
+    /// Assigned name of the node, `{container}` standing for the container name. Defaults to the
+    /// container name.
+    pub name: Option<String>,
+    pub metrics_port: Option<u16>,
+    pub metrics_path: Option<String>,
+}
+
+impl ScanRules {
+    pub fn load(path: &PathBuf) -> Result<Self, UnattendedError> {
+        read_toml(path).map_err(|e| UnattendedError::InvalidInput(e.to_string()))
+    }
+
+    /// Decides whether a scanned node is adopted, returning its configuration if so. Nodes no rule
+    /// matches are adopted when `accept_all` is set and the backend recognized their type.
+    pub fn select(&self, node: &ConfiguredAvs, accept_all: bool) -> Option<ConfiguredAvs> {
+        let mut node = ConfiguredAvs { assigned_name: node.container_name.clone(), ..node.clone() };
+
+        match self.rules.iter().find(|rule| rule.matches(&node)) {
+            Some(rule) if rule.skip => return None,
+            Some(rule) => {
+                if let Some(node_type) = &rule.node_type {
+                    node.avs_type = node_type.clone();
+                }
+                if let Some(name) = &rule.name {
+                    node.assigned_name = name.replace(CONTAINER_PLACEHOLDER, &node.container_name);
+                }
+                node.metric_port = rule.metrics_port.or(node.metric_port);
+                node.metrics_path = rule.metrics_path.clone().or(node.metrics_path);
+            }
+            None if !accept_all => return None,
+            None => {}
+        }
+
+        (node.avs_type != UNKNOWN_NODE_TYPE).then_some(node)
+    }
+}
+
+impl ScanRule {
+    fn matches(&self, node: &ConfiguredAvs) -> bool {
+        let image = node.image.as_ref().map(|image| image.to_string()).unwrap_or_default();
+        self.container.as_ref().is_none_or(|pattern| wildcard_match(pattern, &node.container_name)) &&
+            self.image.as_ref().is_none_or(|pattern| wildcard_match(pattern, &image))
+    }
+}
+
+#[cfg(test)]
+mod scan_rules_tests {
+    use ivynet_docker::container::ContainerImage;
+
+    use super::*;
+
+    const RULES: &str = r#"
+[[rule]]
+container = "postgres*"
+skip = true
+
+[[rule]]
+image = "ghcr.io/acme/*"
+node_type = "EigenDA"
+name = "da-{container}"
+metrics_port = 9092
+"#;
+
+    fn node(container_name: &str, repository: &str, avs_type: &str) -> ConfiguredAvs {
+        ConfiguredAvs {
+            assigned_name: format!("{container_name}_{repository}"),
+            container_name: container_name.to_string(),
+            avs_type: avs_type.to_string(),
+            metric_port: None,
+            metrics_path: None,
+            manifest: None,
+            image: Some(ContainerImage { repository: repository.to_string(), tag: None }),
+            runtime: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_select_with_rules() {
+        let rules: ScanRules = toml::from_str(RULES).unwrap();
+
+        // Skipped even though the backend knows the type
+        assert_eq!(rules.select(&node("postgres-1", "postgres", "EigenDA"), true), None);
+
+        let adopted = rules.select(&node("node-1", "ghcr.io/acme/da", UNKNOWN_NODE_TYPE), false);
+        let adopted = adopted.unwrap();
+        assert_eq!(adopted.assigned_name, "da-node-1");
+        assert_eq!(adopted.avs_type, "EigenDA");
+        assert_eq!(adopted.metric_port, Some(9092));
+
+        // Unmatched nodes need --accept-all and a known type
+        let lagrange = node("lagrange", "lagrange/worker", "LagrangeZkWorker");
+        assert_eq!(rules.select(&lagrange, false), None);
+        assert_eq!(rules.select(&lagrange, true).unwrap().assigned_name, "lagrange");
+        assert_eq!(rules.select(&node("web", "nginx", UNKNOWN_NODE_TYPE), true), None);
+    }
+
+    #[test]
+    fn test_unknown_rule_fields_are_rejected() {
+        assert!(toml::from_str::<ScanRules>("[[rule]]\ncontainer = \"a\"\nskpi = true\n").is_err());
+        assert_eq!(toml::from_str::<ScanRules>("").unwrap(), ScanRules::default());
+    }
+}
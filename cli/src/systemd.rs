@@ -0,0 +1,274 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use ivynet_docker::{
+    container::{ContainerId, ContainerImage},
+    procfs::{parse_listening_sockets, socket_inodes, PROC_ROOT},
+};
+use ivynet_grpc::async_trait;
+use tracing::debug;
+
+use crate::{monitor::PotentialAvs, node_source::NodeSource, telemetry::NodeRuntime};
+
+/// Executables under these directories belong to the OS rather than to an operator-installed node.
+const SYSTEM_BINARY_DIRS: [&str; 5] =
+    ["/sbin/", "/usr/sbin/", "/lib/", "/usr/lib/", "/usr/libexec/"];
+
+/// Container runtimes are covered by the Docker node source. Their proxies listen on every
+/// published container port, so they would otherwise show up as duplicate candidates.
+const IGNORED_UNITS: [&str; 2] = ["docker.service", "containerd.service"];
+
+/// Node source for AVS nodes run as native binaries under systemd. Services are discovered from
+/// the process table in /proc: each process is mapped to its unit through its cgroup, and only
+/// units with listening TCP sockets are reported. Reading other users' file descriptors requires
+/// root, so ports (and with them candidates) may be missing when run unprivileged.
+#[derive(Clone, Debug)]
+pub struct SystemdClient {
+    proc_root: PathBuf,
+}
+
+impl Default for SystemdClient {
+    fn default() -> Self {
+        Self::new(PROC_ROOT)
+    }
+}
+
+/// A running systemd service, represented by the lowest PID in its cgroup.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServiceProcess {
+    pub unit: String,
+    pub pid: u32,
+    pub binary: PathBuf,
+    pub ports: Vec<u16>,
+}
+
+impl SystemdClient {
+    pub fn new(proc_root: impl Into<PathBuf>) -> Self {
+        Self { proc_root: proc_root.into() }
+    }
+
+    /// All running services that are listening on at least one TCP port.
+    pub fn services(&self) -> Vec<ServiceProcess> {
+        let listening = self.listening_sockets();
+        let mut units: BTreeMap<String, ServiceProcess> = BTreeMap::new();
+
+        for (pid, unit, binary) in self.processes() {
+            let ports = self.process_ports(pid, &listening);
+            match units.get_mut(&unit) {
+                Some(service) => {
+                    // Forked workers share the unit; keep the main process as the representative
+                    if pid < service.pid {
+                        service.pid = pid;
+                        service.binary = binary;
+                    }
+                    service.ports.extend(ports);
+                }
+                None => {
+                    units.insert(unit.clone(), ServiceProcess { unit, pid, binary, ports });
+                }
+            }
+        }
+
+        units
+            .into_values()
+            .filter_map(|mut service| {
+                service.ports.sort_unstable();
+                service.ports.dedup();
+                (!service.ports.is_empty()).then_some(service)
+            })
+            .collect()
+    }
+
+    /// Finds the main process of a running unit, regardless of whether it is listening.
+    pub fn find_unit(&self, unit: &str) -> Option<(u32, PathBuf)> {
+        self.processes()
+            .into_iter()
+            .filter(|(_, u, _)| u == unit)
+            .map(|(pid, _, binary)| (pid, binary))
+            .min_by_key(|(pid, _)| *pid)
+    }
+
+    /// (pid, unit, binary) for every process that belongs to a system service.
+    fn processes(&self) -> Vec<(u32, String, PathBuf)> {
+        let Ok(entries) = fs::read_dir(&self.proc_root) else {
+            return Vec::new();
+        };
+
+        entries
+            .flatten()
+            .filter_map(|entry| {
+                let pid = entry.file_name().to_str()?.parse::<u32>().ok()?;
+                let dir = entry.path();
+                let unit = unit_from_cgroup(&fs::read_to_string(dir.join("cgroup")).ok()?)?;
+                if IGNORED_UNITS.contains(&unit.as_str()) {
+                    return None;
+                }
+                // Kernel threads have no executable
+                let binary = fs::read_link(dir.join("exe")).ok()?;
+                let path = binary.to_string_lossy();
+                if SYSTEM_BINARY_DIRS.iter().any(|dir| path.starts_with(dir)) {
+                    return None;
+                }
+                Some((pid, unit, binary))
+            })
+            .collect()
+    }
+
+    /// Listening sockets in the host network namespace, keyed by socket inode.
+    fn listening_sockets(&self) -> HashMap<u64, u16> {
+        ["tcp", "tcp6"]
+            .iter()
+            .filter_map(|file| fs::read_to_string(self.proc_root.join("net").join(file)).ok())
+            .flat_map(|contents| parse_listening_sockets(&contents))
+            .collect()
+    }
+
+    fn process_ports(&self, pid: u32, listening: &HashMap<u64, u16>) -> Vec<u16> {
+        let Ok(inodes) = socket_inodes(&self.proc_root, pid) else {
+            debug!("Cannot read file descriptors of process {}", pid);
+            return Vec::new();
+        };
+        inodes.iter().filter_map(|inode| listening.get(inode).copied()).collect()
+    }
+}
+
+#[async_trait]
+impl NodeSource for SystemdClient {
+    async fn potential_nodes(&self) -> Vec<PotentialAvs> {
+        let client = self.clone();
+        let services = tokio::task::spawn_blocking(move || client.services()).await;
+
+        let mut potentials = Vec::new();
+        for service in services.unwrap_or_default() {
+            let manifest = match binary_digest(&service.binary) {
+                Ok(digest) => digest,
+                Err(e) => {
+                    debug!("Cannot hash binary {}: {}", service.binary.display(), e);
+                    continue;
+                }
+            };
+            let binary_name = service
+                .binary
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| service.unit.clone());
+
+            potentials.push(PotentialAvs {
+                container_name: service.unit,
+                docker_image: ContainerImage { repository: binary_name, tag: None },
+                manifest,
+                ports: service.ports,
+                runtime: NodeRuntime::Systemd { binary: service.binary, log_file: None },
+            });
+        }
+
+        potentials
+    }
+}
+
+/// The SHA-256 of a node binary, used as its manifest in place of an image digest.
+pub fn binary_digest(path: &Path) -> Result<ContainerId, std::io::Error> {
+    sha256::try_digest(path).map(ContainerId)
+}
+
+/// Extracts the innermost service unit from the contents of /proc/<pid>/cgroup. Both the unified
+/// (`0::/system.slice/x.service`) and legacy (`1:name=systemd:/...`) hierarchies are understood.
+/// User sessions and container scopes are not services and yield `None`.
+fn unit_from_cgroup(cgroup: &str) -> Option<String> {
+    let path = cgroup.lines().find_map(|line| {
+        line.strip_prefix("0::").or_else(|| line.split_once(":name=systemd:").map(|(_, p)| p))
+    })?;
+    if !path.starts_with("/system.slice/") {
+        return None;
+    }
+    path.rsplit('/').find(|segment| segment.ends_with(".service")).map(str::to_string)
+}
+
+#[cfg(test)]
+mod systemd_tests {
+    use std::os::unix::fs::symlink;
+
+    use super::*;
+
+    #[test]
+    fn test_unit_from_cgroup() {
+        assert_eq!(
+            unit_from_cgroup("0::/system.slice/eigenda.service\n").as_deref(),
+            Some("eigenda.service")
+        );
+        assert_eq!(
+            unit_from_cgroup(
+                "12:pids:/\n1:name=systemd:/system.slice/nodes.slice/lagrange.service\n"
+            )
+            .as_deref(),
+            Some("lagrange.service")
+        );
+        assert_eq!(unit_from_cgroup("0::/user.slice/user-1000.slice/session-2.scope\n"), None);
+        assert_eq!(unit_from_cgroup("0::/system.slice/docker-abc123.scope\n"), None);
+    }
+
+    fn fake_process(root: &Path, pid: u32, cgroup: &str, exe: &str, sockets: &[u64]) {
+        let dir = root.join(pid.to_string());
+        fs::create_dir_all(dir.join("fd")).unwrap();
+        fs::write(dir.join("cgroup"), cgroup).unwrap();
+        symlink(exe, dir.join("exe")).unwrap();
+        for (fd, inode) in sockets.iter().enumerate() {
+            symlink(format!("socket:[{inode}]"), dir.join("fd").join(fd.to_string())).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_services_from_proc() {
+        let root = std::env::current_dir().unwrap().join("test_systemd_proc");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("net")).unwrap();
+        let tcp = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 00000000:2382 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 1001 1 0000000000000000 100 0 0 10 0
+   1: 0100007F:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 1002 1 0000000000000000 100 0 0 10 0
+";
+        fs::write(root.join("net").join("tcp"), tcp).unwrap();
+
+        fake_process(
+            &root,
+            410,
+            "0::/system.slice/eigenda.service\n",
+            "/opt/eigenda/node",
+            &[1001],
+        );
+        fake_process(
+            &root,
+            420,
+            "0::/system.slice/eigenda.service\n",
+            "/opt/eigenda/worker",
+            &[1002],
+        );
+        fake_process(&root, 300, "0::/system.slice/ssh.service\n", "/usr/sbin/sshd", &[1002]);
+        fake_process(&root, 500, "0::/system.slice/idle.service\n", "/opt/idle/bin", &[]);
+        fake_process(
+            &root,
+            600,
+            "0::/system.slice/docker.service\n",
+            "/usr/bin/docker-proxy",
+            &[1001],
+        );
+
+        let client = SystemdClient::new(&root);
+        let services = client.services();
+        let found = client.find_unit("idle.service");
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(
+            services,
+            vec![ServiceProcess {
+                unit: "eigenda.service".to_string(),
+                pid: 410,
+                binary: PathBuf::from("/opt/eigenda/node"),
+                ports: vec![8080, 9090],
+            }]
+        );
+        assert_eq!(found, Some((500, PathBuf::from("/opt/idle/bin"))));
+    }
+}
@@ -0,0 +1,86 @@
+use std::collections::HashSet;
+
+use ivynet_grpc::{
+    backend::backend_client::BackendClient,
+    capabilities::{FEATURES, PROTOCOL_VERSION},
+    messages::ClientHello,
+    tonic::{transport::Channel, Code, Request},
+    Status,
+};
+use tracing::{debug, info, warn};
+
+mod version_hash {
+    include!(concat!(env!("OUT_DIR"), "/version.rs"));
+}
+
+/// What the backend offers, as negotiated with `Hello` when the daemon starts.
+#[derive(Clone, Debug, Default)]
+pub struct Capabilities {
+    /// Features offered by the backend, or `None` when it could not be asked. Every feature is
+    /// then tried, and given up on once the backend turns out not to have it.
+    features: Option<HashSet<String>>,
+}
+
+impl Capabilities {
+    /// Tells the backend which protocol version and features this client has and learns what it
+    /// offers in return. Fails only when the backend refuses the client, as it needs an update.
+    pub async fn negotiate(backend_client: &mut BackendClient<Channel>) -> Result<Self, Status> {
+        let hello = ClientHello {
+            protocol_version: PROTOCOL_VERSION,
+            client_version: version_hash::VERSION_HASH.to_string(),
+            features: FEATURES.iter().map(|feature| feature.to_string()).collect(),
+        };
+        match backend_client.hello(Request::new(hello)).await {
+            Ok(response) => {
+                let hello = response.into_inner();
+                if let Some(notice) = &hello.notice {
+                    warn!("{}", notice);
+                }
+                debug!(
+                    "Negotiated protocol version {} with features {:?}",
+                    hello.protocol_version.min(PROTOCOL_VERSION),
+                    hello.features
+                );
+                Ok(Self::offered(hello.features))
+            }
+            Err(e) if e.code() == Code::FailedPrecondition => Err(e),
+            Err(e) if e.code() == Code::Unimplemented => {
+                info!("Backend predates protocol negotiation, detecting its features on use");
+                Ok(Self::default())
+            }
+            Err(e) => {
+                warn!("Failed to negotiate the protocol with the backend: {}", e.message());
+                Ok(Self::default())
+            }
+        }
+    }
+
+    /// Capabilities of a backend offering exactly `features`.
+    pub fn offered(features: impl IntoIterator<Item = String>) -> Self {
+        Self { features: Some(features.into_iter().collect()) }
+    }
+
+    /// Whether `feature` should be used. Features of a backend that could not be asked are tried.
+    pub fn offers(&self, feature: &str) -> bool {
+        self.features.as_ref().is_none_or(|features| features.contains(feature))
+    }
+}
+
+#[cfg(test)]
+mod capabilities_tests {
+    use ivynet_grpc::capabilities::{FEATURE_SESSIONS, FEATURE_TELEMETRY_STREAM};
+
+    use super::*;
+
+    #[test]
+    fn test_offers() {
+        // Features of a backend that could not be asked are tried
+        let unknown = Capabilities::default();
+        assert!(unknown.offers(FEATURE_SESSIONS));
+        assert!(unknown.offers(FEATURE_TELEMETRY_STREAM));
+
+        let negotiated = Capabilities::offered(vec![FEATURE_SESSIONS.to_string()]);
+        assert!(negotiated.offers(FEATURE_SESSIONS));
+        assert!(!negotiated.offers(FEATURE_TELEMETRY_STREAM));
+    }
+}
@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+
+use ivynet_docker::{
+    container::Container,
+    dockerapi::{DockerApi, DockerClient},
+    stats::ContainerStats,
+};
+use ivynet_grpc::messages::{MetricType, Metrics};
+use tokio::task::{AbortHandle, JoinSet};
+use tokio_stream::StreamExt;
+use tracing::{debug, info};
+
+use super::{
+    stats::{ContainerUsage, TelemetryStats},
+    ConfiguredAvs,
+};
+
+/// A resource usage metric reported for the container of a docker node. The metric is tagged with
+/// the node through its assigned name when signed, like the node's own metrics.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContainerMetric {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub metric_type: MetricType,
+    pub value: f64,
+}
+
+/// Manager for the per-container stats streams. Each docker node gets a background task following
+/// the docker stats API, which keeps the node's latest usage sample in `TelemetryStats`. The
+/// metrics listener picks the samples up from there on every broadcast.
+#[derive(Debug)]
+pub struct ContainerStatsManager {
+    docker: DockerClient,
+    stats: TelemetryStats,
+    listener_set: JoinSet<()>,
+    /// Abort handles of the spawned streams, keyed by container name
+    listeners: HashMap<String, AbortHandle>,
+}
+
+impl ContainerStatsManager {
+    pub fn new(docker: &DockerClient, stats: TelemetryStats) -> Self {
+        Self {
+            docker: docker.clone(),
+            stats,
+            listener_set: JoinSet::new(),
+            listeners: HashMap::new(),
+        }
+    }
+
+    /// Start following the stats of a node's container. The task ends, and the node's usage sample
+    /// is cleared, once the container stops.
+    pub fn add_listener(&mut self, container: &Container, node_data: &ConfiguredAvs) {
+        // Reap streams of containers that have since stopped
+        while self.listener_set.try_join_next().is_some() {}
+        // Another start event for the same container replaces its stream. Aborted before it
+        // ends, the old stream cannot clear the samples of the new one.
+        if let Some(previous) = self.listeners.remove(&node_data.container_name) {
+            previous.abort();
+        }
+
+        let docker = self.docker.clone();
+        let stats = self.stats.clone();
+        let container = container.clone();
+        let node = node_data.clone();
+        let handle = self.listener_set.spawn(async move {
+            follow_container_stats(&docker, &stats, container, &node).await;
+            stats.clear_container_stats(&node.assigned_name);
+            info!("Stats stream closed for container: {}", node.container_name);
+        });
+        self.listeners.insert(node_data.container_name.clone(), handle);
+        info!("Added stats listener for container: {}", node_data.container_name);
+    }
+
+    /// Stop following the stats of a node that is no longer monitored.
+    pub fn remove_listener(&mut self, node_data: &ConfiguredAvs) {
+        if let Some(handle) = self.listeners.remove(&node_data.container_name) {
+            handle.abort();
+            self.stats.clear_container_stats(&node_data.assigned_name);
+        }
+    }
+}
+
+/// Records every sample of the container's stats stream until the stream ends or fails.
+pub async fn follow_container_stats(
+    docker: &impl DockerApi,
+    stats: &TelemetryStats,
+    container: Container,
+    node_data: &ConfiguredAvs,
+) {
+    // Restarts under the restart policy end the stream, so the count is current for its lifetime
+    let restart_count = docker.restart_count(&container).await.unwrap_or_default();
+    let mut stream = docker.stream_stats(container).await;
+    while let Some(sample) = stream.next().await {
+        match sample {
+            Ok(sample) => {
+                let usage = ContainerUsage { stats: ContainerStats::from(&sample), restart_count };
+                stats.record_container_stats(node_data, usage);
+            }
+            Err(e) => {
+                debug!("Stats stream error for container {}: {}", node_data.container_name, e);
+                break;
+            }
+        }
+    }
+}
+
+pub fn container_metrics(usage: &ContainerUsage) -> [ContainerMetric; 8] {
+    let stats = &usage.stats;
+    let metric =
+        |name, help, metric_type, value| ContainerMetric { name, help, metric_type, value };
+    [
+        metric(
+            "ivynet_container_cpu_percent",
+            "Container CPU usage, in percent of a single core.",
+            MetricType::Gauge,
+            stats.cpu_percent,
+        ),
+        metric(
+            "ivynet_container_memory_usage_bytes",
+            "Container memory usage, excluding page cache.",
+            MetricType::Gauge,
+            stats.memory_usage as f64,
+        ),
+        metric(
+            "ivynet_container_memory_limit_bytes",
+            "Container memory limit.",
+            MetricType::Gauge,
+            stats.memory_limit as f64,
+        ),
+        metric(
+            "ivynet_container_network_rx_bytes_total",
+            "Bytes received by the container across all networks.",
+            MetricType::Counter,
+            stats.network_rx_bytes as f64,
+        ),
+        metric(
+            "ivynet_container_network_tx_bytes_total",
+            "Bytes sent by the container across all networks.",
+            MetricType::Counter,
+            stats.network_tx_bytes as f64,
+        ),
+        metric(
+            "ivynet_container_block_read_bytes_total",
+            "Bytes read by the container from block devices.",
+            MetricType::Counter,
+            stats.block_read_bytes as f64,
+        ),
+        metric(
+            "ivynet_container_block_write_bytes_total",
+            "Bytes written by the container to block devices.",
+            MetricType::Counter,
+            stats.block_write_bytes as f64,
+        ),
+        metric(
+            "ivynet_container_restarts_total",
+            "Times the container was restarted by the docker daemon.",
+            MetricType::Counter,
+            usage.restart_count as f64,
+        ),
+    ]
+}
+
+impl From<ContainerMetric> for Metrics {
+    fn from(metric: ContainerMetric) -> Self {
+        Metrics {
+            name: metric.name.to_string(),
+            value: metric.value,
+            attributes: Vec::new(),
+            metric_type: metric.metric_type.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod container_stats_tests {
+    use bollard::secret::ContainerSummary;
+    use ivynet_docker::mocks::mock_dockerapi::MockDockerClient;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_follow_container_stats() {
+        let docker = MockDockerClient::new();
+        let stats = TelemetryStats::new();
+        let node = ConfiguredAvs {
+            assigned_name: "da".to_string(),
+            container_name: "eigenda-native-node".to_string(),
+            avs_type: "eigenda".to_string(),
+            metric_port: None,
+            metrics_path: None,
+            manifest: None,
+            image: None,
+            runtime: Default::default(),
+        };
+
+        follow_container_stats(&docker, &stats, Container::new(ContainerSummary::default()), &node)
+            .await;
+
+        let usage = stats.container_usage("da").unwrap();
+        assert_eq!(usage.stats.cpu_percent, 50.0);
+        assert_eq!(usage.restart_count, 0);
+
+        let metrics: Vec<Metrics> =
+            container_metrics(&usage).into_iter().map(Metrics::from).collect();
+        let memory =
+            metrics.iter().find(|m| m.name == "ivynet_container_memory_usage_bytes").unwrap();
+        assert_eq!(memory.value, 90_000_000.0);
+        assert_eq!(memory.metric_type(), MetricType::Gauge);
+        let restarts =
+            metrics.iter().find(|m| m.name == "ivynet_container_restarts_total").unwrap();
+        assert_eq!(restarts.metric_type(), MetricType::Counter);
+    }
+}
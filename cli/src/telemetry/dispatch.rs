@@ -1,29 +1,138 @@
-use std::ops::Deref;
+use std::{
+    ops::Deref,
+    time::{Duration, Instant},
+};
 
 use ivynet_grpc::{
     backend::backend_client::BackendClient,
-    messages::{SignedLog, SignedMachineData, SignedMetrics, SignedNodeDataV2},
-    tonic::{self, transport::Channel},
+    capabilities::{FEATURE_HEARTBEATS, FEATURE_SESSIONS, FEATURE_TELEMETRY_STREAM},
+    heartbeat::{
+        heartbeat_client::HeartbeatClient, ClientHeartbeat, MachineHeartbeat, NodeHeartbeat,
+    },
+    messages::{SignedLog, SignedLogBatch, SignedMachineData, SignedMetrics, SignedNodeDataV2},
+    tonic::{self, transport::Channel, Code},
 };
 use kameo::{message::Message, Actor};
-use tracing::error;
+use tracing::{debug, error, info, warn};
+
+use super::{
+    capabilities::Capabilities,
+    session::TelemetrySession,
+    spool::TelemetrySpool,
+    stats::TelemetryStats,
+    stream::{CommandGate, TelemetryStream},
+    ErrorChannelTx,
+};
+use crate::ivy_machine::{IvyMachine, MachineIdentityError};
 
-use super::ErrorChannelTx;
+/// How often the dispatcher checks whether spooled telemetry can be replayed.
+const SPOOL_FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+/// Bounds for the exponential backoff applied between replay attempts while the backend is down.
+const SPOOL_MIN_BACKOFF: Duration = Duration::from_secs(10);
+const SPOOL_MAX_BACKOFF: Duration = Duration::from_secs(300);
+/// How many times the oldest spooled message may time out before it is dropped, so a message the
+/// backend cannot handle in time does not hold up the ones spooled behind it.
+const SPOOL_MAX_ATTEMPTS: u32 = 10;
 
 #[derive(Debug, Clone)]
 pub enum TelemetryMsg {
     SignedNodeData(SignedNodeDataV2),
     Metrics(SignedMetrics),
     Log(SignedLog),
+    LogBatch(SignedLogBatch),
     SignedMachineData(SignedMachineData),
 }
 
+impl TelemetryMsg {
+    /// Signs the message again with a fresh timestamp and sequence number. Spooled messages are
+    /// re-signed before replay, as the backend rejects signatures older than its freshness window.
+    /// Metrics and machine data keep the time they were collected at, so the backend stores them
+    /// at that time rather than when they were replayed.
+    pub fn resign(self, machine: &IvyMachine) -> Result<Self, MachineIdentityError> {
+        Ok(match self {
+            TelemetryMsg::Metrics(metrics) => {
+                TelemetryMsg::Metrics(machine.sign_collected_metrics(
+                    metrics.avs_name,
+                    &metrics.metrics,
+                    collected_at(metrics.collected_at, metrics.timestamp),
+                )?)
+            }
+            TelemetryMsg::Log(log) => TelemetryMsg::Log(machine.sign_log(&log.avs_name, &log.log)?),
+            TelemetryMsg::LogBatch(batch) => {
+                TelemetryMsg::LogBatch(machine.sign_log_batch(&batch.avs_name, &batch.logs)?)
+            }
+            TelemetryMsg::SignedNodeData(signed) => match signed.node_data {
+                Some(node_data) => {
+                    TelemetryMsg::SignedNodeData(machine.sign_node_data_v2(&node_data)?)
+                }
+                None => TelemetryMsg::SignedNodeData(signed),
+            },
+            TelemetryMsg::SignedMachineData(signed) => match signed.machine_data {
+                Some(machine_data) => {
+                    TelemetryMsg::SignedMachineData(machine.sign_collected_machine_data(
+                        machine_data,
+                        collected_at(signed.collected_at, signed.timestamp),
+                    )?)
+                }
+                None => TelemetryMsg::SignedMachineData(signed),
+            },
+        })
+    }
+}
+
+/// Messages spooled before they carried their collection time were signed when collected.
+fn collected_at(collected_at: u64, timestamp: u64) -> u64 {
+    if collected_at != 0 {
+        collected_at
+    } else {
+        timestamp
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct TelemetryDispatchHandle(kameo::actor::ActorRef<TelemetryDispatch>);
 
 impl TelemetryDispatchHandle {
-    pub fn new(backend_client: BackendClient<Channel>, error_tx: ErrorChannelTx) -> Self {
-        Self(kameo::actor::spawn(TelemetryDispatch::new(backend_client, error_tx)))
+    /// Spawns the dispatcher. If a spool is provided, messages that fail to reach the backend are
+    /// persisted to it and replayed in order once the backend is reachable again. Commands the
+    /// backend pushes over the telemetry stream are handed to `commands`. Sessions, the stream
+    /// and heartbeats are only used when the backend offers them.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        backend_client: BackendClient<Channel>,
+        heartbeat_client: HeartbeatClient<Channel>,
+        machine: IvyMachine,
+        capabilities: &Capabilities,
+        commands: CommandGate,
+        error_tx: ErrorChannelTx,
+        spool: Option<TelemetrySpool>,
+        stats: TelemetryStats,
+    ) -> Self {
+        let actor = kameo::actor::spawn(TelemetryDispatch::new(
+            backend_client,
+            heartbeat_client,
+            machine,
+            capabilities,
+            commands,
+            error_tx,
+            spool,
+            stats,
+        ));
+
+        // Also keeps the telemetry stream open, so commands arrive while there is no telemetry
+        let weak = actor.downgrade();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SPOOL_FLUSH_INTERVAL);
+            loop {
+                interval.tick().await;
+                let Some(actor) = weak.upgrade() else { break };
+                if actor.tell(FlushSpool).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self(actor)
     }
 }
 
@@ -40,14 +149,311 @@ impl Deref for TelemetryDispatchHandle {
 pub struct TelemetryDispatch {
     pub error_tx: ErrorChannelTx,
     pub backend_client: BackendClient<Channel>,
+    heartbeat_client: HeartbeatClient<Channel>,
+    machine: IvyMachine,
+    session: TelemetrySession,
+    /// Unset once the backend turns out not to accept heartbeats
+    heartbeats: bool,
+    stream: TelemetryStream,
+    spool: Option<TelemetrySpool>,
+    stats: TelemetryStats,
+    backoff: Duration,
+    next_retry: Instant,
+    /// Failed replays of the oldest spooled message that count towards [`SPOOL_MAX_ATTEMPTS`]
+    attempts: u32,
 }
 
 impl TelemetryDispatch {
-    pub fn new(backend_client: BackendClient<Channel>, error_tx: ErrorChannelTx) -> Self {
-        Self { error_tx, backend_client }
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        backend_client: BackendClient<Channel>,
+        heartbeat_client: HeartbeatClient<Channel>,
+        machine: IvyMachine,
+        capabilities: &Capabilities,
+        commands: CommandGate,
+        error_tx: ErrorChannelTx,
+        spool: Option<TelemetrySpool>,
+        stats: TelemetryStats,
+    ) -> Self {
+        Self {
+            error_tx,
+            session: TelemetrySession::new(machine.clone(), capabilities.offers(FEATURE_SESSIONS)),
+            heartbeats: capabilities.offers(FEATURE_HEARTBEATS),
+            stream: TelemetryStream::new(
+                backend_client.clone(),
+                machine.clone(),
+                capabilities.offers(FEATURE_TELEMETRY_STREAM),
+                commands,
+            ),
+            backend_client,
+            heartbeat_client,
+            machine,
+            spool,
+            stats,
+            backoff: SPOOL_MIN_BACKOFF,
+            next_retry: Instant::now(),
+            attempts: 0,
+        }
+    }
+
+    fn spool_msg(&mut self, msg: &TelemetryMsg) {
+        if let Some(spool) = self.spool.as_mut() {
+            if let Err(e) = spool.push(msg) {
+                error!("Failed to spool telemetry message, dropping it: {}", e);
+            }
+        }
+    }
+
+    fn update_spool_stats(&self) {
+        if let Some(spool) = self.spool.as_ref() {
+            self.stats.record_spool(spool);
+        }
+    }
+
+    /// Sends the heartbeats of the daemon, its machine and `nodes`. They are only accepted in a
+    /// session, so nothing is sent while there is none.
+    async fn send_heartbeats(&mut self, nodes: Vec<String>) -> Result<(), tonic::Status> {
+        self.session.refresh(&mut self.backend_client).await;
+        if !self.session.is_open() {
+            debug!("No telemetry session, skipping heartbeats");
+            return Ok(());
+        }
+
+        let client_id = format!("{:?}", self.machine.pubkey());
+        let machine_id = self.machine.id.to_string();
+        let client = &mut self.heartbeat_client;
+        client.send_client_heartbeat(self.session.request(ClientHeartbeat { client_id })).await?;
+        client
+            .send_machine_heartbeat(
+                self.session.request(MachineHeartbeat { machine_id: machine_id.clone() }),
+            )
+            .await?;
+        for node_id in nodes {
+            let heartbeat = NodeHeartbeat { node_id, machine_id: machine_id.clone() };
+            client.send_node_heartbeat(self.session.request(heartbeat)).await?;
+        }
+        Ok(())
+    }
+
+    /// Replays spooled messages in order until the spool is empty or the backend becomes
+    /// unreachable again, in which case the next attempt is pushed back exponentially.
+    async fn flush_spool(&mut self) {
+        let Self {
+            spool,
+            backend_client,
+            machine,
+            session,
+            stream,
+            stats,
+            backoff,
+            next_retry,
+            attempts,
+            ..
+        } = self;
+        let Some(spool) = spool.as_mut() else { return };
+        if spool.is_empty() || Instant::now() < *next_retry {
+            return;
+        }
+
+        let mut replayed = 0;
+        loop {
+            let msg = match spool.peek() {
+                Ok(Some(msg)) => msg,
+                Ok(None) => break,
+                Err(e) => {
+                    error!("Failed to read telemetry spool: {}", e);
+                    break;
+                }
+            };
+            let msg = match msg.resign(machine) {
+                Ok(msg) => msg,
+                Err(e) => {
+                    error!("Failed to re-sign spooled telemetry message, dropping it: {}", e);
+                    spool.pop();
+                    continue;
+                }
+            };
+            match send(backend_client, machine, session, stream, stats, msg).await {
+                Ok(()) => {
+                    spool.pop();
+                    *attempts = 0;
+                    replayed += 1;
+                }
+                Err(e) if is_retryable(&e) => {
+                    // Without a connection the message never reached the backend, so only
+                    // timeouts count against it, however long an outage lasts
+                    if e.code() == Code::DeadlineExceeded {
+                        *attempts += 1;
+                        if *attempts >= SPOOL_MAX_ATTEMPTS {
+                            error!(
+                                "Spooled telemetry message timed out {} times, dropping it",
+                                SPOOL_MAX_ATTEMPTS
+                            );
+                            spool.pop();
+                            *attempts = 0;
+                            continue;
+                        }
+                    }
+                    *next_retry = Instant::now() + *backoff;
+                    warn!(
+                        "Backend still unreachable after replaying {} spooled message(s), retrying \
+                         in {}s: {}",
+                        replayed,
+                        backoff.as_secs(),
+                        e.message()
+                    );
+                    *backoff = (*backoff * 2).min(SPOOL_MAX_BACKOFF);
+                    return;
+                }
+                Err(e) => {
+                    error!("Backend rejected spooled telemetry message, dropping it: {:?}", e);
+                    spool.pop();
+                    *attempts = 0;
+                }
+            }
+        }
+
+        *backoff = SPOOL_MIN_BACKOFF;
+        if replayed > 0 {
+            info!("Telemetry spool drained, replayed {} message(s)", replayed);
+        }
     }
 }
 
+/// Sends a single telemetry message to the backend, recording the outcome in `stats`. The
+/// telemetry stream is used while it is open, and single calls otherwise.
+async fn send(
+    backend_client: &mut BackendClient<Channel>,
+    machine: &IvyMachine,
+    session: &mut TelemetrySession,
+    stream: &mut TelemetryStream,
+    stats: &TelemetryStats,
+    msg: TelemetryMsg,
+) -> Result<(), tonic::Status> {
+    let res = match deliver(backend_client, session, stream, msg.clone()).await {
+        // The key the message was signed with is no longer registered, e.g. after
+        // `ivy key rotate-identity`, so it is signed again with the key now in the config
+        Err(e) if e.code() == Code::NotFound && reload_identity(machine) => {
+            session.invalidate();
+            match msg.resign(machine) {
+                Ok(msg) => deliver(backend_client, session, stream, msg).await,
+                Err(e) => Err(tonic::Status::internal(format!(
+                    "Failed to re-sign telemetry message: {e}"
+                ))),
+            }
+        }
+        res => res,
+    };
+    match res {
+        Ok(()) => {
+            stats.record_dispatch_success();
+            Ok(())
+        }
+        Err(e) => {
+            stats.record_dispatch_error();
+            Err(e)
+        }
+    }
+}
+
+async fn deliver(
+    backend_client: &mut BackendClient<Channel>,
+    session: &mut TelemetrySession,
+    stream: &mut TelemetryStream,
+    msg: TelemetryMsg,
+) -> Result<(), tonic::Status> {
+    match stream.send(msg.clone()).await {
+        Some(res) => res,
+        None => match send_in_session(backend_client, session, msg.clone()).await {
+            // The backend no longer knows the session, e.g. after a restart
+            Err(e) if e.code() == Code::Unauthenticated => {
+                session.invalidate();
+                send_in_session(backend_client, session, msg).await
+            }
+            res => res,
+        },
+    }
+}
+
+/// Reloads the identity key after the backend refused the one in use. Returns whether it changed.
+fn reload_identity(machine: &IvyMachine) -> bool {
+    match machine.reload_identity() {
+        Ok(true) => {
+            info!("Identity key was rotated, signing with {:?} from now on", machine.pubkey());
+            true
+        }
+        Ok(false) => false,
+        Err(e) => {
+            warn!("Failed to reload identity key: {}", e);
+            false
+        }
+    }
+}
+
+async fn send_in_session(
+    backend_client: &mut BackendClient<Channel>,
+    session: &mut TelemetrySession,
+    msg: TelemetryMsg,
+) -> Result<(), tonic::Status> {
+    session.refresh(backend_client).await;
+    let res = match msg {
+        TelemetryMsg::Metrics(metrics) => backend_client.metrics(session.request(metrics)).await,
+        TelemetryMsg::Log(log) => backend_client.logs(session.request(log)).await,
+        TelemetryMsg::LogBatch(batch) => backend_client.logs_batch(session.request(batch)).await,
+        TelemetryMsg::SignedNodeData(node_data) => {
+            backend_client.node_data_v2(session.request(node_data)).await
+        }
+        TelemetryMsg::SignedMachineData(machine_data) => {
+            backend_client.machine_data(session.request(machine_data)).await
+        }
+    };
+    res.map(|_| ())
+}
+
+/// Whether a failed call is worth retrying later. Only transport failures are: a message the
+/// backend received and failed on is likely to fail the same way on replay, and would hold up
+/// every message spooled behind it.
+fn is_retryable(status: &tonic::Status) -> bool {
+    matches!(status.code(), Code::Unavailable | Code::DeadlineExceeded)
+}
+
+/// Heartbeats of the daemon and its machine, along with the names of the nodes found running.
+/// Sent at a fixed interval, however much telemetry there is, so a machine whose nodes are all
+/// down can be told apart from a daemon that stopped.
+#[derive(Debug, Clone)]
+pub struct SendHeartbeats {
+    pub nodes: Vec<String>,
+}
+
+impl Message<SendHeartbeats> for TelemetryDispatch {
+    type Reply = ();
+
+    async fn handle(
+        &mut self,
+        msg: SendHeartbeats,
+        _: kameo::message::Context<'_, Self, Self::Reply>,
+    ) -> Self::Reply {
+        if !self.heartbeats {
+            return;
+        }
+        match self.send_heartbeats(msg.nodes).await {
+            Ok(()) => {}
+            // The backend no longer knows the session, the next round opens a new one
+            Err(e) if e.code() == Code::Unauthenticated => self.session.invalidate(),
+            Err(e) if e.code() == Code::Unimplemented => {
+                info!("Backend does not accept heartbeats");
+                self.heartbeats = false;
+            }
+            Err(e) => warn!("Failed to send heartbeats: {}", e.message()),
+        }
+    }
+}
+
+/// Periodic tick prompting the dispatcher to replay spooled messages, report spool health and
+/// reopen the telemetry stream.
+#[derive(Debug, Clone, Copy)]
+pub struct FlushSpool;
+
 impl Message<TelemetryMsg> for TelemetryDispatch {
     type Reply = ();
 
@@ -56,22 +462,73 @@ impl Message<TelemetryMsg> for TelemetryDispatch {
         msg: TelemetryMsg,
         _: kameo::message::Context<'_, Self, Self::Reply>,
     ) -> Self::Reply {
-        let res = match msg {
-            TelemetryMsg::Metrics(metrics) => self.backend_client.metrics(metrics).await,
-            TelemetryMsg::Log(log) => self.backend_client.logs(log).await,
-            TelemetryMsg::SignedNodeData(node_data) => {
-                self.backend_client.node_data_v2(node_data).await
-            }
-            TelemetryMsg::SignedMachineData(machine_data) => {
-                self.backend_client.machine_data(machine_data).await
+        let Some(spool) = self.spool.as_ref() else {
+            if let Err(e) = send(
+                &mut self.backend_client,
+                &self.machine,
+                &mut self.session,
+                &mut self.stream,
+                &self.stats,
+                msg,
+            )
+            .await
+            {
+                error!("Telemetry dispatch error: {:?}", e);
             }
+            return;
         };
-        match res {
-            Ok(_) => {}
+
+        // Keep ordering intact: while older messages are waiting, new ones queue behind them.
+        if !spool.is_empty() {
+            self.spool_msg(&msg);
+            self.flush_spool().await;
+            self.update_spool_stats();
+            return;
+        }
+
+        match send(
+            &mut self.backend_client,
+            &self.machine,
+            &mut self.session,
+            &mut self.stream,
+            &self.stats,
+            msg.clone(),
+        )
+        .await
+        {
+            Ok(()) => {}
+            Err(e) if is_retryable(&e) => {
+                warn!("Telemetry dispatch failed, spooling message for replay: {}", e.message());
+                self.spool_msg(&msg);
+                self.next_retry = Instant::now() + self.backoff;
+            }
             Err(e) => {
                 error!("Telemetry dispatch error: {:?}", e);
             }
         }
+        self.update_spool_stats();
+    }
+}
+
+impl Message<FlushSpool> for TelemetryDispatch {
+    type Reply = ();
+
+    async fn handle(
+        &mut self,
+        _: FlushSpool,
+        _: kameo::message::Context<'_, Self, Self::Reply>,
+    ) -> Self::Reply {
+        if let Some(spool) = self.spool.as_ref().filter(|s| !s.is_empty()) {
+            warn!(
+                "Telemetry spool depth: {} message(s), {} bytes, oldest entry age: {}s",
+                spool.depth(),
+                spool.size_bytes(),
+                spool.oldest_age().unwrap_or_default().as_secs()
+            );
+        }
+        self.flush_spool().await;
+        self.update_spool_stats();
+        self.stream.connect().await;
     }
 }
 
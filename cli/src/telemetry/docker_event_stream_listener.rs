@@ -1,75 +1,111 @@
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use bollard::secret::{EventMessage, EventMessageTypeEnum};
+use ethers::types::Address;
 use ivynet_docker::{
-    container::{ContainerId, ContainerImage},
+    container::{Container, ContainerId, ContainerImage, PortDiscovery},
     dockerapi::{DockerApi, DockerClient, DockerStreamError},
 };
 use ivynet_grpc::{
     backend::backend_client::BackendClient,
-    messages::{NodeDataV2, NodeTypeQueries, NodeTypeQuery},
+    messages::{
+        command::Kind, LogBackfill, NodeDataV2, NodeTypeQueries, NodeTypeQuery, UpdateNode,
+    },
     tonic::{transport::Channel, Request, Response},
-    BackendMiddleware,
 };
-use tokio::time::sleep;
+use tokio::{
+    sync::mpsc,
+    time::{sleep, Instant, Interval},
+};
 use tokio_stream::StreamExt;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
-use crate::ivy_machine::{IvyMachine, MachineIdentityError};
+use crate::{
+    control::{ControlCommand, ControlRequest, ControlResponse, ControlRx},
+    ivy_machine::{IvyMachine, MachineIdentityError},
+    monitor::{upsert_discovered_node, AutoDiscovery, MonitorConfig, MonitorConfigError},
+};
 
 use super::{
-    dispatch::{TelemetryDispatchError, TelemetryDispatchHandle},
+    container_stats_listener::ContainerStatsManager,
+    dispatch::{SendHeartbeats, TelemetryDispatchError, TelemetryDispatchHandle, TelemetryMsg},
+    labels::labelled_node,
     logs_listener::LogsListenerManager,
-    machine_data_listener::MachineDataMonitorHandle,
     metrics_listener::MetricsListenerHandle,
-    node_data_listener::NodeDataMonitorHandle,
-    ConfiguredAvs,
+    stream::{command_name, PinnedSignerRx, RemoteCommand, RemoteCommandRx},
+    ConfiguredAvs, NodeRuntime,
 };
 
 const TELEMETRY_INTERVAL_IN_MINUTES: u64 = 1;
+/// How often heartbeats are sent, well within the backend's heartbeat TTL.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(60);
+/// Bounds of the telemetry interval the backend can set.
+const MIN_TELEMETRY_INTERVAL: Duration = Duration::from_secs(15);
+const MAX_TELEMETRY_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+/// How far back the backend can ask for logs to be sent again.
+const MAX_LOG_BACKFILL_SECS: u64 = 24 * 60 * 60;
+/// How long a container that started without an open metrics port gets to open one.
+const METRICS_PORT_RETRY_DELAY: Duration = Duration::from_secs(10);
+/// Metrics ports found late, waiting for the listener.
+const DISCOVERED_PORT_CHANNEL_CAPACITY: usize = 16;
 
 #[derive(Debug)]
-pub struct DockerStreamListener<D: DockerApi, B: BackendMiddleware> {
+pub struct DockerStreamListener<D: DockerApi> {
     pub docker: D,
-    pub machine_data_monitor_handle: MachineDataMonitorHandle<B>,
-    pub node_data_monitor_handle: NodeDataMonitorHandle<B>,
     pub metrics_listener_handle: MetricsListenerHandle,
     pub logs_listener_handle: LogsListenerManager,
+    pub container_stats_handle: ContainerStatsManager,
     pub dispatch: TelemetryDispatchHandle,
     pub machine: IvyMachine,
     pub backend: BackendClient<Channel>,
+    pub port_discovery: PortDiscovery,
+    pub auto_discovery: AutoDiscovery,
+    /// Metrics ports found for nodes some time after their container started
+    discovered_ports: mpsc::Sender<UpdateNode>,
+    discovered_ports_rx: mpsc::Receiver<UpdateNode>,
 }
 
-impl<B: BackendMiddleware> DockerStreamListener<DockerClient, B> {
+impl DockerStreamListener<DockerClient> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        machine_data_monitor: MachineDataMonitorHandle<B>,
-        node_data_monitor: NodeDataMonitorHandle<B>,
         metrics_listener: MetricsListenerHandle,
         logs_listener: LogsListenerManager,
+        container_stats: ContainerStatsManager,
         dispatch: TelemetryDispatchHandle,
         machine: IvyMachine,
         backend: BackendClient<Channel>,
+        port_discovery: PortDiscovery,
+        auto_discovery: AutoDiscovery,
     ) -> Self {
+        let (discovered_ports, discovered_ports_rx) =
+            mpsc::channel(DISCOVERED_PORT_CHANNEL_CAPACITY);
         Self {
             docker: DockerClient::default(),
-            machine_data_monitor_handle: machine_data_monitor,
-            node_data_monitor_handle: node_data_monitor,
             metrics_listener_handle: metrics_listener,
             logs_listener_handle: logs_listener,
+            container_stats_handle: container_stats,
             dispatch,
             machine,
             backend,
+            port_discovery,
+            auto_discovery,
+            discovered_ports,
+            discovered_ports_rx,
         }
     }
 
     pub async fn run(
         mut self,
-        known_nodes: Vec<ConfiguredAvs>,
+        mut known_nodes: Vec<ConfiguredAvs>,
+        mut control_rx: ControlRx,
+        mut command_rx: RemoteCommandRx,
+        mut signer_rx: PinnedSignerRx,
     ) -> Result<(), DockerStreamListenerError> {
         let mut docker_stream = self.docker.stream_events().await;
 
         let mut telemetry_interval =
             tokio::time::interval(Duration::from_secs(TELEMETRY_INTERVAL_IN_MINUTES * 60));
+        let mut heartbeat_interval = tokio::time::interval(HEARTBEAT_INTERVAL);
 
         loop {
             tokio::select! {
@@ -82,7 +118,7 @@ impl<B: BackendMiddleware> DockerStreamListener<DockerClient, B> {
                                 if let Some(action) = event.action.as_deref() {
                                     match action {
                                         "start" => {
-                                            self.on_start(event, &known_nodes).await?;
+                                            self.on_start(event, &mut known_nodes).await?;
                                         }
                                         "stop" | "kill" | "die" => {
                                             self.on_stop(event).await?;
@@ -108,7 +144,7 @@ impl<B: BackendMiddleware> DockerStreamListener<DockerClient, B> {
                     info!("Broadcasting telemetry on tick...");
 
                     let signed_machine_data = self.machine.sign_machine_data()?;
-                    if let Err(e) = self.machine_data_monitor_handle.ask_send_machine_data(signed_machine_data).await {
+                    if let Err(e) = self.dispatch.tell(TelemetryMsg::SignedMachineData(signed_machine_data)).await {
                         error!("Failed to send machine data: {}", e);
                     }
 
@@ -122,7 +158,7 @@ impl<B: BackendMiddleware> DockerStreamListener<DockerClient, B> {
                             node_running: Some(node.node_running().await),
                         };
                         let signed = self.machine.sign_node_data_v2(&node_data)?;
-                        if let Err(e) = self.node_data_monitor_handle.ask_send_node_data(signed).await {
+                        if let Err(e) = self.dispatch.tell(TelemetryMsg::SignedNodeData(signed)).await {
                             error!("Failed to send node data: {}", e);
                         }
                     }
@@ -134,6 +170,61 @@ impl<B: BackendMiddleware> DockerStreamListener<DockerClient, B> {
 
 
                 }
+
+                // 3) The heartbeat interval ticks. Sent from this loop, so they stop when it is stuck.
+                _ = heartbeat_interval.tick() => {
+                    let mut nodes = Vec::new();
+                    for node in known_nodes.iter() {
+                        if node.node_running().await {
+                            nodes.push(node.assigned_name.clone());
+                        }
+                    }
+                    if let Err(e) = self.dispatch.tell(SendHeartbeats { nodes }).await {
+                        error!("Failed to send heartbeats: {}", e);
+                    }
+                }
+
+                // 4) A request arrives on the control socket.
+                Some(ControlCommand { request, reply }) = control_rx.recv() => {
+                    let response = self.on_control(request, &mut known_nodes).await;
+                    // The requesting client may have disconnected in the meantime
+                    let _ = reply.send(response);
+                }
+
+                // 5) A command arrives from the backend.
+                Some(RemoteCommand { kind, reply }) = command_rx.recv() => {
+                    let name = command_name(&kind);
+                    let result =
+                        self.on_command(kind, &mut known_nodes, &mut telemetry_interval).await;
+                    match &result {
+                        Ok(message) => info!("Carried out {} command: {}", name, message),
+                        Err(e) => warn!("Failed to carry out {} command: {}", name, e),
+                    }
+                    let _ = reply.send(result);
+                }
+
+                // 6) The telemetry stream pinned the command signer the backend announced.
+                Some(signer) = signer_rx.recv() => {
+                    match store_command_signer(signer) {
+                        Ok(()) => info!("Stored command signer {:?}", signer),
+                        Err(e) => warn!("Failed to store command signer: {}", e),
+                    }
+                }
+
+                // 7) A node that started without an open metrics port opened one. Left alone if
+                // it got a port some other way in the meantime.
+                Some(update) = self.discovered_ports_rx.recv() => {
+                    let unset = known_nodes.iter().any(|node| {
+                        node.assigned_name == update.assigned_name && node.metric_port.is_none()
+                    });
+                    if unset {
+                        let name = update.assigned_name.clone();
+                        match self.update_node(update, &mut known_nodes).await {
+                            Ok(message) => info!("Found metrics port of {}: {}", name, message),
+                            Err(e) => warn!("Failed to set metrics port of {}: {}", name, e),
+                        }
+                    }
+                }
             }
         }
 
@@ -143,13 +234,22 @@ impl<B: BackendMiddleware> DockerStreamListener<DockerClient, B> {
     pub async fn on_start(
         &mut self,
         event: EventMessage,
-        avses: &[ConfiguredAvs],
+        avses: &mut Vec<ConfiguredAvs>,
     ) -> Result<(), DockerStreamListenerError> {
         let actor = event.actor.ok_or(DockerStreamError::MissingActor)?;
         let attributes = actor.attributes.ok_or(DockerStreamError::MissingAttributes)?;
         let inc_container_name =
             attributes.get("name").ok_or(DockerStreamError::MissingAttributes)?;
+        self.on_container_started(inc_container_name, avses).await
+    }
 
+    /// Starts following a running container if it is a configured node, declares itself one
+    /// through labels, or is adopted by auto-discovery.
+    async fn on_container_started(
+        &mut self,
+        inc_container_name: &str,
+        avses: &mut Vec<ConfiguredAvs>,
+    ) -> Result<(), DockerStreamListenerError> {
         let inc_container = match self.docker.find_container_by_name(inc_container_name).await {
             Some(container) => container,
             None => {
@@ -161,17 +261,11 @@ impl<B: BackendMiddleware> DockerStreamListener<DockerClient, B> {
         let inc_container_digest =
             inc_container.repo_digest(&self.docker.inner()).await.unwrap_or_default().to_string();
 
-        let metrics_port = match inc_container.metrics_port(&self.docker).await {
-            Some(port) => Some(port),
-            None => {
-                // wait for metrics port to potentially come up
-                sleep(Duration::from_secs(10)).await;
-                inc_container.metrics_port(&self.docker).await
-            }
-        };
-
-        let mut configured = None;
-        for avs in avses {
+        let labelled = labelled_node(&self.docker, &inc_container).await;
+        let from_labels = labelled.is_some();
+        let mut configured = labelled;
+        // Labels take precedence over matching the container against configured nodes
+        for avs in avses.iter().filter(|_| !from_labels) {
             // First try to find by container name
             if avs.container_name == *inc_container_name {
                 configured = Some(avs.clone());
@@ -193,11 +287,20 @@ impl<B: BackendMiddleware> DockerStreamListener<DockerClient, B> {
             }
         }
 
+        let known = configured.is_some();
         let configured = match configured {
             Some(avs) => Some(avs),
+            None if !self.auto_discovery.enabled => {
+                debug!("Ignoring unconfigured container: {}", inc_container_name);
+                None
+            }
+            None if !self.auto_discovery.allows(inc_container_name, &inc_image_name) => {
+                info!("Not adopting container {}: matches the deny-list", inc_container_name);
+                None
+            }
             None => {
                 let node_type_query = NodeTypeQuery {
-                    container_name: inc_container_name.clone(),
+                    container_name: inc_container_name.to_string(),
                     image_name: inc_image_name.clone(),
                     image_digest: inc_container_digest.clone(),
                 };
@@ -213,13 +316,15 @@ impl<B: BackendMiddleware> DockerStreamListener<DockerClient, B> {
                             if node_type.node_type != "unknown" {
                                 Some(ConfiguredAvs {
                                     assigned_name: inc_container_name.to_string(),
-                                    container_name: inc_container_name.clone(),
+                                    container_name: inc_container_name.to_string(),
                                     avs_type: node_type.node_type.clone(),
-                                    metric_port: metrics_port,
+                                    metric_port: None,
+                                    metrics_path: None,
                                     manifest: Some(ContainerId::from(
                                         inc_container_digest.as_str(),
                                     )),
                                     image: Some(ContainerImage::from(inc_image_name.as_str())),
+                                    runtime: NodeRuntime::Docker,
                                 })
                             } else {
                                 None
@@ -232,33 +337,309 @@ impl<B: BackendMiddleware> DockerStreamListener<DockerClient, B> {
                 }
             }
         };
+        let Some(mut configured) = configured else { return Ok(()) };
+        debug!("Found container: {}", inc_container_name);
+
+        // Discovering the port may take a while, so it is only done for containers that turned
+        // out to be nodes. Configured nodes keep the port they were configured with.
+        let mut rediscover = false;
+        if (from_labels || !known) && configured.metric_port.is_none() {
+            configured.metric_port =
+                inc_container.metrics_port(&self.docker, self.port_discovery).await;
+            rediscover = configured.metric_port.is_none();
+        }
 
-        if let Some(configured) = configured {
-            debug!("Found container: {}", inc_container_name);
+        if !known {
+            info!(
+                "Auto-adopted container {} as {} node {}",
+                inc_container_name, configured.avs_type, configured.assigned_name
+            );
+        }
+        if from_labels || !known {
+            match register_discovered_node(avses, configured) {
+                Some(node) => configured = node,
+                None => return Ok(()),
+            }
+        }
 
-            let node_data_v2 = NodeDataV2 {
-                name: configured.assigned_name.clone(),
-                node_type: Some(configured.avs_type.clone()),
-                manifest: Some(inc_container_digest.clone()),
-                metrics_alive: Some(configured.metrics_alive().await),
-                node_running: Some(true),
-            };
-            let signed = self.machine.sign_node_data_v2(&node_data_v2)?;
+        let node_data_v2 = NodeDataV2 {
+            name: configured.assigned_name.clone(),
+            node_type: Some(configured.avs_type.clone()),
+            manifest: Some(inc_container_digest.clone()),
+            metrics_alive: Some(configured.metrics_alive().await),
+            node_running: Some(true),
+        };
+        let signed = self.machine.sign_node_data_v2(&node_data_v2)?;
+
+        if let Err(e) = self.dispatch.tell(TelemetryMsg::SignedNodeData(signed)).await {
+            error!("Error sending node data: {:?}", e);
+        }
+        if let Err(e) = self.metrics_listener_handle.tell_add_node(configured.clone()).await {
+            error!("Error adding node: {:?}", e);
+        }
+        if let Err(e) = self.logs_listener_handle.add_listener(&inc_container, &configured).await {
+            error!("Error adding listener: {:?}", e);
+        }
+        self.container_stats_handle.add_listener(&inc_container, &configured);
+        if rediscover {
+            self.rediscover_metrics_port(&inc_container, &configured.assigned_name);
+        }
+
+        Ok(())
+    }
 
-            if let Err(e) = self.node_data_monitor_handle.ask_send_node_data(signed).await {
-                error!("Error sending node data: {:?}", e);
+    /// Looks for the metrics port of a node again once its container had some time to open it,
+    /// without holding up the listener in the meantime. A port found is handed back to the
+    /// listener, which applies it like an `UpdateNode` command.
+    fn rediscover_metrics_port(&self, container: &Container, assigned_name: &str) {
+        let docker = self.docker.clone();
+        let container = container.clone();
+        let discovery = self.port_discovery;
+        let discovered_ports = self.discovered_ports.clone();
+        let assigned_name = assigned_name.to_string();
+        tokio::spawn(async move {
+            sleep(METRICS_PORT_RETRY_DELAY).await;
+            if let Some(port) = container.metrics_port(&docker, discovery).await {
+                let update = UpdateNode {
+                    assigned_name,
+                    metric_port: Some(port.into()),
+                    metrics_path: None,
+                };
+                // Lost if the listener stopped in the meantime
+                let _ = discovered_ports.send(update).await;
             }
-            if let Err(e) = self.metrics_listener_handle.tell_add_node(configured.clone()).await {
-                error!("Error adding node: {:?}", e);
+        });
+    }
+
+    /// Handles a node change requested through the control socket. Changes are applied to the
+    /// running listeners and stored in the monitor config.
+    pub async fn on_control(
+        &mut self,
+        request: ControlRequest,
+        avses: &mut Vec<ConfiguredAvs>,
+    ) -> ControlResponse {
+        let result = match request {
+            ControlRequest::AddNode { node } => self.add_node(node, avses).await,
+            ControlRequest::RemoveNode { assigned_name } => {
+                self.remove_node(&assigned_name, avses).await
             }
-            if let Err(e) =
-                self.logs_listener_handle.add_listener(&inc_container, &configured).await
-            {
-                error!("Error adding listener: {:?}", e);
+            ControlRequest::ListNodes => {
+                return ControlResponse::Nodes { nodes: avses.clone() };
+            }
+            ControlRequest::Status => {
+                return ControlResponse::error("Status is answered by the control server");
             }
+        };
+        match result {
+            Ok(node) => ControlResponse::Node { node },
+            Err(e) => ControlResponse::error(e),
         }
+    }
 
-        Ok(())
+    async fn add_node(
+        &mut self,
+        mut node: ConfiguredAvs,
+        avses: &mut Vec<ConfiguredAvs>,
+    ) -> Result<ConfiguredAvs, String> {
+        if let Some(existing) = avses.iter().find(|avs| {
+            avs.assigned_name == node.assigned_name || avs.container_name == node.container_name
+        }) {
+            return Err(format!(
+                "Node {} is already monitored under container {}",
+                existing.assigned_name, existing.container_name
+            ));
+        }
+
+        let container = self.docker.find_container_by_name(&node.container_name).await;
+        if let Some(container) = &container {
+            if node.image.is_none() {
+                node.image = container.image().map(ContainerImage::from);
+            }
+            if node.manifest.is_none() {
+                node.manifest = container
+                    .repo_digest(&self.docker.inner())
+                    .await
+                    .map(|digest| ContainerId::from(digest.as_str()));
+            }
+            if node.metric_port.is_none() {
+                node.metric_port = container.metrics_port(&self.docker, self.port_discovery).await;
+            }
+        }
+
+        let mut config = MonitorConfig::load_from_default_path().map_err(|e| e.to_string())?;
+        if config.upsert_node(node.clone()) {
+            config.store().map_err(|e| format!("Failed to store node: {e}"))?;
+        }
+        avses.push(node.clone());
+
+        if let Err(e) = self.metrics_listener_handle.tell_add_node(node.clone()).await {
+            error!("Error adding node: {:?}", e);
+        }
+        match container {
+            Some(container) => {
+                let node_data = NodeDataV2 {
+                    name: node.assigned_name.clone(),
+                    node_type: Some(node.avs_type.clone()),
+                    manifest: node.manifest.as_ref().map(ToString::to_string),
+                    metrics_alive: Some(node.metrics_alive().await),
+                    node_running: Some(true),
+                };
+                match self.machine.sign_node_data_v2(&node_data) {
+                    Ok(signed) => {
+                        if let Err(e) =
+                            self.dispatch.tell(TelemetryMsg::SignedNodeData(signed)).await
+                        {
+                            error!("Error sending node data: {:?}", e);
+                        }
+                    }
+                    Err(e) => error!("Failed to sign node data: {}", e),
+                }
+                if let Err(e) = self.logs_listener_handle.add_listener(&container, &node).await {
+                    error!("Error adding listener: {:?}", e);
+                }
+                self.container_stats_handle.add_listener(&container, &node);
+            }
+            // Picked up by `on_start` once the container starts
+            None => info!("Container {} for node is not running", node.container_name),
+        }
+
+        info!("Added node {} through the control socket", node.assigned_name);
+        Ok(node)
+    }
+
+    async fn remove_node(
+        &mut self,
+        assigned_name: &str,
+        avses: &mut Vec<ConfiguredAvs>,
+    ) -> Result<ConfiguredAvs, String> {
+        let idx = avses
+            .iter()
+            .position(|avs| avs.assigned_name == assigned_name)
+            .ok_or_else(|| format!("No node named {assigned_name} is monitored"))?;
+
+        let mut config = MonitorConfig::load_from_default_path().map_err(|e| e.to_string())?;
+        if config.remove_node(assigned_name).is_some() {
+            config.store().map_err(|e| format!("Failed to store config: {e}"))?;
+        }
+        let node = avses.remove(idx);
+
+        if let Err(e) = self.metrics_listener_handle.tell_remove_node(node.clone()).await {
+            error!("Error removing node: {:?}", e);
+        }
+        self.logs_listener_handle.remove_listener(&node.container_name);
+        self.container_stats_handle.remove_listener(&node);
+
+        info!("Removed node {} through the control socket", node.assigned_name);
+        Ok(node)
+    }
+
+    /// Carries out a command pushed by the backend, which already passed the allow-list. Returns
+    /// what was done, or why it could not be.
+    pub async fn on_command(
+        &mut self,
+        command: Kind,
+        avses: &mut Vec<ConfiguredAvs>,
+        telemetry_interval: &mut Interval,
+    ) -> Result<String, String> {
+        match command {
+            Kind::SetScrapeInterval(interval) => {
+                let period = Duration::from_secs(interval.seconds);
+                if !(MIN_TELEMETRY_INTERVAL..=MAX_TELEMETRY_INTERVAL).contains(&period) {
+                    return Err(format!(
+                        "Interval must be between {}s and {}s",
+                        MIN_TELEMETRY_INTERVAL.as_secs(),
+                        MAX_TELEMETRY_INTERVAL.as_secs()
+                    ));
+                }
+                // Kept until the monitor restarts
+                *telemetry_interval = tokio::time::interval_at(Instant::now() + period, period);
+                Ok(format!("Telemetry is sent every {}s", period.as_secs()))
+            }
+            Kind::LogBackfill(backfill) => self.backfill_logs(backfill, avses).await,
+            Kind::Rescan(_) => self.rescan(avses).await,
+            Kind::UpdateNode(update) => self.update_node(update, avses).await,
+        }
+    }
+
+    async fn backfill_logs(
+        &mut self,
+        backfill: LogBackfill,
+        avses: &[ConfiguredAvs],
+    ) -> Result<String, String> {
+        let node = avses
+            .iter()
+            .find(|avs| avs.assigned_name == backfill.node_name)
+            .ok_or_else(|| format!("No node named {} is monitored", backfill.node_name))?;
+        if !node.runtime.is_docker() {
+            return Err(format!("Logs of {} are not read from docker", node.assigned_name));
+        }
+        let container = self
+            .docker
+            .find_container_by_name(&node.container_name)
+            .await
+            .ok_or_else(|| format!("Container {} is not running", node.container_name))?;
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let since = backfill.since.max(now.saturating_sub(MAX_LOG_BACKFILL_SECS));
+        let sent = self
+            .logs_listener_handle
+            .backfill(&container, node, since as i64)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(format!("Sent {sent} log lines of {}", node.assigned_name))
+    }
+
+    /// Looks for running containers that are not monitored yet, as if they had just started.
+    async fn rescan(&mut self, avses: &mut Vec<ConfiguredAvs>) -> Result<String, String> {
+        let known = avses.len();
+        for container in self.docker.list_containers().await {
+            let Some(name) = container.names().and_then(|names| names.into_iter().next()) else {
+                continue;
+            };
+            if avses.iter().any(|avs| avs.container_name == name) {
+                continue;
+            }
+            self.on_container_started(&name, avses).await.map_err(|e| e.to_string())?;
+        }
+        Ok(format!("Found {} new node(s)", avses.len().saturating_sub(known)))
+    }
+
+    /// Changes where the metrics of a node are scraped from, and stores the change in the monitor
+    /// config.
+    async fn update_node(
+        &mut self,
+        update: UpdateNode,
+        avses: &mut [ConfiguredAvs],
+    ) -> Result<String, String> {
+        let node = avses
+            .iter_mut()
+            .find(|avs| avs.assigned_name == update.assigned_name)
+            .ok_or_else(|| format!("No node named {} is monitored", update.assigned_name))?;
+        let previous = node.clone();
+
+        if let Some(port) = update.metric_port {
+            node.metric_port =
+                Some(u16::try_from(port).map_err(|_| format!("Invalid metrics port {port}"))?);
+        }
+        if let Some(path) = update.metrics_path {
+            // An empty path goes back to the default
+            node.metrics_path = Some(path).filter(|path| !path.is_empty());
+        }
+        if *node == previous {
+            return Ok(format!("Node {} is unchanged", node.assigned_name));
+        }
+
+        let mut config = MonitorConfig::load_from_default_path().map_err(|e| e.to_string())?;
+        if config.upsert_node(node.clone()) {
+            config.store().map_err(|e| format!("Failed to store node: {e}"))?;
+        }
+        if let Err(e) = self.metrics_listener_handle.tell_remove_node(previous).await {
+            error!("Error removing node: {:?}", e);
+        }
+        if let Err(e) = self.metrics_listener_handle.tell_add_node(node.clone()).await {
+            error!("Error adding node: {:?}", e);
+        }
+        Ok(format!("Updated node {}", node.assigned_name))
     }
 
     pub async fn on_stop(&self, event: EventMessage) -> Result<(), DockerStreamError> {
@@ -278,6 +659,36 @@ impl<B: BackendMiddleware> DockerStreamListener<DockerClient, B> {
     }
 }
 
+/// Adds a node declared through container labels or adopted by auto-discovery to the running
+/// listener's known nodes and to the stored monitor config, by the rules of
+/// [`upsert_discovered_node`]. Returns the node as it is followed, or `None` when its name is taken
+/// by another container. The node is followed even if the config cannot be read, but the config
+/// is then left untouched.
+fn register_discovered_node(
+    avses: &mut Vec<ConfiguredAvs>,
+    node: ConfiguredAvs,
+) -> Option<ConfiguredAvs> {
+    let (node, _) = upsert_discovered_node(avses, node)?;
+
+    let stored = MonitorConfig::load_from_default_path().and_then(|mut config| {
+        if config.upsert_node(node.clone()) {
+            config.store()?;
+        }
+        Ok(())
+    });
+    if let Err(e) = stored {
+        error!("Failed to store node {}: {}", node.assigned_name, e);
+    }
+    Some(node)
+}
+
+/// Stores the command signer pinned on first contact with the backend in the monitor config.
+fn store_command_signer(signer: Address) -> Result<(), MonitorConfigError> {
+    let mut config = MonitorConfig::load_from_default_path()?;
+    config.remote_commands.signer = Some(signer);
+    config.store()
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum DockerStreamListenerError {
     #[error("Dockerstream error: {0}")]
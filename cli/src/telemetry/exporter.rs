@@ -0,0 +1,440 @@
+use std::{collections::BTreeMap, fmt::Write as _, time::SystemTime};
+
+use axum::{extract::State, http::header, response::IntoResponse, routing::get, Router};
+use ivynet_grpc::messages::{MetricType, Metrics};
+use tokio::net::TcpListener;
+
+use crate::ivy_machine::{DiskInfo, SystemInformation};
+
+use super::{
+    container_stats_listener::container_metrics,
+    stats::{ContainerUsage, NodeStats, StatsSnapshot, TelemetryStats},
+};
+
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Labels attached to every re-exposed node metric. Scraped labels with the same name are kept
+/// under an `exported_` prefix, following Prometheus' own `honor_labels: false` behaviour.
+const NODE_LABELS: [&str; 2] = ["assigned_name", "avs_type"];
+
+/// Serves the local Prometheus `/metrics` endpoint until the listener fails. Every node metric
+/// scraped by the monitor is re-exposed, relabelled with the node it came from, alongside machine
+/// stats and the daemon's own telemetry health.
+pub async fn serve(listener: TcpListener, stats: TelemetryStats) -> Result<(), std::io::Error> {
+    let app = Router::new().route("/metrics", get(metrics)).with_state(stats);
+    axum::serve(listener, app).await
+}
+
+async fn metrics(State(stats): State<TelemetryStats>) -> impl IntoResponse {
+    let system = tokio::task::spawn_blocking(SystemInformation::from_system).await.ok();
+    let body = render(&stats.snapshot(), system.as_ref());
+    ([(header::CONTENT_TYPE, CONTENT_TYPE)], body)
+}
+
+fn render(snapshot: &StatsSnapshot, system: Option<&SystemInformation>) -> String {
+    let mut out = String::new();
+    render_node_metrics(&mut out, snapshot);
+    if let Some(system) = system {
+        render_machine(&mut out, system);
+    }
+    render_daemon(&mut out, snapshot);
+    out
+}
+
+fn render_node_metrics(out: &mut String, snapshot: &StatsSnapshot) {
+    // Samples of one family must be contiguous, so group them across nodes before writing. A
+    // family is typed the way the node that exposes it first declared it.
+    let mut families: BTreeMap<&str, (&str, Vec<String>)> = BTreeMap::new();
+    for (assigned_name, node) in &snapshot.nodes {
+        for metric in &node.metrics {
+            let (family, kind) = text_family(&metric.name, metric.metric_type());
+            families.entry(family).or_insert_with(|| (kind, vec![])).1.push(sample(
+                &metric.name,
+                &node_labels(metric, assigned_name, &node.avs_type),
+                metric.value,
+            ));
+        }
+    }
+    // Nodes do not send the help of their metrics along, so only the types are known
+    for (family, (kind, samples)) in &families {
+        let _ = writeln!(out, "# TYPE {family} {kind}");
+        for line in samples {
+            out.push_str(line);
+        }
+    }
+
+    let per_node = |f: fn(&NodeStats) -> Option<f64>| {
+        snapshot
+            .nodes
+            .iter()
+            .filter_map(|(name, node)| f(node).map(|value| (node_identity(name, node), value)))
+            .collect::<Vec<_>>()
+    };
+    family(
+        out,
+        "ivynet_node_scrape_errors_total",
+        "Failed metrics scrapes per node.",
+        "counter",
+        per_node(|node| Some(node.scrape_errors as f64)),
+    );
+    family(
+        out,
+        "ivynet_node_last_scrape_timestamp_seconds",
+        "Unix time of the last successful metrics scrape per node.",
+        "gauge",
+        per_node(|node| node.last_scrape.map(unix_seconds)),
+    );
+
+    // Nodes without a running container have no usage sample, and are left out of these families
+    for (i, metric) in container_metrics(&ContainerUsage::default()).iter().enumerate() {
+        let kind = if metric.metric_type == MetricType::Counter { "counter" } else { "gauge" };
+        let samples = snapshot
+            .nodes
+            .iter()
+            .filter_map(|(name, node)| {
+                let usage = node.container.as_ref()?;
+                Some((node_identity(name, node), container_metrics(usage)[i].value))
+            })
+            .collect();
+        family(out, metric.name, metric.help, kind, samples);
+    }
+}
+
+fn render_machine(out: &mut String, system: &SystemInformation) {
+    gauge(out, "ivynet_machine_cpu_cores", "Number of CPU cores.", system.cpu_cores as f64);
+    gauge(
+        out,
+        "ivynet_machine_cpu_usage",
+        "Summed CPU usage across cores, in percent.",
+        system.cpu_usage,
+    );
+    gauge(out, "ivynet_machine_memory_used_bytes", "Used memory.", system.memory_usage as f64);
+    gauge(out, "ivynet_machine_memory_free_bytes", "Free memory.", system.memory_free as f64);
+    gauge(out, "ivynet_machine_memory_total_bytes", "Total memory.", system.memory_total as f64);
+    gauge(out, "ivynet_machine_uptime_seconds", "Machine uptime.", system.uptime as f64);
+
+    let per_disk = |f: fn(&DiskInfo) -> u64| {
+        system
+            .disks
+            .iter()
+            .map(|disk| (vec![("disk".to_string(), disk.id.clone())], f(disk) as f64))
+            .collect::<Vec<_>>()
+    };
+    family(out, "ivynet_machine_disk_total_bytes", "Disk size.", "gauge", per_disk(|d| d.total));
+    family(
+        out,
+        "ivynet_machine_disk_free_bytes",
+        "Disk space available.",
+        "gauge",
+        per_disk(|d| d.free),
+    );
+    family(
+        out,
+        "ivynet_machine_disk_used_bytes",
+        "Disk space used.",
+        "gauge",
+        per_disk(|d| d.used),
+    );
+}
+
+fn render_daemon(out: &mut String, snapshot: &StatsSnapshot) {
+    let dispatch = &snapshot.dispatch;
+    gauge(
+        out,
+        "ivynet_daemon_spool_depth",
+        "Telemetry messages waiting in the on-disk spool.",
+        dispatch.spool_depth as f64,
+    );
+    gauge(
+        out,
+        "ivynet_daemon_spool_bytes",
+        "Size of the on-disk spool.",
+        dispatch.spool_bytes as f64,
+    );
+    gauge(
+        out,
+        "ivynet_daemon_spool_oldest_age_seconds",
+        "Age of the oldest spooled telemetry message.",
+        dispatch.spool_oldest_age.unwrap_or_default().as_secs_f64(),
+    );
+    if let Some(last_success) = dispatch.last_success {
+        gauge(
+            out,
+            "ivynet_daemon_last_successful_dispatch_timestamp_seconds",
+            "Unix time of the last telemetry message accepted by the backend.",
+            unix_seconds(last_success),
+        );
+    }
+    family(
+        out,
+        "ivynet_daemon_dispatches_total",
+        "Telemetry messages accepted by the backend.",
+        "counter",
+        vec![(vec![], dispatch.successes as f64)],
+    );
+    family(
+        out,
+        "ivynet_daemon_dispatch_errors_total",
+        "Failed attempts to send telemetry to the backend.",
+        "counter",
+        vec![(vec![], dispatch.errors as f64)],
+    );
+}
+
+/// Family a scraped sample belongs to in the text format, and the type the family is declared
+/// with. Types the text format does not have, such as OpenMetrics' info and state sets, are
+/// untyped there, and so are the `_created` series.
+fn text_family(name: &str, metric_type: MetricType) -> (&str, &'static str) {
+    let family = |suffixes: &[&str]| {
+        suffixes.iter().find_map(|suffix| name.strip_suffix(suffix)).unwrap_or(name)
+    };
+    match metric_type {
+        _ if name.ends_with("_created") => (name, "untyped"),
+        MetricType::Counter => (name, "counter"),
+        MetricType::Gauge => (name, "gauge"),
+        MetricType::Histogram => (family(&["_bucket", "_sum", "_count"]), "histogram"),
+        MetricType::Summary => (family(&["_sum", "_count"]), "summary"),
+        MetricType::Unknown |
+        MetricType::GaugeHistogram |
+        MetricType::Info |
+        MetricType::StateSet => (name, "untyped"),
+    }
+}
+
+fn node_identity(assigned_name: &str, node: &NodeStats) -> Vec<(String, String)> {
+    vec![
+        ("assigned_name".to_string(), assigned_name.to_string()),
+        ("avs_type".to_string(), node.avs_type.clone()),
+    ]
+}
+
+fn node_labels(metric: &Metrics, assigned_name: &str, avs_type: &str) -> Vec<(String, String)> {
+    let mut labels: Vec<(String, String)> = metric
+        .attributes
+        .iter()
+        .map(|attr| {
+            let name = if NODE_LABELS.contains(&attr.name.as_str()) {
+                format!("exported_{}", attr.name)
+            } else {
+                attr.name.clone()
+            };
+            (name, attr.value.clone())
+        })
+        .collect();
+    labels.push(("assigned_name".to_string(), assigned_name.to_string()));
+    labels.push(("avs_type".to_string(), avs_type.to_string()));
+    labels
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: f64) {
+    family(out, name, help, "gauge", vec![(vec![], value)]);
+}
+
+fn family(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    kind: &str,
+    samples: Vec<(Vec<(String, String)>, f64)>,
+) {
+    if samples.is_empty() {
+        return;
+    }
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+    for (labels, value) in samples {
+        out.push_str(&sample(name, &labels, value));
+    }
+}
+
+fn sample(name: &str, labels: &[(String, String)], value: f64) -> String {
+    let mut line = name.to_string();
+    if !labels.is_empty() {
+        let labels = labels
+            .iter()
+            .map(|(k, v)| format!("{k}=\"{}\"", escape_label_value(v)))
+            .collect::<Vec<_>>()
+            .join(",");
+        let _ = write!(line, "{{{labels}}}");
+    }
+    let _ = writeln!(line, " {}", format_value(value));
+    line
+}
+
+fn escape_label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+fn unix_seconds(time: SystemTime) -> f64 {
+    time.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs_f64()
+}
+
+#[cfg(test)]
+mod exporter_tests {
+    use std::time::Duration;
+
+    use ivynet_docker::stats::ContainerStats;
+    use ivynet_grpc::messages::MetricsAttribute;
+
+    use super::*;
+    use crate::telemetry::stats::DispatchStats;
+
+    fn metric(name: &str, value: f64, attributes: &[(&str, &str)]) -> Metrics {
+        typed_metric(name, MetricType::Unknown, value, attributes)
+    }
+
+    fn typed_metric(
+        name: &str,
+        metric_type: MetricType,
+        value: f64,
+        attributes: &[(&str, &str)],
+    ) -> Metrics {
+        Metrics {
+            name: name.to_string(),
+            value,
+            attributes: attributes
+                .iter()
+                .map(|(n, v)| MetricsAttribute { name: n.to_string(), value: v.to_string() })
+                .collect(),
+            metric_type: metric_type.into(),
+        }
+    }
+
+    fn node(avs_type: &str, metrics: Vec<Metrics>) -> NodeStats {
+        NodeStats {
+            avs_type: avs_type.to_string(),
+            last_scrape: None,
+            scrape_errors: 0,
+            last_dispatch: None,
+            dispatch_errors: 0,
+            metrics,
+            container: None,
+        }
+    }
+
+    #[test]
+    fn test_node_metrics_relabelled_and_grouped() {
+        let mut snapshot = StatsSnapshot::default();
+        snapshot.nodes.insert(
+            "da".to_string(),
+            node(
+                "eigenda",
+                vec![
+                    metric("up", 1.0, &[]),
+                    metric("requests", 3.0, &[("avs_type", "upstream"), ("path", "/")]),
+                ],
+            ),
+        );
+        snapshot.nodes.insert("mach".to_string(), node("altlayer", vec![metric("up", 0.0, &[])]));
+
+        let out = render(&snapshot, None);
+        assert!(out.contains(
+            "requests{exported_avs_type=\"upstream\",path=\"/\",assigned_name=\"da\",avs_type=\"eigenda\"} 3\n"
+        ));
+        assert!(out.contains(
+            "# TYPE up untyped\nup{assigned_name=\"da\",avs_type=\"eigenda\"} 1\nup{assigned_name=\"mach\",avs_type=\"altlayer\"} 0\n"
+        ));
+    }
+
+    #[test]
+    fn test_node_metric_types() {
+        let histogram = |name, value| typed_metric(name, MetricType::Histogram, value, &[]);
+        let mut snapshot = StatsSnapshot::default();
+        snapshot.nodes.insert(
+            "da".to_string(),
+            node(
+                "eigenda",
+                vec![
+                    typed_metric("requests_total", MetricType::Counter, 3.0, &[]),
+                    typed_metric("requests_created", MetricType::Counter, 1.0, &[]),
+                    histogram("latency_bucket", 1.0),
+                    histogram("latency_sum", 0.5),
+                    histogram("latency_count", 1.0),
+                    typed_metric("build_info", MetricType::Info, 1.0, &[]),
+                ],
+            ),
+        );
+
+        let out = render(&snapshot, None);
+        let labels = "{assigned_name=\"da\",avs_type=\"eigenda\"}";
+        assert!(out.contains(&format!("# TYPE requests_total counter\nrequests_total{labels} 3\n")));
+        assert!(out.contains("# TYPE requests_created untyped\n"));
+        assert!(out.contains(&format!(
+            "# TYPE latency histogram\nlatency_bucket{labels} 1\nlatency_sum{labels} 0.5\nlatency_count{labels} 1\n"
+        )));
+        assert!(out.contains("# TYPE build_info untyped\n"));
+    }
+
+    #[test]
+    fn test_container_usage_per_node() {
+        let mut snapshot = StatsSnapshot::default();
+        let mut da = node("eigenda", vec![]);
+        da.container = Some(ContainerUsage {
+            stats: ContainerStats { cpu_percent: 12.5, memory_usage: 2048, ..Default::default() },
+            restart_count: 3,
+        });
+        snapshot.nodes.insert("da".to_string(), da);
+        snapshot.nodes.insert("stopped".to_string(), node("altlayer", vec![]));
+
+        let out = render(&snapshot, None);
+        assert!(out.contains(
+            "# TYPE ivynet_container_cpu_percent gauge\nivynet_container_cpu_percent{assigned_name=\"da\",avs_type=\"eigenda\"} 12.5\n"
+        ));
+        assert!(out.contains(
+            "ivynet_container_memory_usage_bytes{assigned_name=\"da\",avs_type=\"eigenda\"} 2048\n"
+        ));
+        assert!(out.contains(
+            "# TYPE ivynet_container_restarts_total counter\nivynet_container_restarts_total{assigned_name=\"da\",avs_type=\"eigenda\"} 3\n"
+        ));
+        assert!(!out.contains("ivynet_container_cpu_percent{assigned_name=\"stopped\""));
+    }
+
+    #[test]
+    fn test_label_values_escaped() {
+        let labels = vec![("msg".to_string(), "a \"b\"\\c\nd".to_string())];
+        assert_eq!(sample("m", &labels, 1.5), "m{msg=\"a \\\"b\\\"\\\\c\\nd\"} 1.5\n");
+    }
+
+    #[test]
+    fn test_special_values() {
+        assert_eq!(format_value(f64::NAN), "NaN");
+        assert_eq!(format_value(f64::INFINITY), "+Inf");
+        assert_eq!(format_value(f64::NEG_INFINITY), "-Inf");
+        assert_eq!(format_value(0.25), "0.25");
+    }
+
+    #[test]
+    fn test_daemon_health() {
+        let snapshot = StatsSnapshot {
+            nodes: BTreeMap::new(),
+            dispatch: DispatchStats {
+                last_success: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(42)),
+                successes: 7,
+                errors: 2,
+                spool_depth: 3,
+                spool_bytes: 1024,
+                spool_oldest_age: Some(Duration::from_secs(90)),
+            },
+        };
+
+        let out = render(&snapshot, None);
+        assert!(
+            out.contains("# TYPE ivynet_daemon_spool_depth gauge\nivynet_daemon_spool_depth 3\n")
+        );
+        assert!(out.contains("ivynet_daemon_spool_bytes 1024\n"));
+        assert!(out.contains("ivynet_daemon_spool_oldest_age_seconds 90\n"));
+        assert!(out.contains("ivynet_daemon_last_successful_dispatch_timestamp_seconds 42\n"));
+        assert!(out.contains("ivynet_daemon_dispatches_total 7\n"));
+        assert!(out.contains("ivynet_daemon_dispatch_errors_total 2\n"));
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+
+use ivynet_docker::{
+    container::{Container, ContainerId, ContainerImage},
+    dockerapi::DockerApi,
+};
+use tracing::warn;
+
+use super::{ConfiguredAvs, NodeRuntime};
+
+/// Node type of the container, in the same format as `avs_type` in the monitor config. Containers
+/// carrying this label are monitored without going through `ivy scan`.
+pub const NODE_TYPE_LABEL: &str = "ivynet.node_type";
+/// Assigned name of the node. Defaults to the container name.
+pub const NAME_LABEL: &str = "ivynet.name";
+/// Port the node serves metrics on. Probed from the container's ports when missing.
+pub const METRICS_PORT_LABEL: &str = "ivynet.metrics_port";
+/// Path of the metrics endpoint. Defaults to `/metrics`.
+pub const METRICS_PATH_LABEL: &str = "ivynet.metrics_path";
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum LabelError {
+    #[error("Label {NODE_TYPE_LABEL} is empty")]
+    EmptyNodeType,
+
+    #[error("Label {NAME_LABEL} is empty")]
+    EmptyName,
+
+    #[error("Label {METRICS_PORT_LABEL} is not a valid port: {0}")]
+    InvalidMetricsPort(String),
+
+    #[error("Label {METRICS_PATH_LABEL} must be an absolute path: {0}")]
+    InvalidMetricsPath(String),
+}
+
+/// Monitoring declared on a container through `ivynet.*` labels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeLabels {
+    pub node_type: String,
+    pub name: Option<String>,
+    pub metrics_port: Option<u16>,
+    pub metrics_path: Option<String>,
+}
+
+impl NodeLabels {
+    /// Reads the node labels of a container. Returns `None` for containers without a node type
+    /// label, which are left to `ivy scan`.
+    pub fn parse(labels: &HashMap<String, String>) -> Result<Option<Self>, LabelError> {
+        let Some(node_type) = labels.get(NODE_TYPE_LABEL) else {
+            return Ok(None);
+        };
+        let node_type = node_type.trim();
+        if node_type.is_empty() {
+            return Err(LabelError::EmptyNodeType);
+        }
+
+        let name = match labels.get(NAME_LABEL).map(|name| name.trim()) {
+            Some("") => return Err(LabelError::EmptyName),
+            name => name.map(str::to_string),
+        };
+        let metrics_port = labels
+            .get(METRICS_PORT_LABEL)
+            .map(|port| {
+                port.trim()
+                    .parse::<u16>()
+                    .ok()
+                    .filter(|port| *port != 0)
+                    .ok_or_else(|| LabelError::InvalidMetricsPort(port.clone()))
+            })
+            .transpose()?;
+        let metrics_path = labels
+            .get(METRICS_PATH_LABEL)
+            .map(|path| match path.trim() {
+                path if path.starts_with('/') => Ok(path.to_string()),
+                _ => Err(LabelError::InvalidMetricsPath(path.clone())),
+            })
+            .transpose()?;
+
+        Ok(Some(Self { node_type: node_type.to_string(), name, metrics_port, metrics_path }))
+    }
+
+    pub fn into_configured(
+        self,
+        container_name: &str,
+        image: Option<ContainerImage>,
+        manifest: Option<ContainerId>,
+    ) -> ConfiguredAvs {
+        ConfiguredAvs {
+            assigned_name: self.name.unwrap_or_else(|| container_name.to_string()),
+            container_name: container_name.to_string(),
+            avs_type: self.node_type,
+            metric_port: self.metrics_port,
+            metrics_path: self.metrics_path,
+            manifest,
+            image,
+            runtime: NodeRuntime::Docker,
+        }
+    }
+}
+
+/// Builds the node configuration a container declares through its labels. Containers with invalid
+/// labels are skipped with a warning.
+pub async fn labelled_node(
+    docker: &impl DockerApi,
+    container: &Container,
+) -> Option<ConfiguredAvs> {
+    let container_name = container.names()?.first()?.clone();
+    let labels = match NodeLabels::parse(container.labels()?) {
+        Ok(labels) => labels?,
+        Err(e) => {
+            warn!("Ignoring labels of container {}: {}", container_name, e);
+            return None;
+        }
+    };
+
+    let image = container.image().map(ContainerImage::from);
+    let manifest = container
+        .repo_digest(&docker.inner())
+        .await
+        .map(|digest| ContainerId::from(digest.as_str()));
+    Some(labels.into_configured(&container_name, image, manifest))
+}
+
+/// All running containers that declare a node through their labels.
+pub async fn labelled_nodes(docker: &impl DockerApi) -> Vec<ConfiguredAvs> {
+    let mut nodes = Vec::new();
+    for container in docker.list_containers().await {
+        if let Some(node) = labelled_node(docker, &container).await {
+            nodes.push(node);
+        }
+    }
+    nodes
+}
+
+#[cfg(test)]
+mod labels_tests {
+    use super::*;
+
+    fn labels(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_parse_node_labels() {
+        let parsed = NodeLabels::parse(&labels(&[
+            (NODE_TYPE_LABEL, "EigenDA"),
+            (NAME_LABEL, "da-holesky"),
+            (METRICS_PORT_LABEL, "9092"),
+            (METRICS_PATH_LABEL, "/debug/metrics"),
+            ("com.docker.compose.service", "da-node"),
+        ]))
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            parsed,
+            NodeLabels {
+                node_type: "EigenDA".to_string(),
+                name: Some("da-holesky".to_string()),
+                metrics_port: Some(9092),
+                metrics_path: Some("/debug/metrics".to_string()),
+            }
+        );
+
+        let node = parsed.into_configured("eigenda-native-node", None, None);
+        assert_eq!(node.assigned_name, "da-holesky");
+        assert_eq!(node.metrics_path(), "/debug/metrics");
+    }
+
+    #[test]
+    fn test_unlabelled_and_defaults() {
+        assert_eq!(NodeLabels::parse(&labels(&[("maintainer", "ivynet")])), Ok(None));
+
+        let node = NodeLabels::parse(&labels(&[(NODE_TYPE_LABEL, "EigenDA")]))
+            .unwrap()
+            .unwrap()
+            .into_configured("eigenda-native-node", None, None);
+        assert_eq!(node.assigned_name, "eigenda-native-node");
+        assert_eq!(node.metric_port, None);
+        assert_eq!(node.metrics_path(), "/metrics");
+    }
+
+    #[test]
+    fn test_invalid_labels() {
+        assert_eq!(
+            NodeLabels::parse(&labels(&[(NODE_TYPE_LABEL, " ")])),
+            Err(LabelError::EmptyNodeType)
+        );
+        assert_eq!(
+            NodeLabels::parse(&labels(&[(NODE_TYPE_LABEL, "EigenDA"), (METRICS_PORT_LABEL, "0")])),
+            Err(LabelError::InvalidMetricsPort("0".to_string()))
+        );
+        assert_eq!(
+            NodeLabels::parse(&labels(&[
+                (NODE_TYPE_LABEL, "EigenDA"),
+                (METRICS_PATH_LABEL, "metrics")
+            ])),
+            Err(LabelError::InvalidMetricsPath("metrics".to_string()))
+        );
+    }
+}
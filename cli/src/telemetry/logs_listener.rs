@@ -1,22 +1,40 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    process::Stdio,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use bollard::container::LogOutput;
+use futures::stream::{self, BoxStream};
 use ivynet_docker::{container::Container, dockerapi::DockerClient};
 use ivynet_signer::sign_utils::IvySigningError;
 use kameo::{actor::ActorRef, Actor};
-use tokio::{task::JoinSet, time};
+use linemux::MuxedLines;
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    process::Command,
+    task::{AbortHandle, JoinSet},
+    time,
+};
 use tokio_stream::StreamExt;
 use tracing::{error, info};
 
 use crate::{
     ivy_machine::{IvyMachine, MachineIdentityError},
-    telemetry::{dispatch::TelemetryDispatchHandle, ConfiguredAvs},
+    telemetry::{dispatch::TelemetryDispatchHandle, ConfiguredAvs, NodeRuntime},
 };
 
 use super::dispatch::TelemetryMsg;
 
 type LogListenerResult = Result<ListenerData, LogListenerError>;
 
+/// A batch is sent as soon as it reaches either size limit, or when the flush interval elapses,
+/// whichever comes first.
+const LOG_BATCH_MAX_LINES: usize = 500;
+const LOG_BATCH_MAX_BYTES: usize = 256 * 1024;
+const LOG_BATCH_FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
 /// Manager for a set of LogsListeners. This will spawn and manage the underlying listeners as
 /// futures, and is made accessible via the `LogsListenerHandle`.
 #[derive(Debug)]
@@ -25,6 +43,8 @@ pub struct LogsListenerManager {
     machine: Arc<IvyMachine>,
     dispatcher: TelemetryDispatchHandle,
     listener_set: JoinSet<LogListenerResult>,
+    /// Abort handles of the spawned listeners, keyed by container or unit name
+    listeners: HashMap<String, AbortHandle>,
 }
 
 impl LogsListenerManager {
@@ -38,6 +58,7 @@ impl LogsListenerManager {
             machine: machine.clone(),
             dispatcher: dispatcher.clone(),
             listener_set: JoinSet::new(),
+            listeners: HashMap::new(),
         }
     }
 
@@ -50,13 +71,36 @@ impl LogsListenerManager {
         node_data: &ConfiguredAvs,
     ) -> Result<(), LogListenerError> {
         let listener_data = ListenerData {
-            container: container.clone(),
+            source: LogSource::Container(Box::new(container.clone())),
             node_data: node_data.clone(),
             machine: self.machine.clone(),
         };
         self.add_listener_from_data(&listener_data).await
     }
 
+    /// Add a listener for a node running under systemd, reading from its log file if one is
+    /// configured and from journald otherwise.
+    pub async fn add_systemd_listener(
+        &mut self,
+        node_data: &ConfiguredAvs,
+    ) -> Result<(), LogListenerError> {
+        let source = match &node_data.runtime {
+            NodeRuntime::Systemd { log_file: Some(path), .. } => LogSource::File(path.clone()),
+            NodeRuntime::Systemd { log_file: None, .. } => {
+                LogSource::Journald(node_data.container_name.clone())
+            }
+            NodeRuntime::Docker => {
+                return Err(LogListenerError::LogListenerError(format!(
+                    "{} is not a systemd node",
+                    node_data.assigned_name
+                )))
+            }
+        };
+        let listener_data =
+            ListenerData { source, node_data: node_data.clone(), machine: self.machine.clone() };
+        self.add_listener_from_data(&listener_data).await
+    }
+
     pub async fn add_listener_from_data(
         &mut self,
         data: &ListenerData,
@@ -64,10 +108,54 @@ impl LogsListenerManager {
         // TODO: Have not rely on ConfiguredAvs
         let listener =
             LogsListener::new(self.docker.clone(), self.dispatcher.clone(), data.clone());
-        self.listener_set.spawn(async move { listener_fut(listener).await });
+        let handle = self.listener_set.spawn(async move { listener_fut(listener).await });
+        self.listeners.insert(data.node_data.container_name.clone(), handle);
         info!("Added log listener for container: {}", data.node_data.container_name);
         Ok(())
     }
+
+    /// Stops the listener of a node that is no longer monitored. Returns whether one was running.
+    pub fn remove_listener(&mut self, container_name: &str) -> bool {
+        // Reap listeners whose streams have since closed
+        while self.listener_set.try_join_next().is_some() {}
+
+        match self.listeners.remove(container_name) {
+            Some(handle) if !handle.is_finished() => {
+                handle.abort();
+                info!("Removed log listener for container: {}", container_name);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Sends the logs a container wrote from `since` (unix seconds) until now again, e.g. to fill
+    /// a gap in what the backend received. Returns the number of lines sent.
+    pub async fn backfill(
+        &self,
+        container: &Container,
+        node_data: &ConfiguredAvs,
+        since: i64,
+    ) -> Result<usize, LogListenerError> {
+        let until = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let logs = container.logs_between(&self.docker, since, until as i64);
+        tokio::pin!(logs);
+
+        let mut batch = LogBatch::default();
+        let mut sent = 0;
+        while let Some(log) = logs.next().await {
+            batch.push(log?.to_string());
+            if batch.is_full() {
+                sent += batch.lines.len();
+                flush_batch(&self.machine, &self.dispatcher, &node_data.assigned_name, &mut batch)
+                    .await?;
+            }
+        }
+        sent += batch.lines.len();
+        flush_batch(&self.machine, &self.dispatcher, &node_data.assigned_name, &mut batch).await?;
+        info!("Backfilled {} log lines of {}", sent, node_data.assigned_name);
+        Ok(sent)
+    }
 }
 
 /// An individual instance of a LogListener, which listens to the logs of a single container and
@@ -92,11 +180,20 @@ impl Actor for LogsListener {
 
 #[derive(Debug, Clone)]
 pub struct ListenerData {
-    pub container: Container,
+    pub source: LogSource,
     pub node_data: ConfiguredAvs,
     pub machine: Arc<IvyMachine>,
 }
 
+/// Where a listener reads its node's log lines from.
+#[derive(Debug, Clone)]
+pub enum LogSource {
+    Container(Box<Container>),
+    /// Systemd unit name, followed through `journalctl`.
+    Journald(String),
+    File(PathBuf),
+}
+
 impl LogsListener {
     pub fn new(
         docker: DockerClient,
@@ -108,38 +205,132 @@ impl LogsListener {
 
     async fn try_listen(&self) -> Result<(), LogListenerError> {
         time::sleep(Duration::from_secs(10)).await;
-        let mut stream = self.listener_data.container.stream_logs_latest(&self.docker);
+        let mut stream = self.log_stream().await?;
+        let mut batch = LogBatch::default();
+        let mut flush_interval = time::interval(LOG_BATCH_FLUSH_INTERVAL);
 
-        while let Some(log_result) = stream.next().await {
-            match log_result {
-                Ok(log) => {
-                    self.handle_log(log).await?;
-                }
-                Err(e) => {
-                    // error!("{}", format!("Log read error | {} | : {}", self.container.image(),
-                    // e));
-                    return Err(LogListenerError::DockerError(e));
+        loop {
+            tokio::select! {
+                log_result = stream.next() => match log_result {
+                    Some(Ok(log)) => {
+                        batch.push(log);
+                        if batch.is_full() {
+                            self.flush(&mut batch).await?;
+                        }
+                    }
+                    Some(Err(e)) => {
+                        self.flush(&mut batch).await?;
+                        return Err(e);
+                    }
+                    None => break,
+                },
+                _ = flush_interval.tick() => {
+                    self.flush(&mut batch).await?;
                 }
             }
         }
+
+        self.flush(&mut batch).await?;
         info!("Log stream closed for container: {}", self.listener_data.node_data.container_name);
         Ok(())
     }
 
-    async fn handle_log(&self, log: LogOutput) -> Result<(), LogListenerError> {
-        // println!("log: {:#?}", log);
-        let log = log.to_string();
-        let signed = self
-            .listener_data
-            .machine
-            .sign_log(&self.listener_data.node_data.assigned_name, &log)?;
-        match self.dispatcher.tell(TelemetryMsg::Log(signed)).await {
-            Ok(_) => {}
-            Err(e) => {
-                error!("Failed to send or save log: {} | With log: {}", e, &log);
+    /// Opens the node's log source as a stream of lines, starting from the current end.
+    async fn log_stream(
+        &self,
+    ) -> Result<BoxStream<'static, Result<String, LogListenerError>>, LogListenerError> {
+        match &self.listener_data.source {
+            LogSource::Container(container) => Ok(Box::pin(
+                container
+                    .stream_logs_latest(&self.docker)
+                    .map(|log| log.map(|log| log.to_string()).map_err(Into::into)),
+            )),
+            LogSource::Journald(unit) => {
+                let mut child = Command::new("journalctl")
+                    .args(["--unit", unit, "--follow", "--lines=0", "--output=cat"])
+                    .stdout(Stdio::piped())
+                    .kill_on_drop(true)
+                    .spawn()?;
+                let stdout = child.stdout.take().ok_or_else(|| {
+                    LogListenerError::LogListenerError("journalctl has no stdout".to_string())
+                })?;
+                let lines = BufReader::new(stdout).lines();
+                // The child is carried along so it lives, and is killed, with the stream
+                Ok(Box::pin(stream::unfold((lines, child), |(mut lines, child)| async move {
+                    match lines.next_line().await {
+                        Ok(Some(line)) => Some((Ok(line), (lines, child))),
+                        Ok(None) => None,
+                        Err(e) => Some((Err(e.into()), (lines, child))),
+                    }
+                })))
             }
-        };
-        Ok(())
+            LogSource::File(path) => {
+                let mut lines = MuxedLines::new()?;
+                lines.add_file(path).await?;
+                Ok(Box::pin(stream::unfold(lines, |mut lines| async move {
+                    match lines.next_line().await {
+                        Ok(Some(line)) => Some((Ok(line.line().to_string()), lines)),
+                        Ok(None) => None,
+                        Err(e) => Some((Err(e.into()), lines)),
+                    }
+                })))
+            }
+        }
+    }
+
+    async fn flush(&self, batch: &mut LogBatch) -> Result<(), LogListenerError> {
+        flush_batch(
+            &self.listener_data.machine,
+            &self.dispatcher,
+            &self.listener_data.node_data.assigned_name,
+            batch,
+        )
+        .await
+    }
+}
+
+/// Signs the buffered lines as a single batch and hands it to the dispatcher.
+async fn flush_batch(
+    machine: &IvyMachine,
+    dispatcher: &TelemetryDispatchHandle,
+    assigned_name: &str,
+    batch: &mut LogBatch,
+) -> Result<(), LogListenerError> {
+    if batch.is_empty() {
+        return Ok(());
+    }
+    let lines = batch.take();
+    let signed = machine.sign_log_batch(assigned_name, &lines)?;
+    if let Err(e) = dispatcher.tell(TelemetryMsg::LogBatch(signed)).await {
+        error!("Failed to send or save batch of {} log lines: {}", lines.len(), e);
+    };
+    Ok(())
+}
+
+/// Log lines buffered by a listener until they are sent as one batch.
+#[derive(Debug, Default)]
+struct LogBatch {
+    lines: Vec<String>,
+    bytes: usize,
+}
+
+impl LogBatch {
+    fn push(&mut self, line: String) {
+        self.bytes += line.len();
+        self.lines.push(line);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    fn is_full(&self) -> bool {
+        self.lines.len() >= LOG_BATCH_MAX_LINES || self.bytes >= LOG_BATCH_MAX_BYTES
+    }
+
+    fn take(&mut self) -> Vec<String> {
+        self.bytes = 0;
+        std::mem::take(&mut self.lines)
     }
 }
 
@@ -147,6 +338,8 @@ impl LogsListener {
 pub enum LogListenerError {
     #[error("Docker API error: {0}")]
     DockerError(#[from] bollard::errors::Error),
+    #[error("Log source IO error: {0}")]
+    IoError(#[from] std::io::Error),
     #[error("LogListener error: {0}")]
     LogListenerError(String),
     #[error("Signature error: {0}")]
@@ -166,3 +359,33 @@ async fn listener_fut(listener: LogsListener) -> Result<ListenerData, LogListene
     }
     Ok(listener.listener_data)
 }
+
+#[cfg(test)]
+mod log_batch_tests {
+    use super::*;
+
+    #[test]
+    fn test_log_batch_full_by_lines() {
+        let mut batch = LogBatch::default();
+        for _ in 0..LOG_BATCH_MAX_LINES - 1 {
+            batch.push("line".to_string());
+        }
+        assert!(!batch.is_full());
+        batch.push("line".to_string());
+        assert!(batch.is_full());
+
+        let lines = batch.take();
+        assert_eq!(lines.len(), LOG_BATCH_MAX_LINES);
+        assert!(batch.is_empty());
+        assert!(!batch.is_full());
+    }
+
+    #[test]
+    fn test_log_batch_full_by_bytes() {
+        let mut batch = LogBatch::default();
+        batch.push("a".repeat(LOG_BATCH_MAX_BYTES - 1));
+        assert!(!batch.is_full());
+        batch.push("a".to_string());
+        assert!(batch.is_full());
+    }
+}
@@ -12,8 +12,10 @@ use crate::{
 };
 
 use super::{
+    container_stats_listener::container_metrics,
     dispatch::{TelemetryDispatchError, TelemetryDispatchHandle},
     parser::TelemetryParser,
+    stats::TelemetryStats,
     ConfiguredAvs, ErrorChannelTx,
 };
 
@@ -31,9 +33,11 @@ impl MetricsListenerHandle {
         machine: IvyMachine,
         avses: &[ConfiguredAvs],
         dispatch: &TelemetryDispatchHandle,
+        stats: TelemetryStats,
         error_tx: ErrorChannelTx,
     ) -> Self {
-        let listener = MetricsListener::new(machine, avses.to_vec(), dispatch.clone(), error_tx);
+        let listener =
+            MetricsListener::new(machine, avses.to_vec(), dispatch.clone(), stats, error_tx);
         let actor = kameo::actor::spawn(listener);
         Self { actor }
     }
@@ -104,6 +108,7 @@ impl Message<MetricsMsg> for MetricsListener {
                 self.avses.retain(|x| x.container_name != configured_avs.container_name);
                 if avs_num != self.avses.len() {
                     info!("Detected container stop: {}", configured_avs.container_name);
+                    self.stats.remove_node(&configured_avs.assigned_name);
                 } else {
                     // Return early if no nodes were dropped due to an earlier removal.
                     // This will frequently happen on a docker down action, as the event
@@ -113,8 +118,15 @@ impl Message<MetricsMsg> for MetricsListener {
                 }
             }
             MetricsMsg::RemoveNodeByName(container_name) => {
+                let stats = &self.stats;
                 let avs_num = self.avses.len();
-                self.avses.retain(|x| x.container_name != container_name);
+                self.avses.retain(|x| {
+                    let keep = x.container_name != container_name;
+                    if !keep {
+                        stats.remove_node(&x.assigned_name);
+                    }
+                    keep
+                });
                 if avs_num != self.avses.len() {
                     info!("Detected container stop: {}", container_name);
                 } else {
@@ -193,6 +205,7 @@ pub struct MetricsListener {
     machine: IvyMachine,
     avses: Vec<ConfiguredAvs>,
     dispatch: TelemetryDispatchHandle,
+    stats: TelemetryStats,
     _error_tx: ErrorChannelTx,
     http_client: reqwest::Client,
 }
@@ -202,14 +215,21 @@ impl MetricsListener {
         machine: IvyMachine,
         avses: Vec<ConfiguredAvs>,
         dispatch: TelemetryDispatchHandle,
+        stats: TelemetryStats,
         _error_tx: ErrorChannelTx,
     ) -> Self {
-        Self { machine, avses, dispatch, _error_tx, http_client: reqwest::Client::new() }
+        Self { machine, avses, dispatch, stats, _error_tx, http_client: reqwest::Client::new() }
     }
 
     async fn broadcast_metrics(&mut self) -> Result<(), MetricsListenerError> {
-        report_metrics(&self.machine, self.avses.as_slice(), &self.dispatch, &self.http_client)
-            .await
+        report_metrics(
+            &self.machine,
+            self.avses.as_slice(),
+            &self.dispatch,
+            &self.stats,
+            &self.http_client,
+        )
+        .await
     }
 }
 
@@ -217,17 +237,43 @@ pub async fn report_metrics(
     machine: &IvyMachine,
     avses: &[ConfiguredAvs],
     dispatch: &TelemetryDispatchHandle,
+    stats: &TelemetryStats,
     http_client: &reqwest::Client,
 ) -> Result<(), MetricsListenerError> {
     for avs in avses {
-        if let Some(port) = avs.metric_port {
-            let metrics: Vec<Metrics> =
-                fetch_telemetry_from(http_client, &avs.container_name, port)
-                    .await
-                    .unwrap_or_default();
-
-            let signed_metrics = machine.sign_metrics(Some(avs.assigned_name.clone()), &metrics)?;
-            dispatch.tell(TelemetryMsg::Metrics(signed_metrics)).await?;
+        let usage = stats.container_usage(&avs.assigned_name);
+        if avs.metric_port.is_none() && usage.is_none() {
+            continue;
+        }
+
+        let mut metrics: Vec<Metrics> = match avs.metric_port {
+            Some(port) => {
+                let path = avs.metrics_path();
+                match fetch_telemetry_from(http_client, &avs.container_name, port, path).await {
+                    Ok(metrics) => {
+                        stats.record_scrape(avs, &metrics);
+                        metrics
+                    }
+                    Err(e) => {
+                        debug!("{}", e);
+                        stats.record_scrape_error(avs);
+                        Vec::new()
+                    }
+                }
+            }
+            None => Vec::new(),
+        };
+        if let Some(usage) = usage {
+            metrics.extend(container_metrics(&usage).into_iter().map(Metrics::from));
+        }
+
+        let signed_metrics = machine.sign_metrics(Some(avs.assigned_name.clone()), &metrics)?;
+        match dispatch.tell(TelemetryMsg::Metrics(signed_metrics)).await {
+            Ok(()) => stats.record_node_dispatch(avs),
+            Err(e) => {
+                stats.record_node_dispatch_error(avs);
+                return Err(e.into());
+            }
         }
     }
     Ok(())
@@ -237,11 +283,12 @@ pub async fn fetch_telemetry_from(
     client: &Client,
     container_name: &str,
     port: u16,
+    path: &str,
 ) -> Result<Vec<Metrics>, MetricsListenerError> {
     const TIMEOUT_SECS: u64 = 10;
 
     let resp = client
-        .get(format!("http://localhost:{}/metrics", port))
+        .get(format!("http://localhost:{}{}", port, path))
         .timeout(Duration::from_secs(TIMEOUT_SECS))
         .send()
         .await
@@ -266,26 +313,11 @@ pub async fn fetch_telemetry_from(
         }
     })?;
 
-    let mut metrics = Vec::new();
-    for (line_number, line) in body.lines().enumerate() {
-        let trimmed = line.trim();
-        // Skip empty lines and Prometheus metadata/comment lines
-        if trimmed.is_empty() || trimmed.starts_with("# ") {
-            continue;
-        }
-
-        match TelemetryParser::new(line).parse() {
-            Some(metric) => metrics.push(metric),
-            None => {
-                debug!(
-                    "Failed to parse metric at line {}: '{}' for container {}",
-                    line_number + 1,
-                    line,
-                    container_name
-                );
-            }
-        }
+    let exposition = TelemetryParser::new(&body).parse_exposition();
+    for error in &exposition.errors {
+        debug!("Failed to parse metrics for container {}: {}", container_name, error);
     }
+    let metrics = exposition.into_metrics();
 
     if metrics.is_empty() {
         Err(MetricsListenerError::FetchError {
@@ -1,34 +1,59 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, net::SocketAddr, path::PathBuf, sync::Arc};
 
+use capabilities::Capabilities;
+use container_stats_listener::ContainerStatsManager;
 use convert_case::{Case, Casing};
-use dispatch::{TelemetryDispatchError, TelemetryDispatchHandle};
+use dispatch::{TelemetryDispatchError, TelemetryDispatchHandle, TelemetryMsg};
 use docker_event_stream_listener::DockerStreamListener;
 use ivynet_docker::{
     container::{Container, ContainerId, ContainerImage},
     dockerapi::{DockerApi, DockerClient},
 };
 use ivynet_grpc::{
-    backend::backend_client::BackendClient, messages::NodeDataV2, tonic::transport::Channel,
-    BackendClientMiddleware, BackendMiddleware,
+    backend::backend_client::BackendClient, heartbeat::heartbeat_client::HeartbeatClient,
+    messages::NodeDataV2, tonic::transport::Channel,
 };
 use logs_listener::LogsListenerManager;
-use machine_data_listener::MachineDataMonitorHandle;
 use metrics_listener::MetricsListenerHandle;
-use node_data_listener::NodeDataMonitorHandle;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use spool::{TelemetrySpool, DEFAULT_SPOOL_MAX_BYTES};
+use stats::TelemetryStats;
+use stream::CommandGate;
 use tokio::sync::broadcast;
 use tracing::{error, info, warn};
 
-use crate::{error::Error, ivy_machine::IvyMachine};
+use crate::{
+    config::DEFAULT_CONFIG_PATH,
+    control,
+    error::Error,
+    ivy_machine::IvyMachine,
+    monitor::MonitorConfig,
+    systemd::{binary_digest, SystemdClient},
+};
 
+pub mod capabilities;
+pub mod container_stats_listener;
 pub mod dispatch;
 pub mod docker_event_stream_listener;
+pub mod exporter;
+pub mod labels;
 pub mod logs_listener;
-pub mod machine_data_listener;
 pub mod metrics_listener;
-pub mod node_data_listener;
 pub mod parser;
+pub mod session;
+pub mod spool;
+pub mod stats;
+pub mod stream;
+
+const TELEMETRY_SPOOL_DIR: &str = "spool";
+
+/// Control requests waiting for the docker stream listener.
+const CONTROL_CHANNEL_CAPACITY: usize = 16;
+/// Backend commands waiting for the docker stream listener.
+const COMMAND_CHANNEL_CAPACITY: usize = 16;
+
+pub const DEFAULT_METRICS_PATH: &str = "/metrics";
 
 pub type ErrorChannelTx = broadcast::Sender<TelemetryError>;
 pub type ErrorChannelRx = broadcast::Receiver<TelemetryError>;
@@ -54,16 +79,46 @@ pub struct ConfiguredAvs {
     pub container_name: String,
     pub avs_type: String,
     pub metric_port: Option<u16>,
+    /// HTTP path of the metrics endpoint, when it is not served on `/metrics`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metrics_path: Option<String>,
     pub manifest: Option<ContainerId>,
     pub image: Option<ContainerImage>,
+    #[serde(skip_serializing_if = "NodeRuntime::is_docker")]
+    pub runtime: NodeRuntime,
+}
+
+/// How a configured node is run, which decides where its liveness, manifest and logs come from.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, Hash, Eq, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum NodeRuntime {
+    #[default]
+    Docker,
+    /// A native binary managed by systemd. The node's `container_name` holds the unit name.
+    Systemd {
+        binary: PathBuf,
+        /// Logs are tailed from this file when set, otherwise they are read from journald.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        log_file: Option<PathBuf>,
+    },
+}
+
+impl NodeRuntime {
+    pub fn is_docker(&self) -> bool {
+        matches!(self, NodeRuntime::Docker)
+    }
 }
 
 impl ConfiguredAvs {
+    pub fn metrics_path(&self) -> &str {
+        self.metrics_path.as_deref().unwrap_or(DEFAULT_METRICS_PATH)
+    }
+
     pub async fn metrics_alive(&self) -> bool {
         if self.metric_port.is_some() {
             let client = Client::new();
             let metrics_endpoint =
-                format!("http://localhost:{}/metrics", self.metric_port.unwrap());
+                format!("http://localhost:{}{}", self.metric_port.unwrap(), self.metrics_path());
             client.get(&metrics_endpoint).send().await.is_ok()
         } else {
             false
@@ -71,8 +126,18 @@ impl ConfiguredAvs {
     }
 
     pub async fn node_running(&self) -> bool {
-        let docker = DockerClient::default();
-        docker.find_container_by_name(&self.container_name).await.is_some()
+        match self.runtime {
+            NodeRuntime::Docker => {
+                let docker = DockerClient::default();
+                docker.find_container_by_name(&self.container_name).await.is_some()
+            }
+            NodeRuntime::Systemd { .. } => {
+                let unit = self.container_name.clone();
+                tokio::task::spawn_blocking(move || SystemdClient::default().find_unit(&unit))
+                    .await
+                    .is_ok_and(|process| process.is_some())
+            }
+        }
     }
 }
 
@@ -94,9 +159,13 @@ impl<'de> Deserialize<'de> for ConfiguredAvs {
             container_name: String,
             #[serde(default)]
             metric_port: Option<u16>,
+            #[serde(default)]
+            metrics_path: Option<String>,
             avs_type: AvsTypeField,
             image: Option<ContainerImage>,
             manifest: Option<ContainerId>,
+            #[serde(default)]
+            runtime: NodeRuntime,
         }
 
         let helper = Helper::deserialize(deserializer)?;
@@ -124,8 +193,10 @@ impl<'de> Deserialize<'de> for ConfiguredAvs {
             container_name: helper.container_name.trim_start_matches('/').to_string(),
             avs_type,
             metric_port: helper.metric_port,
+            metrics_path: helper.metrics_path,
             manifest: helper.manifest,
             image: helper.image,
+            runtime: helper.runtime,
         })
     }
 }
@@ -140,7 +211,12 @@ impl<'de> Deserialize<'de> for ConfiguredAvs {
  * 1. Dispatcher: The dispatcher is responsible for receiving telemetry data from the various
  *    other listeners via a tokio mpsc channel and sending it to the backend. It is the central
  *    hub for telemetry data transmission. Interface is accessible via the
- *    TelemetryDispatchHandle.
+ *    TelemetryDispatchHandle. Messages that cannot be delivered are written to an on-disk spool
+ *    under ~/.ivynet/spool and replayed in order, with backoff, once the backend is reachable
+ *    again. Messages are sent over a long-lived telemetry stream when the backend offers one,
+ *    and otherwise in a telemetry session, which spares the backend from verifying the
+ *    signature of every message. Commands the backend pushes over the stream are checked by a
+ *    CommandGate and forwarded to the docker stream listener.
  *
  * 2. Logs Listener: The logs listener is responsible for listening to logs from containers and
  *    sending them to the dispatcher. It is composed of a LogsListenerManager and a set of
@@ -149,50 +225,160 @@ impl<'de> Deserialize<'de> for ConfiguredAvs {
  *    a single container and sending them to the dispatcher. If a given LogsListener receives a
  *    signal that the docker log stream is closed, it shuts down and is removed from the managed
  *    list. The LogsListenerManager serves as the handle for the interior set of all logs
- *    listeners.
+ *    listeners. Nodes running under systemd are read from their log file or from journald.
  *
  * 3. Metrics Listener: The metrics listener is responsible for listening to metrics from
  *    containers and sending them to the dispatcher. It receives an initial set of configured
  *    nodes and sends metrics for all containers in its set to the dispatcher at fixed
  *    intervals. Additionally, its list of nodes may be managed via the MetricsListenerHandle
  *    interface, and it will transmit metrics for all nodes in its set after each update in
- *    addition to the fixed interval.
+ *    addition to the fixed interval. Resource usage of each docker node's container, followed
+ *    through the docker stats API by the ContainerStatsManager, is reported alongside the
+ *    node's own metrics.
  *
  * 4. Docker Stream Listener: The docker stream listener is responsible for listening to docker
  *    stream events and sending them to the other listeners for processing. It has no associated
- *    handle and is spawned as a future in the listen function.
+ *    handle and is spawned as a future in the listen function. It also owns the set of monitored
+ *    nodes, and applies node changes requested through the control socket and commands from the
+ *    backend. Heartbeats of the daemon, the machine and its running nodes are sent from its loop
+ *    through the dispatcher at a fixed interval.
+ *
+ * 5. Metrics Exporter (optional): When an exporter address is given, a local Prometheus
+ *    `/metrics` endpoint is served from the TelemetryStats shared by the metrics listener and
+ *    the dispatcher. It re-exposes the scraped node metrics alongside machine stats and the
+ *    daemon's own dispatch health.
+ *
+ * 6. Control Socket: A unix socket under ~/.ivynet accepting line-delimited JSON requests from
+ *    `ivy node` and `ivy status`. Node changes are forwarded to the docker stream listener over an
+ *    mpsc channel, and status is put together from its nodes and the TelemetryStats.
  *
  */
 pub async fn listen(
     backend_client: BackendClient<Channel>,
+    heartbeat_client: HeartbeatClient<Channel>,
     machine: IvyMachine,
-    avses: &[ConfiguredAvs],
+    monitor_config: &MonitorConfig,
+    exporter_addr: Option<SocketAddr>,
 ) -> Result<(), Error> {
+    let avses = monitor_config.configured_avses.as_slice();
     let docker = DockerClient::default();
 
-    let (error_tx, error_rx) = tokio::sync::broadcast::channel(64);
+    // Outdated clients are refused here, before they send anything the backend cannot handle
+    let capabilities = Capabilities::negotiate(&mut backend_client.clone()).await?;
+
+    let stats = TelemetryStats::new();
+    if let Some(addr) = exporter_addr {
+        // Bind up front so a bad address fails the daemon instead of a background task
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        info!("Serving local metrics on http://{}/metrics", addr);
+        let stats = stats.clone();
+        tokio::spawn(async move {
+            if let Err(e) = exporter::serve(listener, stats).await {
+                error!("Local metrics exporter stopped: {}", e);
+            }
+        });
+    }
 
-    // Telemtry dispatcher recieves telemetry messages from other listeners and sends them to the
-    // backend
-    let dispatch = TelemetryDispatchHandle::new(backend_client.clone(), error_tx.clone());
+    // Bind up front so a second daemon fails instead of taking over the socket
+    let control_listener = control::bind(&control::default_socket_path())?;
+    let (control_tx, control_rx) = tokio::sync::mpsc::channel(CONTROL_CHANNEL_CAPACITY);
+    {
+        let stats = stats.clone();
+        tokio::spawn(async move {
+            if let Err(e) = control::serve(control_listener, control_tx, stats).await {
+                error!("Control socket stopped: {}", e);
+            }
+        });
+    }
 
-    let backend_middleware = BackendClientMiddleware::new(backend_client.clone());
+    let (error_tx, error_rx) = tokio::sync::broadcast::channel(64);
 
-    let node_data_monitor =
-        NodeDataMonitorHandle::new(backend_middleware.clone(), error_tx.clone());
+    // Messages that fail to reach the backend are persisted here and replayed once it recovers
+    let spool = match TelemetrySpool::open(
+        DEFAULT_CONFIG_PATH.join(TELEMETRY_SPOOL_DIR),
+        DEFAULT_SPOOL_MAX_BYTES,
+    ) {
+        Ok(spool) => {
+            if !spool.is_empty() {
+                info!("Found {} spooled telemetry message(s) to replay", spool.depth());
+            }
+            Some(spool)
+        }
+        Err(e) => {
+            error!(
+                "Failed to open telemetry spool, undeliverable telemetry will be dropped: {}",
+                e
+            );
+            None
+        }
+    };
 
-    let machine_data_monitor = MachineDataMonitorHandle::new(backend_middleware, error_tx.clone());
+    // Commands the backend pushes over the telemetry stream are checked against the allow-list and
+    // carried out by the docker stream listener
+    let (command_tx, command_rx) = tokio::sync::mpsc::channel(COMMAND_CHANNEL_CAPACITY);
+    let (signer_tx, signer_rx) = tokio::sync::mpsc::channel(1);
+    let commands =
+        CommandGate::new(machine.id, monitor_config.remote_commands.clone(), command_tx, signer_tx);
+
+    // Telemtry dispatcher recieves telemetry messages from other listeners and sends them to the
+    // backend
+    let dispatch = TelemetryDispatchHandle::new(
+        backend_client.clone(),
+        heartbeat_client,
+        machine.clone(),
+        &capabilities,
+        commands,
+        error_tx.clone(),
+        spool,
+        stats.clone(),
+    );
 
     // Logs Listener handles logs from containers and sends them to the dispatcher
     let mut logs_listener_handle =
         LogsListenerManager::new(&docker, machine.clone().into(), &dispatch);
 
+    // Container stats manager follows resource usage of docker nodes for the metrics listener
+    let mut container_stats_handle = ContainerStatsManager::new(&docker, stats.clone());
+
     // Metrics Listener handles metrics from containers and sends them to the dispatcher
     let metrics_listener_handle =
-        MetricsListenerHandle::new(machine.clone(), avses, &dispatch, error_tx);
+        MetricsListenerHandle::new(machine.clone(), avses, &dispatch, stats, error_tx);
 
     // On start, send already-configured node data and setup logs listeners
     for node in avses.iter() {
+        if let NodeRuntime::Systemd { binary, .. } = &node.runtime {
+            let running = node.node_running().await;
+            if !running {
+                warn!("Systemd unit for configured node is not running: {}", node.container_name);
+            }
+            let manifest = match binary_digest(binary) {
+                Ok(digest) => Some(digest.to_string()),
+                Err(e) => {
+                    warn!("Cannot hash binary {} for node: {}", binary.display(), e);
+                    node.manifest.as_ref().map(ToString::to_string)
+                }
+            };
+
+            let node_data = NodeDataV2 {
+                name: node.assigned_name.to_string(),
+                node_type: Some(node.avs_type.clone()),
+                manifest,
+                metrics_alive: Some(node.metrics_alive().await),
+                node_running: Some(running),
+            };
+            let signed = machine.sign_node_data_v2(&node_data)?;
+
+            if let Err(e) = dispatch.tell(TelemetryMsg::SignedNodeData(signed)).await {
+                error!("Failed to send node data: {}", e);
+            }
+            if running {
+                if let Err(e) = logs_listener_handle.add_systemd_listener(node).await {
+                    error!("Failed to add logs listener for unit: {}", e);
+                }
+            }
+            continue;
+        }
+
         info!("Searching for node: {}", node.container_name);
         let container: Option<Container> =
             match docker.find_container_by_name(&node.container_name).await {
@@ -242,12 +428,13 @@ pub async fn listen(
             };
             let signed = machine.sign_node_data_v2(&node_data)?;
 
-            if let Err(e) = node_data_monitor.ask_send_node_data(signed).await {
+            if let Err(e) = dispatch.tell(TelemetryMsg::SignedNodeData(signed)).await {
                 error!("Failed to send node data: {}", e);
             }
             if let Err(e) = logs_listener_handle.add_listener(&container, node).await {
                 error!("Failed to add logs listener for container: {}", e);
             };
+            container_stats_handle.add_listener(&container, node);
         } else {
             warn!("Cannot find container for configured node: {}.", node.container_name);
 
@@ -261,7 +448,7 @@ pub async fn listen(
 
             let signed = machine.sign_node_data_v2(&not_running_node_data)?;
 
-            if let Err(e) = node_data_monitor.ask_send_node_data(signed).await {
+            if let Err(e) = dispatch.tell(TelemetryMsg::SignedNodeData(signed)).await {
                 error!("Failed to send node data: {}", e);
             }
 
@@ -272,15 +459,16 @@ pub async fn listen(
     // Stream listener listens for docker events and sends them to the other listeners for
     // processing
     let docker_listener = DockerStreamListener::new(
-        machine_data_monitor,
-        node_data_monitor,
         metrics_listener_handle,
         logs_listener_handle,
+        container_stats_handle,
         dispatch.clone(),
         machine,
         backend_client,
+        monitor_config.port_discovery,
+        monitor_config.auto_discovery.clone(),
     );
-    tokio::spawn(docker_listener.run(avses.to_vec()));
+    tokio::spawn(docker_listener.run(avses.to_vec(), control_rx, command_rx, signer_rx));
 
     // This should never return unless the error channel is closed
     handle_telemetry_errors(error_rx).await?;
@@ -297,7 +485,9 @@ async fn handle_telemetry_errors(mut error_rx: ErrorChannelRx) -> Result<(), Err
 
 #[cfg(test)]
 mod monitor_config_tests {
-    use crate::monitor::MonitorConfig;
+    use ivynet_docker::container::PortDiscovery;
+
+    use crate::monitor::{upsert_discovered_node, AutoDiscovery, MonitorConfig};
 
     use super::*;
     use serde_json::json;
@@ -472,6 +662,142 @@ mod monitor_config_tests {
         }
     }
 
+    #[test]
+    fn test_runtime_defaults_and_roundtrip() {
+        let toml_str = r#"
+            [[configured_avses]]
+            assigned_name = "eigenda"
+            container_name = "/eigenda"
+            avs_type = "EigenDA"
+
+            [[configured_avses]]
+            assigned_name = "lagrange"
+            container_name = "lagrange.service"
+            avs_type = "LagrangeZkWorker"
+            metric_port = 9100
+            [configured_avses.runtime]
+            kind = "systemd"
+            binary = "/opt/lagrange/worker"
+        "#;
+
+        let config: MonitorConfig = toml::from_str(toml_str).unwrap();
+        assert_eq!(config.configured_avses[0].runtime, NodeRuntime::Docker);
+        assert_eq!(
+            config.configured_avses[1].runtime,
+            NodeRuntime::Systemd { binary: PathBuf::from("/opt/lagrange/worker"), log_file: None }
+        );
+
+        let stored = toml::to_string(&config).unwrap();
+        assert_eq!(stored.matches("[configured_avses.runtime]").count(), 1);
+        let reloaded: MonitorConfig = toml::from_str(&stored).unwrap();
+        assert_eq!(reloaded.configured_avses, config.configured_avses);
+    }
+
+    #[test]
+    fn test_port_discovery_config() {
+        let toml_str = r#"
+            [[configured_avses]]
+            assigned_name = "eigenda"
+            container_name = "/eigenda"
+            avs_type = "EigenDA"
+        "#;
+        let config: MonitorConfig = toml::from_str(toml_str).unwrap();
+        assert_eq!(config.port_discovery, PortDiscovery::Procfs);
+
+        let toml_str = format!("port_discovery = \"sidecar\"\n{toml_str}");
+        let config: MonitorConfig = toml::from_str(&toml_str).unwrap();
+        assert_eq!(config.port_discovery, PortDiscovery::Sidecar);
+
+        let reloaded: MonitorConfig = toml::from_str(&toml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(reloaded.port_discovery, PortDiscovery::Sidecar);
+    }
+
+    #[test]
+    fn test_upsert_node() {
+        let node = |assigned_name: &str, container_name: &str, metric_port| ConfiguredAvs {
+            assigned_name: assigned_name.to_string(),
+            container_name: container_name.to_string(),
+            avs_type: "EigenDA".to_string(),
+            metric_port,
+            metrics_path: None,
+            manifest: None,
+            image: None,
+            runtime: NodeRuntime::Docker,
+        };
+        let mut config = MonitorConfig {
+            configured_avses: vec![node("my-da", "eigenda", None)],
+            ..Default::default()
+        };
+
+        // Existing nodes keep their name but pick up label changes
+        assert!(config.upsert_node(node("eigenda", "eigenda", Some(9092))));
+        assert_eq!(config.configured_avses, vec![node("my-da", "eigenda", Some(9092))]);
+        assert!(!config.upsert_node(node("eigenda", "eigenda", Some(9092))));
+
+        assert!(!config.upsert_node(node("my-da", "eigenda-2", None)));
+        assert!(config.upsert_node(node("da-2", "eigenda-2", None)));
+        assert_eq!(config.configured_avses.len(), 2);
+    }
+
+    #[test]
+    fn test_upsert_discovered_node() {
+        let node = |assigned_name: &str, container_name: &str| ConfiguredAvs {
+            assigned_name: assigned_name.to_string(),
+            container_name: container_name.to_string(),
+            avs_type: "EigenDA".to_string(),
+            metric_port: None,
+            metrics_path: None,
+            manifest: None,
+            image: None,
+            runtime: NodeRuntime::Docker,
+        };
+        let mut nodes = vec![node("my-da", "eigenda")];
+
+        // A container followed under another name keeps reporting under that name
+        assert_eq!(
+            upsert_discovered_node(&mut nodes, node("eigenda", "eigenda")),
+            Some((node("my-da", "eigenda"), false))
+        );
+
+        // A new container cannot take the name of another one
+        assert_eq!(upsert_discovered_node(&mut nodes, node("my-da", "eigenda-2")), None);
+        assert_eq!(nodes, vec![node("my-da", "eigenda")]);
+
+        assert_eq!(
+            upsert_discovered_node(&mut nodes, node("da-2", "eigenda-2")),
+            Some((node("da-2", "eigenda-2"), true))
+        );
+        assert_eq!(nodes.len(), 2);
+    }
+
+    #[test]
+    fn test_auto_discovery_config() {
+        let toml_str = r#"
+            [auto_discovery]
+            enabled = true
+            deny = ["postgres*", "ghcr.io/acme/*", "node-?"]
+
+            [[configured_avses]]
+            assigned_name = "eigenda"
+            container_name = "/eigenda"
+            avs_type = "EigenDA"
+        "#;
+        let config: MonitorConfig = toml::from_str(toml_str).unwrap();
+        let discovery = &config.auto_discovery;
+        assert!(discovery.enabled);
+        assert!(!discovery.allows("postgres-main", "postgres:16"));
+        assert!(!discovery.allows("sidecar", "ghcr.io/acme/exporter:1.0"));
+        assert!(!discovery.allows("node-1", "layr-labs/node"));
+        assert!(discovery.allows("node-12", "ghcr.io/layr-labs/eigenda/opr-node:0.8.4"));
+
+        let reloaded: MonitorConfig = toml::from_str(&toml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(&reloaded.auto_discovery, discovery);
+
+        let config: MonitorConfig = toml::from_str("configured_avses = []").unwrap();
+        assert_eq!(config.auto_discovery, AutoDiscovery::default());
+        assert!(!config.auto_discovery.enabled);
+    }
+
     #[test]
     fn test_container_name_slash_handling() {
         let variations = vec![
@@ -1,183 +1,457 @@
-use ivynet_grpc::messages::{Metrics, MetricsAttribute};
+use ivynet_grpc::messages::{MetricType, Metrics, MetricsAttribute};
 
-#[derive(PartialEq, Debug)]
-enum TelemetryToken {
-    Tag(String),
-    Number(f64),
-    OpenBracket,
-    CloseBracket,
-    Quote,
-    Equal,
-    Comma,
+/// Parser for the Prometheus text exposition format and its OpenMetrics superset. A body is parsed
+/// into metric families that keep their `# HELP` and `# TYPE` metadata, with the `_bucket`,
+/// `_sum`, `_count` etc. series of histograms and summaries grouped under their family. Malformed
+/// lines are skipped and reported with their position rather than failing the whole scrape.
+pub struct TelemetryParser<'a> {
+    input: &'a str,
 }
 
-pub struct TelemetryParser {
-    line: String,
-    position: usize,
-    tokens: Vec<TelemetryToken>,
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Exposition {
+    pub families: Vec<MetricFamily>,
+    pub errors: Vec<ParseError>,
 }
 
-impl TelemetryParser {
-    pub fn new(input: &str) -> Self {
-        Self { line: input.to_string(), position: 0, tokens: Vec::new() }
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricFamily {
+    pub name: String,
+    pub help: Option<String>,
+    pub metric_type: MetricType,
+    pub samples: Vec<Sample>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub name: String,
+    pub labels: Vec<(String, String)>,
+    pub value: f64,
+    /// As written by the exporter: milliseconds for Prometheus, seconds for OpenMetrics.
+    pub timestamp: Option<f64>,
+    pub exemplar: Option<Exemplar>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exemplar {
+    pub labels: Vec<(String, String)>,
+    pub value: f64,
+    pub timestamp: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("line {line}, column {column}: {kind}")]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: ParseErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum ParseErrorKind {
+    #[error("expected a metric name")]
+    MissingName,
+    #[error("expected a label name")]
+    MissingLabelName,
+    #[error("expected '{0}'")]
+    Expected(char),
+    #[error("unterminated label value")]
+    UnterminatedLabelValue,
+    #[error("invalid escape sequence '\\{0}'")]
+    InvalidEscape(char),
+    #[error("duplicate label '{0}'")]
+    DuplicateLabel(String),
+    #[error("invalid sample value '{0}'")]
+    InvalidValue(String),
+    #[error("invalid timestamp '{0}'")]
+    InvalidTimestamp(String),
+    #[error("unknown metric type '{0}'")]
+    UnknownType(String),
+    #[error("sample '{sample}' is missing the '{label}' label")]
+    MissingLabel { sample: String, label: &'static str },
+    #[error("unexpected trailing content")]
+    TrailingContent,
+}
+
+impl<'a> TelemetryParser<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Self { input }
     }
 
-    pub fn parse(&mut self) -> Option<Metrics> {
-        if let (Some(name), attributes, Some(value)) =
-            (self.name(), self.attributes(), self.value())
-        {
-            Some(Metrics { name, attributes: attributes.unwrap_or_default(), value })
-        } else {
-            None
+    /// Parses a single sample line, ignoring metadata. Returns `None` for blank, comment and
+    /// malformed lines.
+    pub fn parse(&self) -> Option<Metrics> {
+        let line = self.input.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
         }
+        let sample = Cursor::new(line).sample().ok()?;
+        Some(sample.into_metrics(MetricType::Unknown))
     }
 
-    fn name(&mut self) -> Option<String> {
-        self.expecting_string()
-    }
+    /// Parses a full exposition body.
+    pub fn parse_exposition(&self) -> Exposition {
+        let mut exposition = Exposition::default();
 
-    fn attributes(&mut self) -> Option<Vec<MetricsAttribute>> {
-        let mut attributes = Vec::new();
+        for (index, raw) in self.input.lines().enumerate() {
+            let line = raw.trim_start();
+            if line.is_empty() {
+                continue;
+            }
+            let offset = raw.len() - line.len();
+            let error = |column: usize, kind: ParseErrorKind| ParseError {
+                line: index + 1,
+                column: offset + column + 1,
+                kind,
+            };
 
-        self.expecting_special_token(TelemetryToken::OpenBracket)?;
+            if let Some(comment) = line.strip_prefix('#') {
+                match parse_comment(comment) {
+                    Ok(Comment::Help(name, help)) => exposition.family_mut(name).help = Some(help),
+                    Ok(Comment::Type(name, metric_type)) => {
+                        exposition.family_mut(name).metric_type = metric_type
+                    }
+                    Ok(Comment::Eof) => break,
+                    Ok(Comment::Other) => {}
+                    // Columns inside comments are relative to the text after '#'
+                    Err((column, kind)) => exposition.errors.push(error(column + 1, kind)),
+                }
+                continue;
+            }
 
-        while let Some(attribute) = self.attribute() {
-            attributes.push(attribute);
+            match Cursor::new(line).sample() {
+                Ok(sample) => {
+                    if let Err(kind) = exposition.push_sample(sample) {
+                        exposition.errors.push(error(0, kind));
+                    }
+                }
+                Err((column, kind)) => exposition.errors.push(error(column, kind)),
+            }
         }
 
-        self.expecting_special_token(TelemetryToken::CloseBracket)?;
-        Some(attributes)
+        exposition
     }
+}
 
-    fn attribute(&mut self) -> Option<MetricsAttribute> {
-        // Eating probable comma
-        _ = self.expecting_special_token(TelemetryToken::Comma);
+impl Exposition {
+    /// Flattens every family into individual samples tagged with their family's type.
+    pub fn into_metrics(self) -> Vec<Metrics> {
+        self.families
+            .into_iter()
+            .flat_map(|family| {
+                let metric_type = family.metric_type;
+                family.samples.into_iter().map(move |sample| sample.into_metrics(metric_type))
+            })
+            .collect()
+    }
 
-        if let Some(attr_name) = self.expecting_string() {
-            self.expecting_special_token(TelemetryToken::Equal)?;
-            self.expecting_special_token(TelemetryToken::Quote)?;
+    /// Metadata applies to the family currently being read, or opens a new one.
+    fn family_mut(&mut self, name: &str) -> &mut MetricFamily {
+        if self.families.last().is_none_or(|family| family.name != name) {
+            self.families.push(MetricFamily::new(name));
+        }
+        self.families.last_mut().expect("Family was just pushed")
+    }
+
+    fn push_sample(&mut self, sample: Sample) -> Result<(), ParseErrorKind> {
+        let family = match self.families.last_mut() {
+            Some(family) if family.contains(&sample.name) => family,
+            _ => {
+                self.families.push(MetricFamily::new(&sample.name));
+                self.families.last_mut().expect("Family was just pushed")
+            }
+        };
 
-            if let Some(attr_value) = self.expecting_string() {
-                self.expecting_special_token(TelemetryToken::Quote)?;
-                return Some(MetricsAttribute { name: attr_name, value: attr_value });
+        let required = match family.metric_type {
+            MetricType::Histogram | MetricType::GaugeHistogram
+                if sample.name.ends_with("_bucket") =>
+            {
+                Some("le")
             }
+            MetricType::Summary if sample.name == family.name => Some("quantile"),
+            _ => None,
+        };
+        if let Some(label) = required {
+            if !sample.labels.iter().any(|(name, _)| name == label) {
+                return Err(ParseErrorKind::MissingLabel { sample: sample.name, label });
+            }
+        }
+
+        family.samples.push(sample);
+        Ok(())
+    }
+}
+
+impl MetricFamily {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            help: None,
+            metric_type: MetricType::Unknown,
+            samples: vec![],
         }
-        None
     }
 
-    fn expecting_special_token(&mut self, token: TelemetryToken) -> Option<TelemetryToken> {
-        match self.get_token() {
-            Some(tok) => {
-                if tok == token {
-                    Some(tok)
-                } else {
-                    self.put_token(tok);
-                    None
-                }
-            }
-            None => None,
+    /// Whether a sample named `name` is one of this family's series.
+    fn contains(&self, name: &str) -> bool {
+        let suffixes: &[&str] = match self.metric_type {
+            MetricType::Counter => &["_total", "_created"],
+            MetricType::Histogram => &["_bucket", "_sum", "_count", "_created"],
+            MetricType::GaugeHistogram => &["_bucket", "_gsum", "_gcount"],
+            MetricType::Summary => &["_sum", "_count", "_created"],
+            MetricType::Info => &["_info"],
+            MetricType::Unknown | MetricType::Gauge | MetricType::StateSet => &[],
+        };
+        name == self.name ||
+            name.strip_prefix(self.name.as_str()).is_some_and(|rest| suffixes.contains(&rest))
+    }
+}
+
+impl Sample {
+    fn into_metrics(self, metric_type: MetricType) -> Metrics {
+        Metrics {
+            name: self.name,
+            value: self.value,
+            attributes: self
+                .labels
+                .into_iter()
+                .map(|(name, value)| MetricsAttribute { name, value })
+                .collect(),
+            metric_type: metric_type.into(),
         }
     }
+}
+
+enum Comment<'a> {
+    Help(&'a str, String),
+    Type(&'a str, MetricType),
+    Eof,
+    Other,
+}
+
+fn parse_comment(comment: &str) -> Result<Comment<'_>, (usize, ParseErrorKind)> {
+    let mut cursor = Cursor::new(comment);
+    cursor.skip_whitespace();
+    let keyword = cursor.take_while(|c| !c.is_ascii_whitespace());
+    if keyword == "EOF" && cursor.at_end() {
+        return Ok(Comment::Eof);
+    }
+    if keyword != "HELP" && keyword != "TYPE" {
+        return Ok(Comment::Other);
+    }
+
+    cursor.skip_whitespace();
+    let name = cursor.metric_name()?;
+    cursor.skip_whitespace();
+    let rest = cursor.rest().trim_end();
+
+    if keyword == "HELP" {
+        return Ok(Comment::Help(name, unescape_help(rest)));
+    }
+    let metric_type = match rest {
+        "counter" => MetricType::Counter,
+        "gauge" => MetricType::Gauge,
+        "histogram" => MetricType::Histogram,
+        "gaugehistogram" => MetricType::GaugeHistogram,
+        "summary" => MetricType::Summary,
+        "info" => MetricType::Info,
+        "stateset" => MetricType::StateSet,
+        "untyped" | "unknown" => MetricType::Unknown,
+        other => return Err((cursor.pos, ParseErrorKind::UnknownType(other.to_string()))),
+    };
+    Ok(Comment::Type(name, metric_type))
+}
 
-    fn expecting_string(&mut self) -> Option<String> {
-        match self.get_token() {
-            Some(TelemetryToken::Tag(val)) => Some(val),
-            Some(tok) => {
-                self.put_token(tok);
-                None
+fn unescape_help(help: &str) -> String {
+    let mut out = String::with_capacity(help.len());
+    let mut chars = help.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('\\') => out.push('\\'),
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
             }
-            None => None,
+            None => out.push('\\'),
         }
     }
+    out
+}
+
+/// Single forward pass over a line. Structural characters are all ASCII, so byte offsets always
+/// land on char boundaries.
+struct Cursor<'a> {
+    line: &'a str,
+    pos: usize,
+}
+
+type CursorResult<T> = Result<T, (usize, ParseErrorKind)>;
 
-    fn value(&mut self) -> Option<f64> {
-        match self.get_token() {
-            Some(TelemetryToken::Number(num)) => Some(num),
-            Some(tok) => {
-                self.put_token(tok);
-                None
+impl<'a> Cursor<'a> {
+    fn new(line: &'a str) -> Self {
+        Self { line, pos: 0 }
+    }
+
+    fn sample(mut self) -> CursorResult<Sample> {
+        let name = self.metric_name()?.to_string();
+        self.skip_whitespace();
+        let labels = if self.peek() == Some(b'{') { self.labels()? } else { vec![] };
+        self.skip_whitespace();
+        let value = self.number(ParseErrorKind::InvalidValue)?;
+        self.skip_whitespace();
+        let timestamp = self.timestamp()?;
+        self.skip_whitespace();
+
+        let exemplar = if self.peek() == Some(b'#') {
+            self.pos += 1;
+            self.skip_whitespace();
+            if self.peek() != Some(b'{') {
+                return Err((self.pos, ParseErrorKind::Expected('{')));
             }
-            None => None,
+            let labels = self.labels()?;
+            self.skip_whitespace();
+            let value = self.number(ParseErrorKind::InvalidValue)?;
+            self.skip_whitespace();
+            let timestamp = self.timestamp()?;
+            Some(Exemplar { labels, value, timestamp })
+        } else {
+            None
+        };
+
+        self.skip_whitespace();
+        if !self.at_end() {
+            return Err((self.pos, ParseErrorKind::TrailingContent));
         }
+        Ok(Sample { name, labels, value, timestamp, exemplar })
     }
 
-    fn put_token(&mut self, token: TelemetryToken) {
-        self.tokens.push(token)
+    fn metric_name(&mut self) -> CursorResult<&'a str> {
+        let start = self.pos;
+        let name = self.take_while(|c| c.is_ascii_alphanumeric() || c == b'_' || c == b':');
+        if name.is_empty() || name.as_bytes()[0].is_ascii_digit() {
+            return Err((start, ParseErrorKind::MissingName));
+        }
+        Ok(name)
     }
 
-    fn get_token(&mut self) -> Option<TelemetryToken> {
-        self.eat_whitespaces();
+    fn labels(&mut self) -> CursorResult<Vec<(String, String)>> {
+        // Opening brace
+        self.pos += 1;
+        let mut labels: Vec<(String, String)> = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some(b'}') {
+                self.pos += 1;
+                return Ok(labels);
+            }
 
-        if self.tokens.is_empty() {
-            let mut string_val = String::new();
+            let start = self.pos;
+            let name = self.take_while(|c| c.is_ascii_alphanumeric() || c == b'_');
+            if name.is_empty() || name.as_bytes()[0].is_ascii_digit() {
+                return Err((start, ParseErrorKind::MissingLabelName));
+            }
+            self.skip_whitespace();
+            self.expect(b'=')?;
+            self.skip_whitespace();
+            self.expect(b'"')?;
+            let value = self.label_value()?;
+            if labels.iter().any(|(existing, _)| existing == name) {
+                return Err((start, ParseErrorKind::DuplicateLabel(name.to_string())));
+            }
+            labels.push((name.to_string(), value));
 
-            while let Some(c) = self.line.chars().nth(self.position) {
-                if let Some(token) = TelemetryParser::special_token(c) {
-                    if string_val.is_empty() {
-                        self.position += 1;
-                        return Some(token);
-                    } else {
-                        break;
-                    }
-                } else if c.is_whitespace() {
-                    break;
-                } else {
-                    string_val.push(c);
-                    self.position += 1;
-                }
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {}
+                _ => return Err((self.pos, ParseErrorKind::Expected('}'))),
             }
+        }
+    }
 
-            if !string_val.is_empty() {
-                // It might be a number, so we need to try to parse it to one
-                if let Ok(float_num) = string_val.parse::<f64>() {
-                    Some(TelemetryToken::Number(float_num))
-                } else if let Ok(int_num) = string_val.parse::<i64>() {
-                    Some(TelemetryToken::Number(int_num as f64))
-                } else {
-                    Some(TelemetryToken::Tag(string_val))
+    /// Reads a label value up to its closing quote, resolving escapes.
+    fn label_value(&mut self) -> CursorResult<String> {
+        let mut value = String::new();
+        let mut chars = self.rest().char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += i + 1;
+                    return Ok(value);
                 }
-            } else {
-                None
+                '\\' => match chars.next() {
+                    Some((_, '\\')) => value.push('\\'),
+                    Some((_, '"')) => value.push('"'),
+                    Some((_, 'n')) => value.push('\n'),
+                    Some((_, other)) => {
+                        return Err((self.pos + i, ParseErrorKind::InvalidEscape(other)))
+                    }
+                    None => break,
+                },
+                c => value.push(c),
             }
-        } else {
-            self.tokens.pop()
         }
+        Err((self.line.len(), ParseErrorKind::UnterminatedLabelValue))
     }
 
-    fn special_token(c: char) -> Option<TelemetryToken> {
-        match c {
-            '=' => Some(TelemetryToken::Equal),
-            '"' => Some(TelemetryToken::Quote),
-            ',' => Some(TelemetryToken::Comma),
-            '{' => Some(TelemetryToken::OpenBracket),
-            '}' => Some(TelemetryToken::CloseBracket),
-            _ => None,
+    fn timestamp(&mut self) -> CursorResult<Option<f64>> {
+        if self.at_end() || self.peek() == Some(b'#') {
+            return Ok(None);
         }
+        self.number(ParseErrorKind::InvalidTimestamp).map(Some)
     }
 
-    fn eat_whitespaces(&mut self) {
-        while let Some(c) = self.line.chars().nth(self.position) {
-            if c.is_whitespace() {
-                self.position += 1;
-            } else {
-                break;
-            }
+    /// Accepts Go float syntax as used by exporters, including `NaN`, `+Inf` and `-Inf`.
+    fn number(&mut self, kind: fn(String) -> ParseErrorKind) -> CursorResult<f64> {
+        let start = self.pos;
+        let token = self.take_while(|c| !c.is_ascii_whitespace());
+        token.parse::<f64>().map_err(|_| (start, kind(token.to_string())))
+    }
+
+    fn expect(&mut self, c: u8) -> CursorResult<()> {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err((self.pos, ParseErrorKind::Expected(c as char)))
         }
-        self.eat_comment();
     }
 
-    fn eat_comment(&mut self) {
-        if let Some(c) = self.line.chars().nth(self.position) {
-            if c == '#' {
-                self.position = self.line.len();
-            }
+    fn take_while(&mut self, pred: impl Fn(u8) -> bool) -> &'a str {
+        let start = self.pos;
+        while self.peek().is_some_and(&pred) {
+            self.pos += 1;
         }
+        &self.line[start..self.pos]
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.take_while(|c| c == b' ' || c == b'\t');
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.line.as_bytes().get(self.pos).copied()
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.line[self.pos..]
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.line.len()
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::TelemetryParser;
+    use ivynet_grpc::messages::MetricType;
+
+    use super::{ParseError, ParseErrorKind, TelemetryParser};
 
     #[test]
     fn test_empty_line() {
@@ -242,4 +516,143 @@ mod test {
             panic!("Parsed entry returned None");
         }
     }
+
+    #[test]
+    fn test_metadata_kept() {
+        let exposition = TelemetryParser::new(
+            "# HELP http_requests_total Requests served.\\nPer path.\n\
+             # TYPE http_requests_total counter\n\
+             http_requests_total{path=\"/\"} 10\n\
+             # A free-form comment\n\
+             up 1\n",
+        )
+        .parse_exposition();
+
+        assert!(exposition.errors.is_empty());
+        assert_eq!(exposition.families.len(), 2);
+        let requests = &exposition.families[0];
+        assert_eq!(requests.name, "http_requests_total");
+        assert_eq!(requests.help.as_deref(), Some("Requests served.\nPer path."));
+        assert_eq!(requests.metric_type, MetricType::Counter);
+        assert_eq!(exposition.families[1].metric_type, MetricType::Unknown);
+    }
+
+    #[test]
+    fn test_escaped_label_values() {
+        let metrics = TelemetryParser::new(
+            r#"metric_name{msg="say \"hi\", then {leave}",path="C:\\tmp\nnext"} 1"#,
+        )
+        .parse()
+        .expect("Line should parse");
+        assert_eq!(metrics.attributes[0].value, "say \"hi\", then {leave}");
+        assert_eq!(metrics.attributes[1].value, "C:\\tmp\nnext");
+    }
+
+    #[test]
+    fn test_special_values_and_timestamps() {
+        let exposition =
+            TelemetryParser::new("a NaN\nb +Inf 1700000000000\nc -Inf\nd 1.5 1700000000.250\n")
+                .parse_exposition();
+
+        assert!(exposition.errors.is_empty());
+        let samples: Vec<_> = exposition.families.iter().map(|f| &f.samples[0]).collect();
+        assert!(samples[0].value.is_nan());
+        assert_eq!(samples[1].value, f64::INFINITY);
+        assert_eq!(samples[1].timestamp, Some(1_700_000_000_000.0));
+        assert_eq!(samples[2].value, f64::NEG_INFINITY);
+        assert_eq!(samples[3].timestamp, Some(1_700_000_000.25));
+    }
+
+    #[test]
+    fn test_exemplar() {
+        let exposition = TelemetryParser::new(
+            "# TYPE rpc_seconds histogram\n\
+             rpc_seconds_bucket{le=\"0.5\"} 3 # {trace_id=\"abc\"} 0.42 1700000000.1\n",
+        )
+        .parse_exposition();
+
+        assert!(exposition.errors.is_empty());
+        let exemplar = exposition.families[0].samples[0].exemplar.as_ref().unwrap();
+        assert_eq!(exemplar.labels, vec![("trace_id".to_string(), "abc".to_string())]);
+        assert_eq!(exemplar.value, 0.42);
+        assert_eq!(exemplar.timestamp, Some(1_700_000_000.1));
+    }
+
+    #[test]
+    fn test_histogram_and_summary_grouping() {
+        let exposition = TelemetryParser::new(
+            "# TYPE rpc_seconds histogram\n\
+             rpc_seconds_bucket{le=\"0.1\"} 1\n\
+             rpc_seconds_bucket{le=\"+Inf\"} 4\n\
+             rpc_seconds_sum 0.9\n\
+             rpc_seconds_count 4\n\
+             # TYPE gc_seconds summary\n\
+             gc_seconds{quantile=\"0.5\"} 0.01\n\
+             gc_seconds_sum 0.2\n\
+             gc_seconds_count 12\n\
+             rpc_seconds_total 1\n",
+        )
+        .parse_exposition();
+
+        assert!(exposition.errors.is_empty());
+        let families: Vec<_> =
+            exposition.families.iter().map(|f| (f.name.as_str(), f.samples.len())).collect();
+        assert_eq!(families, vec![("rpc_seconds", 4), ("gc_seconds", 3), ("rpc_seconds_total", 1)]);
+
+        let metrics = exposition.into_metrics();
+        assert_eq!(metrics.len(), 8);
+        assert_eq!(metrics[3].metric_type(), MetricType::Histogram);
+        assert_eq!(metrics[4].metric_type(), MetricType::Summary);
+        assert_eq!(metrics[7].metric_type(), MetricType::Unknown);
+    }
+
+    #[test]
+    fn test_missing_bucket_label() {
+        let exposition =
+            TelemetryParser::new("# TYPE rpc_seconds histogram\nrpc_seconds_bucket 1\n")
+                .parse_exposition();
+        assert_eq!(
+            exposition.errors,
+            vec![ParseError {
+                line: 2,
+                column: 1,
+                kind: ParseErrorKind::MissingLabel {
+                    sample: "rpc_seconds_bucket".to_string(),
+                    label: "le"
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn test_malformed_lines_reported() {
+        let exposition = TelemetryParser::new(
+            "good 1\n\
+             bad{a=\"1\" 2\n\
+             bad_value abc\n\
+             {a=\"1\"} 3\n\
+             # TYPE thing sometimes\n\
+             also_good 2\n",
+        )
+        .parse_exposition();
+
+        assert_eq!(exposition.families.len(), 2);
+        let positions: Vec<_> =
+            exposition.errors.iter().map(|e| (e.line, e.column, e.kind.clone())).collect();
+        assert_eq!(
+            positions,
+            vec![
+                (2, 11, ParseErrorKind::Expected('}')),
+                (3, 11, ParseErrorKind::InvalidValue("abc".to_string())),
+                (4, 1, ParseErrorKind::MissingName),
+                (5, 14, ParseErrorKind::UnknownType("sometimes".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_eof_stops_parsing() {
+        let exposition = TelemetryParser::new("a 1\n# EOF\nb 2\n").parse_exposition();
+        assert_eq!(exposition.families.len(), 1);
+    }
 }
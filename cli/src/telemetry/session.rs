@@ -0,0 +1,114 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use ivynet_grpc::{
+    backend::backend_client::BackendClient,
+    capabilities::declare_protocol,
+    tonic::{
+        metadata::{Ascii, MetadataValue},
+        transport::Channel,
+        Code, Request,
+    },
+    SESSION_METADATA_KEY,
+};
+use tracing::{debug, info, warn};
+
+use crate::ivy_machine::IvyMachine;
+
+/// How long before its expiry a session is renewed.
+const RENEW_BEFORE: Duration = Duration::from_secs(60);
+/// Pause after failing to open a session, during which messages are only signed.
+const RETRY_AFTER: Duration = Duration::from_secs(30);
+
+/// Telemetry session with the backend. Messages sent in a session are accepted on its token, so
+/// the backend does not have to verify each of their signatures. Messages stay signed, which lets
+/// them through on their signature alone whenever there is no session.
+#[derive(Debug)]
+pub struct TelemetrySession {
+    machine: IvyMachine,
+    token: Option<MetadataValue<Ascii>>,
+    /// Expiry of the token in unix milliseconds
+    expires_at: u64,
+    next_attempt: Instant,
+    /// Set once the backend turns out not to offer sessions
+    unsupported: bool,
+}
+
+impl TelemetrySession {
+    /// Sessions are only opened when `offered`, i.e. unless the backend said it has none.
+    pub fn new(machine: IvyMachine, offered: bool) -> Self {
+        Self {
+            machine,
+            token: None,
+            expires_at: 0,
+            next_attempt: Instant::now(),
+            unsupported: !offered,
+        }
+    }
+
+    /// Makes sure there is a session that does not expire soon, opening a new one if needed.
+    pub async fn refresh(&mut self, backend_client: &mut BackendClient<Channel>) {
+        if self.unsupported || (self.token.is_some() && !self.expires_soon()) {
+            return;
+        }
+        self.token = None;
+        if Instant::now() < self.next_attempt {
+            return;
+        }
+
+        let request = match self.machine.sign_session_request() {
+            Ok(request) => request,
+            Err(e) => {
+                warn!("Failed to sign telemetry session request: {}", e);
+                self.next_attempt = Instant::now() + RETRY_AFTER;
+                return;
+            }
+        };
+        match backend_client.open_session(request).await {
+            Ok(response) => {
+                let session = response.into_inner();
+                match MetadataValue::try_from(session.token) {
+                    Ok(token) => {
+                        debug!("Opened telemetry session");
+                        self.token = Some(token);
+                        self.expires_at = session.expires_at;
+                    }
+                    Err(_) => warn!("Backend returned an invalid session token"),
+                }
+            }
+            Err(e) if e.code() == Code::Unimplemented => {
+                info!("Backend does not offer telemetry sessions, sending signed messages only");
+                self.unsupported = true;
+            }
+            Err(e) => {
+                debug!("Failed to open telemetry session: {}", e.message());
+                self.next_attempt = Instant::now() + RETRY_AFTER;
+            }
+        }
+    }
+
+    /// Whether there is a session, which `refresh` may have failed to open.
+    pub fn is_open(&self) -> bool {
+        self.token.is_some()
+    }
+
+    /// Drops the session after the backend refused its token.
+    pub fn invalidate(&mut self) {
+        self.token = None;
+    }
+
+    /// Wraps a message into a request carrying the negotiated protocol version and the session
+    /// token, if there is a session.
+    pub fn request<T>(&self, message: T) -> Request<T> {
+        let mut request = Request::new(message);
+        declare_protocol(&mut request);
+        if let Some(token) = &self.token {
+            request.metadata_mut().insert(SESSION_METADATA_KEY, token.clone());
+        }
+        request
+    }
+
+    fn expires_soon(&self) -> bool {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        now + RENEW_BEFORE >= Duration::from_millis(self.expires_at)
+    }
+}
@@ -0,0 +1,355 @@
+use std::{
+    collections::VecDeque,
+    fs,
+    io::Write,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use ivynet_grpc::messages::{
+    SignedLog, SignedLogBatch, SignedMachineData, SignedMetrics, SignedNodeDataV2,
+};
+use prost::Message;
+use tracing::warn;
+
+use super::dispatch::TelemetryMsg;
+
+/// Default upper bound on the total on-disk size of the spool.
+pub const DEFAULT_SPOOL_MAX_BYTES: u64 = 64 * 1024 * 1024;
+
+const SPOOL_ENTRY_EXTENSION: &str = "msg";
+const SPOOL_TMP_EXTENSION: &str = "tmp";
+
+/// Single byte tags prefixed to every spooled payload so the message type can be recovered on
+/// replay. These are persisted on disk and must never be renumbered.
+const TAG_NODE_DATA: u8 = 1;
+const TAG_METRICS: u8 = 2;
+const TAG_LOG: u8 = 3;
+const TAG_MACHINE_DATA: u8 = 4;
+const TAG_LOG_BATCH: u8 = 5;
+
+#[derive(Debug, Clone)]
+struct SpoolEntry {
+    seq: u64,
+    size: u64,
+    created_at: SystemTime,
+}
+
+/// Durable FIFO of telemetry messages that could not be delivered to the backend. Each message is
+/// stored as its own file named by a monotonically increasing sequence number, so ordering
+/// survives restarts and a crash mid-write can at worst lose the message being written. The spool
+/// is bounded by `max_bytes`; once full, the oldest entries are evicted first.
+#[derive(Debug)]
+pub struct TelemetrySpool {
+    dir: PathBuf,
+    max_bytes: u64,
+    entries: VecDeque<SpoolEntry>,
+    total_bytes: u64,
+    next_seq: u64,
+}
+
+impl TelemetrySpool {
+    /// Opens the spool at `dir`, creating the directory if needed and picking up any entries left
+    /// over from a previous run.
+    pub fn open(dir: impl Into<PathBuf>, max_bytes: u64) -> Result<Self, SpoolError> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).map_err(|e| SpoolError::Io { path: dir.clone(), source: e })?;
+
+        let mut entries = Vec::new();
+        let read_dir =
+            fs::read_dir(&dir).map_err(|e| SpoolError::Io { path: dir.clone(), source: e })?;
+        for dir_entry in read_dir.flatten() {
+            let path = dir_entry.path();
+            match path.extension().and_then(|ext| ext.to_str()) {
+                Some(SPOOL_ENTRY_EXTENSION) => {}
+                Some(SPOOL_TMP_EXTENSION) => {
+                    // Partial write from an interrupted run.
+                    let _ = fs::remove_file(&path);
+                    continue;
+                }
+                _ => continue,
+            }
+            let Some(seq) =
+                path.file_stem().and_then(|stem| stem.to_str()).and_then(|s| s.parse().ok())
+            else {
+                continue;
+            };
+            let Ok(metadata) = dir_entry.metadata() else { continue };
+            let created_at = metadata.modified().unwrap_or_else(|_| SystemTime::now());
+            entries.push(SpoolEntry { seq, size: metadata.len(), created_at });
+        }
+        entries.sort_by_key(|e| e.seq);
+
+        let total_bytes = entries.iter().map(|e| e.size).sum();
+        let next_seq = entries.last().map(|e| e.seq + 1).unwrap_or(0);
+
+        let mut spool = Self { dir, max_bytes, entries: entries.into(), total_bytes, next_seq };
+        spool.enforce_limit();
+        Ok(spool)
+    }
+
+    /// Number of messages currently waiting for replay.
+    pub fn depth(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Total on-disk size of all spooled messages, in bytes.
+    pub fn size_bytes(&self) -> u64 {
+        self.total_bytes
+    }
+
+    /// Age of the oldest message waiting for replay, if any.
+    pub fn oldest_age(&self) -> Option<Duration> {
+        self.entries
+            .front()
+            .map(|e| SystemTime::now().duration_since(e.created_at).unwrap_or_default())
+    }
+
+    /// Appends a message to the back of the spool, evicting the oldest entries if the size bound
+    /// is exceeded.
+    pub fn push(&mut self, msg: &TelemetryMsg) -> Result<(), SpoolError> {
+        let bytes = encode(msg);
+        let seq = self.next_seq;
+        let path = self.entry_path(seq);
+        let tmp_path = path.with_extension(SPOOL_TMP_EXTENSION);
+
+        let write = || -> std::io::Result<()> {
+            let mut file = fs::File::create(&tmp_path)?;
+            file.write_all(&bytes)?;
+            file.sync_all()?;
+            fs::rename(&tmp_path, &path)
+        };
+        write().map_err(|e| SpoolError::Io { path: path.clone(), source: e })?;
+
+        self.next_seq += 1;
+        self.total_bytes += bytes.len() as u64;
+        self.entries.push_back(SpoolEntry {
+            seq,
+            size: bytes.len() as u64,
+            created_at: SystemTime::now(),
+        });
+        self.enforce_limit();
+        Ok(())
+    }
+
+    /// Returns the oldest message without removing it. Entries that can no longer be decoded are
+    /// discarded.
+    pub fn peek(&mut self) -> Result<Option<TelemetryMsg>, SpoolError> {
+        while let Some(entry) = self.entries.front() {
+            let path = self.entry_path(entry.seq);
+            let bytes = match fs::read(&path) {
+                Ok(bytes) => bytes,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    self.pop_front();
+                    continue;
+                }
+                Err(e) => return Err(SpoolError::Io { path, source: e }),
+            };
+            match decode(&bytes) {
+                Ok(msg) => return Ok(Some(msg)),
+                Err(e) => {
+                    warn!("Discarding corrupt spool entry {}: {}", path.display(), e);
+                    self.pop_front();
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Removes the oldest message, typically after it has been successfully replayed.
+    pub fn pop(&mut self) {
+        self.pop_front();
+    }
+
+    fn pop_front(&mut self) {
+        if let Some(entry) = self.entries.pop_front() {
+            self.total_bytes = self.total_bytes.saturating_sub(entry.size);
+            let path = self.entry_path(entry.seq);
+            if let Err(e) = fs::remove_file(&path) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    warn!("Failed to remove spool entry {}: {}", path.display(), e);
+                }
+            }
+        }
+    }
+
+    fn enforce_limit(&mut self) {
+        let mut evicted = 0;
+        while self.total_bytes > self.max_bytes && !self.entries.is_empty() {
+            self.pop_front();
+            evicted += 1;
+        }
+        if evicted > 0 {
+            warn!(
+                "Telemetry spool exceeded {} bytes, dropped {} oldest message(s)",
+                self.max_bytes, evicted
+            );
+        }
+    }
+
+    fn entry_path(&self, seq: u64) -> PathBuf {
+        self.dir.join(format!("{seq:020}.{SPOOL_ENTRY_EXTENSION}"))
+    }
+}
+
+fn encode(msg: &TelemetryMsg) -> Vec<u8> {
+    let (tag, payload) = match msg {
+        TelemetryMsg::SignedNodeData(m) => (TAG_NODE_DATA, m.encode_to_vec()),
+        TelemetryMsg::Metrics(m) => (TAG_METRICS, m.encode_to_vec()),
+        TelemetryMsg::Log(m) => (TAG_LOG, m.encode_to_vec()),
+        TelemetryMsg::SignedMachineData(m) => (TAG_MACHINE_DATA, m.encode_to_vec()),
+        TelemetryMsg::LogBatch(m) => (TAG_LOG_BATCH, m.encode_to_vec()),
+    };
+    let mut bytes = Vec::with_capacity(payload.len() + 1);
+    bytes.push(tag);
+    bytes.extend_from_slice(&payload);
+    bytes
+}
+
+fn decode(bytes: &[u8]) -> Result<TelemetryMsg, SpoolError> {
+    let (tag, payload) = bytes.split_first().ok_or(SpoolError::EmptyEntry)?;
+    let msg = match *tag {
+        TAG_NODE_DATA => TelemetryMsg::SignedNodeData(SignedNodeDataV2::decode(payload)?),
+        TAG_METRICS => TelemetryMsg::Metrics(SignedMetrics::decode(payload)?),
+        TAG_LOG => TelemetryMsg::Log(SignedLog::decode(payload)?),
+        TAG_MACHINE_DATA => TelemetryMsg::SignedMachineData(SignedMachineData::decode(payload)?),
+        TAG_LOG_BATCH => TelemetryMsg::LogBatch(SignedLogBatch::decode(payload)?),
+        other => return Err(SpoolError::UnknownTag(other)),
+    };
+    Ok(msg)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SpoolError {
+    #[error("Spool I/O error at path {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Spool entry is empty")]
+    EmptyEntry,
+
+    #[error("Unknown spool entry tag: {0}")]
+    UnknownTag(u8),
+
+    #[error("Failed to decode spool entry: {0}")]
+    Decode(#[from] prost::DecodeError),
+}
+
+#[cfg(test)]
+mod spool_tests {
+    use super::*;
+    use ivynet_grpc::messages::{MetricType, Metrics};
+
+    fn spool_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ivy-spool-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn log_msg(log: &str) -> TelemetryMsg {
+        TelemetryMsg::Log(SignedLog {
+            signature: vec![1; 65],
+            machine_id: vec![2; 16],
+            avs_name: "test_avs".to_string(),
+            log: log.to_string(),
+            ..Default::default()
+        })
+    }
+
+    fn log_text(msg: TelemetryMsg) -> String {
+        match msg {
+            TelemetryMsg::Log(log) => log.log,
+            other => panic!("Unexpected message: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_spool_fifo_order() {
+        let dir = spool_dir("fifo");
+        let mut spool = TelemetrySpool::open(&dir, DEFAULT_SPOOL_MAX_BYTES).unwrap();
+        spool.push(&log_msg("first")).unwrap();
+        spool
+            .push(&TelemetryMsg::Metrics(SignedMetrics {
+                signature: vec![],
+                machine_id: vec![],
+                avs_name: Some("test_avs".to_string()),
+                metrics: vec![Metrics {
+                    name: "up".to_string(),
+                    value: 1.0,
+                    attributes: vec![],
+                    metric_type: MetricType::Gauge.into(),
+                }],
+                ..Default::default()
+            }))
+            .unwrap();
+        spool.push(&log_msg("third")).unwrap();
+        assert_eq!(spool.depth(), 3);
+        assert!(spool.oldest_age().is_some());
+
+        assert_eq!(log_text(spool.peek().unwrap().unwrap()), "first");
+        spool.pop();
+        assert!(matches!(spool.peek().unwrap(), Some(TelemetryMsg::Metrics(_))));
+        spool.pop();
+        assert_eq!(log_text(spool.peek().unwrap().unwrap()), "third");
+        spool.pop();
+        assert!(spool.peek().unwrap().is_none());
+        assert_eq!(spool.size_bytes(), 0);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_spool_survives_reopen() {
+        let dir = spool_dir("reopen");
+        {
+            let mut spool = TelemetrySpool::open(&dir, DEFAULT_SPOOL_MAX_BYTES).unwrap();
+            spool.push(&log_msg("a")).unwrap();
+            spool.push(&log_msg("b")).unwrap();
+        }
+        let mut spool = TelemetrySpool::open(&dir, DEFAULT_SPOOL_MAX_BYTES).unwrap();
+        assert_eq!(spool.depth(), 2);
+        assert_eq!(log_text(spool.peek().unwrap().unwrap()), "a");
+        spool.pop();
+        spool.push(&log_msg("c")).unwrap();
+        assert_eq!(log_text(spool.peek().unwrap().unwrap()), "b");
+        spool.pop();
+        assert_eq!(log_text(spool.peek().unwrap().unwrap()), "c");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_spool_evicts_oldest_when_full() {
+        let dir = spool_dir("evict");
+        let entry_size = encode(&log_msg("0")).len() as u64;
+        let mut spool = TelemetrySpool::open(&dir, entry_size * 2).unwrap();
+        for i in 0..5 {
+            spool.push(&log_msg(&i.to_string())).unwrap();
+        }
+        assert_eq!(spool.depth(), 2);
+        assert_eq!(log_text(spool.peek().unwrap().unwrap()), "3");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_spool_discards_corrupt_entries() {
+        let dir = spool_dir("corrupt");
+        let mut spool = TelemetrySpool::open(&dir, DEFAULT_SPOOL_MAX_BYTES).unwrap();
+        spool.push(&log_msg("bad")).unwrap();
+        spool.push(&log_msg("good")).unwrap();
+        fs::write(spool.entry_path(0), [0xff, 0x00]).unwrap();
+
+        assert_eq!(log_text(spool.peek().unwrap().unwrap()), "good");
+        assert_eq!(spool.depth(), 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
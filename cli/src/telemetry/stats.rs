@@ -0,0 +1,137 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use ivynet_docker::stats::ContainerStats;
+use ivynet_grpc::messages::Metrics;
+
+use super::{spool::TelemetrySpool, ConfiguredAvs};
+
+/// Per-node view of the most recent metrics scrape.
+#[derive(Debug, Clone, Default)]
+pub struct NodeStats {
+    pub avs_type: String,
+    pub last_scrape: Option<SystemTime>,
+    pub scrape_errors: u64,
+    /// Last time the node's metrics were handed to the dispatcher.
+    pub last_dispatch: Option<SystemTime>,
+    pub dispatch_errors: u64,
+    pub metrics: Vec<Metrics>,
+    /// Latest resource usage sample for docker nodes whose container is running.
+    pub container: Option<ContainerUsage>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ContainerUsage {
+    pub stats: ContainerStats,
+    pub restart_count: u64,
+}
+
+/// Health of the telemetry dispatcher.
+#[derive(Debug, Clone, Default)]
+pub struct DispatchStats {
+    pub last_success: Option<SystemTime>,
+    pub successes: u64,
+    pub errors: u64,
+    pub spool_depth: usize,
+    pub spool_bytes: u64,
+    pub spool_oldest_age: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StatsSnapshot {
+    /// Keyed by the node's assigned name.
+    pub nodes: BTreeMap<String, NodeStats>,
+    pub dispatch: DispatchStats,
+}
+
+/// Shared, cheaply cloneable record of what the monitor daemon has scraped and dispatched. The
+/// listeners write to it as they work; readers such as the local metrics exporter take snapshots.
+#[derive(Debug, Clone, Default)]
+pub struct TelemetryStats(Arc<RwLock<StatsSnapshot>>);
+
+impl TelemetryStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        self.0.read().expect("Telemetry stats lock poisoned").clone()
+    }
+
+    pub fn record_scrape(&self, avs: &ConfiguredAvs, metrics: &[Metrics]) {
+        self.update_node(avs, |node| {
+            node.last_scrape = Some(SystemTime::now());
+            node.metrics = metrics.to_vec();
+        });
+    }
+
+    pub fn record_scrape_error(&self, avs: &ConfiguredAvs) {
+        self.update_node(avs, |node| {
+            node.scrape_errors += 1;
+            node.metrics.clear();
+        });
+    }
+
+    pub fn record_node_dispatch(&self, avs: &ConfiguredAvs) {
+        self.update_node(avs, |node| node.last_dispatch = Some(SystemTime::now()));
+    }
+
+    pub fn record_node_dispatch_error(&self, avs: &ConfiguredAvs) {
+        self.update_node(avs, |node| node.dispatch_errors += 1);
+    }
+
+    pub fn record_container_stats(&self, avs: &ConfiguredAvs, usage: ContainerUsage) {
+        self.update_node(avs, |node| node.container = Some(usage));
+    }
+
+    /// Drops the usage sample of a node whose container has stopped, keeping its scrape history.
+    pub fn clear_container_stats(&self, assigned_name: &str) {
+        if let Some(node) = self.write().nodes.get_mut(assigned_name) {
+            node.container = None;
+        }
+    }
+
+    pub fn container_usage(&self, assigned_name: &str) -> Option<ContainerUsage> {
+        self.0
+            .read()
+            .expect("Telemetry stats lock poisoned")
+            .nodes
+            .get(assigned_name)
+            .and_then(|node| node.container)
+    }
+
+    pub fn remove_node(&self, assigned_name: &str) {
+        self.write().nodes.remove(assigned_name);
+    }
+
+    pub fn record_dispatch_success(&self) {
+        let mut stats = self.write();
+        stats.dispatch.last_success = Some(SystemTime::now());
+        stats.dispatch.successes += 1;
+    }
+
+    pub fn record_dispatch_error(&self) {
+        self.write().dispatch.errors += 1;
+    }
+
+    pub fn record_spool(&self, spool: &TelemetrySpool) {
+        let mut stats = self.write();
+        stats.dispatch.spool_depth = spool.depth();
+        stats.dispatch.spool_bytes = spool.size_bytes();
+        stats.dispatch.spool_oldest_age = spool.oldest_age();
+    }
+
+    fn update_node(&self, avs: &ConfiguredAvs, f: impl FnOnce(&mut NodeStats)) {
+        let mut stats = self.write();
+        let node = stats.nodes.entry(avs.assigned_name.clone()).or_default();
+        node.avs_type = avs.avs_type.clone();
+        f(node);
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, StatsSnapshot> {
+        self.0.write().expect("Telemetry stats lock poisoned")
+    }
+}
@@ -0,0 +1,447 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use ethers::types::{Address, Signature};
+use ivynet_grpc::{
+    backend::backend_client::BackendClient,
+    capabilities::declare_protocol,
+    messages::{
+        command::Kind, telemetry_downstream, telemetry_upstream::Payload, CommandResult,
+        SignedCommand, TelemetryAck, TelemetryDownstream, TelemetryUpstream,
+    },
+    tonic::{transport::Channel, Code, Request, Status, Streaming},
+};
+use ivynet_signer::{sign_utils::recover_from_hash, typed_data::command_digest};
+use tokio::{
+    sync::{mpsc, oneshot},
+    time::timeout,
+};
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use super::dispatch::TelemetryMsg;
+use crate::{ivy_machine::IvyMachine, monitor::RemoteCommands};
+
+/// Messages buffered in each direction of the stream.
+const STREAM_BUFFER: usize = 64;
+/// How long a message waits for its acknowledgement before the stream is given up.
+const ACK_TIMEOUT: Duration = Duration::from_secs(30);
+/// Pause after failing to open the stream, during which telemetry is sent in single calls.
+const RETRY_AFTER: Duration = Duration::from_secs(60);
+/// Upstream id of command results, whose acknowledgements nobody waits for.
+const UNACKED_ID: u64 = 0;
+
+pub type RemoteCommandTx = mpsc::Sender<RemoteCommand>;
+pub type RemoteCommandRx = mpsc::Receiver<RemoteCommand>;
+/// Command signers pinned by the [`CommandGate`], for the docker stream listener to store in the
+/// monitor config along with its other changes to it.
+pub type PinnedSignerTx = mpsc::Sender<Address>;
+pub type PinnedSignerRx = mpsc::Receiver<Address>;
+
+/// A command from the backend that passed verification, forwarded to the docker stream listener
+/// to be carried out. What was done, or why it failed, is sent back through `reply`.
+#[derive(Debug)]
+pub struct RemoteCommand {
+    pub kind: Kind,
+    pub reply: oneshot::Sender<Result<String, String>>,
+}
+
+/// Name a command type goes by in the allow-list of the monitor config.
+pub fn command_name(kind: &Kind) -> &'static str {
+    match kind {
+        Kind::SetScrapeInterval(_) => "set_scrape_interval",
+        Kind::LogBackfill(_) => "log_backfill",
+        Kind::Rescan(_) => "rescan",
+        Kind::UpdateNode(_) => "update_node",
+    }
+}
+
+impl From<TelemetryMsg> for Payload {
+    fn from(msg: TelemetryMsg) -> Self {
+        match msg {
+            TelemetryMsg::SignedNodeData(node_data) => Payload::NodeData(node_data),
+            TelemetryMsg::Metrics(metrics) => Payload::Metrics(metrics),
+            TelemetryMsg::Log(log) => Payload::Log(log),
+            TelemetryMsg::LogBatch(batch) => Payload::LogBatch(batch),
+            TelemetryMsg::SignedMachineData(machine_data) => Payload::MachineData(machine_data),
+        }
+    }
+}
+
+/// Long-lived telemetry stream with the backend. Telemetry goes upstream and is acknowledged one
+/// message at a time, so the dispatcher handles failures just as with single calls. The backend
+/// pushes commands downstream, which are handed to the [`CommandGate`].
+///
+/// When the stream cannot be opened, telemetry falls back to single calls until the next attempt.
+#[derive(Debug)]
+pub struct TelemetryStream {
+    backend_client: BackendClient<Channel>,
+    machine: IvyMachine,
+    commands: CommandGate,
+    connection: Option<Connection>,
+    next_id: u64,
+    next_attempt: Instant,
+    /// Set once the backend turns out not to offer the stream
+    unsupported: bool,
+}
+
+#[derive(Debug)]
+struct Connection {
+    upstream: mpsc::Sender<TelemetryUpstream>,
+    acks: mpsc::Receiver<TelemetryAck>,
+}
+
+impl TelemetryStream {
+    /// The stream is only opened when `offered`, i.e. unless the backend said it has none.
+    pub fn new(
+        backend_client: BackendClient<Channel>,
+        machine: IvyMachine,
+        offered: bool,
+        commands: CommandGate,
+    ) -> Self {
+        Self {
+            backend_client,
+            machine,
+            commands,
+            connection: None,
+            next_id: UNACKED_ID,
+            next_attempt: Instant::now(),
+            unsupported: !offered,
+        }
+    }
+
+    /// Sends a message over the stream and waits for its acknowledgement. Returns `None` when
+    /// there is no stream, in which case the message has to be sent in a single call.
+    pub async fn send(&mut self, msg: TelemetryMsg) -> Option<Result<(), Status>> {
+        if !self.connect().await {
+            return None;
+        }
+        self.next_id += 1;
+        let id = self.next_id;
+        let connection = self.connection.as_mut()?;
+
+        let message = TelemetryUpstream { id, payload: Some(msg.into()) };
+        if connection.upstream.send(message).await.is_err() {
+            self.connection = None;
+            return None;
+        }
+        let ack = loop {
+            match timeout(ACK_TIMEOUT, connection.acks.recv()).await {
+                Ok(Some(ack)) if ack.id == id => break ack,
+                // Acknowledgement of a message that was given up on
+                Ok(Some(_)) => continue,
+                Ok(None) | Err(_) => {
+                    debug!("Telemetry stream closed while waiting for an acknowledgement");
+                    self.connection = None;
+                    return None;
+                }
+            }
+        };
+
+        Some(match ack.code {
+            0 => Ok(()),
+            code => Err(Status::new(Code::from(code), ack.message)),
+        })
+    }
+
+    /// Makes sure the stream is open, opening it if needed. Returns whether it is.
+    pub async fn connect(&mut self) -> bool {
+        if self.connection.as_ref().is_some_and(|connection| !connection.upstream.is_closed()) {
+            return true;
+        }
+        self.connection = None;
+        if self.unsupported || Instant::now() < self.next_attempt {
+            return false;
+        }
+
+        match self.open().await {
+            Ok(connection) => {
+                info!("Opened telemetry stream");
+                self.connection = Some(connection);
+                true
+            }
+            Err(e) if e.code() == Code::Unimplemented => {
+                info!("Backend does not offer a telemetry stream, sending single messages only");
+                self.unsupported = true;
+                false
+            }
+            Err(e) => {
+                debug!("Failed to open telemetry stream: {}", e.message());
+                self.next_attempt = Instant::now() + RETRY_AFTER;
+                false
+            }
+        }
+    }
+
+    async fn open(&mut self) -> Result<Connection, Status> {
+        let hello = self
+            .machine
+            .sign_session_request()
+            .map_err(|e| Status::internal(format!("Failed to sign stream hello: {e}")))?;
+        let (upstream, upstream_rx) = mpsc::channel(STREAM_BUFFER);
+        upstream
+            .try_send(TelemetryUpstream { id: UNACKED_ID, payload: Some(Payload::Hello(hello)) })
+            .map_err(|_| Status::internal("Stream buffer is full"))?;
+
+        let mut request = Request::new(ReceiverStream::new(upstream_rx));
+        declare_protocol(&mut request);
+        let mut downstream = self.backend_client.telemetry_stream(request).await?.into_inner();
+        let welcome = match timeout(ACK_TIMEOUT, downstream.message()).await {
+            Ok(Ok(Some(TelemetryDownstream {
+                payload: Some(telemetry_downstream::Payload::Welcome(welcome)),
+            }))) => welcome,
+            Ok(Err(e)) => return Err(e),
+            _ => return Err(Status::unavailable("Backend did not welcome the telemetry stream")),
+        };
+        self.commands.pin_signer(&welcome.command_signer);
+
+        let (acks_tx, acks) = mpsc::channel(STREAM_BUFFER);
+        tokio::spawn(read_downstream(downstream, acks_tx, upstream.clone(), self.commands.clone()));
+        Ok(Connection { upstream, acks })
+    }
+}
+
+/// Reads the stream until the backend closes it, passing acknowledgements to the waiting sender
+/// and commands to the gate.
+async fn read_downstream(
+    mut downstream: Streaming<TelemetryDownstream>,
+    acks: mpsc::Sender<TelemetryAck>,
+    upstream: mpsc::Sender<TelemetryUpstream>,
+    commands: CommandGate,
+) {
+    use telemetry_downstream::Payload as Downstream;
+
+    loop {
+        match downstream.message().await {
+            Ok(Some(TelemetryDownstream { payload: Some(Downstream::Ack(ack)) })) => {
+                if ack.id != UNACKED_ID && acks.send(ack).await.is_err() {
+                    break;
+                }
+            }
+            Ok(Some(TelemetryDownstream { payload: Some(Downstream::Command(command)) })) => {
+                commands.dispatch(command, &upstream);
+            }
+            Ok(Some(_)) => {}
+            Ok(None) => break,
+            Err(e) => {
+                debug!("Telemetry stream failed: {}", e.message());
+                break;
+            }
+        }
+    }
+    debug!("Telemetry stream closed by the backend");
+}
+
+/// Checks commands pushed by the backend before they are carried out. A command has to be signed
+/// with the pinned command key for this machine, must not have expired or been received before,
+/// and its type has to be on the allow-list of the monitor config.
+#[derive(Debug, Clone)]
+pub struct CommandGate {
+    machine_id: Uuid,
+    config: RemoteCommands,
+    commands: RemoteCommandTx,
+    pinned_signers: PinnedSignerTx,
+    /// Ids of the commands received, with when they expire in unix milliseconds. Expired commands
+    /// are refused anyway, so their ids are dropped.
+    seen: Arc<Mutex<HashMap<Uuid, u64>>>,
+}
+
+impl CommandGate {
+    pub fn new(
+        machine_id: Uuid,
+        config: RemoteCommands,
+        commands: RemoteCommandTx,
+        pinned_signers: PinnedSignerTx,
+    ) -> Self {
+        Self {
+            machine_id,
+            config,
+            commands,
+            pinned_signers,
+            seen: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Pins the command key the backend announced, unless another one is pinned already. The
+    /// docker stream listener stores it, so it stays pinned after a restart.
+    fn pin_signer(&mut self, announced: &[u8]) {
+        if announced.len() != Address::len_bytes() {
+            // The backend does not send commands
+            return;
+        }
+        let announced = Address::from_slice(announced);
+        match self.config.signer {
+            Some(pinned) if pinned == announced => {}
+            Some(pinned) => warn!(
+                "Backend signs commands with {:?} instead of the pinned {:?}, its commands will be \
+                 refused",
+                announced, pinned
+            ),
+            None => {
+                info!("Pinning command signer {:?}", announced);
+                self.config.signer = Some(announced);
+                if self.pinned_signers.try_send(announced).is_err() {
+                    warn!("Failed to store command signer: monitor is busy or shutting down");
+                }
+            }
+        }
+    }
+
+    /// Forwards a command to the docker stream listener once it passed the checks, and reports the
+    /// outcome back upstream.
+    fn dispatch(&self, command: SignedCommand, upstream: &mpsc::Sender<TelemetryUpstream>) {
+        let command_id = command.command_id.clone();
+        let outcome = self.verify(&command).and_then(|kind| {
+            info!("Received {} command from the backend", command_name(&kind));
+            let (reply, outcome) = oneshot::channel();
+            self.commands
+                .try_send(RemoteCommand { kind, reply })
+                .map(|_| outcome)
+                .map_err(|_| "Monitor is busy or shutting down".to_string())
+        });
+
+        let upstream = upstream.clone();
+        tokio::spawn(async move {
+            let result = match outcome {
+                Ok(outcome) => {
+                    outcome.await.unwrap_or_else(|_| Err("Command was dropped".to_string()))
+                }
+                Err(e) => {
+                    warn!("Refused command from the backend: {}", e);
+                    Err(e)
+                }
+            };
+            let (success, message) = match result {
+                Ok(message) => (true, message),
+                Err(message) => (false, message),
+            };
+            let result = CommandResult { command_id, success, message };
+            let message =
+                TelemetryUpstream { id: UNACKED_ID, payload: Some(Payload::CommandResult(result)) };
+            // Lost along with the stream if it closed in the meantime
+            let _ = upstream.send(message).await;
+        });
+    }
+
+    fn verify(&self, command: &SignedCommand) -> Result<Kind, String> {
+        let command_id = Uuid::from_slice(&command.command_id)
+            .map_err(|_| "Command id is invalid".to_string())?;
+        let (payload, kind) = command
+            .command
+            .as_ref()
+            .and_then(|payload| Some((payload, payload.kind.as_ref()?)))
+            .ok_or_else(|| "Command is empty".to_string())?;
+        let signer = self.config.signer.ok_or_else(|| "No command signer is pinned".to_string())?;
+
+        if command.machine_id != self.machine_id.as_bytes() {
+            return Err("Command is for another machine".to_string());
+        }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+        if now > command.expires_at as u128 {
+            return Err("Command has expired".to_string());
+        }
+        let signature = Signature::try_from(command.signature.as_slice())
+            .map_err(|_| "Command signature is invalid".to_string())?;
+        let digest = command_digest(&self.machine_id, &command_id, command.expires_at, payload);
+        if recover_from_hash(digest, &signature).ok() != Some(signer) {
+            return Err("Command is not signed by the pinned command signer".to_string());
+        }
+        let name = command_name(kind);
+        if !self.config.allows(name) {
+            return Err(format!("Command {name} is not allowed on this machine"));
+        }
+        let mut seen = self.seen.lock().expect("command lock poisoned");
+        seen.retain(|_, expires_at| *expires_at as u128 >= now);
+        if seen.insert(command_id, command.expires_at).is_some() {
+            return Err("Command was already received".to_string());
+        }
+        Ok(kind.clone())
+    }
+}
+
+#[cfg(test)]
+mod stream_tests {
+    use ivynet_grpc::messages::{Command, Rescan, UpdateNode};
+    use ivynet_signer::{sign_utils::sign_hash, IvyWallet};
+
+    use super::*;
+
+    fn signed(
+        wallet: &IvyWallet,
+        machine_id: Uuid,
+        command_id: Uuid,
+        expires_at: u64,
+        kind: Kind,
+    ) -> SignedCommand {
+        let command = Command { kind: Some(kind) };
+        let digest = command_digest(&machine_id, &command_id, expires_at, &command);
+        SignedCommand {
+            signature: sign_hash(digest, wallet).unwrap().to_vec(),
+            machine_id: machine_id.as_bytes().to_vec(),
+            command_id: command_id.as_bytes().to_vec(),
+            expires_at,
+            command: Some(command),
+        }
+    }
+
+    #[test]
+    fn test_command_gate_verification() {
+        let backend = IvyWallet::new();
+        let machine_id = Uuid::new_v4();
+        let (tx, _rx) = mpsc::channel(1);
+        let (signers, _signers_rx) = mpsc::channel(1);
+        let config = RemoteCommands { signer: Some(backend.address()), ..Default::default() };
+        let gate = CommandGate::new(machine_id, config, tx, signers);
+        let later =
+            SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64 + 60_000;
+        let rescan = || Kind::Rescan(Rescan {});
+
+        let command = signed(&backend, machine_id, Uuid::new_v4(), later, rescan());
+        assert!(gate.verify(&command).is_ok());
+        assert_eq!(gate.verify(&command).unwrap_err(), "Command was already received");
+
+        let expired = signed(&backend, machine_id, Uuid::new_v4(), 1, rescan());
+        assert_eq!(gate.verify(&expired).unwrap_err(), "Command has expired");
+
+        // Ids are forgotten once their command expired
+        gate.seen.lock().unwrap().insert(Uuid::new_v4(), 1);
+        let fresh = signed(&backend, machine_id, Uuid::new_v4(), later, rescan());
+        assert!(gate.verify(&fresh).is_ok());
+        assert_eq!(gate.seen.lock().unwrap().len(), 2);
+
+        let other_machine = signed(&backend, Uuid::new_v4(), Uuid::new_v4(), later, rescan());
+        assert_eq!(gate.verify(&other_machine).unwrap_err(), "Command is for another machine");
+
+        let forged = signed(&IvyWallet::new(), machine_id, Uuid::new_v4(), later, rescan());
+        assert!(gate.verify(&forged).is_err());
+
+        let mut tampered = signed(&backend, machine_id, Uuid::new_v4(), later, rescan());
+        tampered.expires_at += 1;
+        assert!(gate.verify(&tampered).is_err());
+
+        // Changing node configuration is not allowed by default
+        let update = Kind::UpdateNode(UpdateNode {
+            assigned_name: "eigenda".to_string(),
+            metric_port: Some(9092),
+            metrics_path: None,
+        });
+        let update = signed(&backend, machine_id, Uuid::new_v4(), later, update);
+        assert!(gate.verify(&update).unwrap_err().contains("not allowed"));
+    }
+
+    #[test]
+    fn test_command_gate_requires_pinned_signer() {
+        let backend = IvyWallet::new();
+        let machine_id = Uuid::new_v4();
+        let (tx, _rx) = mpsc::channel(1);
+        let (signers, _signers_rx) = mpsc::channel(1);
+        let gate = CommandGate::new(machine_id, RemoteCommands::default(), tx, signers);
+        let command =
+            signed(&backend, machine_id, Uuid::new_v4(), u64::MAX, Kind::Rescan(Rescan {}));
+        assert_eq!(gate.verify(&command).unwrap_err(), "No command signer is pinned");
+    }
+}
@@ -0,0 +1,90 @@
+use std::io::IsTerminal;
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::error::Error;
+
+/// Exit code for invalid flags, rules files or missing non-interactive input. Matches the code
+/// clap exits with on usage errors.
+pub const EXIT_USAGE: u8 = 2;
+/// Exit code when no credentials were given, or the backend rejected them.
+pub const EXIT_AUTH: u8 = 3;
+/// Exit code when the backend could not be reached.
+pub const EXIT_BACKEND_UNAVAILABLE: u8 = 4;
+/// Exit code when a node name is already taken on this machine.
+pub const EXIT_NAME_CONFLICT: u8 = 5;
+/// Exit code for any other failure.
+pub const EXIT_FAILURE: u8 = 1;
+
+/// Failures of commands run without a terminal, e.g. from cloud-init or Ansible. Each failure maps
+/// to its own exit code so rollouts can tell retryable errors from misconfiguration.
+#[derive(Debug, thiserror::Error)]
+pub enum UnattendedError {
+    #[error("{0} is required when not running in a terminal")]
+    MissingInput(String),
+
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+
+    #[error("No credentials given. Provide --email with --password-env or --password-file")]
+    MissingCredentials,
+
+    #[error("Registration rejected by the backend: {0}")]
+    RegistrationFailed(String),
+
+    #[error("Backend unavailable: {0}")]
+    BackendUnavailable(String),
+
+    #[error("Node name {name} for container {container} is already in use")]
+    NameConflict { name: String, container: String },
+}
+
+impl UnattendedError {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::MissingInput(_) | Self::InvalidInput(_) => EXIT_USAGE,
+            Self::MissingCredentials | Self::RegistrationFailed(_) => EXIT_AUTH,
+            Self::BackendUnavailable(_) => EXIT_BACKEND_UNAVAILABLE,
+            Self::NameConflict { .. } => EXIT_NAME_CONFLICT,
+        }
+    }
+}
+
+/// Exit code for an error returned from a command. Errors raised outside of the unattended paths
+/// exit with `EXIT_FAILURE`.
+pub fn exit_code(error: &anyhow::Error) -> u8 {
+    error
+        .chain()
+        .find_map(|e| match e.downcast_ref::<Error>() {
+            Some(Error::Unattended(e)) => Some(e),
+            _ => e.downcast_ref::<UnattendedError>(),
+        })
+        .map_or(EXIT_FAILURE, UnattendedError::exit_code)
+}
+
+/// Whether prompts can be shown. Prompts need both a terminal to read from and one to draw on.
+pub fn is_interactive() -> bool {
+    std::io::stdin().is_terminal() && std::io::stderr().is_terminal()
+}
+
+/// Format of the result printed by a command on success.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl OutputFormat {
+    /// Prints `report` as a single line of JSON, or as the text produced by `text`.
+    pub fn print<T: Serialize>(self, report: &T, text: impl FnOnce(&T) -> String) {
+        match self {
+            Self::Text => println!("{}", text(report)),
+            Self::Json => println!(
+                "{}",
+                serde_json::to_string(report).expect("Reports always serialize to JSON")
+            ),
+        }
+    }
+}
@@ -1,7 +1,9 @@
-use std::str::FromStr;
+use std::{str::FromStr, time::Duration};
 
+use chrono::{DateTime, TimeDelta, Utc};
 use clap::Parser;
 use ivynet_grpc::client::Uri;
+use ivynet_heartbeat::HeartbeatConfig;
 use ivynet_notifications::{NotificationConfig, SendgridSpecificTemplates, SendgridTemplates};
 use tracing::Level;
 
@@ -30,6 +32,42 @@ pub struct Config {
     #[arg(long, env = "IVY_GRPC_PORT", default_value_t = 50050)]
     pub grpc_port: u16,
 
+    /// How far the signing time of a message may be from the server time, in seconds
+    #[arg(long, env = "IVY_SIGNATURE_MAX_SKEW_SECS", default_value_t = 300)]
+    pub signature_max_skew_secs: u64,
+
+    /// Time (RFC 3339) from which messages with legacy signatures are rejected. Legacy signatures
+    /// are accepted as long as this is not set.
+    #[arg(long, env = "IVY_LEGACY_SIGNATURES_UNTIL")]
+    pub legacy_signatures_until: Option<DateTime<Utc>>,
+
+    /// How long a telemetry session stays valid, in seconds
+    #[arg(long, env = "IVY_SESSION_TTL_SECS", default_value_t = 900)]
+    pub session_ttl_secs: u64,
+
+    /// Heartbeats of an entry received within this many seconds of the last one written are not
+    /// written to the database again. 0 writes every heartbeat.
+    #[arg(long, env = "IVY_HEARTBEAT_WRITE_INTERVAL_SECS", default_value_t = 30)]
+    pub heartbeat_write_interval_secs: u64,
+
+    /// How long a client, machine or node may go without a heartbeat before it is reported, in
+    /// seconds. Organizations can set their own in their heartbeat settings.
+    #[arg(long, env = "IVY_HEARTBEAT_TTL_SECS", default_value_t = 900)]
+    pub heartbeat_ttl_secs: u64,
+
+    /// How often heartbeats are checked against their TTL, in seconds
+    #[arg(long, env = "IVY_HEARTBEAT_SWEEP_INTERVAL_SECS", default_value_t = 60)]
+    pub heartbeat_sweep_interval_secs: u64,
+
+    /// How often unacknowledged alerts are escalated by the escalation policy of their
+    /// organization, in seconds
+    #[arg(long, env = "IVY_ESCALATION_INTERVAL_SECS", default_value_t = 60)]
+    pub escalation_interval_secs: u64,
+
+    /// Private key machine commands are signed with. No commands are sent without it.
+    #[arg(long, env = "IVY_COMMAND_KEY", hide_env_values = true)]
+    pub command_key: Option<String>,
+
     #[arg(long, env = "IVY_EVENTS_TLS_CA")]
     pub events_tls_ca: Option<String>,
 
@@ -107,6 +145,15 @@ pub struct Config {
     pub stn_updated_eigen_avs: Option<String>,
 }
 
+impl From<&Config> for HeartbeatConfig {
+    fn from(val: &Config) -> Self {
+        HeartbeatConfig {
+            ttl: TimeDelta::seconds(val.heartbeat_ttl_secs as i64),
+            sweep_interval: Duration::from_secs(val.heartbeat_sweep_interval_secs),
+        }
+    }
+}
+
 impl From<Config> for NotificationConfig {
     fn from(val: Config) -> Self {
         NotificationConfig {
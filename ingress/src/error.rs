@@ -12,6 +12,9 @@ pub enum IngressError {
     #[error(transparent)]
     DatabaseError(#[from] ivynet_database::error::DatabaseError),
 
+    #[error(transparent)]
+    WalletError(#[from] ivynet_signer::IvyWalletError),
+
     #[error(transparent)]
     NotificationDispatcherError(#[from] ivynet_notifications::NotificationDispatcherError),
 }
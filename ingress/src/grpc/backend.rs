@@ -1,49 +1,85 @@
 use crate::error::IngressError;
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
 use ivynet_database::{
     alerts::{
-        alert_db::AlertDb, machine::alert_handler::MachineAlertHandler,
-        node::alert_handler::NodeAlertHandler,
+        alert_db::AlertDb, escalation::EscalationHandler,
+        machine::alert_handler::MachineAlertHandler, node::alert_handler::NodeAlertHandler,
     },
     client_log::ClientLog,
     data::{
         machine_data::convert_system_metrics,
         node_data::{update_avs_active_set, update_avs_version},
     },
+    error::DatabaseError,
     log::{ContainerLog, LogLevel},
     metric::Metric,
-    Account, Avs, AvsVersionHash, Machine,
+    Account, Avs, AvsVersionHash, Client, Machine, MachineCommand,
 };
-use ivynet_error::ethers::types::Address;
+use ivynet_error::ethers::types::{Address, Signature};
 
 use ivynet_docker_registry::node_types::get_node_type;
 use ivynet_grpc::{
     self,
     backend::backend_server::{Backend, BackendServer},
+    capabilities::{
+        check_protocol, FEATURE_HEARTBEATS, FEATURE_REMOTE_COMMANDS, FEATURE_SESSIONS,
+        FEATURE_TELEMETRY_STREAM, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+    },
     client::{Request, Response},
+    heartbeat::heartbeat_server::HeartbeatServer,
     messages::{
-        MachineData, Metrics, NodeData, NodeDataV2, NodeType as NodeTypeMessage, NodeTypeQueries,
-        NodeTypes, RegistrationCredentials, SignedClientLog, SignedLog, SignedMachineData,
-        SignedMetrics, SignedNameChange, SignedNodeData, SignedNodeDataV2,
+        telemetry_downstream, telemetry_upstream, ClientHello, Command, CommandResult, MachineData,
+        Metrics, NodeDataV2, NodeType as NodeTypeMessage, NodeTypeQueries, NodeTypes, Pong,
+        RegistrationCredentials, ServerHello, Session, SignedClientLog, SignedCommand,
+        SignedKeyRotation, SignedLog, SignedLogBatch, SignedMachineData, SignedMetrics,
+        SignedNameChange, SignedNodeDataV2, SignedPing, SignedSessionRequest, StreamWelcome,
+        TelemetryAck, TelemetryDownstream, TelemetryUpstream,
     },
-    server, Status,
+    server,
+    tonic::Streaming,
+    Status,
 };
 
 use ivynet_docker::logs::{find_log_level, find_or_create_log_timestamp, sanitize_log};
-use ivynet_heartbeat::{HeartbeatMonitor, MachineId, NodeId};
+use ivynet_heartbeat::{
+    store::{CachedHeartbeatStore, PgHeartbeatStore},
+    ClientId, HeartbeatConfig, HeartbeatMonitor, MachineId, NodeId,
+};
 use ivynet_node_type::NodeType;
 use ivynet_notifications::{NotificationConfig, NotificationDispatcher};
 use sqlx::PgPool;
-use std::{str::FromStr, sync::Arc};
-use tracing::debug;
+use std::{
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{debug, error, warn};
 use uuid::Uuid;
 
-use super::data_validator::validate_request;
+use ivynet_signer::{
+    sign_utils::{recover_from_hash, recover_key_rotation, sign_hash},
+    typed_data::{command_digest, key_rotation_digest, Envelope, SIGNATURE_VERSION},
+    IvyWallet,
+};
 
+use super::{
+    data_validator::{validate_request, SignatureFields, SignaturePolicy},
+    heartbeat::HeartbeatService,
+    session::{self, SessionStore},
+};
+
+#[derive(Clone)]
 pub struct BackendService {
     pub node_alert_handler: NodeAlertHandler,
     pub machine_alert_handler: MachineAlertHandler,
     pub heartbeats: HeartbeatMonitor<AlertDb>,
     pool: PgPool,
+    signatures: Arc<SignaturePolicy>,
+    sessions: Arc<SessionStore>,
+    /// Key commands for machines are signed with. Commands stay queued while it is not set.
+    command_signer: Option<Arc<IvyWallet>>,
 }
 
 impl BackendService {
@@ -52,59 +88,205 @@ impl BackendService {
         heartbeats: HeartbeatMonitor<AlertDb>,
         node_alert_handler: NodeAlertHandler,
         machine_alert_handler: MachineAlertHandler,
+        signatures: SignaturePolicy,
+        sessions: Arc<SessionStore>,
+        command_signer: Option<IvyWallet>,
     ) -> Self {
-        Self { node_alert_handler, machine_alert_handler, heartbeats, pool }
+        Self {
+            node_alert_handler,
+            machine_alert_handler,
+            heartbeats,
+            pool,
+            signatures: Arc::new(signatures),
+            sessions,
+            command_signer: command_signer.map(Arc::new),
+        }
     }
-}
 
-type NameChange = (String, String); //Old name, new name
+    /// Features offered to clients in `Hello`. Commands are only offered with a key to sign them.
+    fn features(&self) -> Vec<String> {
+        let mut features = vec![
+            FEATURE_SESSIONS.to_string(),
+            FEATURE_TELEMETRY_STREAM.to_string(),
+            FEATURE_HEARTBEATS.to_string(),
+        ];
+        if self.command_signer.is_some() {
+            features.push(FEATURE_REMOTE_COMMANDS.to_string());
+        }
+        features
+    }
 
-#[ivynet_grpc::async_trait]
-impl Backend for BackendService {
-    async fn register(
+    /// Serves an open telemetry stream until the machine closes it or its session expires or is
+    /// revoked. Every upstream message is acknowledged with the status it was handled with, and
+    /// queued commands are pushed as they come in.
+    async fn run_telemetry_stream(
+        self,
+        session: session::Session,
+        mut upstream: Streaming<TelemetryUpstream>,
+        downstream: mpsc::Sender<Result<TelemetryDownstream, Status>>,
+    ) {
+        let machine_id = session.machine_id;
+        let lifetime = session.expires_at().duration_since(SystemTime::now()).unwrap_or_default();
+        let expiry = tokio::time::sleep(lifetime);
+        tokio::pin!(expiry);
+        let mut command_poll = tokio::time::interval(COMMAND_POLL_INTERVAL);
+        let mut session_check = tokio::time::interval(SESSION_CHECK_INTERVAL);
+        session_check.tick().await;
+
+        loop {
+            tokio::select! {
+                message = upstream.message() => match message {
+                    Ok(Some(message)) => {
+                        let ack = self.handle_upstream(session, message).await;
+                        if downstream.send(Ok(ack)).await.is_err() {
+                            break;
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(e) => {
+                        debug!("Telemetry stream of machine {machine_id} failed: {e}");
+                        break;
+                    }
+                },
+                _ = command_poll.tick(), if self.command_signer.is_some() => {
+                    if let Err(e) = self.push_commands(machine_id, &downstream).await {
+                        warn!("Failed to push commands to machine {machine_id}: {e}");
+                    }
+                }
+                _ = session_check.tick() => match self.sessions.is_revoked(&session).await {
+                    Ok(false) => {}
+                    Ok(true) => {
+                        debug!("Session of the telemetry stream of machine {machine_id} was revoked");
+                        let _ = downstream
+                            .send(Err(Status::unauthenticated("Session was revoked")))
+                            .await;
+                        break;
+                    }
+                    Err(e) => warn!("Failed to check the session of machine {machine_id}: {e}"),
+                },
+                _ = &mut expiry => {
+                    debug!("Session of the telemetry stream of machine {machine_id} expired");
+                    break;
+                }
+            }
+        }
+    }
+
+    async fn handle_upstream(
         &self,
-        request: Request<RegistrationCredentials>,
-    ) -> Result<Response<()>, Status> {
-        let req = request.into_inner();
-        let account =
-            Account::verify(&self.pool, &req.email, &req.password).await.map_err(|_| {
-                Status::not_found(format!("User {} not found or password is incorrect", req.email))
-            })?;
-        let client_id = Address::from_slice(&req.public_key);
-        account
-            .attach_client(
-                &self.pool,
-                &client_id,
-                Uuid::from_slice(&req.machine_id)
-                    .map_err(|_| Status::invalid_argument("Wrong machine_id size".to_string()))?,
-                &req.hostname,
-            )
-            .await
-            .map_err(|_| Status::not_found(format!("Cannot register new node for {account:?}",)))?;
-        debug!(
-            "User {} has registered new client with address {:?} and machine id {:?}",
-            &req.email, client_id, req.machine_id
-        );
+        session: session::Session,
+        message: TelemetryUpstream,
+    ) -> TelemetryDownstream {
+        use telemetry_upstream::Payload;
+
+        let result = match message.payload {
+            Some(Payload::Metrics(metrics)) => self.handle_metrics(Some(session), metrics).await,
+            Some(Payload::LogBatch(logs)) => self.handle_logs_batch(Some(session), logs).await,
+            Some(Payload::Log(log)) => self.handle_logs(Some(session), log).await,
+            Some(Payload::NodeData(node_data)) => {
+                self.handle_node_data_v2(Some(session), node_data).await
+            }
+            Some(Payload::MachineData(machine_data)) => {
+                self.handle_machine_data(Some(session), machine_data).await
+            }
+            Some(Payload::ClientLog(log)) => self.handle_client_logs(Some(session), log).await,
+            Some(Payload::CommandResult(result)) => {
+                self.record_command_result(&session, result).await
+            }
+            Some(Payload::Hello(_)) => {
+                Err(Status::failed_precondition("Telemetry stream is already open"))
+            }
+            None => Err(Status::invalid_argument("Telemetry message is empty")),
+        };
 
-        Ok(Response::new(()))
+        let (code, status_message) = match result {
+            Ok(()) => (0, String::new()),
+            Err(status) => (status.code() as i32, status.message().to_string()),
+        };
+        TelemetryDownstream {
+            payload: Some(telemetry_downstream::Payload::Ack(TelemetryAck {
+                id: message.id,
+                code,
+                message: status_message,
+            })),
+        }
     }
 
-    async fn logs(&self, request: Request<SignedLog>) -> Result<Response<()>, Status> {
-        let request = request.into_inner();
+    async fn push_commands(
+        &self,
+        machine_id: Uuid,
+        downstream: &mpsc::Sender<Result<TelemetryDownstream, Status>>,
+    ) -> Result<(), Status> {
+        let Some(signer) = &self.command_signer else {
+            return Ok(());
+        };
+        for command in MachineCommand::pending(&self.pool, machine_id).await? {
+            let expires_at = command.expires_at.and_utc().timestamp_millis().max(0) as u64;
+            let payload = Command::from(&command.command);
+            let digest = command_digest(&machine_id, &command.id, expires_at, &payload);
+            let signature = sign_hash(digest, signer)
+                .map_err(|e| Status::internal(format!("Failed to sign command: {e}")))?;
+            let signed = SignedCommand {
+                signature: signature.to_vec(),
+                machine_id: machine_id.as_bytes().to_vec(),
+                command_id: command.id.as_bytes().to_vec(),
+                expires_at,
+                command: Some(payload),
+            };
+            let message = TelemetryDownstream {
+                payload: Some(telemetry_downstream::Payload::Command(signed)),
+            };
+            if downstream.send(Ok(message)).await.is_err() {
+                break;
+            }
+            MachineCommand::mark_delivered(&self.pool, command.id).await?;
+            debug!("Sent command {} to machine {machine_id}", command.id);
+        }
+        Ok(())
+    }
+
+    async fn record_command_result(
+        &self,
+        session: &session::Session,
+        result: CommandResult,
+    ) -> Result<(), Status> {
+        let command_id = Uuid::from_slice(&result.command_id)
+            .map_err(|_| Status::invalid_argument("Command id is invalid"))?;
+        MachineCommand::record_result(
+            &self.pool,
+            session.machine_id,
+            command_id,
+            result.success,
+            &result.message,
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn handle_logs(
+        &self,
+        session: Option<session::Session>,
+        request: SignedLog,
+    ) -> Result<(), Status> {
         debug!("Received logs: {:?}", request.log);
 
-        let signed_data = validate_request::<String, SignedLog>(
+        let signed_data = validate_request::<Log, SignedLog>(
             &self.pool,
-            &request.machine_id,
-            &request.signature,
-            Some(request.log),
+            &self.signatures,
+            session,
+            SignatureFields {
+                machine_id: &request.machine_id,
+                signature: &request.signature,
+                version: request.signature_version,
+                timestamp: request.timestamp,
+                sequence: request.sequence,
+            },
+            Some((request.avs_name, request.log)),
         )
         .await?;
 
         let machine_id = signed_data.machine_id;
-        let log = signed_data.data;
-
-        let avs_name = request.avs_name;
+        let (avs_name, log) = signed_data.data;
         let log = sanitize_log(log.as_str());
         let log_level = LogLevel::from_str(&find_log_level(&log))
             .map_err(|_| Status::invalid_argument("Log level is invalid".to_string()))?;
@@ -119,21 +301,77 @@ impl Backend for BackendService {
         };
         debug!("STORING LOG: {:?}", log);
 
-        ContainerLog::record(&self.pool, &log)
-            .await
-            .map_err(|e| Status::internal(format!("Failed while saving logs: {e:?}")))?;
+        ContainerLog::record(&self.pool, &log).await.map_err(log_write_status)?;
 
-        Ok(Response::new(()))
+        Ok(())
     }
 
-    async fn client_logs(&self, request: Request<SignedClientLog>) -> Result<Response<()>, Status> {
-        let request = request.into_inner();
+    async fn handle_logs_batch(
+        &self,
+        session: Option<session::Session>,
+        request: SignedLogBatch,
+    ) -> Result<(), Status> {
+        debug!("Received log batch of {} lines for {}", request.logs.len(), request.avs_name);
+
+        let signed_data = validate_request::<LogBatch, SignedLogBatch>(
+            &self.pool,
+            &self.signatures,
+            session,
+            SignatureFields {
+                machine_id: &request.machine_id,
+                signature: &request.signature,
+                version: request.signature_version,
+                timestamp: request.timestamp,
+                sequence: request.sequence,
+            },
+            Some((request.avs_name, request.logs)),
+        )
+        .await?;
+
+        let machine_id = signed_data.machine_id;
+        let (avs_name, logs) = signed_data.data;
+
+        let logs = logs
+            .iter()
+            .map(|log| {
+                let log = sanitize_log(log.as_str());
+                let log_level = LogLevel::from_str(&find_log_level(&log))
+                    .map_err(|_| Status::invalid_argument("Log level is invalid".to_string()))?;
+                let created_at = Some(find_or_create_log_timestamp(&log));
+                Ok(ContainerLog {
+                    machine_id,
+                    avs_name: avs_name.clone(),
+                    log,
+                    log_level,
+                    created_at,
+                    other_fields: None,
+                })
+            })
+            .collect::<Result<Vec<_>, Status>>()?;
+
+        ContainerLog::record_batch(&self.pool, &logs).await.map_err(log_write_status)?;
+
+        Ok(())
+    }
+
+    async fn handle_client_logs(
+        &self,
+        session: Option<session::Session>,
+        request: SignedClientLog,
+    ) -> Result<(), Status> {
         debug!("Received logs: {:?}", request.log);
 
         let signed_data = validate_request::<String, SignedClientLog>(
             &self.pool,
-            &request.machine_id,
-            &request.signature,
+            &self.signatures,
+            session,
+            SignatureFields {
+                machine_id: &request.machine_id,
+                signature: &request.signature,
+                version: request.signature_version,
+                timestamp: request.timestamp,
+                sequence: request.sequence,
+            },
             Some(request.log),
         )
         .await?;
@@ -152,25 +390,31 @@ impl Backend for BackendService {
             .await
             .map_err(|e| Status::internal(format!("Failed while saving logs: {e:?}")))?;
 
-        Ok(Response::new(()))
+        Ok(())
     }
 
-    async fn machine_data(
+    async fn handle_machine_data(
         &self,
-        request: Request<SignedMachineData>,
-    ) -> Result<Response<()>, Status> {
-        let req = request.into_inner();
-
-        let signed_data = validate_request::<MachineData, SignedMachineData>(
+        session: Option<session::Session>,
+        req: SignedMachineData,
+    ) -> Result<(), Status> {
+        let signed_data = validate_request::<CollectedMachineData, SignedMachineData>(
             &self.pool,
-            &req.machine_id,
-            &req.signature,
-            req.machine_data,
+            &self.signatures,
+            session,
+            SignatureFields {
+                machine_id: &req.machine_id,
+                signature: &req.signature,
+                version: req.signature_version,
+                timestamp: req.timestamp,
+                sequence: req.sequence,
+            },
+            req.machine_data.map(|machine_data| (machine_data, req.collected_at)),
         )
         .await?;
 
         let machine_id = signed_data.machine_id;
-        let machine_data = signed_data.data;
+        let (machine_data, collected_at) = signed_data.data;
 
         self.machine_alert_handler
             .handle_machine_data_alerts(&self.pool, machine_id, &machine_data)
@@ -190,6 +434,7 @@ impl Backend for BackendService {
             machine_id,
             None,
             &system_metrics.iter().map(|v| v.into()).collect::<Vec<_>>(),
+            collection_time(collected_at),
         )
         .await
         .map_err(|e| Status::internal(format!("Failed while saving system metrics: {e:?}")))?;
@@ -198,16 +443,25 @@ impl Backend for BackendService {
         let machine_id = MachineId::new(machine_id);
         self.heartbeats.post_machine_heartbeat(machine_id).await?;
 
-        Ok(Response::new(()))
+        Ok(())
     }
 
-    async fn node_data(&self, request: Request<SignedNodeData>) -> Result<Response<()>, Status> {
-        let req = request.into_inner();
-
-        let signed_data = validate_request::<NodeData, SignedNodeData>(
+    async fn handle_node_data_v2(
+        &self,
+        session: Option<session::Session>,
+        req: SignedNodeDataV2,
+    ) -> Result<(), Status> {
+        let signed_data = validate_request::<NodeDataV2, SignedNodeDataV2>(
             &self.pool,
-            &req.machine_id,
-            &req.signature,
+            &self.signatures,
+            session,
+            SignatureFields {
+                machine_id: &req.machine_id,
+                signature: &req.signature,
+                version: req.signature_version,
+                timestamp: req.timestamp,
+                sequence: req.sequence,
+            },
             req.node_data,
         )
         .await?;
@@ -215,78 +469,223 @@ impl Backend for BackendService {
         let machine_id = signed_data.machine_id;
         let node_data = signed_data.data;
 
-        let recovered_node_data = RecoveredNodeData::from(node_data);
+        process_node_data(&self.pool, machine_id, node_data.clone()).await?;
 
-        process_node_data(&self.pool, machine_id, recovered_node_data.clone()).await?;
+        let node_running = node_data.node_running.unwrap_or(false);
+        let name = node_data.name.clone();
+        self.node_alert_handler.handle_node_data_alerts(node_data, machine_id).await.map_err(
+            |e| Status::internal(format!("Failed while sending node data to alert actor: {e}")),
+        )?;
 
-        // Heartbeat
-        let node_id = NodeId::new(machine_id, recovered_node_data.name);
-        self.heartbeats.post_node_heartbeat(node_id).await?;
+        // heartbeat
+        if node_running {
+            let node_id = NodeId::new(machine_id, name);
+            self.heartbeats.post_node_heartbeat(node_id).await?;
+        }
         let machine_id = MachineId::new(machine_id);
         self.heartbeats.post_machine_heartbeat(machine_id).await?;
 
-        Ok(Response::new(()))
+        Ok(())
     }
 
-    async fn node_data_v2(
+    async fn handle_metrics(
         &self,
-        request: Request<SignedNodeDataV2>,
-    ) -> Result<Response<()>, Status> {
-        let req = request.into_inner();
-
-        let signed_data = validate_request::<NodeDataV2, SignedNodeDataV2>(
+        session: Option<session::Session>,
+        req: SignedMetrics,
+    ) -> Result<(), Status> {
+        let signed_data = validate_request::<MetricsBatch, SignedMetrics>(
             &self.pool,
-            &req.machine_id,
-            &req.signature,
-            req.node_data,
+            &self.signatures,
+            session,
+            SignatureFields {
+                machine_id: &req.machine_id,
+                signature: &req.signature,
+                version: req.signature_version,
+                timestamp: req.timestamp,
+                sequence: req.sequence,
+            },
+            Some((req.avs_name, req.metrics, req.collected_at)),
         )
         .await?;
 
         let machine_id = signed_data.machine_id;
-        let node_data = signed_data.data;
+        let (avs_name, metrics, collected_at) = signed_data.data;
+        let metrics = metrics.iter().map(|v| v.into()).collect::<Vec<Metric>>();
 
-        let recovered_node_data = RecoveredNodeData::from(node_data.clone());
+        let collected_at = collection_time(collected_at);
+        // Alerts were already evaluated against newer metrics than a replayed batch
+        let outdated = match avs_name.as_deref() {
+            Some(name) => Metric::latest_collection(&self.pool, machine_id, name)
+                .await
+                .map_err(|e| Status::internal(format!("Failed while reading metrics: {e:?}")))?
+                .is_some_and(|latest| collected_at < latest),
+            None => false,
+        };
+        _ = Metric::record(&self.pool, machine_id, avs_name.as_deref(), &metrics, collected_at)
+            .await
+            .map_err(|e| Status::internal(format!("Failed while saving metrics: {e:?}")))?;
 
-        process_node_data(&self.pool, machine_id, recovered_node_data.clone()).await?;
+        // The metrics are saved, so failing alerts must not make the machine send them again
+        if let Some(avs_name) = avs_name.filter(|_| !outdated) {
+            if let Err(e) = self
+                .node_alert_handler
+                .handle_metric_alerts(machine_id, &avs_name, &metrics, collected_at)
+                .await
+            {
+                error!("Failed to evaluate alert rules for node {avs_name}: {e}");
+            }
+            if let Err(e) = self
+                .node_alert_handler
+                .handle_performance_alert(machine_id, &avs_name, &metrics)
+                .await
+            {
+                error!("Failed to evaluate performance score of node {avs_name}: {e}");
+            }
+        }
 
-        self.node_alert_handler.handle_node_data_alerts(node_data, machine_id).await.map_err(
-            |e| Status::internal(format!("Failed while sending node data to alert actor: {e}")),
-        )?;
+        Ok(())
+    }
+}
 
-        // heartbeat
-        if recovered_node_data.node_running.unwrap_or(false) {
-            let node_id = NodeId::new(machine_id, recovered_node_data.name);
-            self.heartbeats.post_node_heartbeat(node_id).await?;
+type NameChange = (String, String); //Old name, new name
+type LogBatch = (String, Vec<String>); //Avs name, logs
+type Log = (String, String); //Avs name, log
+type MetricsBatch = (Option<String>, Vec<Metrics>, u64); //Avs name, metrics, collected at
+type CollectedMachineData = (MachineData, u64); //Machine data, collected at
+type KeyRotation = (Vec<u8>, Address, u64); //Machine id, new key, timestamp
+
+/// Logs that break a constraint, e.g. for a node the machine does not have, are rejected as
+/// invalid so the machine does not send them again.
+fn log_write_status(e: DatabaseError) -> Status {
+    if e.is_constraint_violation() {
+        Status::invalid_argument(format!("Logs were rejected: {e}"))
+    } else {
+        Status::internal(format!("Failed while saving logs: {e:?}"))
+    }
+}
+
+/// When telemetry was collected, from the unix milliseconds the machine reported. Telemetry that
+/// does not say, e.g. from older clients, and times in the future are taken as collected now.
+fn collection_time(collected_at: u64) -> NaiveDateTime {
+    let now = Utc::now().naive_utc();
+    match DateTime::from_timestamp_millis(collected_at as i64) {
+        Some(time) if collected_at != 0 => time.naive_utc().min(now),
+        _ => now,
+    }
+}
+
+/// How far a key rotation's timestamp may be from the server time
+const KEY_ROTATION_MAX_SKEW_MS: u64 = 5 * 60 * 1000;
+/// How often an open telemetry stream looks for queued commands
+const COMMAND_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// How often an open telemetry stream checks that its key was not rotated out
+const SESSION_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// Downstream messages buffered per telemetry stream
+const TELEMETRY_STREAM_BUFFER: usize = 64;
+
+#[ivynet_grpc::async_trait]
+impl Backend for BackendService {
+    async fn register(
+        &self,
+        request: Request<RegistrationCredentials>,
+    ) -> Result<Response<()>, Status> {
+        let req = request.into_inner();
+        let account =
+            Account::verify(&self.pool, &req.email, &req.password).await.map_err(|_| {
+                Status::not_found(format!("User {} not found or password is incorrect", req.email))
+            })?;
+        let client_id = Address::from_slice(&req.public_key);
+        account
+            .attach_client(
+                &self.pool,
+                &client_id,
+                Uuid::from_slice(&req.machine_id)
+                    .map_err(|_| Status::invalid_argument("Wrong machine_id size".to_string()))?,
+                &req.hostname,
+            )
+            .await
+            .map_err(|_| Status::not_found(format!("Cannot register new node for {account:?}",)))?;
+        debug!(
+            "User {} has registered new client with address {:?} and machine id {:?}",
+            &req.email, client_id, req.machine_id
+        );
+
+        Ok(Response::new(()))
+    }
+
+    async fn hello(&self, request: Request<ClientHello>) -> Result<Response<ServerHello>, Status> {
+        let hello = request.into_inner();
+        if hello.protocol_version < MIN_PROTOCOL_VERSION {
+            return Err(Status::failed_precondition(format!(
+                "Client {} speaks protocol version {}, but the backend requires version {} or \
+                 newer. Please update the client",
+                hello.client_version, hello.protocol_version, MIN_PROTOCOL_VERSION
+            )));
         }
-        let machine_id = MachineId::new(machine_id);
-        self.heartbeats.post_machine_heartbeat(machine_id).await?;
+        debug!(
+            "Client {} connected with protocol version {} and features {:?}",
+            hello.client_version, hello.protocol_version, hello.features
+        );
 
+        let notice = (hello.protocol_version < PROTOCOL_VERSION).then(|| {
+            format!(
+                "Protocol version {} is deprecated, please update the client",
+                hello.protocol_version
+            )
+        });
+        Ok(Response::new(ServerHello {
+            protocol_version: PROTOCOL_VERSION,
+            min_protocol_version: MIN_PROTOCOL_VERSION,
+            features: self.features(),
+            notice,
+        }))
+    }
+
+    async fn logs(&self, request: Request<SignedLog>) -> Result<Response<()>, Status> {
+        check_protocol(&request)?;
+        let session = self.sessions.authorize(&request).await?;
+        self.handle_logs(session, request.into_inner()).await?;
         Ok(Response::new(()))
     }
 
-    async fn metrics(&self, request: Request<SignedMetrics>) -> Result<Response<()>, Status> {
-        let req = request.into_inner();
+    async fn logs_batch(&self, request: Request<SignedLogBatch>) -> Result<Response<()>, Status> {
+        check_protocol(&request)?;
+        let session = self.sessions.authorize(&request).await?;
+        self.handle_logs_batch(session, request.into_inner()).await?;
+        Ok(Response::new(()))
+    }
 
-        let signed_data = validate_request::<Vec<Metrics>, SignedMetrics>(
-            &self.pool,
-            &req.machine_id,
-            &req.signature,
-            Some(req.metrics),
-        )
-        .await?;
+    async fn client_logs(&self, request: Request<SignedClientLog>) -> Result<Response<()>, Status> {
+        check_protocol(&request)?;
+        let session = self.sessions.authorize(&request).await?;
+        self.handle_client_logs(session, request.into_inner()).await?;
+        Ok(Response::new(()))
+    }
 
-        let machine_id = signed_data.machine_id;
-        let metrics = signed_data.data;
+    async fn machine_data(
+        &self,
+        request: Request<SignedMachineData>,
+    ) -> Result<Response<()>, Status> {
+        check_protocol(&request)?;
+        let session = self.sessions.authorize(&request).await?;
+        self.handle_machine_data(session, request.into_inner()).await?;
+        Ok(Response::new(()))
+    }
 
-        _ = Metric::record(
-            &self.pool,
-            machine_id,
-            req.avs_name.as_deref(),
-            &metrics.iter().map(|v| v.into()).collect::<Vec<_>>(),
-        )
-        .await
-        .map_err(|e| Status::internal(format!("Failed while saving metrics: {e:?}")))?;
+    async fn node_data_v2(
+        &self,
+        request: Request<SignedNodeDataV2>,
+    ) -> Result<Response<()>, Status> {
+        check_protocol(&request)?;
+        let session = self.sessions.authorize(&request).await?;
+        self.handle_node_data_v2(session, request.into_inner()).await?;
+        Ok(Response::new(()))
+    }
 
+    async fn metrics(&self, request: Request<SignedMetrics>) -> Result<Response<()>, Status> {
+        check_protocol(&request)?;
+        let session = self.sessions.authorize(&request).await?;
+        self.handle_metrics(session, request.into_inner()).await?;
         Ok(Response::new(()))
     }
 
@@ -331,8 +730,15 @@ impl Backend for BackendService {
 
         let signed_data = validate_request::<NameChange, SignedNameChange>(
             &self.pool,
-            &req.machine_id,
-            &req.signature,
+            &self.signatures,
+            None,
+            SignatureFields {
+                machine_id: &req.machine_id,
+                signature: &req.signature,
+                version: req.signature_version,
+                timestamp: req.timestamp,
+                sequence: req.sequence,
+            },
             Some((req.old_name, req.new_name)),
         )
         .await?;
@@ -352,11 +758,208 @@ impl Backend for BackendService {
 
         Ok(Response::new(()))
     }
+
+    async fn ping(&self, request: Request<SignedPing>) -> Result<Response<Pong>, Status> {
+        let req = request.into_inner();
+
+        // Fails unless the signing key is registered for the machine
+        validate_request::<u64, SignedPing>(
+            &self.pool,
+            &self.signatures,
+            None,
+            SignatureFields {
+                machine_id: &req.machine_id,
+                signature: &req.signature,
+                version: req.signature_version,
+                timestamp: req.timestamp,
+                sequence: req.sequence,
+            },
+            Some(req.timestamp),
+        )
+        .await?;
+
+        let server_time =
+            SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+        Ok(Response::new(Pong { server_time }))
+    }
+
+    async fn rotate_key(
+        &self,
+        request: Request<SignedKeyRotation>,
+    ) -> Result<Response<()>, Status> {
+        let req = request.into_inner();
+        if req.new_public_key.len() != Address::len_bytes() {
+            return Err(Status::invalid_argument("New public key has wrong length".to_string()));
+        }
+        let new_client_id = Address::from_slice(&req.new_public_key);
+
+        // The current key has to own the machine, which also stops a rotation from being replayed
+        // once it went through
+        let signed_data = validate_request::<KeyRotation, SignedKeyRotation>(
+            &self.pool,
+            &self.signatures,
+            None,
+            SignatureFields {
+                machine_id: &req.machine_id,
+                signature: &req.signature,
+                version: req.signature_version,
+                timestamp: req.timestamp,
+                sequence: req.sequence,
+            },
+            Some((req.machine_id.clone(), new_client_id, req.timestamp)),
+        )
+        .await?;
+
+        let server_time =
+            SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+        if server_time.abs_diff(req.timestamp) > KEY_ROTATION_MAX_SKEW_MS {
+            return Err(Status::invalid_argument("Key rotation has expired".to_string()));
+        }
+
+        let new_key_signature = Signature::try_from(req.new_key_signature.as_slice())
+            .map_err(|_| Status::invalid_argument("New key signature is invalid"))?;
+        let new_key_signer = if req.signature_version == SIGNATURE_VERSION {
+            let envelope = Envelope {
+                machine_id: signed_data.machine_id,
+                timestamp: req.timestamp,
+                sequence: req.sequence,
+            };
+            recover_from_hash(key_rotation_digest(&envelope, &new_client_id), &new_key_signature)
+        } else {
+            recover_key_rotation(&req.machine_id, &new_client_id, req.timestamp, &new_key_signature)
+        }
+        .map_err(|e| {
+            Status::invalid_argument(format!("Failed to recover new key signature: {e}"))
+        })?;
+        if new_key_signer != new_client_id {
+            return Err(Status::permission_denied("New key signature does not match the key"));
+        }
+
+        if Client::get(&self.pool, &new_client_id).await?.is_some() {
+            return Err(Status::already_exists("New key is already registered".to_string()));
+        }
+
+        let old_client_id = signed_data.client_id;
+        Client::rotate(&self.pool, &old_client_id, &new_client_id).await.map_err(|e| match e {
+            DatabaseError::SharedClient(machines) => Status::failed_precondition(format!(
+                "The key is shared by {machines} machines, so rotating it here would rotate it \
+                 for all of them. Register this machine with a key of its own instead"
+            )),
+            e => Status::internal(format!("Failed to rotate key: {e:?}")),
+        })?;
+        if let Err(e) = self
+            .heartbeats
+            .rotate_client(ClientId::new(old_client_id), ClientId::new(new_client_id))
+            .await
+        {
+            warn!("Failed to move heartbeat of rotated client {:?}: {}", old_client_id, e);
+        }
+
+        debug!(
+            "Machine {} rotated its client key from {:?} to {:?}",
+            signed_data.machine_id, old_client_id, new_client_id
+        );
+
+        Ok(Response::new(()))
+    }
+
+    async fn open_session(
+        &self,
+        request: Request<SignedSessionRequest>,
+    ) -> Result<Response<Session>, Status> {
+        let req = request.into_inner();
+
+        let signed_data = validate_request::<(), SignedSessionRequest>(
+            &self.pool,
+            &self.signatures,
+            None,
+            SignatureFields {
+                machine_id: &req.machine_id,
+                signature: &req.signature,
+                version: req.signature_version,
+                timestamp: req.timestamp,
+                sequence: req.sequence,
+            },
+            Some(()),
+        )
+        .await?;
+
+        let (token, expires_at) =
+            self.sessions.open(signed_data.machine_id, signed_data.client_id).await?;
+        debug!("Opened telemetry session for machine {}", signed_data.machine_id);
+
+        Ok(Response::new(Session { token, expires_at }))
+    }
+
+    type TelemetryStreamStream = ReceiverStream<Result<TelemetryDownstream, Status>>;
+
+    async fn telemetry_stream(
+        &self,
+        request: Request<Streaming<TelemetryUpstream>>,
+    ) -> Result<Response<Self::TelemetryStreamStream>, Status> {
+        check_protocol(&request)?;
+        let mut upstream = request.into_inner();
+        let hello = match upstream.message().await? {
+            Some(TelemetryUpstream {
+                payload: Some(telemetry_upstream::Payload::Hello(hello)),
+                ..
+            }) => hello,
+            _ => {
+                return Err(Status::invalid_argument("Telemetry stream has to start with a hello"))
+            }
+        };
+
+        let signed_data = validate_request::<(), SignedSessionRequest>(
+            &self.pool,
+            &self.signatures,
+            None,
+            SignatureFields {
+                machine_id: &hello.machine_id,
+                signature: &hello.signature,
+                version: hello.signature_version,
+                timestamp: hello.timestamp,
+                sequence: hello.sequence,
+            },
+            Some(()),
+        )
+        .await?;
+
+        // The stream is only as long-lived as a session, after which the machine reconnects and
+        // proves it still holds a registered key
+        let session = self.sessions.detached(signed_data.machine_id, signed_data.client_id);
+        let expires_at =
+            session.expires_at().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+        let command_signer = self
+            .command_signer
+            .as_ref()
+            .map(|signer| signer.address().as_bytes().to_vec())
+            .unwrap_or_default();
+
+        let (tx, rx) = mpsc::channel(TELEMETRY_STREAM_BUFFER);
+        let welcome = TelemetryDownstream {
+            payload: Some(telemetry_downstream::Payload::Welcome(StreamWelcome {
+                expires_at,
+                command_signer,
+            })),
+        };
+        tx.send(Ok(welcome)).await.map_err(|_| Status::cancelled("Telemetry stream closed"))?;
+        debug!("Opened telemetry stream for machine {}", signed_data.machine_id);
+
+        tokio::spawn(self.clone().run_telemetry_stream(session, upstream, tx));
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn serve(
     pool: PgPool,
     notification_config: NotificationConfig,
+    signatures: SignaturePolicy,
+    sessions: SessionStore,
+    heartbeat_config: HeartbeatConfig,
+    heartbeat_write_interval: TimeDelta,
+    escalation_interval: Duration,
+    command_signer: Option<IvyWallet>,
     tls_cert: Option<String>,
     tls_key: Option<String>,
     port: u16,
@@ -367,18 +970,35 @@ pub async fn serve(
     let notification_dispatcher =
         Arc::new(NotificationDispatcher::new(notification_config, AlertDb::new(pool.clone())));
 
-    let heartbeat_monitor = HeartbeatMonitor::new(pool.clone(), notification_dispatcher.clone());
+    let heartbeat_store =
+        CachedHeartbeatStore::new(PgHeartbeatStore::new(pool.clone()), heartbeat_write_interval);
+    let heartbeat_monitor = HeartbeatMonitor::new(
+        pool.clone(),
+        notification_dispatcher.clone(),
+        heartbeat_store,
+        heartbeat_config,
+    );
+    let sessions = Arc::new(sessions);
+
+    tokio::spawn(
+        EscalationHandler::new(notification_dispatcher.clone(), pool.clone())
+            .run(escalation_interval),
+    );
 
     let server = server::Server::new(
         BackendServer::new(BackendService::new(
             pool.clone(),
-            heartbeat_monitor,
+            heartbeat_monitor.clone(),
             NodeAlertHandler::new(notification_dispatcher.clone(), pool.clone()),
             MachineAlertHandler::new(notification_dispatcher.clone(), pool),
+            signatures,
+            sessions.clone(),
+            command_signer,
         )),
         tls_cert,
         tls_key,
-    );
+    )
+    .add_service(HeartbeatServer::new(HeartbeatService::new(heartbeat_monitor, sessions)));
 
     tokio::select! {
         e = server.serve(server::Endpoint::Port(port)) => e?,
@@ -388,43 +1008,10 @@ pub async fn serve(
     Ok(())
 }
 
-#[derive(Debug, Clone)]
-pub struct RecoveredNodeData {
-    pub name: String,
-    pub node_type: Option<String>,
-    pub manifest: Option<String>,
-    pub metrics_alive: Option<bool>,
-    pub node_running: Option<bool>,
-}
-
-impl From<NodeData> for RecoveredNodeData {
-    fn from(node_data: NodeData) -> Self {
-        RecoveredNodeData {
-            name: node_data.name,
-            node_type: Some(node_data.node_type),
-            manifest: Some(node_data.manifest),
-            metrics_alive: Some(node_data.metrics_alive),
-            node_running: None,
-        }
-    }
-}
-
-impl From<NodeDataV2> for RecoveredNodeData {
-    fn from(node_data: NodeDataV2) -> Self {
-        RecoveredNodeData {
-            name: node_data.name,
-            node_type: node_data.node_type,
-            manifest: node_data.manifest,
-            metrics_alive: node_data.metrics_alive,
-            node_running: node_data.node_running,
-        }
-    }
-}
-
 async fn process_node_data(
     pool: &PgPool,
     machine_id: Uuid,
-    node_data: RecoveredNodeData,
+    node_data: NodeDataV2,
 ) -> Result<(), Status> {
     let name = node_data.name;
     let node_type = node_data.node_type;
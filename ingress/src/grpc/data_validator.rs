@@ -1,87 +1,200 @@
-use ivynet_database::machine::Machine;
-use ivynet_error::ethers::types::{Signature, H160};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, Utc};
+use ivynet_database::{machine::Machine, SignatureSequence};
+use ivynet_error::ethers::types::{Signature, H160, H256};
 
 use ivynet_grpc::{
     self,
     messages::{
-        MachineData, Metrics, NodeData, NodeDataV2, SignedClientLog, SignedLog, SignedMachineData,
-        SignedMetrics, SignedNameChange, SignedNodeData, SignedNodeDataV2,
+        MachineData, Metrics, NodeDataV2, SignedClientLog, SignedKeyRotation, SignedLog,
+        SignedLogBatch, SignedMachineData, SignedMetrics, SignedNameChange, SignedNodeDataV2,
+        SignedPing, SignedSessionRequest,
     },
     Status,
 };
-use ivynet_signer::sign_utils::{
-    recover_client_log, recover_log, recover_machine_data, recover_metrics, recover_name_change,
-    recover_node_data, recover_node_data_v2,
+use ivynet_signer::{
+    sign_utils::{
+        recover_client_log, recover_from_hash, recover_key_rotation, recover_log,
+        recover_log_batch, recover_machine_data, recover_metrics, recover_name_change,
+        recover_node_data_v2, recover_ping,
+    },
+    typed_data::{
+        client_log_digest, key_rotation_digest, log_batch_digest, log_digest, machine_data_digest,
+        metrics_digest, name_change_digest, node_data_digest, ping_digest, session_digest,
+        Envelope, LEGACY_SIGNATURE_VERSION, SIGNATURE_VERSION,
+    },
 };
 use sqlx::PgPool;
+use tracing::warn;
 use uuid::Uuid;
 
+use super::session::Session;
+
 pub struct SignedData<T> {
     pub machine_id: Uuid,
     pub client_id: H160,
     pub data: T,
 }
 
+/// Signature related fields of a signed message.
+pub struct SignatureFields<'a> {
+    pub machine_id: &'a [u8],
+    pub signature: &'a [u8],
+    pub version: u32,
+    /// Signing time in unix milliseconds
+    pub timestamp: u64,
+    pub sequence: u64,
+}
+
 pub trait SignedDataValidator {
     type DataType;
 
+    /// Recovers the signer of a legacy signature, which covers the payload only.
     fn recover_signature(
         data: &Self::DataType,
         signature: &Signature,
     ) -> impl std::future::Future<Output = Result<H160, Status>> + Send;
+
+    /// Typed data digest a current signature is made over, or `None` for messages that only have
+    /// legacy signatures.
+    fn typed_digest(data: &Self::DataType, envelope: &Envelope) -> Option<H256>;
+}
+
+/// Rules signatures are held to: how far the signing time of a typed signature may be from the
+/// server time, which sequence numbers each machine already used, and until when legacy
+/// signatures are accepted.
+///
+/// Used sequence numbers are kept in the database, so replays are caught across replicas and
+/// restarts.
+pub struct SignaturePolicy {
+    max_skew_ms: u64,
+    legacy_until: Option<DateTime<Utc>>,
+}
+
+impl SignaturePolicy {
+    /// Without `legacy_until` legacy signatures are accepted for as long as the transition lasts.
+    pub fn new(max_skew: Duration, legacy_until: Option<DateTime<Utc>>) -> Self {
+        if legacy_until.is_none() {
+            warn!("Legacy signatures are accepted without a cutoff date");
+        }
+        Self { max_skew_ms: max_skew.as_millis() as u64, legacy_until }
+    }
+
+    fn check_legacy_allowed(&self) -> Result<(), Status> {
+        match self.legacy_until {
+            Some(until) if Utc::now() >= until => Err(Status::failed_precondition(
+                "Legacy signatures are no longer accepted, please update the client".to_string(),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails with `OutOfRange` rather than `InvalidArgument`, so clients can tell a skewed clock
+    /// apart from a bad signature.
+    fn check_fresh(&self, timestamp: u64) -> Result<(), Status> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+        if (now as u64).abs_diff(timestamp) > self.max_skew_ms {
+            return Err(Status::out_of_range(format!(
+                "Signature timestamp {timestamp} is outside of the accepted window around server \
+                 time {now}, check the machine clock"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Records the sequence number of a message from `machine_id`, failing if it was used before
+    /// or is too far behind the highest one seen.
+    async fn check_sequence(
+        &self,
+        pool: &PgPool,
+        machine_id: Uuid,
+        sequence: u64,
+    ) -> Result<(), Status> {
+        // Sequence numbers are based on the signing time in microseconds, so anything older than
+        // the freshness window on both sides can be forgotten
+        let window = self.max_skew_ms.saturating_mul(2_000).min(i64::MAX as u64) as i64;
+        let stored = i64::try_from(sequence)
+            .map_err(|_| Status::invalid_argument(format!("Sequence number {sequence} is invalid")))?;
+        if SignatureSequence::accept(pool, machine_id, stored, window).await? {
+            Ok(())
+        } else {
+            Err(Status::already_exists(format!("Sequence number {sequence} was already used")))
+        }
+    }
 }
 
 // Common validation logic
 pub async fn validate_request<T, V>(
     pool: &PgPool,
-    machine_id: &[u8],
-    signature: &[u8],
+    policy: &SignaturePolicy,
+    session: Option<Session>,
+    fields: SignatureFields<'_>,
     data: Option<T>,
 ) -> Result<SignedData<T>, Status>
 where
     V: SignedDataValidator<DataType = T>,
 {
-    // Only relevant for node_data
-    // Handle the Option<NodeData> case
+    // Handle the Option<NodeDataV2> case
     let data = if let Some(data) = data {
         data
     } else {
         return Err(Status::invalid_argument("Data missing from payload".to_string()));
     };
 
+    // Validate machine ID
+    let machine_id = Uuid::from_slice(fields.machine_id)
+        .map_err(|e| Status::invalid_argument(format!("Machine id has wrong length ({e:?})")))?;
+
+    // The session was opened with a verified signature of the machine's key
+    if let Some(session) = session {
+        if session.machine_id != machine_id {
+            return Err(Status::permission_denied("Session belongs to another machine"));
+        }
+        return Ok(SignedData { machine_id, client_id: session.client_id, data });
+    }
+
     // Validate signature
-    let signature = Signature::try_from(signature)
+    let signature = Signature::try_from(fields.signature)
         .map_err(|_| Status::invalid_argument("Signature is invalid"))?;
 
-    let client_id = V::recover_signature(&data, &signature).await?;
-
-    // Validate machine ID
-    let machine_id = Uuid::from_slice(machine_id)
-        .map_err(|e| Status::invalid_argument(format!("Machine id has wrong length ({e:?})")))?;
+    let client_id = match fields.version {
+        LEGACY_SIGNATURE_VERSION => {
+            policy.check_legacy_allowed()?;
+            V::recover_signature(&data, &signature).await?
+        }
+        SIGNATURE_VERSION => {
+            policy.check_fresh(fields.timestamp)?;
+            let envelope =
+                Envelope { machine_id, timestamp: fields.timestamp, sequence: fields.sequence };
+            let digest = V::typed_digest(&data, &envelope).ok_or_else(|| {
+                Status::invalid_argument("Message does not support typed signatures".to_string())
+            })?;
+            recover_from_hash(digest, &signature).map_err(|e| {
+                Status::invalid_argument(format!("Failed to recover typed signature: {e}"))
+            })?
+        }
+        version => {
+            return Err(Status::invalid_argument(format!(
+                "Unsupported signature version {version}"
+            )))
+        }
+    };
 
     // Check machine ownership
     if !Machine::is_owned_by(pool, &client_id, machine_id).await.unwrap_or(false) {
         return Err(Status::not_found("Machine not registered for given client".to_string()));
     }
 
-    Ok(SignedData { machine_id, client_id, data })
-}
-
-// Implementation for v1
-impl SignedDataValidator for SignedNodeData {
-    type DataType = NodeData;
-
-    async fn recover_signature(
-        data: &Self::DataType,
-        signature: &Signature,
-    ) -> Result<H160, Status> {
-        recover_node_data(data, signature).map_err(|e| {
-            Status::invalid_argument(format!("Failed to recover signature for node data: {e}"))
-        })
+    // Only checked once the signature is known to come from the machine, so others cannot use up
+    // its sequence numbers
+    if fields.version == SIGNATURE_VERSION {
+        policy.check_sequence(pool, machine_id, fields.sequence).await?;
     }
+
+    Ok(SignedData { machine_id, client_id, data })
 }
 
-// Implementation for v2
 impl SignedDataValidator for SignedNodeDataV2 {
     type DataType = NodeDataV2;
 
@@ -93,19 +206,27 @@ impl SignedDataValidator for SignedNodeDataV2 {
             Status::invalid_argument(format!("Failed to recover signature for node data v2: {e}"))
         })
     }
+
+    fn typed_digest(data: &Self::DataType, envelope: &Envelope) -> Option<H256> {
+        Some(node_data_digest(envelope, data))
+    }
 }
 
 impl SignedDataValidator for SignedMetrics {
-    type DataType = Vec<Metrics>;
+    type DataType = (Option<String>, Vec<Metrics>, u64); //avs name, metrics, collected at
 
     async fn recover_signature(
         data: &Self::DataType,
         signature: &Signature,
     ) -> Result<H160, Status> {
-        recover_metrics(data, signature).map_err(|e| {
+        recover_metrics(&data.1, signature).map_err(|e| {
             Status::invalid_argument(format!("Failed to recover signature for metrics: {e}"))
         })
     }
+
+    fn typed_digest(data: &Self::DataType, envelope: &Envelope) -> Option<H256> {
+        Some(metrics_digest(envelope, data.0.as_deref(), &data.1, data.2))
+    }
 }
 
 impl SignedDataValidator for SignedNameChange {
@@ -119,19 +240,44 @@ impl SignedDataValidator for SignedNameChange {
             Status::invalid_argument(format!("Failed to recover signature for name change: {e}"))
         })
     }
+
+    fn typed_digest(data: &Self::DataType, envelope: &Envelope) -> Option<H256> {
+        Some(name_change_digest(envelope, &data.0, &data.1))
+    }
 }
 
 impl SignedDataValidator for SignedLog {
-    type DataType = String;
+    type DataType = (String, String); //avs name, log
 
     async fn recover_signature(
         data: &Self::DataType,
         signature: &Signature,
     ) -> Result<H160, Status> {
-        recover_log(data, signature).map_err(|e| {
+        recover_log(&data.1, signature).map_err(|e| {
             Status::invalid_argument(format!("Failed to recover signature for logs: {e}"))
         })
     }
+
+    fn typed_digest(data: &Self::DataType, envelope: &Envelope) -> Option<H256> {
+        Some(log_digest(envelope, &data.0, &data.1))
+    }
+}
+
+impl SignedDataValidator for SignedLogBatch {
+    type DataType = (String, Vec<String>); //avs name, logs
+
+    async fn recover_signature(
+        data: &Self::DataType,
+        signature: &Signature,
+    ) -> Result<H160, Status> {
+        recover_log_batch(&data.0, &data.1, signature).map_err(|e| {
+            Status::invalid_argument(format!("Failed to recover signature for log batch: {e}"))
+        })
+    }
+
+    fn typed_digest(data: &Self::DataType, envelope: &Envelope) -> Option<H256> {
+        Some(log_batch_digest(envelope, &data.0, &data.1))
+    }
 }
 
 impl SignedDataValidator for SignedClientLog {
@@ -145,17 +291,98 @@ impl SignedDataValidator for SignedClientLog {
             Status::invalid_argument(format!("Failed to recover signature for client logs: {e}"))
         })
     }
+
+    fn typed_digest(data: &Self::DataType, envelope: &Envelope) -> Option<H256> {
+        Some(client_log_digest(envelope, data))
+    }
 }
 
 impl SignedDataValidator for SignedMachineData {
-    type DataType = MachineData;
+    type DataType = (MachineData, u64); //machine data, collected at
 
     async fn recover_signature(
         data: &Self::DataType,
         signature: &Signature,
     ) -> Result<H160, Status> {
-        recover_machine_data(data, signature).map_err(|e| {
+        recover_machine_data(&data.0, signature).map_err(|e| {
             Status::invalid_argument(format!("Failed to recover signature for machine data: {e}"))
         })
     }
+
+    fn typed_digest(data: &Self::DataType, envelope: &Envelope) -> Option<H256> {
+        Some(machine_data_digest(envelope, &data.0, data.1))
+    }
+}
+
+impl SignedDataValidator for SignedPing {
+    type DataType = u64;
+
+    async fn recover_signature(
+        data: &Self::DataType,
+        signature: &Signature,
+    ) -> Result<H160, Status> {
+        recover_ping(*data, signature).map_err(|e| {
+            Status::invalid_argument(format!("Failed to recover signature for ping: {e}"))
+        })
+    }
+
+    // The timestamp is part of the envelope
+    fn typed_digest(_: &Self::DataType, envelope: &Envelope) -> Option<H256> {
+        Some(ping_digest(envelope))
+    }
+}
+
+impl SignedDataValidator for SignedKeyRotation {
+    type DataType = (Vec<u8>, H160, u64); //machine id, new key, timestamp
+
+    async fn recover_signature(
+        data: &Self::DataType,
+        signature: &Signature,
+    ) -> Result<H160, Status> {
+        recover_key_rotation(&data.0, &data.1, data.2, signature).map_err(|e| {
+            Status::invalid_argument(format!("Failed to recover signature for key rotation: {e}"))
+        })
+    }
+
+    // The machine id and timestamp are part of the envelope
+    fn typed_digest(data: &Self::DataType, envelope: &Envelope) -> Option<H256> {
+        Some(key_rotation_digest(envelope, &data.1))
+    }
+}
+
+impl SignedDataValidator for SignedSessionRequest {
+    type DataType = ();
+
+    // Without a timestamp and sequence a session request could be replayed
+    async fn recover_signature(_: &Self::DataType, _: &Signature) -> Result<H160, Status> {
+        Err(Status::failed_precondition("Sessions require a typed signature".to_string()))
+    }
+
+    fn typed_digest(_: &Self::DataType, envelope: &Envelope) -> Option<H256> {
+        Some(session_digest(envelope))
+    }
+}
+
+#[cfg(test)]
+mod data_validator_tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_freshness_and_legacy_cutoff() {
+        let policy = SignaturePolicy::new(Duration::from_secs(60), None);
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+        assert!(policy.check_fresh(now).is_ok());
+        assert_eq!(
+            policy.check_fresh(now - 120_000).unwrap_err().code(),
+            ivynet_grpc::tonic::Code::OutOfRange
+        );
+        assert!(policy.check_fresh(now + 120_000).is_err());
+        assert!(policy.check_legacy_allowed().is_ok());
+
+        let expired = SignaturePolicy::new(Duration::from_secs(60), Some(Utc::now()));
+        assert_eq!(
+            expired.check_legacy_allowed().unwrap_err().code(),
+            ivynet_grpc::tonic::Code::FailedPrecondition
+        );
+    }
 }
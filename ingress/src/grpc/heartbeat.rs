@@ -0,0 +1,80 @@
+use std::sync::Arc;
+
+use ivynet_database::alerts::alert_db::AlertDb;
+use ivynet_grpc::{
+    capabilities::check_protocol,
+    client::{Request, Response},
+    heartbeat::{heartbeat_server::Heartbeat, ClientHeartbeat, MachineHeartbeat, NodeHeartbeat},
+    Status,
+};
+use ivynet_heartbeat::{ClientId, HeartbeatMonitor, MachineId, NodeId};
+
+use super::session::{Session, SessionStore};
+
+/// Heartbeats sent by the daemon, independent of how much telemetry it has to send. Only
+/// accepted in a telemetry session, and only for the machine and client the session was opened
+/// with, so nobody can keep someone else's machine looking alive.
+#[derive(Clone)]
+pub struct HeartbeatService {
+    heartbeats: HeartbeatMonitor<AlertDb>,
+    sessions: Arc<SessionStore>,
+}
+
+impl HeartbeatService {
+    pub fn new(heartbeats: HeartbeatMonitor<AlertDb>, sessions: Arc<SessionStore>) -> Self {
+        Self { heartbeats, sessions }
+    }
+
+    async fn session<T>(&self, request: &Request<T>) -> Result<Session, Status> {
+        check_protocol(request)?;
+        self.sessions
+            .authorize(request)
+            .await?
+            .ok_or_else(|| Status::unauthenticated("Heartbeats are only accepted in a session"))
+    }
+}
+
+fn check_machine(session: &Session, machine_id: &MachineId) -> Result<(), Status> {
+    if *machine_id != MachineId::new(session.machine_id) {
+        return Err(Status::permission_denied("Session belongs to another machine"));
+    }
+    Ok(())
+}
+
+#[ivynet_grpc::async_trait]
+impl Heartbeat for HeartbeatService {
+    async fn send_client_heartbeat(
+        &self,
+        request: Request<ClientHeartbeat>,
+    ) -> Result<Response<()>, Status> {
+        let session = self.session(&request).await?;
+        let client_id: ClientId = request.into_inner().try_into()?;
+        if client_id != ClientId::new(session.client_id) {
+            return Err(Status::permission_denied("Session belongs to another client"));
+        }
+        self.heartbeats.post_client_heartbeat(client_id).await?;
+        Ok(Response::new(()))
+    }
+
+    async fn send_machine_heartbeat(
+        &self,
+        request: Request<MachineHeartbeat>,
+    ) -> Result<Response<()>, Status> {
+        let session = self.session(&request).await?;
+        let machine_id: MachineId = request.into_inner().try_into()?;
+        check_machine(&session, &machine_id)?;
+        self.heartbeats.post_machine_heartbeat(machine_id).await?;
+        Ok(Response::new(()))
+    }
+
+    async fn send_node_heartbeat(
+        &self,
+        request: Request<NodeHeartbeat>,
+    ) -> Result<Response<()>, Status> {
+        let session = self.session(&request).await?;
+        let node_id: NodeId = request.into_inner().try_into()?;
+        check_machine(&session, &MachineId::new(node_id.machine))?;
+        self.heartbeats.post_node_heartbeat(node_id).await?;
+        Ok(Response::new(()))
+    }
+}
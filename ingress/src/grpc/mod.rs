@@ -1,9 +1,12 @@
 pub mod backend;
 pub mod data_validator;
 pub mod events;
+pub mod heartbeat;
+pub mod session;
 
 pub use backend::BackendService;
 pub use events::EventsService;
+pub use heartbeat::HeartbeatService;
 
 pub use backend::serve as backend_serve;
 pub use events::serve as events_serve;
@@ -0,0 +1,149 @@
+use std::{
+    collections::HashMap,
+    sync::RwLock,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use chrono::{DateTime, Utc};
+use ivynet_database::{Machine, TelemetrySession};
+use ivynet_error::ethers::types::H160;
+use ivynet_grpc::{client::Request, Status, SESSION_METADATA_KEY};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Telemetry sessions. A machine proves it holds its key once when opening a session, after which
+/// its telemetry is accepted on the session token alone, without recovering a signature and
+/// checking machine ownership for every message.
+///
+/// Sessions are kept in the database, so a token is accepted by every ingress replica and
+/// survives restarts. Each replica caches the sessions it looked up for [`SESSION_CACHE_TTL`], so
+/// sessions deleted in the database, e.g. when the key is rotated, stop being accepted within
+/// that time.
+pub struct SessionStore {
+    ttl: Duration,
+    pool: PgPool,
+    cache: RwLock<SessionCache>,
+}
+
+/// How long a replica keeps using a session it read from the database, the same time a telemetry
+/// stream takes to notice its key was rotated out.
+const SESSION_CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// Sessions by the hash of their token
+struct SessionCache {
+    sessions: HashMap<String, CachedSession>,
+    pruned_at: Instant,
+}
+
+struct CachedSession {
+    session: Session,
+    read_at: Instant,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Session {
+    pub machine_id: Uuid,
+    pub client_id: H160,
+    expires_at: SystemTime,
+}
+
+impl Session {
+    pub fn expires_at(&self) -> SystemTime {
+        self.expires_at
+    }
+}
+
+impl From<TelemetrySession> for Session {
+    fn from(session: TelemetrySession) -> Self {
+        Self {
+            machine_id: session.machine_id,
+            client_id: session.client_id,
+            expires_at: session.expires_at.and_utc().into(),
+        }
+    }
+}
+
+impl SessionStore {
+    pub fn new(pool: PgPool, ttl: Duration) -> Self {
+        let cache = SessionCache { sessions: HashMap::new(), pruned_at: Instant::now() };
+        Self { ttl, pool, cache: RwLock::new(cache) }
+    }
+
+    /// Opens a session for the machine, replacing any session it had before. Returns the token and
+    /// the expiry in unix milliseconds.
+    pub async fn open(&self, machine_id: Uuid, client_id: H160) -> Result<(String, u64), Status> {
+        let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        let expires_at = SystemTime::now() + self.ttl;
+
+        let session = TelemetrySession {
+            machine_id,
+            client_id,
+            expires_at: DateTime::<Utc>::from(expires_at).naive_utc(),
+        };
+        TelemetrySession::open(&self.pool, &token, &session).await?;
+        self.cache
+            .write()
+            .expect("Write lock failed")
+            .sessions
+            .retain(|_, cached| cached.session.machine_id != machine_id);
+
+        let expires_at = expires_at.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+        Ok((token, expires_at as u64))
+    }
+
+    /// Session for a connection that authenticated itself, like a telemetry stream. It is not kept
+    /// in the store, so it neither replaces nor is replaced by the sessions opened with `open`,
+    /// and has to be checked with `is_revoked` while it is used.
+    pub fn detached(&self, machine_id: Uuid, client_id: H160) -> Session {
+        Session { machine_id, client_id, expires_at: SystemTime::now() + self.ttl }
+    }
+
+    /// Whether the client of the session no longer owns its machine, e.g. after the key was
+    /// rotated, so the session must not be used anymore.
+    pub async fn is_revoked(&self, session: &Session) -> Result<bool, Status> {
+        Ok(!Machine::is_owned_by(&self.pool, &session.client_id, session.machine_id).await?)
+    }
+
+    /// Session a request was sent in. Requests without a token are left to signature validation,
+    /// while an unknown or expired token is rejected so the client opens a new session.
+    pub async fn authorize<T>(&self, request: &Request<T>) -> Result<Option<Session>, Status> {
+        let Some(token) = request.metadata().get(SESSION_METADATA_KEY) else {
+            return Ok(None);
+        };
+        let token =
+            token.to_str().map_err(|_| Status::unauthenticated("Session token is invalid"))?;
+        let token_hash = sha256::digest(token);
+        if let Some(session) = self.cached(&token_hash) {
+            return Ok(Some(session));
+        }
+        match TelemetrySession::get(&self.pool, token).await? {
+            Some(session) => {
+                let session = Session::from(session);
+                self.cache_session(token_hash, session);
+                Ok(Some(session))
+            }
+            None => Err(Status::unauthenticated("Session is unknown or expired")),
+        }
+    }
+
+    fn cached(&self, token_hash: &str) -> Option<Session> {
+        let cache = self.cache.read().expect("Read lock failed");
+        let cached = cache.sessions.get(token_hash)?;
+        let fresh = cached.read_at.elapsed() < SESSION_CACHE_TTL;
+        (fresh && cached.session.expires_at > SystemTime::now()).then_some(cached.session)
+    }
+
+    /// Caches a session read from the database. Entries past their cache lifetime are dropped
+    /// once per lifetime, so sessions that are no longer used do not pile up.
+    fn cache_session(&self, token_hash: String, session: Session) {
+        let mut cache = self.cache.write().expect("Write lock failed");
+        let now = Instant::now();
+        if now.duration_since(cache.pruned_at) >= SESSION_CACHE_TTL {
+            cache
+                .sessions
+                .retain(|_, cached| now.duration_since(cached.read_at) < SESSION_CACHE_TTL);
+            cache.pruned_at = now;
+        }
+        cache.sessions.insert(token_hash, CachedSession { session, read_at: now });
+    }
+}
@@ -1,6 +1,14 @@
+use std::time::Duration;
+
+use chrono::TimeDelta;
 use clap::Parser as _;
-use ingress::{config::Config, error::IngressError, grpc};
+use ingress::{
+    config::Config,
+    error::IngressError,
+    grpc::{self, data_validator::SignaturePolicy, session::SessionStore},
+};
 use ivynet_database::configure;
+use ivynet_signer::IvyWallet;
 use tracing::{error, warn, Level};
 use tracing_subscriber::FmtSubscriber;
 
@@ -27,10 +35,25 @@ async fn main() -> Result<(), IngressError> {
     let events_tls_cert = config.events_tls_cert.clone();
     let events_tls_key = config.events_tls_key.clone();
     let events_port = config.events_port;
+    let signatures = SignaturePolicy::new(
+        Duration::from_secs(config.signature_max_skew_secs),
+        config.legacy_signatures_until,
+    );
+    let sessions = SessionStore::new(pool.clone(), Duration::from_secs(config.session_ttl_secs));
+    let command_signer = config.command_key.clone().map(IvyWallet::from_private_key).transpose()?;
+    if command_signer.is_none() {
+        warn!("IVY_COMMAND_KEY is not set, no commands will be sent to machines");
+    }
 
     let grpc_service = grpc::backend_serve(
         pool.clone(),
         config.clone().into(),
+        signatures,
+        sessions,
+        (&config).into(),
+        TimeDelta::seconds(config.heartbeat_write_interval_secs as i64),
+        Duration::from_secs(config.escalation_interval_secs),
+        command_signer,
         grpc_tls_cert,
         grpc_tls_key,
         grpc_port,
@@ -77,7 +77,7 @@ pub enum Alert {
         machine: Uuid,
         resource: String,
     } = 10,
-    // TODO: Find out how exactly this should be used
+    // Raised while the eigen_performance_score of an EigenDA node is below the healthy threshold
     LowPerformanceScore {
         // Node Alert
         node_name: String,
@@ -149,6 +149,24 @@ impl Alert {
     pub fn id(&self) -> usize {
         self.discriminant().id()
     }
+
+    /// Name and type of the node the alert was raised for, if it is a node alert
+    pub fn node(&self) -> Option<(&str, &str)> {
+        match self {
+            Alert::Custom { node_name, node_type, .. } |
+            Alert::ActiveSetNoDeployment { node_name, node_type, .. } |
+            Alert::UnregisteredFromActiveSet { node_name, node_type, .. } |
+            Alert::NodeNotResponding { node_name, node_type } |
+            Alert::NodeNotRunning { node_name, node_type } |
+            Alert::NoChainInfo { node_name, node_type } |
+            Alert::NoMetrics { node_name, node_type } |
+            Alert::NoOperatorId { node_name, node_type } |
+            Alert::LowPerformanceScore { node_name, node_type, .. } |
+            Alert::NodeNeedsUpdate { node_name, node_type, .. } => Some((node_name, node_type)),
+            _ => None,
+        }
+    }
+
     // Generate a UUIDv5 seed for the notification. Uses a combination of stable parameters
     // on the notification type (EG: not time, or percentage, which may vary between
     // notifications, even though they apply to an ongoing condition) and the notification type
@@ -1,5 +1,5 @@
 use async_trait::async_trait;
-use ivynet_alerts::{Alert, SendState};
+use ivynet_alerts::{Alert, AlertType, SendState};
 use ivynet_notifications::{
     Channel, Notification, NotificationDispatcher, NotificationDispatcherError,
 };
@@ -7,8 +7,12 @@ use std::sync::Arc;
 
 use sqlx::{types::Uuid, PgPool};
 
-use super::alert_db::AlertDb;
-use crate::NotificationSettings;
+use super::{
+    alert_db::AlertDb,
+    escalation::{EscalationChannel, EscalationPolicy},
+    silence::{AlertSilence, SilenceTarget},
+};
+use crate::{error::DatabaseError, NotificationSettings};
 
 /// Represents a new alert that can be created for either nodes or organizations
 pub trait NewAlert {
@@ -26,7 +30,7 @@ pub trait ActiveAlert {
 /// Common trait for alert handlers that provides shared functionality
 #[async_trait]
 pub trait AlertHandler {
-    type Error: From<NotificationDispatcherError>;
+    type Error: From<NotificationDispatcherError> + From<DatabaseError>;
     type NewAlertType: NewAlert + Send;
     type ActiveAlertType: ActiveAlert + Send;
 
@@ -40,7 +44,10 @@ pub trait AlertHandler {
         existing_alerts: Vec<Self::ActiveAlertType>,
     ) -> Result<Vec<Self::NewAlertType>, Self::Error>;
 
-    /// Send notifications for the given alerts through configured channels
+    /// Send notifications for the given alerts through configured channels. Alerts matched by a
+    /// silence of the organization are not sent. Node and machine alerts of an organization with
+    /// an escalation policy are only sent on the channels of its immediate steps, the
+    /// [`EscalationHandler`](super::escalation::EscalationHandler) takes the others.
     async fn send_notifications(
         &self,
         alerts: &mut Vec<Self::NewAlertType>,
@@ -63,7 +70,31 @@ pub trait AlertHandler {
             channels.push(Channel::Telegram(settings.telegram_chats));
         }
 
+        // Only node and machine alerts have a machine, and escalate
+        if machine_id.is_some() {
+            if let Some(policy) =
+                EscalationPolicy::get(self.get_db_pool(), organization_id as i64).await?
+            {
+                channels.retain(|channel| policy.is_immediate(EscalationChannel::from(channel)));
+            }
+        }
+
+        let silences =
+            AlertSilence::get_active_for_org(self.get_db_pool(), organization_id as i64).await?;
+
         for alert in alerts.iter_mut() {
+            let alert_type = alert.get_alert_type();
+            let node = alert_type.node();
+            let target = SilenceTarget {
+                alert_type: AlertType::from(&alert_type),
+                machine_id,
+                node_name: node.map(|(node_name, _)| node_name),
+                node_type: node.map(|(_, node_type)| node_type),
+            };
+            if silences.iter().any(|silence| silence.matches(&target)) {
+                continue;
+            }
+
             for channel in channels.iter() {
                 if enabled_alert_ids.contains(&alert.get_alert_type().id()) {
                     let notification = Notification {
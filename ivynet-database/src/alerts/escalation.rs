@@ -0,0 +1,580 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use ivynet_alerts::{Alert, AlertType, SendState};
+use ivynet_notifications::{Channel, Notification, NotificationDispatcher};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgPool};
+use tracing::error;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use super::{
+    alert_db::AlertDb,
+    silence::{AlertSilence, SilenceTarget},
+};
+use crate::{error::DatabaseError, NotificationSettings};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, sqlx::Type, Deserialize, Serialize, ToSchema)]
+#[sqlx(type_name = "escalation_channel", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum EscalationChannel {
+    Telegram,
+    Email,
+    PagerDuty,
+}
+
+impl EscalationChannel {
+    /// The channel with the targets of the organization, if the organization enabled it
+    pub fn channel(&self, settings: &NotificationSettings) -> Option<Channel> {
+        match self {
+            Self::Telegram => {
+                settings.telegram.then(|| Channel::Telegram(settings.telegram_chats.clone()))
+            }
+            Self::Email => settings.email.then(|| Channel::Email(settings.sendgrid_emails.clone())),
+            Self::PagerDuty => {
+                settings.pagerduty.then(|| Channel::PagerDuty(settings.pagerduty_keys.clone()))
+            }
+        }
+    }
+}
+
+impl From<&Channel> for EscalationChannel {
+    fn from(channel: &Channel) -> Self {
+        match channel {
+            Channel::Telegram(_) => Self::Telegram,
+            Channel::Email(_) => Self::Email,
+            Channel::PagerDuty(_) => Self::PagerDuty,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+pub struct EscalationStep {
+    pub channel: EscalationChannel,
+    /// How long an alert has to go unacknowledged before the channel is notified, in seconds
+    #[serde(default)]
+    pub after_secs: i64,
+}
+
+/// How the node and machine alerts of an organization escalate while nobody acknowledges them.
+/// Alerts of organizations without a policy are notified once, on every channel.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+pub struct EscalationPolicy {
+    pub steps: Vec<EscalationStep>,
+    /// How often every channel is notified again once all steps were taken, in seconds
+    pub repeat_interval_secs: Option<i64>,
+}
+
+impl EscalationPolicy {
+    /// Whether new alerts are notified on `channel` right away
+    pub fn is_immediate(&self, channel: EscalationChannel) -> bool {
+        self.steps.iter().any(|step| step.channel == channel && step.after_secs <= 0)
+    }
+
+    /// Channels an alert of `age`, last notified `since_notified` ago, is due to be notified on.
+    /// `taken` tells the channels it was notified on, or cannot be.
+    pub fn due(
+        &self,
+        age: TimeDelta,
+        since_notified: TimeDelta,
+        taken: impl Fn(EscalationChannel) -> bool,
+    ) -> Vec<EscalationChannel> {
+        let pending = self.steps.iter().filter(|step| !taken(step.channel)).collect::<Vec<_>>();
+        if !pending.is_empty() {
+            return pending
+                .into_iter()
+                .filter(|step| age >= TimeDelta::seconds(step.after_secs))
+                .map(|step| step.channel)
+                .collect();
+        }
+
+        match self.repeat_interval_secs {
+            Some(interval) if since_notified >= TimeDelta::seconds(interval) => {
+                self.steps.iter().map(|step| step.channel).collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    pub async fn get(pool: &PgPool, organization_id: i64) -> Result<Option<Self>, DatabaseError> {
+        let Some(repeat_interval_secs) = sqlx::query_scalar!(
+            "SELECT repeat_interval_secs FROM escalation_policy WHERE organization_id = $1",
+            organization_id
+        )
+        .fetch_optional(pool)
+        .await?
+        else {
+            return Ok(None);
+        };
+        let steps = sqlx::query_as!(
+            EscalationStep,
+            r#"SELECT channel AS "channel: EscalationChannel", after_secs
+               FROM escalation_step
+               WHERE organization_id = $1
+               ORDER BY after_secs"#,
+            organization_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(Some(Self { steps, repeat_interval_secs }))
+    }
+
+    /// Policies of every organization that set one.
+    pub async fn get_all(pool: &PgPool) -> Result<HashMap<i64, Self>, DatabaseError> {
+        let mut policies = HashMap::new();
+
+        let rows =
+            sqlx::query!("SELECT organization_id, repeat_interval_secs FROM escalation_policy")
+                .fetch_all(pool)
+                .await?;
+        for row in rows {
+            policies.insert(
+                row.organization_id,
+                Self { steps: vec![], repeat_interval_secs: row.repeat_interval_secs },
+            );
+        }
+
+        let rows = sqlx::query!(
+            r#"SELECT organization_id, channel AS "channel: EscalationChannel", after_secs
+               FROM escalation_step
+               ORDER BY after_secs"#
+        )
+        .fetch_all(pool)
+        .await?;
+        for row in rows {
+            if let Some(policy) = policies.get_mut(&row.organization_id) {
+                policy
+                    .steps
+                    .push(EscalationStep { channel: row.channel, after_secs: row.after_secs });
+            }
+        }
+
+        Ok(policies)
+    }
+
+    /// Replaces the policy of the organization, including every step.
+    pub async fn set(&self, pool: &PgPool, organization_id: i64) -> Result<(), DatabaseError> {
+        let mut tx = pool.begin().await?;
+
+        sqlx::query!(
+            r#"INSERT INTO
+                escalation_policy (organization_id, repeat_interval_secs, updated_at)
+            VALUES
+                ($1, $2, NOW())
+            ON CONFLICT (organization_id)
+            DO UPDATE SET
+                repeat_interval_secs = EXCLUDED.repeat_interval_secs,
+                updated_at = EXCLUDED.updated_at"#,
+            organization_id,
+            self.repeat_interval_secs
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!("DELETE FROM escalation_step WHERE organization_id = $1", organization_id)
+            .execute(&mut *tx)
+            .await?;
+
+        for step in &self.steps {
+            sqlx::query!(
+                "INSERT INTO escalation_step (organization_id, channel, after_secs) VALUES ($1, $2, $3)",
+                organization_id,
+                step.channel as EscalationChannel,
+                step.after_secs
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Removes the policy of the organization, so its alerts are only notified once.
+    pub async fn delete(pool: &PgPool, organization_id: i64) -> Result<(), DatabaseError> {
+        sqlx::query!("DELETE FROM escalation_policy WHERE organization_id = $1", organization_id)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum AlertTable {
+    Node,
+    Machine,
+}
+
+struct DbEscalatingAlert {
+    alert_id: Uuid,
+    machine_id: Uuid,
+    created_at: NaiveDateTime,
+    notified_at: Option<NaiveDateTime>,
+    alert_data: serde_json::Value,
+    telegram_send: SendState,
+    sendgrid_send: SendState,
+    pagerduty_send: SendState,
+}
+
+/// An unacknowledged node or machine alert
+struct EscalatingAlert {
+    alert_id: Uuid,
+    machine_id: Uuid,
+    alert: Alert,
+    created_at: NaiveDateTime,
+    notified_at: Option<NaiveDateTime>,
+    telegram_send: SendState,
+    sendgrid_send: SendState,
+    pagerduty_send: SendState,
+}
+
+impl TryFrom<DbEscalatingAlert> for EscalatingAlert {
+    type Error = serde_json::Error;
+
+    fn try_from(value: DbEscalatingAlert) -> Result<Self, Self::Error> {
+        Ok(Self {
+            alert_id: value.alert_id,
+            machine_id: value.machine_id,
+            alert: serde_json::from_value(value.alert_data)?,
+            created_at: value.created_at,
+            notified_at: value.notified_at,
+            telegram_send: value.telegram_send,
+            sendgrid_send: value.sendgrid_send,
+            pagerduty_send: value.pagerduty_send,
+        })
+    }
+}
+
+impl EscalatingAlert {
+    fn send_state(&self, channel: EscalationChannel) -> SendState {
+        match channel {
+            EscalationChannel::Telegram => self.telegram_send,
+            EscalationChannel::Email => self.sendgrid_send,
+            EscalationChannel::PagerDuty => self.pagerduty_send,
+        }
+    }
+
+    fn set_send_state(&mut self, channel: EscalationChannel, state: SendState) {
+        match channel {
+            EscalationChannel::Telegram => self.telegram_send = state,
+            EscalationChannel::Email => self.sendgrid_send = state,
+            EscalationChannel::PagerDuty => self.pagerduty_send = state,
+        }
+    }
+
+    /// Channels of the policy the alert is due to be notified on at `now`
+    fn due(
+        &self,
+        policy: &EscalationPolicy,
+        settings: &NotificationSettings,
+        now: NaiveDateTime,
+    ) -> Vec<(EscalationChannel, Channel)> {
+        let age = now - self.created_at;
+        let since_notified = now - self.notified_at.unwrap_or(self.created_at);
+        policy
+            .due(age, since_notified, |channel| {
+                channel.channel(settings).is_none() ||
+                    !matches!(self.send_state(channel), SendState::NoSend)
+            })
+            .into_iter()
+            .filter_map(|channel| Some((channel, channel.channel(settings)?)))
+            .collect()
+    }
+
+    async fn unacknowledged(
+        pool: &PgPool,
+        table: AlertTable,
+        organization_id: i64,
+    ) -> Result<Vec<Self>, DatabaseError> {
+        let alerts = match table {
+            AlertTable::Node => {
+                sqlx::query_as!(
+                    DbEscalatingAlert,
+                    r#"SELECT
+                        alert_id, machine_id, created_at, notified_at, alert_data,
+                        telegram_send AS "telegram_send!: SendState",
+                        sendgrid_send AS "sendgrid_send!: SendState",
+                        pagerduty_send AS "pagerduty_send!: SendState"
+                       FROM node_alerts_active
+                       WHERE organization_id = $1 AND acknowledged_at IS NULL"#,
+                    organization_id
+                )
+                .fetch_all(pool)
+                .await?
+            }
+            AlertTable::Machine => {
+                sqlx::query_as!(
+                    DbEscalatingAlert,
+                    r#"SELECT
+                        alert_id, machine_id, created_at, notified_at, alert_data,
+                        telegram_send AS "telegram_send!: SendState",
+                        sendgrid_send AS "sendgrid_send!: SendState",
+                        pagerduty_send AS "pagerduty_send!: SendState"
+                       FROM machine_alerts_active
+                       WHERE organization_id = $1 AND acknowledged_at IS NULL"#,
+                    organization_id
+                )
+                .fetch_all(pool)
+                .await?
+            }
+        };
+        Ok(alerts.into_iter().filter_map(|alert| alert.try_into().ok()).collect())
+    }
+
+    /// Locks the alert until the transaction of `conn` ends, so it is escalated by one ingress
+    /// at a time. None if it was acknowledged or resolved since, or is locked by another ingress.
+    async fn lock(
+        conn: &mut PgConnection,
+        table: AlertTable,
+        organization_id: i64,
+        alert_id: Uuid,
+    ) -> Result<Option<Self>, DatabaseError> {
+        let alert = match table {
+            AlertTable::Node => {
+                sqlx::query_as!(
+                    DbEscalatingAlert,
+                    r#"SELECT
+                        alert_id, machine_id, created_at, notified_at, alert_data,
+                        telegram_send AS "telegram_send!: SendState",
+                        sendgrid_send AS "sendgrid_send!: SendState",
+                        pagerduty_send AS "pagerduty_send!: SendState"
+                       FROM node_alerts_active
+                       WHERE organization_id = $1 AND alert_id = $2 AND acknowledged_at IS NULL
+                       FOR UPDATE SKIP LOCKED"#,
+                    organization_id,
+                    alert_id
+                )
+                .fetch_optional(conn)
+                .await?
+            }
+            AlertTable::Machine => {
+                sqlx::query_as!(
+                    DbEscalatingAlert,
+                    r#"SELECT
+                        alert_id, machine_id, created_at, notified_at, alert_data,
+                        telegram_send AS "telegram_send!: SendState",
+                        sendgrid_send AS "sendgrid_send!: SendState",
+                        pagerduty_send AS "pagerduty_send!: SendState"
+                       FROM machine_alerts_active
+                       WHERE organization_id = $1 AND alert_id = $2 AND acknowledged_at IS NULL
+                       FOR UPDATE SKIP LOCKED"#,
+                    organization_id,
+                    alert_id
+                )
+                .fetch_optional(conn)
+                .await?
+            }
+        };
+        Ok(alert.and_then(|alert| alert.try_into().ok()))
+    }
+
+    /// Records the send states of the alert and that it was notified at `notified_at`.
+    async fn record(
+        &self,
+        conn: &mut PgConnection,
+        table: AlertTable,
+        organization_id: i64,
+        notified_at: NaiveDateTime,
+    ) -> Result<(), DatabaseError> {
+        match table {
+            AlertTable::Node => {
+                sqlx::query!(
+                    r#"UPDATE node_alerts_active
+                       SET telegram_send = $3, sendgrid_send = $4, pagerduty_send = $5,
+                        notified_at = $6
+                       WHERE organization_id = $1 AND alert_id = $2"#,
+                    organization_id,
+                    self.alert_id,
+                    self.telegram_send as SendState,
+                    self.sendgrid_send as SendState,
+                    self.pagerduty_send as SendState,
+                    notified_at
+                )
+                .execute(conn)
+                .await?;
+            }
+            AlertTable::Machine => {
+                sqlx::query!(
+                    r#"UPDATE machine_alerts_active
+                       SET telegram_send = $3, sendgrid_send = $4, pagerduty_send = $5,
+                        notified_at = $6
+                       WHERE organization_id = $1 AND alert_id = $2"#,
+                    organization_id,
+                    self.alert_id,
+                    self.telegram_send as SendState,
+                    self.sendgrid_send as SendState,
+                    self.pagerduty_send as SendState,
+                    notified_at
+                )
+                .execute(conn)
+                .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Notifies the unacknowledged node and machine alerts of organizations with an escalation policy
+/// on the channels whose step is due, and again once the repeat interval passed. Each step taken
+/// is recorded in the send state of the alert.
+#[derive(Clone)]
+pub struct EscalationHandler {
+    pub dispatcher: Arc<NotificationDispatcher<AlertDb>>,
+    db_executor: PgPool,
+}
+
+impl EscalationHandler {
+    pub fn new(dispatcher: Arc<NotificationDispatcher<AlertDb>>, db_executor: PgPool) -> Self {
+        Self { dispatcher, db_executor }
+    }
+
+    /// Escalates alerts every `interval`. Runs until the task is dropped.
+    pub async fn run(self, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            self.escalate().await;
+        }
+    }
+
+    pub async fn escalate(&self) {
+        let policies = match EscalationPolicy::get_all(&self.db_executor).await {
+            Ok(policies) => policies,
+            Err(e) => {
+                error!("Failed to load escalation policies: {}", e);
+                return;
+            }
+        };
+        for (organization_id, policy) in policies {
+            if let Err(e) = self.escalate_organization(organization_id, &policy).await {
+                error!("Failed to escalate alerts of organization {}: {}", organization_id, e);
+            }
+        }
+    }
+
+    async fn escalate_organization(
+        &self,
+        organization_id: i64,
+        policy: &EscalationPolicy,
+    ) -> Result<(), DatabaseError> {
+        let settings = NotificationSettings::get(&self.db_executor, organization_id as u64).await?;
+        let silences = AlertSilence::get_active_for_org(&self.db_executor, organization_id).await?;
+
+        for table in [AlertTable::Node, AlertTable::Machine] {
+            let alerts =
+                EscalatingAlert::unacknowledged(&self.db_executor, table, organization_id).await?;
+            for alert in alerts {
+                let alert_type = AlertType::from(&alert.alert);
+                let enabled =
+                    settings.alert_flags.is_alert_enabled(&alert_type).is_ok_and(|enabled| enabled);
+                let node = alert.alert.node();
+                let target = SilenceTarget {
+                    alert_type,
+                    machine_id: Some(alert.machine_id),
+                    node_name: node.map(|(node_name, _)| node_name),
+                    node_type: node.map(|(_, node_type)| node_type),
+                };
+                let silenced = silences.iter().any(|silence| silence.matches(&target));
+                let now = Utc::now().naive_utc();
+                if !enabled || silenced || alert.due(policy, &settings, now).is_empty() {
+                    continue;
+                }
+
+                self.escalate_alert(table, organization_id, alert.alert_id, policy, &settings)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn escalate_alert(
+        &self,
+        table: AlertTable,
+        organization_id: i64,
+        alert_id: Uuid,
+        policy: &EscalationPolicy,
+        settings: &NotificationSettings,
+    ) -> Result<(), DatabaseError> {
+        let mut tx = self.db_executor.begin().await?;
+
+        // What is due is told again from the locked alert, as another ingress may have taken the
+        // step since it was listed
+        let Some(mut alert) =
+            EscalatingAlert::lock(&mut tx, table, organization_id, alert_id).await?
+        else {
+            return Ok(());
+        };
+        let now = Utc::now().naive_utc();
+        let due = alert.due(policy, settings, now);
+        if due.is_empty() {
+            return Ok(());
+        }
+
+        for (escalation_channel, channel) in due {
+            let notification = Notification {
+                id: alert.alert_id,
+                organization: organization_id as u64,
+                machine_id: Some(alert.machine_id),
+                alert: alert.alert.clone(),
+                resolved: false,
+            };
+            let send_state = match self.dispatcher.notify_channel(notification, &channel).await {
+                true => SendState::SendSuccess,
+                false => SendState::SendFailed,
+            };
+            alert.set_send_state(escalation_channel, send_state);
+        }
+
+        alert.record(&mut tx, table, organization_id, now).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod escalation_tests {
+    use super::*;
+
+    fn policy() -> EscalationPolicy {
+        EscalationPolicy {
+            steps: vec![
+                EscalationStep { channel: EscalationChannel::Telegram, after_secs: 0 },
+                EscalationStep { channel: EscalationChannel::Email, after_secs: 600 },
+                EscalationStep { channel: EscalationChannel::PagerDuty, after_secs: 1800 },
+            ],
+            repeat_interval_secs: Some(4 * 3600),
+        }
+    }
+
+    #[test]
+    fn test_steps_are_taken_as_alerts_age() {
+        let policy = policy();
+        assert!(policy.is_immediate(EscalationChannel::Telegram));
+        assert!(!policy.is_immediate(EscalationChannel::Email));
+
+        let telegram_sent = |channel| channel == EscalationChannel::Telegram;
+        let age = TimeDelta::minutes(5);
+        assert!(policy.due(age, age, telegram_sent).is_empty());
+        let age = TimeDelta::minutes(10);
+        assert_eq!(policy.due(age, age, telegram_sent), vec![EscalationChannel::Email]);
+        let age = TimeDelta::minutes(45);
+        assert_eq!(
+            policy.due(age, age, telegram_sent),
+            vec![EscalationChannel::Email, EscalationChannel::PagerDuty]
+        );
+    }
+
+    #[test]
+    fn test_repeats_once_all_steps_were_taken() {
+        let policy = policy();
+        let age = TimeDelta::hours(5);
+        assert!(policy.due(age, TimeDelta::hours(3), |_| true).is_empty());
+        assert_eq!(policy.due(age, TimeDelta::hours(4), |_| true).len(), 3);
+
+        let policy = EscalationPolicy { repeat_interval_secs: None, ..policy };
+        assert!(policy.due(age, TimeDelta::hours(4), |_| true).is_empty());
+    }
+}
@@ -1,8 +1,11 @@
 pub mod alert_db;
 pub mod alert_handler;
+pub mod escalation;
 pub mod machine;
 pub mod node;
 pub mod org;
+pub mod rule;
+pub mod silence;
 
 #[cfg(test)]
 mod test_alerts_db {
@@ -7,12 +7,14 @@ use ivynet_node_type::NodeType;
 use ivynet_notifications::{Channel, NotificationDispatcher, NotificationDispatcherError};
 
 use async_trait::async_trait;
+use chrono::NaiveDateTime;
 use sqlx::{types::Uuid, PgPool};
 
 use crate::{
     alerts::{
         alert_db::AlertDb,
         alert_handler::{ActiveAlert, AlertHandler, NewAlert},
+        rule::{evaluate_rules, AlertRuleState},
     },
     avs_version::{NodeTypeId, VersionData},
     data::{
@@ -20,16 +22,23 @@ use crate::{
         node_data::UpdateStatus,
     },
     error::DatabaseError,
+    metric::Metric,
     Avs, DbAvsVersionData, Machine,
 };
 
-use super::alerts_active::{NewNodeAlert, NodeActiveAlert};
+use super::{
+    alerts_active::{NewNodeAlert, NodeActiveAlert},
+    metric_alert_cache::MetricAlertCache,
+};
 
 pub const RUNNING_METRIC: &str = "running";
 pub const EIGEN_PERFORMANCE_METRIC: &str = "eigen_performance_score";
 
 pub const IDLE_MINUTES_THRESHOLD: i64 = 15;
 pub const EIGEN_PERFORMANCE_HEALTHY_THRESHOLD: f64 = 80.0;
+/// How far above the healthy threshold the performance score has to recover before a
+/// LowPerformanceScore alert is resolved
+pub const EIGEN_PERFORMANCE_RECOVERY_MARGIN: f64 = 5.0;
 
 pub enum UuidAlertType {
     NoMetrics(),
@@ -81,11 +90,12 @@ impl ActiveAlert for NodeActiveAlert {
 pub struct NodeAlertHandler {
     pub dispatcher: Arc<NotificationDispatcher<AlertDb>>,
     db_executor: PgPool,
+    metric_alert_cache: Arc<MetricAlertCache>,
 }
 
 impl NodeAlertHandler {
     pub fn new(dispatcher: Arc<NotificationDispatcher<AlertDb>>, db_executor: PgPool) -> Self {
-        Self { dispatcher, db_executor }
+        Self { dispatcher, db_executor, metric_alert_cache: Arc::new(MetricAlertCache::default()) }
     }
 
     pub async fn handle_node_data_alerts(
@@ -117,6 +127,151 @@ impl NodeAlertHandler {
 
         Ok(())
     }
+
+    /// Evaluates the alert rules of the organization against a batch of metrics of a node,
+    /// collected at `collected_at`. Raises an alert for every series that satisfied a rule for
+    /// long enough and resolves the alerts of series that no longer do. Batches without the metric
+    /// of any rule are skipped.
+    pub async fn handle_metric_alerts(
+        &self,
+        machine_id: Uuid,
+        node_name: &str,
+        metrics: &[Metric],
+        collected_at: NaiveDateTime,
+    ) -> Result<(), NodeAlertError> {
+        let organization_id =
+            self.metric_alert_cache.organization_id(&self.db_executor, machine_id).await?;
+        let alerts = self.metric_alert_cache.alerts(&self.db_executor, organization_id).await?;
+        let metric_names = metrics.iter().map(|metric| metric.name.as_str()).collect::<Vec<_>>();
+        if !alerts.evaluates_any(&metric_names) {
+            return Ok(());
+        }
+
+        let states = AlertRuleState::get_for_node(&self.db_executor, machine_id, node_name).await?;
+
+        let node_type = Avs::get_machines_avs(&self.db_executor, machine_id, node_name)
+            .await?
+            .map(|avs| avs.avs_type.to_string());
+        let evaluation = evaluate_rules(
+            &alerts.rules,
+            &states,
+            machine_id,
+            node_name,
+            node_type.as_deref(),
+            metrics,
+            collected_at,
+        );
+
+        for state in &evaluation.started {
+            state.start(&self.db_executor).await?;
+        }
+
+        if !evaluation.firing.is_empty() {
+            let new_alerts = evaluation
+                .firing
+                .iter()
+                .map(|(_, alert)| NewNodeAlert::new(machine_id, alert.clone(), node_name.into()))
+                .collect::<Vec<_>>();
+            let existing_alerts =
+                NodeActiveAlert::all_alerts_by_machine(&self.db_executor, machine_id).await?;
+            let mut filtered_new_alerts =
+                self.filter_duplicate_alerts(new_alerts.clone(), existing_alerts).await?;
+            self.send_notifications(
+                &mut filtered_new_alerts,
+                organization_id as u64,
+                Some(machine_id),
+            )
+            .await?;
+            NodeActiveAlert::insert_many(&self.db_executor, &filtered_new_alerts).await?;
+
+            for ((state, _), alert) in evaluation.firing.iter().zip(&new_alerts) {
+                state.set_alert(&self.db_executor, alert.id).await?;
+            }
+        }
+
+        for state in evaluation.cleared {
+            resolve_rule_alert(&self.db_executor, state).await?;
+            state.clear(&self.db_executor).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Raises a LowPerformanceScore alert when an EigenDA node reports a performance score below
+    /// the healthy threshold of its organization, and resolves it once the score recovered.
+    pub async fn handle_performance_alert(
+        &self,
+        machine_id: Uuid,
+        node_name: &str,
+        metrics: &[Metric],
+    ) -> Result<(), NodeAlertError> {
+        let Some(score) = metrics.iter().find(|m| m.name == EIGEN_PERFORMANCE_METRIC) else {
+            return Ok(());
+        };
+        let Some(avs) = Avs::get_machines_avs(&self.db_executor, machine_id, node_name).await?
+        else {
+            return Ok(());
+        };
+        if avs.avs_type != NodeType::EigenDA {
+            return Ok(());
+        }
+
+        let organization_id =
+            self.metric_alert_cache.organization_id(&self.db_executor, machine_id).await?;
+        let threshold = self
+            .metric_alert_cache
+            .alerts(&self.db_executor, organization_id)
+            .await?
+            .healthy_threshold;
+
+        let alert = Alert::LowPerformanceScore {
+            node_name: node_name.to_string(),
+            node_type: avs.avs_type.to_string(),
+            performance: score.value.round() as u16,
+        };
+        let new_alert = NewNodeAlert::new(machine_id, alert, node_name.to_string());
+        let alerting = NodeActiveAlert::get(&self.db_executor, new_alert.id).await?.is_some();
+
+        match (alerting, low_performance(score.value, threshold, alerting)) {
+            (false, true) => {
+                let mut new_alerts = vec![new_alert];
+                self.send_notifications(&mut new_alerts, organization_id as u64, Some(machine_id))
+                    .await?;
+                NodeActiveAlert::insert_many(&self.db_executor, &new_alerts).await?;
+            }
+            (true, false) => {
+                NodeActiveAlert::resolve_alert(&self.db_executor, new_alert.id).await?;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether a node with a performance `score` should have a LowPerformanceScore alert, given
+/// whether it already has one. A raised alert holds until the score recovered past the threshold
+/// by [`EIGEN_PERFORMANCE_RECOVERY_MARGIN`], so a score hovering around the threshold doesn't
+/// flap.
+pub fn low_performance(score: f64, threshold: f64, alerting: bool) -> bool {
+    if alerting {
+        score < threshold + EIGEN_PERFORMANCE_RECOVERY_MARGIN
+    } else {
+        score < threshold
+    }
+}
+
+/// Resolves the alert raised for a series of an alert rule, unless it was already removed.
+pub async fn resolve_rule_alert(
+    pool: &PgPool,
+    state: &AlertRuleState,
+) -> Result<(), DatabaseError> {
+    if let Some(alert_id) = state.alert_id {
+        if NodeActiveAlert::get(pool, alert_id).await?.is_some() {
+            NodeActiveAlert::resolve_alert(pool, alert_id).await?;
+        }
+    }
+    Ok(())
 }
 
 #[async_trait]
@@ -383,6 +538,7 @@ pub async fn resolve_org_alerts(
     // Filter existing alerts, removing any that are not in the incoming list
     let to_resolve = db_alerts
         .into_iter()
+        .filter(|alert| !is_metric_alert(alert))
         .filter(|alert| !alerts.iter().any(|new_alert| new_alert.get_id() == alert.alert_id))
         .collect::<Vec<_>>();
 
@@ -403,6 +559,7 @@ pub async fn resolve_machine_alerts(
     // Filter existing alerts, removing any that are not in the incoming list
     let to_resolve = db_alerts
         .into_iter()
+        .filter(|alert| !is_metric_alert(alert))
         .filter(|alert| !alerts.iter().any(|new_alert| new_alert.get_id() == alert.alert_id))
         .collect::<Vec<_>>();
 
@@ -423,6 +580,7 @@ pub async fn resolve_node_alerts(
     // Filter existing alerts, removing any that are not in the incoming list
     let to_resolve = db_alerts
         .into_iter()
+        .filter(|alert| !is_metric_alert(alert))
         .filter(|alert| !alerts.iter().any(|new_alert| new_alert.get_id() == alert.alert_id))
         .collect::<Vec<_>>();
 
@@ -433,6 +591,12 @@ pub async fn resolve_node_alerts(
     Ok(())
 }
 
+/// Alerts raised by alert rules and from performance scores are not derived from AVS data, and are
+/// resolved as metrics come in.
+fn is_metric_alert(alert: &NodeActiveAlert) -> bool {
+    matches!(alert.alert_type, Alert::Custom { .. } | Alert::LowPerformanceScore { .. })
+}
+
 /// node_image_digest: corresponds to the docker image digest for the node.
 pub fn get_update_status(
     version_map: HashMap<NodeTypeId, VersionData>,
@@ -536,4 +700,14 @@ mod tests {
         assert_eq!(filtered_alerts.len(), 1);
         assert_eq!(filtered_alerts[0].alert_type, alert_type_2);
     }
+
+    #[test]
+    fn test_low_performance_hysteresis() {
+        let threshold = EIGEN_PERFORMANCE_HEALTHY_THRESHOLD;
+        assert!(low_performance(79.0, threshold, false));
+        assert!(!low_performance(80.0, threshold, false));
+        // A raised alert holds until the score clears the recovery margin
+        assert!(low_performance(82.0, threshold, true));
+        assert!(!low_performance(threshold + EIGEN_PERFORMANCE_RECOVERY_MARGIN, threshold, true));
+    }
 }
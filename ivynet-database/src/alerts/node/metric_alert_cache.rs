@@ -0,0 +1,155 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use sqlx::{types::Uuid, PgPool};
+
+use crate::{
+    alerts::rule::{AlertRule, CompiledRule},
+    Machine, PerformanceSettings,
+};
+
+use super::alert_handler::NodeAlertError;
+
+/// How long what the cache read is used before it is read again. Rules and settings are changed
+/// through the api, so changes take effect on the metrics coming in within this time.
+pub const METRIC_ALERT_CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// What evaluating the metrics of an organization's nodes needs from the organization
+#[derive(Debug)]
+pub struct OrganizationAlerts {
+    /// Alert rules of the organization, ready to be evaluated
+    pub rules: Vec<CompiledRule>,
+    /// Performance score below which EigenDA nodes raise a LowPerformanceScore alert
+    pub healthy_threshold: f64,
+}
+
+impl OrganizationAlerts {
+    /// Whether any enabled rule is evaluated on one of the metrics. Series of rules only change
+    /// state in batches with their metric.
+    pub fn evaluates_any(&self, metric_names: &[&str]) -> bool {
+        self.rules.iter().any(|compiled| {
+            compiled.rule.enabled && metric_names.contains(&&*compiled.rule.metric_name)
+        })
+    }
+}
+
+struct Cached<T> {
+    value: T,
+    read_at: Instant,
+}
+
+/// Caches the organization of machines and the alert rules and settings of organizations, which
+/// would otherwise be read for every batch of metrics. Entries expire after `ttl`.
+pub struct MetricAlertCache {
+    ttl: Duration,
+    organizations: RwLock<HashMap<Uuid, Cached<i64>>>,
+    alerts: RwLock<HashMap<i64, Cached<Arc<OrganizationAlerts>>>>,
+}
+
+impl MetricAlertCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            organizations: RwLock::new(HashMap::new()),
+            alerts: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub async fn organization_id(
+        &self,
+        pool: &PgPool,
+        machine_id: Uuid,
+    ) -> Result<i64, NodeAlertError> {
+        if let Some(organization_id) = self.fresh(&self.organizations, &machine_id) {
+            return Ok(organization_id);
+        }
+        let organization_id = Machine::get_organization_id(pool, machine_id).await?;
+        self.organizations
+            .write()
+            .expect("Write lock failed")
+            .insert(machine_id, Cached { value: organization_id, read_at: Instant::now() });
+        Ok(organization_id)
+    }
+
+    pub async fn alerts(
+        &self,
+        pool: &PgPool,
+        organization_id: i64,
+    ) -> Result<Arc<OrganizationAlerts>, NodeAlertError> {
+        if let Some(alerts) = self.fresh(&self.alerts, &organization_id) {
+            return Ok(alerts);
+        }
+        let rules = AlertRule::get_all_for_org(pool, organization_id).await?;
+        let healthy_threshold =
+            PerformanceSettings::get(pool, organization_id).await?.healthy_threshold();
+        let alerts = Arc::new(OrganizationAlerts {
+            rules: rules.into_iter().map(CompiledRule::from).collect(),
+            healthy_threshold,
+        });
+        self.alerts
+            .write()
+            .expect("Write lock failed")
+            .insert(organization_id, Cached { value: alerts.clone(), read_at: Instant::now() });
+        Ok(alerts)
+    }
+
+    fn fresh<K: Eq + std::hash::Hash, T: Clone>(
+        &self,
+        entries: &RwLock<HashMap<K, Cached<T>>>,
+        key: &K,
+    ) -> Option<T> {
+        entries
+            .read()
+            .expect("Read lock failed")
+            .get(key)
+            .filter(|cached| cached.read_at.elapsed() < self.ttl)
+            .map(|cached| cached.value.clone())
+    }
+}
+
+impl Default for MetricAlertCache {
+    fn default() -> Self {
+        Self::new(METRIC_ALERT_CACHE_TTL)
+    }
+}
+
+#[cfg(test)]
+mod metric_alert_cache_tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::alerts::rule::{AlertRuleScope, Comparison};
+
+    fn rule(metric_name: &str, enabled: bool) -> CompiledRule {
+        CompiledRule::from(AlertRule {
+            rule_id: Uuid::new_v4(),
+            organization_id: 1,
+            name: "Rule".to_string(),
+            metric_name: metric_name.to_string(),
+            label_matchers: vec![],
+            comparison: Comparison::Gt,
+            threshold: 0.0,
+            for_secs: 0,
+            scope: AlertRuleScope::default(),
+            enabled,
+            created_at: Utc::now().naive_utc(),
+            updated_at: Utc::now().naive_utc(),
+        })
+    }
+
+    #[test]
+    fn test_evaluates_any() {
+        let alerts = OrganizationAlerts {
+            rules: vec![rule("disk_free_bytes", true), rule("cpu_usage", false)],
+            healthy_threshold: 80.0,
+        };
+        assert!(alerts.evaluates_any(&["up", "disk_free_bytes"]));
+        // Disabled rules have no series left to clear
+        assert!(!alerts.evaluates_any(&["cpu_usage"]));
+        assert!(!OrganizationAlerts { rules: vec![], healthy_threshold: 80.0 }
+            .evaluates_any(&["disk_free_bytes"]));
+    }
+}
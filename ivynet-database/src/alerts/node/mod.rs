@@ -1,3 +1,4 @@
 pub mod alert_handler;
 pub mod alerts_active;
 pub mod alerts_historical;
+pub mod metric_alert_cache;
@@ -0,0 +1,680 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use ivynet_alerts::Alert;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Json, PgPool};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{error::DatabaseError, metric::Metric};
+
+/// How the value of a metric is compared to the threshold of a rule
+#[derive(Copy, Clone, Debug, PartialEq, Eq, sqlx::Type, Deserialize, Serialize, ToSchema)]
+#[sqlx(type_name = "alert_rule_comparison", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Comparison {
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Eq,
+    Ne,
+}
+
+impl Comparison {
+    pub fn holds(&self, value: f64, threshold: f64) -> bool {
+        match self {
+            Comparison::Gt => value > threshold,
+            Comparison::Ge => value >= threshold,
+            Comparison::Lt => value < threshold,
+            Comparison::Le => value <= threshold,
+            Comparison::Eq => value == threshold,
+            Comparison::Ne => value != threshold,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+pub enum MatchOp {
+    #[serde(rename = "=")]
+    Eq,
+    #[serde(rename = "!=")]
+    Ne,
+    #[serde(rename = "=~")]
+    Regex,
+    #[serde(rename = "!~")]
+    NotRegex,
+}
+
+/// Condition on a label of a metric, as in Prometheus. Labels a metric does not have match as
+/// empty, and regular expressions have to match the whole value.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+pub struct LabelMatcher {
+    pub name: String,
+    pub op: MatchOp,
+    pub value: String,
+}
+
+impl LabelMatcher {
+    /// Fails for regular expressions that do not compile.
+    pub fn validate(&self) -> Result<(), regex::Error> {
+        match self.op {
+            MatchOp::Regex | MatchOp::NotRegex => self.regex().map(|_| ()),
+            MatchOp::Eq | MatchOp::Ne => Ok(()),
+        }
+    }
+
+    pub fn matches(&self, labels: Option<&HashMap<String, String>>) -> bool {
+        self.matches_compiled(self.regex().ok().as_ref(), labels)
+    }
+
+    /// Same as [`LabelMatcher::matches`], with the regular expression of the matcher compiled
+    /// beforehand. Regular expressions that did not compile match nothing.
+    fn matches_compiled(
+        &self,
+        regex: Option<&Regex>,
+        labels: Option<&HashMap<String, String>>,
+    ) -> bool {
+        let value = labels.and_then(|labels| labels.get(&self.name)).map_or("", String::as_str);
+        match self.op {
+            MatchOp::Eq => value == self.value,
+            MatchOp::Ne => value != self.value,
+            MatchOp::Regex => regex.is_some_and(|regex| regex.is_match(value)),
+            MatchOp::NotRegex => regex.is_some_and(|regex| !regex.is_match(value)),
+        }
+    }
+
+    fn regex(&self) -> Result<Regex, regex::Error> {
+        Regex::new(&format!("^(?:{})$", self.value))
+    }
+}
+
+/// Nodes a rule applies to. Every list that is not empty has to include the node, so an empty
+/// scope applies to every node of the organization.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+#[serde(default)]
+pub struct AlertRuleScope {
+    pub node_types: Vec<String>,
+    pub machines: Vec<Uuid>,
+    pub nodes: Vec<ScopedNode>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+pub struct ScopedNode {
+    pub machine_id: Uuid,
+    pub node_name: String,
+}
+
+impl AlertRuleScope {
+    pub fn includes(&self, machine_id: Uuid, node_name: &str, node_type: Option<&str>) -> bool {
+        (self.node_types.is_empty() ||
+            node_type.is_some_and(|node_type| self.node_types.iter().any(|t| t == node_type))) &&
+            (self.machines.is_empty() || self.machines.contains(&machine_id)) &&
+            (self.nodes.is_empty() ||
+                self.nodes
+                    .iter()
+                    .any(|node| node.machine_id == machine_id && node.node_name == node_name))
+    }
+}
+
+/// A rule as it is created or updated
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize, ToSchema)]
+pub struct AlertRuleDefinition {
+    pub name: String,
+    pub metric_name: String,
+    #[serde(default)]
+    pub label_matchers: Vec<LabelMatcher>,
+    pub comparison: Comparison,
+    pub threshold: f64,
+    /// How long the condition has to hold before an alert is raised, in seconds
+    #[serde(default)]
+    pub for_secs: i64,
+    #[serde(default)]
+    pub scope: AlertRuleScope,
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+/// Raises a custom alert for every series of a metric of a node which compares to the threshold
+/// for at least `for_secs`, and resolves it when it no longer does.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize, ToSchema)]
+pub struct AlertRule {
+    pub rule_id: Uuid,
+    pub organization_id: i64,
+    pub name: String,
+    pub metric_name: String,
+    pub label_matchers: Vec<LabelMatcher>,
+    pub comparison: Comparison,
+    pub threshold: f64,
+    pub for_secs: i64,
+    pub scope: AlertRuleScope,
+    pub enabled: bool,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+struct DbAlertRule {
+    rule_id: Uuid,
+    organization_id: i64,
+    name: String,
+    metric_name: String,
+    label_matchers: Json<Vec<LabelMatcher>>,
+    comparison: Comparison,
+    threshold: f64,
+    for_secs: i64,
+    scope: Json<AlertRuleScope>,
+    enabled: bool,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+}
+
+impl From<DbAlertRule> for AlertRule {
+    fn from(value: DbAlertRule) -> Self {
+        Self {
+            rule_id: value.rule_id,
+            organization_id: value.organization_id,
+            name: value.name,
+            metric_name: value.metric_name,
+            label_matchers: value.label_matchers.0,
+            comparison: value.comparison,
+            threshold: value.threshold,
+            for_secs: value.for_secs,
+            scope: value.scope.0,
+            enabled: value.enabled,
+            created_at: value.created_at,
+            updated_at: value.updated_at,
+        }
+    }
+}
+
+/// A rule with the regular expressions of its label matchers compiled, so it can be evaluated
+/// against many batches of metrics.
+#[derive(Clone, Debug)]
+pub struct CompiledRule {
+    pub rule: AlertRule,
+    /// The compiled expression of every label matcher, in the same order
+    regexes: Vec<Option<Regex>>,
+}
+
+impl From<AlertRule> for CompiledRule {
+    fn from(rule: AlertRule) -> Self {
+        let regexes = rule
+            .label_matchers
+            .iter()
+            .map(|matcher| match matcher.op {
+                MatchOp::Regex | MatchOp::NotRegex => matcher.regex().ok(),
+                MatchOp::Eq | MatchOp::Ne => None,
+            })
+            .collect();
+        Self { rule, regexes }
+    }
+}
+
+impl CompiledRule {
+    /// Metrics the rule is evaluated on
+    pub fn matching<'a>(&'a self, metrics: &'a [Metric]) -> impl Iterator<Item = &'a Metric> {
+        metrics.iter().filter(|metric| {
+            metric.name == self.rule.metric_name &&
+                self.rule.label_matchers.iter().zip(&self.regexes).all(|(matcher, regex)| {
+                    matcher.matches_compiled(regex.as_ref(), metric.attributes.as_ref())
+                })
+        })
+    }
+}
+
+impl AlertRule {
+    /// The alert raised for `series` of the node. Only depends on the rule and the series, so it
+    /// keeps its id for as long as the condition holds.
+    pub fn alert(&self, node_name: &str, node_type: Option<&str>, series: &str) -> Alert {
+        Alert::Custom {
+            node_name: node_name.to_string(),
+            node_type: node_type.unwrap_or("unknown").to_string(),
+            extra_data: serde_json::json!({
+                "rule_id": self.rule_id,
+                "rule": self.name,
+                "metric": self.metric_name,
+                "series": series,
+                "comparison": self.comparison,
+                "threshold": self.threshold,
+            }),
+        }
+    }
+
+    pub async fn create(
+        pool: &PgPool,
+        organization_id: i64,
+        definition: &AlertRuleDefinition,
+    ) -> Result<AlertRule, DatabaseError> {
+        let now = Utc::now().naive_utc();
+        let rule = sqlx::query_as!(
+            DbAlertRule,
+            r#"INSERT INTO alert_rule
+                (rule_id, organization_id, name, metric_name, label_matchers, comparison,
+                 threshold, for_secs, scope, enabled, created_at, updated_at)
+               VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $11)
+               RETURNING
+                rule_id, organization_id, name, metric_name,
+                label_matchers AS "label_matchers: Json<Vec<LabelMatcher>>",
+                comparison AS "comparison: Comparison",
+                threshold, for_secs,
+                scope AS "scope: Json<AlertRuleScope>",
+                enabled, created_at, updated_at"#,
+            Uuid::new_v4(),
+            organization_id,
+            definition.name,
+            definition.metric_name,
+            Json(&definition.label_matchers) as _,
+            definition.comparison as Comparison,
+            definition.threshold,
+            definition.for_secs,
+            Json(&definition.scope) as _,
+            definition.enabled,
+            now,
+        )
+        .fetch_one(pool)
+        .await?;
+        Ok(rule.into())
+    }
+
+    pub async fn get(pool: &PgPool, rule_id: Uuid) -> Result<Option<AlertRule>, DatabaseError> {
+        let rule = sqlx::query_as!(
+            DbAlertRule,
+            r#"SELECT
+                rule_id, organization_id, name, metric_name,
+                label_matchers AS "label_matchers: Json<Vec<LabelMatcher>>",
+                comparison AS "comparison: Comparison",
+                threshold, for_secs,
+                scope AS "scope: Json<AlertRuleScope>",
+                enabled, created_at, updated_at
+               FROM alert_rule
+               WHERE rule_id = $1"#,
+            rule_id
+        )
+        .fetch_optional(pool)
+        .await?;
+        Ok(rule.map(AlertRule::from))
+    }
+
+    pub async fn get_all_for_org(
+        pool: &PgPool,
+        organization_id: i64,
+    ) -> Result<Vec<AlertRule>, DatabaseError> {
+        let rules = sqlx::query_as!(
+            DbAlertRule,
+            r#"SELECT
+                rule_id, organization_id, name, metric_name,
+                label_matchers AS "label_matchers: Json<Vec<LabelMatcher>>",
+                comparison AS "comparison: Comparison",
+                threshold, for_secs,
+                scope AS "scope: Json<AlertRuleScope>",
+                enabled, created_at, updated_at
+               FROM alert_rule
+               WHERE organization_id = $1
+               ORDER BY created_at"#,
+            organization_id
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(rules.into_iter().map(AlertRule::from).collect())
+    }
+
+    /// Replaces the definition of the rule. Callers resolve the alerts of the old definition.
+    pub async fn update(
+        pool: &PgPool,
+        rule_id: Uuid,
+        definition: &AlertRuleDefinition,
+    ) -> Result<Option<AlertRule>, DatabaseError> {
+        let rule = sqlx::query_as!(
+            DbAlertRule,
+            r#"UPDATE alert_rule
+               SET name = $2, metric_name = $3, label_matchers = $4, comparison = $5,
+                   threshold = $6, for_secs = $7, scope = $8, enabled = $9, updated_at = $10
+               WHERE rule_id = $1
+               RETURNING
+                rule_id, organization_id, name, metric_name,
+                label_matchers AS "label_matchers: Json<Vec<LabelMatcher>>",
+                comparison AS "comparison: Comparison",
+                threshold, for_secs,
+                scope AS "scope: Json<AlertRuleScope>",
+                enabled, created_at, updated_at"#,
+            rule_id,
+            definition.name,
+            definition.metric_name,
+            Json(&definition.label_matchers) as _,
+            definition.comparison as Comparison,
+            definition.threshold,
+            definition.for_secs,
+            Json(&definition.scope) as _,
+            definition.enabled,
+            Utc::now().naive_utc(),
+        )
+        .fetch_optional(pool)
+        .await?;
+        Ok(rule.map(AlertRule::from))
+    }
+
+    /// Deletes the rule along with its state. Callers resolve its alerts first.
+    pub async fn delete(pool: &PgPool, rule_id: Uuid) -> Result<(), DatabaseError> {
+        sqlx::query!("DELETE FROM alert_rule WHERE rule_id = $1", rule_id).execute(pool).await?;
+        Ok(())
+    }
+}
+
+/// Identifies a series of a metric by its labels, independent of their order.
+pub fn series(labels: Option<&HashMap<String, String>>) -> String {
+    let sorted = labels.into_iter().flatten().collect::<BTreeMap<_, _>>();
+    sorted
+        .into_iter()
+        .map(|(name, value)| format!("{name}={value:?}"))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// A series of a node whose value has been satisfying a rule since `pending_since`. Once it did
+/// for long enough, the alert raised for it is kept in `alert_id`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AlertRuleState {
+    pub rule_id: Uuid,
+    pub machine_id: Uuid,
+    pub node_name: String,
+    pub series: String,
+    pub pending_since: NaiveDateTime,
+    pub alert_id: Option<Uuid>,
+}
+
+impl AlertRuleState {
+    pub async fn get_for_node(
+        pool: &PgPool,
+        machine_id: Uuid,
+        node_name: &str,
+    ) -> Result<Vec<AlertRuleState>, DatabaseError> {
+        Ok(sqlx::query_as!(
+            AlertRuleState,
+            r#"SELECT rule_id, machine_id, node_name, series, pending_since, alert_id
+               FROM alert_rule_state
+               WHERE machine_id = $1 AND node_name = $2"#,
+            machine_id,
+            node_name
+        )
+        .fetch_all(pool)
+        .await?)
+    }
+
+    pub async fn get_for_rule(
+        pool: &PgPool,
+        rule_id: Uuid,
+    ) -> Result<Vec<AlertRuleState>, DatabaseError> {
+        Ok(sqlx::query_as!(
+            AlertRuleState,
+            r#"SELECT rule_id, machine_id, node_name, series, pending_since, alert_id
+               FROM alert_rule_state
+               WHERE rule_id = $1"#,
+            rule_id
+        )
+        .fetch_all(pool)
+        .await?)
+    }
+
+    /// Records a series that started to satisfy its rule. A series already recorded, e.g. by
+    /// another ingress, keeps the time it started at.
+    pub async fn start(&self, pool: &PgPool) -> Result<(), DatabaseError> {
+        sqlx::query!(
+            r#"INSERT INTO alert_rule_state
+                (rule_id, machine_id, node_name, series, pending_since, alert_id)
+               VALUES ($1, $2, $3, $4, $5, $6)
+               ON CONFLICT (rule_id, machine_id, node_name, series) DO NOTHING"#,
+            self.rule_id,
+            self.machine_id,
+            self.node_name,
+            self.series,
+            self.pending_since,
+            self.alert_id
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn set_alert(&self, pool: &PgPool, alert_id: Uuid) -> Result<(), DatabaseError> {
+        sqlx::query!(
+            r#"UPDATE alert_rule_state SET alert_id = $5
+               WHERE rule_id = $1 AND machine_id = $2 AND node_name = $3 AND series = $4"#,
+            self.rule_id,
+            self.machine_id,
+            self.node_name,
+            self.series,
+            alert_id
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn clear(&self, pool: &PgPool) -> Result<(), DatabaseError> {
+        sqlx::query!(
+            r#"DELETE FROM alert_rule_state
+               WHERE rule_id = $1 AND machine_id = $2 AND node_name = $3 AND series = $4"#,
+            self.rule_id,
+            self.machine_id,
+            self.node_name,
+            self.series
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn clear_rule(pool: &PgPool, rule_id: Uuid) -> Result<(), DatabaseError> {
+        sqlx::query!("DELETE FROM alert_rule_state WHERE rule_id = $1", rule_id)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+}
+
+/// What changes after evaluating rules against a batch of metrics of a node.
+#[derive(Debug, Default)]
+pub struct RuleEvaluation<'a> {
+    /// Series that started to satisfy their rule
+    pub started: Vec<AlertRuleState>,
+    /// Series that satisfied their rule for long enough, and the alert to raise for them
+    pub firing: Vec<(AlertRuleState, Alert)>,
+    /// Series that no longer satisfy their rule, or whose rule no longer applies
+    pub cleared: Vec<&'a AlertRuleState>,
+}
+
+/// Evaluates the rules of an organization against a batch of metrics of a node. Every series
+/// matching a rule is expected in each batch, so series missing from it are cleared.
+pub fn evaluate_rules<'a>(
+    rules: &[CompiledRule],
+    states: &'a [AlertRuleState],
+    machine_id: Uuid,
+    node_name: &str,
+    node_type: Option<&str>,
+    metrics: &[Metric],
+    now: NaiveDateTime,
+) -> RuleEvaluation<'a> {
+    let mut evaluation = RuleEvaluation::default();
+    let mut holding = HashSet::new();
+
+    let applicable = rules.iter().filter(|compiled| {
+        compiled.rule.enabled && compiled.rule.scope.includes(machine_id, node_name, node_type)
+    });
+    for compiled in applicable {
+        let rule = &compiled.rule;
+        for metric in compiled.matching(metrics) {
+            if !rule.comparison.holds(metric.value, rule.threshold) {
+                continue;
+            }
+            let series = series(metric.attributes.as_ref());
+            if !holding.insert((rule.rule_id, series.clone())) {
+                continue;
+            }
+
+            let state = match states
+                .iter()
+                .find(|state| state.rule_id == rule.rule_id && state.series == series)
+            {
+                Some(state) => state.clone(),
+                None => {
+                    let state = AlertRuleState {
+                        rule_id: rule.rule_id,
+                        machine_id,
+                        node_name: node_name.to_string(),
+                        series: series.clone(),
+                        pending_since: now,
+                        alert_id: None,
+                    };
+                    evaluation.started.push(state.clone());
+                    state
+                }
+            };
+
+            if state.alert_id.is_none() &&
+                now - state.pending_since >= TimeDelta::seconds(rule.for_secs)
+            {
+                let alert = rule.alert(node_name, node_type, &series);
+                evaluation.firing.push((state, alert));
+            }
+        }
+    }
+
+    evaluation.cleared = states
+        .iter()
+        .filter(|state| !holding.contains(&(state.rule_id, state.series.clone())))
+        .collect();
+
+    evaluation
+}
+
+#[cfg(test)]
+mod rule_tests {
+    use super::*;
+
+    fn rule(for_secs: i64) -> AlertRule {
+        AlertRule {
+            rule_id: Uuid::new_v4(),
+            organization_id: 1,
+            name: "Low disk".to_string(),
+            metric_name: "disk_free_bytes".to_string(),
+            label_matchers: vec![LabelMatcher {
+                name: "mount".to_string(),
+                op: MatchOp::Regex,
+                value: "/(data|var)".to_string(),
+            }],
+            comparison: Comparison::Lt,
+            threshold: 100.0,
+            for_secs,
+            scope: AlertRuleScope::default(),
+            enabled: true,
+            created_at: Utc::now().naive_utc(),
+            updated_at: Utc::now().naive_utc(),
+        }
+    }
+
+    fn metric(mount: &str, value: f64) -> Metric {
+        Metric {
+            machine_id: Uuid::nil(),
+            avs_name: None,
+            name: "disk_free_bytes".to_string(),
+            value,
+            attributes: Some(HashMap::from([("mount".to_string(), mount.to_string())])),
+            created_at: None,
+        }
+    }
+
+    #[test]
+    fn test_label_matchers() {
+        let rule = CompiledRule::from(rule(0));
+        let metrics = vec![metric("/data", 1.0), metric("/data/sub", 1.0), metric("/var", 1.0)];
+        assert_eq!(rule.matching(&metrics).count(), 2);
+
+        let missing =
+            LabelMatcher { name: "env".to_string(), op: MatchOp::Eq, value: String::new() };
+        assert!(missing.matches(None));
+        assert!(LabelMatcher { name: "a".to_string(), op: MatchOp::Regex, value: "(".to_string() }
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn test_scope() {
+        let machine = Uuid::new_v4();
+        let scope = AlertRuleScope {
+            node_types: vec!["eigenda".to_string()],
+            machines: vec![machine],
+            nodes: vec![],
+        };
+        assert!(scope.includes(machine, "node", Some("eigenda")));
+        assert!(!scope.includes(machine, "node", Some("witness")));
+        assert!(!scope.includes(machine, "node", None));
+        assert!(!scope.includes(Uuid::new_v4(), "node", Some("eigenda")));
+        assert!(AlertRuleScope::default().includes(machine, "node", None));
+    }
+
+    #[test]
+    fn test_series_ignores_label_order() {
+        let a = HashMap::from([("a".to_string(), "1".to_string()), ("b".to_string(), "2".into())]);
+        let b = HashMap::from([("b".to_string(), "2".to_string()), ("a".to_string(), "1".into())]);
+        assert_eq!(series(Some(&a)), series(Some(&b)));
+        assert_eq!(series(None), "");
+    }
+
+    #[test]
+    fn test_rule_fires_after_holding_for_duration() {
+        let rule = rule(60);
+        let rules = vec![CompiledRule::from(rule.clone())];
+        let machine = Uuid::new_v4();
+        let now = Utc::now().naive_utc();
+        let metrics = vec![metric("/data", 50.0)];
+
+        // Starts pending
+        let evaluation = evaluate_rules(&rules, &[], machine, "node", None, &metrics, now);
+        assert_eq!(evaluation.started.len(), 1);
+        assert!(evaluation.firing.is_empty());
+
+        // Fires once it held for long enough
+        let states = evaluation.started;
+        let later = now + TimeDelta::seconds(60);
+        let evaluation = evaluate_rules(&rules, &states, machine, "node", None, &metrics, later);
+        assert!(evaluation.started.is_empty());
+        assert_eq!(evaluation.firing.len(), 1);
+        assert!(evaluation.cleared.is_empty());
+        assert_eq!(evaluation.firing[0].1, rule.alert("node", None, "mount=\"/data\""));
+
+        // Does not fire again while the alert is raised
+        let states = vec![AlertRuleState { alert_id: Some(Uuid::new_v4()), ..states[0].clone() }];
+        let evaluation = evaluate_rules(&rules, &states, machine, "node", None, &metrics, later);
+        assert!(evaluation.firing.is_empty());
+        assert!(evaluation.cleared.is_empty());
+
+        // Clears once the value recovers
+        let metrics = vec![metric("/data", 500.0)];
+        let evaluation = evaluate_rules(&rules, &states, machine, "node", None, &metrics, later);
+        assert_eq!(evaluation.cleared, vec![&states[0]]);
+    }
+
+    #[test]
+    fn test_disabled_rule_clears() {
+        let mut rule = rule(0);
+        let machine = Uuid::new_v4();
+        let now = Utc::now().naive_utc();
+        let metrics = vec![metric("/data", 50.0)];
+        let rules = [CompiledRule::from(rule.clone())];
+        let evaluation = evaluate_rules(&rules, &[], machine, "node", None, &metrics, now);
+        assert_eq!(evaluation.firing.len(), 1);
+
+        rule.enabled = false;
+        let states = evaluation.started;
+        let rules = [CompiledRule::from(rule)];
+        let evaluation = evaluate_rules(&rules, &states, machine, "node", None, &metrics, now);
+        assert!(evaluation.firing.is_empty());
+        assert_eq!(evaluation.cleared.len(), 1);
+    }
+}
@@ -0,0 +1,336 @@
+use chrono::{Datelike, NaiveDateTime, NaiveTime, TimeDelta, Utc, Weekday};
+use ivynet_alerts::AlertType;
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Json, PgPool};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::error::DatabaseError;
+
+/// Longest a recurring window may last, so it ends before it starts again
+pub const MAX_WINDOW_MINUTES: u32 = 7 * 24 * 60;
+
+/// A window that recurs every week, in UTC
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+pub struct RecurringWindow {
+    /// Days the window starts on. Every day if empty.
+    #[serde(default)]
+    #[schema(value_type = Vec<String>, example = json!(["Sat", "Sun"]))]
+    pub weekdays: Vec<Weekday>,
+    /// When the window starts, in minutes after midnight
+    pub start_minute: u32,
+    pub duration_minutes: u32,
+}
+
+impl RecurringWindow {
+    pub fn contains(&self, time: NaiveDateTime) -> bool {
+        let duration = TimeDelta::minutes(self.duration_minutes.into());
+        // The window may have started on one of the days before, if it lasts past midnight
+        (0..=self.duration_minutes.div_ceil(24 * 60)).any(|days_ago| {
+            let date = time.date() - TimeDelta::days(days_ago.into());
+            if !self.weekdays.is_empty() && !self.weekdays.contains(&date.weekday()) {
+                return false;
+            }
+            let start =
+                date.and_time(NaiveTime::MIN) + TimeDelta::minutes(self.start_minute.into());
+            start <= time && time < start + duration
+        })
+    }
+}
+
+/// What an alert is about, to tell whether a silence applies to it
+#[derive(Clone, Copy, Debug)]
+pub struct SilenceTarget<'a> {
+    pub alert_type: AlertType,
+    pub machine_id: Option<Uuid>,
+    pub node_name: Option<&'a str>,
+    pub node_type: Option<&'a str>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+pub struct SilenceDefinition {
+    #[serde(default)]
+    pub comment: String,
+    pub machine_id: Option<Uuid>,
+    pub node_name: Option<String>,
+    pub node_type: Option<String>,
+    pub alert_type: Option<AlertType>,
+    pub starts_at: NaiveDateTime,
+    /// When the silence ends. A silence with a schedule may go on until it is expired.
+    pub ends_at: Option<NaiveDateTime>,
+    pub schedule: Option<RecurringWindow>,
+}
+
+/// Keeps the alerts it matches from being notified while it is in effect. The alerts are still
+/// recorded. Scopes that are not set match any alert.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+pub struct AlertSilence {
+    pub silence_id: Uuid,
+    pub organization_id: i64,
+    pub comment: String,
+    pub machine_id: Option<Uuid>,
+    pub node_name: Option<String>,
+    pub node_type: Option<String>,
+    pub alert_type: Option<AlertType>,
+    pub starts_at: NaiveDateTime,
+    pub ends_at: Option<NaiveDateTime>,
+    pub schedule: Option<RecurringWindow>,
+    pub created_at: NaiveDateTime,
+}
+
+struct DbAlertSilence {
+    silence_id: Uuid,
+    organization_id: i64,
+    comment: String,
+    machine_id: Option<Uuid>,
+    node_name: Option<String>,
+    node_type: Option<String>,
+    alert_type: Option<i32>,
+    starts_at: NaiveDateTime,
+    ends_at: Option<NaiveDateTime>,
+    schedule: Option<Json<RecurringWindow>>,
+    created_at: NaiveDateTime,
+}
+
+impl From<DbAlertSilence> for AlertSilence {
+    fn from(value: DbAlertSilence) -> Self {
+        Self {
+            silence_id: value.silence_id,
+            organization_id: value.organization_id,
+            comment: value.comment,
+            machine_id: value.machine_id,
+            node_name: value.node_name,
+            node_type: value.node_type,
+            alert_type: value.alert_type.map(|id| AlertType::from(id as usize)),
+            starts_at: value.starts_at,
+            ends_at: value.ends_at,
+            schedule: value.schedule.map(|schedule| schedule.0),
+            created_at: value.created_at,
+        }
+    }
+}
+
+impl AlertSilence {
+    /// Whether the silence is in effect at `time`
+    pub fn is_active(&self, time: NaiveDateTime) -> bool {
+        self.starts_at <= time &&
+            self.ends_at.is_none_or(|ends_at| time < ends_at) &&
+            self.schedule.as_ref().is_none_or(|schedule| schedule.contains(time))
+    }
+
+    pub fn matches(&self, target: &SilenceTarget) -> bool {
+        fn scope<T: PartialEq>(silence: Option<T>, target: Option<T>) -> bool {
+            silence.is_none() || silence == target
+        }
+
+        scope(self.alert_type, Some(target.alert_type)) &&
+            scope(self.machine_id, target.machine_id) &&
+            scope(self.node_name.as_deref(), target.node_name) &&
+            scope(self.node_type.as_deref(), target.node_type)
+    }
+
+    pub async fn create(
+        pool: &PgPool,
+        organization_id: i64,
+        definition: &SilenceDefinition,
+    ) -> Result<AlertSilence, DatabaseError> {
+        let silence = sqlx::query_as!(
+            DbAlertSilence,
+            r#"INSERT INTO alert_silence
+                (silence_id, organization_id, comment, machine_id, node_name, node_type,
+                 alert_type, starts_at, ends_at, schedule, created_at)
+               VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, NOW())
+               RETURNING
+                silence_id, organization_id, comment, machine_id, node_name, node_type,
+                alert_type, starts_at, ends_at,
+                schedule AS "schedule: Json<RecurringWindow>",
+                created_at"#,
+            Uuid::new_v4(),
+            organization_id,
+            definition.comment,
+            definition.machine_id,
+            definition.node_name,
+            definition.node_type,
+            definition.alert_type.map(|alert_type| alert_type.id() as i32),
+            definition.starts_at,
+            definition.ends_at,
+            definition.schedule.as_ref().map(Json) as _,
+        )
+        .fetch_one(pool)
+        .await?;
+        Ok(silence.into())
+    }
+
+    pub async fn get(
+        pool: &PgPool,
+        silence_id: Uuid,
+    ) -> Result<Option<AlertSilence>, DatabaseError> {
+        let silence = sqlx::query_as!(
+            DbAlertSilence,
+            r#"SELECT
+                silence_id, organization_id, comment, machine_id, node_name, node_type,
+                alert_type, starts_at, ends_at,
+                schedule AS "schedule: Json<RecurringWindow>",
+                created_at
+               FROM alert_silence
+               WHERE silence_id = $1"#,
+            silence_id
+        )
+        .fetch_optional(pool)
+        .await?;
+        Ok(silence.map(AlertSilence::from))
+    }
+
+    pub async fn get_all_for_org(
+        pool: &PgPool,
+        organization_id: i64,
+    ) -> Result<Vec<AlertSilence>, DatabaseError> {
+        let silences = sqlx::query_as!(
+            DbAlertSilence,
+            r#"SELECT
+                silence_id, organization_id, comment, machine_id, node_name, node_type,
+                alert_type, starts_at, ends_at,
+                schedule AS "schedule: Json<RecurringWindow>",
+                created_at
+               FROM alert_silence
+               WHERE organization_id = $1
+               ORDER BY starts_at DESC"#,
+            organization_id
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(silences.into_iter().map(AlertSilence::from).collect())
+    }
+
+    /// Silences of the organization in effect now
+    pub async fn get_active_for_org(
+        pool: &PgPool,
+        organization_id: i64,
+    ) -> Result<Vec<AlertSilence>, DatabaseError> {
+        let now = Utc::now().naive_utc();
+        let silences = sqlx::query_as!(
+            DbAlertSilence,
+            r#"SELECT
+                silence_id, organization_id, comment, machine_id, node_name, node_type,
+                alert_type, starts_at, ends_at,
+                schedule AS "schedule: Json<RecurringWindow>",
+                created_at
+               FROM alert_silence
+               WHERE organization_id = $1 AND starts_at <= $2 AND
+                (ends_at IS NULL OR ends_at > $2)"#,
+            organization_id,
+            now
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(silences
+            .into_iter()
+            .map(AlertSilence::from)
+            .filter(|silence| silence.is_active(now))
+            .collect())
+    }
+
+    /// Whether an alert about `target` would be silenced now
+    pub async fn is_silenced(
+        pool: &PgPool,
+        organization_id: i64,
+        target: &SilenceTarget<'_>,
+    ) -> Result<bool, DatabaseError> {
+        let silences = Self::get_active_for_org(pool, organization_id).await?;
+        Ok(silences.iter().any(|silence| silence.matches(target)))
+    }
+
+    /// Ends the silence now. Silences that already ended are left as they are.
+    pub async fn expire(pool: &PgPool, silence_id: Uuid) -> Result<(), DatabaseError> {
+        sqlx::query!(
+            r#"UPDATE alert_silence
+               SET starts_at = LEAST(starts_at, NOW()), ends_at = NOW()
+               WHERE silence_id = $1 AND (ends_at IS NULL OR ends_at > NOW())"#,
+            silence_id
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod silence_tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    fn time(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        // 2025-04-05 is a Saturday
+        NaiveDate::from_ymd_opt(2025, 4, day).unwrap().and_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    fn silence() -> AlertSilence {
+        AlertSilence {
+            silence_id: Uuid::new_v4(),
+            organization_id: 1,
+            comment: String::new(),
+            machine_id: None,
+            node_name: None,
+            node_type: None,
+            alert_type: None,
+            starts_at: time(1, 0, 0),
+            ends_at: Some(time(10, 0, 0)),
+            schedule: None,
+            created_at: time(1, 0, 0),
+        }
+    }
+
+    #[test]
+    fn test_recurring_window_past_midnight() {
+        let window = RecurringWindow {
+            weekdays: vec![Weekday::Sat],
+            start_minute: 22 * 60,
+            duration_minutes: 4 * 60,
+        };
+        assert!(!window.contains(time(5, 21, 59)));
+        assert!(window.contains(time(5, 22, 0)));
+        assert!(window.contains(time(6, 1, 59)));
+        assert!(!window.contains(time(6, 2, 0)));
+        assert!(!window.contains(time(6, 22, 30)));
+        assert!(window.contains(time(12, 23, 0)));
+    }
+
+    #[test]
+    fn test_silence_is_active() {
+        let mut silence = silence();
+        assert!(!silence.is_active(time(1, 0, 0) - TimeDelta::seconds(1)));
+        assert!(silence.is_active(time(5, 12, 0)));
+        assert!(!silence.is_active(time(10, 0, 0)));
+
+        silence.schedule =
+            Some(RecurringWindow { weekdays: vec![], start_minute: 120, duration_minutes: 60 });
+        assert!(silence.is_active(time(5, 2, 30)));
+        assert!(!silence.is_active(time(5, 12, 0)));
+    }
+
+    #[test]
+    fn test_silence_scope() {
+        let machine_id = Uuid::new_v4();
+        let target = SilenceTarget {
+            alert_type: AlertType::NodeNotRunning,
+            machine_id: Some(machine_id),
+            node_name: Some("eigenda"),
+            node_type: Some("eigenda"),
+        };
+        assert!(silence().matches(&target));
+
+        let silence = AlertSilence {
+            machine_id: Some(machine_id),
+            alert_type: Some(AlertType::NoMetrics),
+            ..silence()
+        };
+        assert!(!silence.matches(&target));
+        assert!(silence.matches(&SilenceTarget { alert_type: AlertType::NoMetrics, ..target }));
+        assert!(!silence.matches(&SilenceTarget {
+            alert_type: AlertType::NoMetrics,
+            machine_id: None,
+            ..target
+        }));
+    }
+}
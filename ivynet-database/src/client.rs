@@ -81,6 +81,120 @@ impl Client {
         .await?;
         Ok(())
     }
+    /// Moves everything owned by `old_client_id` to `new_client_id` in one transaction: the
+    /// machine, its alerts, client logs and heartbeat alerts. The old client is deleted along
+    /// with its telemetry sessions, so its key no longer validates, and the rotation is recorded
+    /// in `client_key_rotation`.
+    ///
+    /// A key is rotated by the machine holding it, so a key shared by several machines is not
+    /// rotated and [`DatabaseError::SharedClient`] is returned.
+    pub async fn rotate(
+        pool: &PgPool,
+        old_client_id: &Address,
+        new_client_id: &Address,
+    ) -> Result<Client, DatabaseError> {
+        let now: NaiveDateTime = Utc::now().naive_utc();
+        let old_id = old_client_id.as_bytes();
+        let new_id = new_client_id.as_bytes();
+        let mut tx = pool.begin().await?;
+
+        let organization_id = sqlx::query_scalar!(
+            "SELECT organization_id FROM client WHERE client_id = $1 FOR UPDATE",
+            old_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| DatabaseError::InvalidInput(format!("Unknown client {old_client_id:?}")))?;
+
+        // The row lock above keeps machines from being attached to the old client meanwhile
+        let machines =
+            sqlx::query_scalar!("SELECT COUNT(*) FROM machine WHERE client_id = $1", old_id)
+                .fetch_one(&mut *tx)
+                .await?
+                .unwrap_or_default();
+        if machines > 1 {
+            return Err(DatabaseError::SharedClient(machines));
+        }
+
+        query!(
+            "INSERT INTO client (client_id, organization_id, created_at, updated_at) values ($1, $2, $3, $4)",
+            new_id,
+            organization_id,
+            now,
+            now
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        query!("UPDATE machine SET client_id = $1 WHERE client_id = $2", new_id, old_id)
+            .execute(&mut *tx)
+            .await?;
+        query!("UPDATE node_alerts_active SET client_id = $1 WHERE client_id = $2", new_id, old_id)
+            .execute(&mut *tx)
+            .await?;
+        query!(
+            "UPDATE node_alerts_historical SET client_id = $1 WHERE client_id = $2",
+            new_id,
+            old_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        query!(
+            "UPDATE machine_alerts_active SET client_id = $1 WHERE client_id = $2",
+            new_id,
+            old_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        query!(
+            "UPDATE machine_alerts_historical SET client_id = $1 WHERE client_id = $2",
+            new_id,
+            old_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        query!(
+            "UPDATE client_heartbeat_alerts SET client_id = $1 WHERE client_id = $2",
+            new_id,
+            old_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        query!(
+            "UPDATE client_heartbeat_alerts_historical SET client_id = $1 WHERE client_id = $2",
+            new_id,
+            old_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        // Moves the rows into the partition created for the new client
+        query!("UPDATE client_log SET client_id = $1 WHERE client_id = $2", new_id, old_id)
+            .execute(&mut *tx)
+            .await?;
+
+        query!(
+            "INSERT INTO client_key_rotation (old_client_id, new_client_id, organization_id, rotated_at) values ($1, $2, $3, $4)",
+            old_id,
+            new_id,
+            organization_id,
+            now
+        )
+        .execute(&mut *tx)
+        .await?;
+        query!("DELETE FROM telemetry_session WHERE client_id = $1", old_id)
+            .execute(&mut *tx)
+            .await?;
+        query!("DELETE FROM client WHERE client_id = $1", old_id).execute(&mut *tx).await?;
+
+        tx.commit().await?;
+        Ok(Client {
+            client_id: *new_client_id,
+            organization_id,
+            created_at: Some(now),
+            updated_at: Some(now),
+        })
+    }
+
     pub async fn delete(pool: &PgPool, client_id: &Address) -> Result<(), DatabaseError> {
         query!("DELETE FROM client WHERE client_id = $1", client_id.as_bytes())
             .execute(pool)
@@ -93,3 +207,37 @@ impl Client {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod client_tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::Machine;
+
+    fn client_id() -> Address {
+        Address::from_slice(&[1; 20])
+    }
+
+    #[sqlx::test(migrations = "../migrations", fixtures("../fixtures/new_user_registration.sql"))]
+    #[ignore]
+    async fn test_rotate(pool: PgPool) {
+        let new_client_id = Address::from_low_u64_be(3);
+        let client = Client::rotate(&pool, &client_id(), &new_client_id).await.unwrap();
+        assert_eq!(client.client_id, new_client_id);
+        assert!(Client::get(&pool, &client_id()).await.unwrap().is_none());
+
+        let machine_id = Uuid::parse_str("dcbf22c7-9d96-47ac-bf06-62d6544e440d").unwrap();
+        assert!(Machine::is_owned_by(&pool, &new_client_id, machine_id).await.unwrap());
+    }
+
+    #[sqlx::test(migrations = "../migrations", fixtures("../fixtures/new_user_registration.sql"))]
+    #[ignore]
+    async fn test_rotate_shared_client(pool: PgPool) {
+        Machine::create(&pool, &client_id(), "second_machine", Uuid::new_v4(), None).await.unwrap();
+
+        let rotated = Client::rotate(&pool, &client_id(), &Address::from_low_u64_be(3)).await;
+        assert!(matches!(rotated, Err(DatabaseError::SharedClient(2))));
+        assert!(Client::get(&pool, &client_id()).await.unwrap().is_some());
+    }
+}
@@ -285,36 +285,43 @@ pub fn convert_system_metrics(sys_info: &MachineData) -> Vec<Metrics> {
             name: UPTIME_METRIC.to_owned(),
             value: sys_info.uptime.parse::<f64>().unwrap(),
             attributes: Default::default(),
+            metric_type: Default::default(),
         },
         Metrics {
             name: CPU_USAGE_METRIC.to_owned(),
             value: sys_info.cpu_usage.parse::<f64>().unwrap(),
             attributes: Default::default(),
+            metric_type: Default::default(),
         },
         Metrics {
             name: CORES_METRIC.to_owned(),
             value: sys_info.cpu_cores.parse::<f64>().unwrap(),
             attributes: Default::default(),
+            metric_type: Default::default(),
         },
         Metrics {
             name: MEMORY_USAGE_METRIC.to_owned(),
             value: sys_info.memory_used.parse::<f64>().unwrap(),
             attributes: Default::default(),
+            metric_type: Default::default(),
         },
         Metrics {
             name: MEMORY_FREE_METRIC.to_owned(),
             value: sys_info.memory_free.parse::<f64>().unwrap(),
             attributes: Default::default(),
+            metric_type: Default::default(),
         },
         Metrics {
             name: MEMORY_TOTAL_METRIC.to_owned(),
             value: sys_info.memory_total.parse::<f64>().unwrap(),
             attributes: Default::default(),
+            metric_type: Default::default(),
         },
         Metrics {
             name: DISK_TOTAL_METRIC.to_owned(),
             value: sys_info.disk_used_total.parse::<f64>().unwrap(),
             attributes: Default::default(),
+            metric_type: Default::default(),
         },
     ];
     for (i, disk) in sys_info.disks.iter().enumerate() {
@@ -328,6 +335,7 @@ pub fn convert_system_metrics(sys_info: &MachineData) -> Vec<Metrics> {
             name: format!("{}_{}", DISK_INFO_METRIC, i),
             value: 0.0,
             attributes: disk_attributes,
+            metric_type: Default::default(),
         });
     }
 
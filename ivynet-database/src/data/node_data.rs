@@ -34,6 +34,10 @@ pub const EIGEN_PERFORMANCE_HEALTHY_THRESHOLD: f64 = 80.0;
 const CONDENSED_EIGENDA_METRICS_NAMES: [&str; 2] =
     ["eigen_performance_score", "node_reachability_status"];
 
+/// Resource usage metrics reported by the client for the container running a node, such as
+/// `ivynet_container_cpu_percent`. These are kept in every condensed view.
+pub const CONTAINER_METRICS_PREFIX: &str = "ivynet_container_";
+
 #[derive(Serialize, ToSchema, Clone, Debug, Default)]
 pub struct NodeStatusReport {
     pub total_nodes: usize,
@@ -175,9 +179,16 @@ pub fn condense_metrics(node_type: NodeType, metrics: &[Metric]) -> Vec<Metric>
     }
 }
 
-/// Filter the metrics by the given names.
+/// Filter the metrics by the given names. Container resource usage metrics are always kept.
 fn filter_metrics_by_names(metrics: &[Metric], allowed_names: &[&str]) -> Vec<Metric> {
-    metrics.iter().filter(|metric| allowed_names.contains(&metric.name.as_str())).cloned().collect()
+    metrics
+        .iter()
+        .filter(|metric| {
+            allowed_names.contains(&metric.name.as_str()) ||
+                metric.name.starts_with(CONTAINER_METRICS_PREFIX)
+        })
+        .cloned()
+        .collect()
 }
 
 pub async fn update_avs_version(
@@ -380,4 +391,35 @@ mod node_data_tests {
         );
         assert_eq!(status, UpdateStatus::Unknown);
     }
+
+    #[test]
+    fn test_condense_metrics_keeps_container_usage() {
+        let metric = |name: &str| Metric {
+            machine_id: Uuid::nil(),
+            avs_name: Some("eigenda".to_string()),
+            name: name.to_string(),
+            value: 1.0,
+            attributes: None,
+            created_at: None,
+        };
+        let metrics = vec![
+            metric("eigen_performance_score"),
+            metric("node_uptime_seconds"),
+            metric("ivynet_container_cpu_percent"),
+            metric("ivynet_container_memory_usage_bytes"),
+        ];
+
+        let names: Vec<String> = condense_metrics(NodeType::EigenDA, &metrics)
+            .into_iter()
+            .map(|metric| metric.name)
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "eigen_performance_score",
+                "ivynet_container_cpu_percent",
+                "ivynet_container_memory_usage_bytes"
+            ]
+        );
+    }
 }
@@ -53,6 +53,22 @@ pub enum DatabaseError {
 
     #[error("NodeId parse error: failed to parse nodeid {0}")]
     NodeIdParseError(String),
+
+    #[error("Client key is shared by {0} machines")]
+    SharedClient(i64),
+}
+
+impl DatabaseError {
+    /// Whether the write broke a constraint, e.g. referenced a node that does not exist. Such
+    /// writes fail the same way however often they are retried.
+    pub fn is_constraint_violation(&self) -> bool {
+        match self {
+            DatabaseError::SqlxError(sqlx::Error::Database(e)) => {
+                !matches!(e.kind(), sqlx::error::ErrorKind::Other)
+            }
+            _ => false,
+        }
+    }
 }
 
 impl From<DatabaseError> for Status {
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+
+use crate::error::DatabaseError;
+
+/// How long the clients, machines and nodes of an organization may go without a heartbeat before
+/// they are reported. TTLs that are not set fall back to the default of the heartbeat monitor.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+pub struct HeartbeatSettings {
+    pub client_ttl_secs: Option<i64>,
+    pub machine_ttl_secs: Option<i64>,
+    pub node_ttl_secs: Option<i64>,
+    /// TTLs of the nodes of a node type, taking precedence over `node_ttl_secs`
+    #[serde(default)]
+    pub node_type_ttl_secs: HashMap<String, i64>,
+}
+
+impl HeartbeatSettings {
+    /// TTL of a node of `node_type`, if the organization set one.
+    pub fn node_ttl_secs_for(&self, node_type: &str) -> Option<i64> {
+        self.node_type_ttl_secs.get(node_type).copied().or(self.node_ttl_secs)
+    }
+
+    /// Every TTL set, to learn the shortest one.
+    pub fn ttls(&self) -> impl Iterator<Item = i64> + '_ {
+        [self.client_ttl_secs, self.machine_ttl_secs, self.node_ttl_secs]
+            .into_iter()
+            .flatten()
+            .chain(self.node_type_ttl_secs.values().copied())
+    }
+
+    pub async fn get(pool: &PgPool, organization_id: i64) -> Result<Self, DatabaseError> {
+        let row = sqlx::query!(
+            "SELECT client_ttl_secs, machine_ttl_secs, node_ttl_secs FROM heartbeat_settings WHERE organization_id = $1",
+            organization_id
+        )
+        .fetch_optional(pool)
+        .await?;
+        let node_type_ttl_secs = sqlx::query!(
+            "SELECT node_type, ttl_secs FROM node_type_heartbeat_settings WHERE organization_id = $1",
+            organization_id
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| (row.node_type, row.ttl_secs))
+        .collect();
+
+        Ok(Self {
+            client_ttl_secs: row.as_ref().and_then(|row| row.client_ttl_secs),
+            machine_ttl_secs: row.as_ref().and_then(|row| row.machine_ttl_secs),
+            node_ttl_secs: row.as_ref().and_then(|row| row.node_ttl_secs),
+            node_type_ttl_secs,
+        })
+    }
+
+    /// Settings of every organization that changed any of them.
+    pub async fn get_all(pool: &PgPool) -> Result<HashMap<i64, Self>, DatabaseError> {
+        let mut settings = HashMap::new();
+
+        let rows = sqlx::query!(
+            "SELECT organization_id, client_ttl_secs, machine_ttl_secs, node_ttl_secs FROM heartbeat_settings"
+        )
+        .fetch_all(pool)
+        .await?;
+        for row in rows {
+            settings.insert(
+                row.organization_id,
+                Self {
+                    client_ttl_secs: row.client_ttl_secs,
+                    machine_ttl_secs: row.machine_ttl_secs,
+                    node_ttl_secs: row.node_ttl_secs,
+                    node_type_ttl_secs: HashMap::new(),
+                },
+            );
+        }
+
+        let rows = sqlx::query!(
+            "SELECT organization_id, node_type, ttl_secs FROM node_type_heartbeat_settings"
+        )
+        .fetch_all(pool)
+        .await?;
+        for row in rows {
+            settings
+                .entry(row.organization_id)
+                .or_insert_with(Self::default)
+                .node_type_ttl_secs
+                .insert(row.node_type, row.ttl_secs);
+        }
+
+        Ok(settings)
+    }
+
+    /// Replaces the settings of the organization, including every node type TTL.
+    pub async fn set(&self, pool: &PgPool, organization_id: i64) -> Result<(), DatabaseError> {
+        if let Some(ttl) = self.ttls().find(|ttl| *ttl <= 0) {
+            return Err(DatabaseError::InvalidInput(format!("Invalid heartbeat TTL {ttl}")));
+        }
+
+        let mut tx = pool.begin().await?;
+
+        sqlx::query!(
+            r#"INSERT INTO
+                heartbeat_settings
+                (organization_id, client_ttl_secs, machine_ttl_secs, node_ttl_secs, updated_at)
+            VALUES
+                ($1, $2, $3, $4, NOW())
+            ON CONFLICT (organization_id)
+            DO UPDATE SET
+                client_ttl_secs = EXCLUDED.client_ttl_secs,
+                machine_ttl_secs = EXCLUDED.machine_ttl_secs,
+                node_ttl_secs = EXCLUDED.node_ttl_secs, updated_at = EXCLUDED.updated_at"#,
+            organization_id,
+            self.client_ttl_secs,
+            self.machine_ttl_secs,
+            self.node_ttl_secs
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "DELETE FROM node_type_heartbeat_settings WHERE organization_id = $1",
+            organization_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let (node_types, ttls): (Vec<String>, Vec<i64>) = self
+            .node_type_ttl_secs
+            .iter()
+            .map(|(node_type, ttl)| (node_type.clone(), *ttl))
+            .unzip();
+        sqlx::query!(
+            r#"INSERT INTO
+                node_type_heartbeat_settings (organization_id, node_type, ttl_secs)
+            SELECT $1, * FROM UNNEST($2::text[], $3::bigint[])"#,
+            organization_id,
+            &node_types,
+            &ttls
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod heartbeat_settings_tests {
+    use super::*;
+
+    #[test]
+    fn test_node_type_ttl_takes_precedence() {
+        let settings = HeartbeatSettings {
+            node_ttl_secs: Some(900),
+            node_type_ttl_secs: HashMap::from([("eigenda".to_string(), 180)]),
+            ..Default::default()
+        };
+        assert_eq!(settings.node_ttl_secs_for("eigenda"), Some(180));
+        assert_eq!(settings.node_ttl_secs_for("witness"), Some(900));
+        assert_eq!(HeartbeatSettings::default().node_ttl_secs_for("eigenda"), None);
+        assert_eq!(settings.ttls().min(), Some(180));
+    }
+}
@@ -11,13 +11,18 @@ pub mod client_log;
 pub mod data;
 pub mod eigen_avs_metadata;
 pub mod error;
+pub mod heartbeat_settings;
 pub mod log;
 pub mod machine;
+pub mod machine_command;
 pub mod metric;
 pub mod notification_settings;
 pub mod operator_keys;
 pub mod organization;
+pub mod performance_settings;
 pub mod service_settings;
+pub mod signature_sequence;
+pub mod telemetry_session;
 pub mod utils;
 pub mod verification;
 
@@ -27,10 +32,15 @@ pub use avs_active_set::AvsActiveSet;
 pub use avs_version::{AvsVersionData, DbAvsVersionData};
 pub use avs_version_hash::AvsVersionHash;
 pub use client::Client;
+pub use heartbeat_settings::HeartbeatSettings;
 pub use machine::Machine;
+pub use machine_command::MachineCommand;
 pub use notification_settings::NotificationSettings;
 pub use organization::Organization;
+pub use performance_settings::PerformanceSettings;
 pub use service_settings::ServiceSettings;
+pub use signature_sequence::SignatureSequence;
+pub use telemetry_session::TelemetrySession;
 
 pub async fn configure(uri: &str, _migrate: bool) -> Result<PgPool, error::DatabaseError> {
     let pool = PoolOptions::new().max_connections(5).connect(uri).await?;
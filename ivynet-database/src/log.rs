@@ -1,4 +1,4 @@
-use crate::error::DatabaseError;
+use crate::{error::DatabaseError, utils::escape_copy_text};
 use chrono::{DateTime, NaiveDateTime, Utc};
 use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::{json, Value};
@@ -147,6 +147,68 @@ impl ContainerLog {
         }
     }
 
+    /// Records a batch of logs with a single `COPY`. All logs are expected to belong to the same
+    /// machine, which is the partition key of the `log` table.
+    pub async fn record_batch(pool: &PgPool, logs: &[ContainerLog]) -> Result<(), DatabaseError> {
+        let Some(first) = logs.first() else { return Ok(()) };
+
+        match Self::copy_logs(pool, logs).await {
+            Ok(()) => Ok(()),
+            Err(DatabaseError::SqlxError(e))
+                if e.as_database_error()
+                    .map(|dbe| dbe.message().contains("no partition"))
+                    .unwrap_or(false) =>
+            {
+                Self::ensure_partition_exists(pool, first.machine_id).await?;
+                Self::copy_logs(pool, logs).await
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn copy_logs(pool: &PgPool, logs: &[ContainerLog]) -> Result<(), DatabaseError> {
+        let now = Utc::now().timestamp();
+        let mut tx = pool.begin().await?;
+
+        let mut copy = tx
+            .copy_in_raw(
+                "COPY log (machine_id, avs_name, log, log_level, created_at, other_fields) FROM STDIN",
+            )
+            .await?;
+
+        for log in logs {
+            let created_at = DateTime::from_timestamp(log.created_at.unwrap_or(now), 0)
+                .expect("Could not construct datetime")
+                .naive_utc();
+            let other_fields = match &log.other_fields {
+                Some(fields) => escape_copy_text(&json!(fields).to_string()),
+                None => String::from("\\N"),
+            };
+            let level = match log.log_level {
+                LogLevel::Debug => "debug",
+                LogLevel::Info => "info",
+                LogLevel::Warning => "warning",
+                LogLevel::Error => "error",
+                LogLevel::Unknown => "unknown",
+            };
+
+            let row = format!(
+                "{}\t{}\t{}\t{}\t{}\t{}\n",
+                log.machine_id,
+                escape_copy_text(&log.avs_name),
+                escape_copy_text(&log.log),
+                level,
+                created_at,
+                other_fields
+            );
+            copy.send(row.as_bytes()).await?;
+        }
+
+        copy.finish().await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
     async fn ensure_partition_exists(pool: &PgPool, machine_id: Uuid) -> Result<(), DatabaseError> {
         sqlx::query!("SELECT create_log_partition($1)", machine_id).execute(pool).await?;
         Ok(())
@@ -473,6 +535,41 @@ mod logs_db_tests {
 
         Ok(())
     }
+
+    #[ignore]
+    #[sqlx::test(migrations = "../migrations", fixtures("../fixtures/new_user_registration.sql"))]
+    async fn test_record_batch(pool: PgPool) {
+        let machine_id = Uuid::from_str("dcbf22c7-9d96-47ac-bf06-62d6544e440d").unwrap();
+        Avs::record_avs_data_from_client(&pool, machine_id, "test_avs", &NodeType::Unknown, "hash")
+            .await
+            .unwrap();
+
+        let now = Utc::now().timestamp();
+        let logs = [
+            ("first line", LogLevel::Info),
+            ("line with\ttab and \\ backslash", LogLevel::Debug),
+            ("ERR something went wrong\nacross lines", LogLevel::Error),
+        ]
+        .into_iter()
+        .map(|(log, log_level)| ContainerLog {
+            machine_id,
+            avs_name: "test_avs".to_string(),
+            log: log.to_string(),
+            log_level,
+            created_at: Some(now),
+            other_fields: None,
+        })
+        .collect::<Vec<_>>();
+
+        // No partition exists for the machine yet, so this also exercises partition creation.
+        ContainerLog::record_batch(&pool, &logs).await.unwrap();
+
+        let mut stored = ContainerLog::get_all_for_machine(&pool, machine_id).await.unwrap();
+        stored.sort_by(|a, b| a.log.cmp(&b.log));
+        let mut expected = logs.to_vec();
+        expected.sort_by(|a, b| a.log.cmp(&b.log));
+        assert_eq!(stored, expected);
+    }
 }
 
 // #[cfg(feature = "db_tests")]
@@ -0,0 +1,192 @@
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use ivynet_grpc::messages::{self, command::Kind};
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Json, PgPool};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::error::DatabaseError;
+
+/// What a machine is asked to do. Machines only carry out the kinds they allow.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CommandKind {
+    /// Change how often telemetry is collected
+    SetScrapeInterval { seconds: u64 },
+    /// Send the logs of a node again, starting at `since` (unix seconds)
+    LogBackfill { node_name: String, since: i64 },
+    /// Look for nodes that are not monitored yet
+    Rescan,
+    /// Change the configuration of a monitored node
+    UpdateNode { assigned_name: String, metric_port: Option<u16>, metrics_path: Option<String> },
+}
+
+impl From<&CommandKind> for messages::Command {
+    fn from(kind: &CommandKind) -> Self {
+        let kind = match kind.clone() {
+            CommandKind::SetScrapeInterval { seconds } => {
+                Kind::SetScrapeInterval(messages::SetScrapeInterval { seconds })
+            }
+            CommandKind::LogBackfill { node_name, since } => {
+                Kind::LogBackfill(messages::LogBackfill { node_name, since: since.max(0) as u64 })
+            }
+            CommandKind::Rescan => Kind::Rescan(messages::Rescan {}),
+            CommandKind::UpdateNode { assigned_name, metric_port, metrics_path } => {
+                Kind::UpdateNode(messages::UpdateNode {
+                    assigned_name,
+                    metric_port: metric_port.map(u32::from),
+                    metrics_path,
+                })
+            }
+        };
+        messages::Command { kind: Some(kind) }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct MachineCommand {
+    pub id: Uuid,
+    pub machine_id: Uuid,
+    pub command: CommandKind,
+    pub created_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+    pub delivered_at: Option<NaiveDateTime>,
+    pub completed_at: Option<NaiveDateTime>,
+    pub success: Option<bool>,
+    pub result: Option<String>,
+}
+
+struct DbMachineCommand {
+    id: Uuid,
+    machine_id: Uuid,
+    command: Json<CommandKind>,
+    created_at: NaiveDateTime,
+    expires_at: NaiveDateTime,
+    delivered_at: Option<NaiveDateTime>,
+    completed_at: Option<NaiveDateTime>,
+    success: Option<bool>,
+    result: Option<String>,
+}
+
+impl From<DbMachineCommand> for MachineCommand {
+    fn from(value: DbMachineCommand) -> Self {
+        Self {
+            id: value.id,
+            machine_id: value.machine_id,
+            command: value.command.0,
+            created_at: value.created_at,
+            expires_at: value.expires_at,
+            delivered_at: value.delivered_at,
+            completed_at: value.completed_at,
+            success: value.success,
+            result: value.result,
+        }
+    }
+}
+
+impl MachineCommand {
+    /// Queues a command for the machine, to be delivered the next time it is connected and before
+    /// `ttl` runs out.
+    pub async fn enqueue(
+        pool: &PgPool,
+        machine_id: Uuid,
+        command: &CommandKind,
+        ttl: TimeDelta,
+    ) -> Result<MachineCommand, DatabaseError> {
+        let now = Utc::now().naive_utc();
+        let command = MachineCommand {
+            id: Uuid::new_v4(),
+            machine_id,
+            command: command.clone(),
+            created_at: now,
+            expires_at: now + ttl,
+            delivered_at: None,
+            completed_at: None,
+            success: None,
+            result: None,
+        };
+        sqlx::query!(
+            "INSERT INTO machine_command (id, machine_id, command, created_at, expires_at)
+             VALUES ($1, $2, $3, $4, $5)",
+            command.id,
+            command.machine_id,
+            Json(&command.command) as _,
+            command.created_at,
+            command.expires_at,
+        )
+        .execute(pool)
+        .await?;
+        Ok(command)
+    }
+
+    /// Commands for the machine that were not delivered yet and have not expired.
+    pub async fn pending(
+        pool: &PgPool,
+        machine_id: Uuid,
+    ) -> Result<Vec<MachineCommand>, DatabaseError> {
+        let commands = sqlx::query_as!(
+            DbMachineCommand,
+            r#"SELECT id, machine_id, command as "command: Json<CommandKind>", created_at,
+                      expires_at, delivered_at, completed_at, success, result
+               FROM machine_command
+               WHERE machine_id = $1 AND delivered_at IS NULL AND expires_at > NOW()
+               ORDER BY created_at"#,
+            machine_id
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(commands.into_iter().map(MachineCommand::from).collect())
+    }
+
+    /// Most recent commands of the machine, newest first.
+    pub async fn get_all_for_machine(
+        pool: &PgPool,
+        machine_id: Uuid,
+        limit: i64,
+    ) -> Result<Vec<MachineCommand>, DatabaseError> {
+        let commands = sqlx::query_as!(
+            DbMachineCommand,
+            r#"SELECT id, machine_id, command as "command: Json<CommandKind>", created_at,
+                      expires_at, delivered_at, completed_at, success, result
+               FROM machine_command
+               WHERE machine_id = $1
+               ORDER BY created_at DESC
+               LIMIT $2"#,
+            machine_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(commands.into_iter().map(MachineCommand::from).collect())
+    }
+
+    pub async fn mark_delivered(pool: &PgPool, id: Uuid) -> Result<(), DatabaseError> {
+        sqlx::query!("UPDATE machine_command SET delivered_at = NOW() WHERE id = $1", id)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Stores the outcome the machine reported. Only the machine the command was for can complete
+    /// it, and only once.
+    pub async fn record_result(
+        pool: &PgPool,
+        machine_id: Uuid,
+        id: Uuid,
+        success: bool,
+        result: &str,
+    ) -> Result<(), DatabaseError> {
+        sqlx::query!(
+            "UPDATE machine_command
+             SET completed_at = NOW(), success = $3, result = $4
+             WHERE id = $1 AND machine_id = $2 AND completed_at IS NULL",
+            id,
+            machine_id,
+            success,
+            result
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+}
@@ -1,8 +1,8 @@
 use std::collections::HashMap;
 
-use crate::error::DatabaseError;
+use crate::{error::DatabaseError, utils::escape_copy_value};
 
-use chrono::{NaiveDateTime, Utc};
+use chrono::NaiveDateTime;
 use ivynet_grpc::messages::Metrics;
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
@@ -147,13 +147,30 @@ impl Metric {
         Ok(organized)
     }
 
+    /// Replaces the metrics of the machine, or of one of its nodes, with `metrics` collected at
+    /// `collected_at`.
+    /// When the metrics of a node that are currently kept were collected, if it has any.
+    pub async fn latest_collection(
+        pool: &PgPool,
+        machine_id: Uuid,
+        avs_name: &str,
+    ) -> Result<Option<NaiveDateTime>, DatabaseError> {
+        Ok(sqlx::query_scalar!(
+            "SELECT MAX(created_at) FROM metric WHERE machine_id = $1 AND avs_name = $2",
+            machine_id,
+            avs_name
+        )
+        .fetch_one(pool)
+        .await?)
+    }
+
     pub async fn record(
         pool: &PgPool,
         machine_id: Uuid,
         avs_name: Option<&str>,
         metrics: &[Metric],
+        collected_at: NaiveDateTime,
     ) -> Result<(), DatabaseError> {
-        let now: NaiveDateTime = Utc::now().naive_utc();
         let mut tx = pool.begin().await?;
 
         // Delete existing metrics (keeping this part the same)
@@ -189,22 +206,22 @@ impl Metric {
                 Some(attrs) => {
                     let attrs_string = serde_json::to_string(attrs)
                         .map_err(|e| DatabaseError::SerializationError(e.to_string()))?;
-                    Self::escape_copy_value(&attrs_string)
+                    escape_copy_value(&attrs_string)
                 }
                 _ => String::from("\\N"),
             };
 
             let avs_name_str =
-                avs_name.map(Self::escape_copy_value).unwrap_or_else(|| String::from("\\N"));
+                avs_name.map(escape_copy_value).unwrap_or_else(|| String::from("\\N"));
 
             let row = format!(
                 "{}\t{}\t{}\t{}\t{}\t{}\n",
                 machine_id,
                 avs_name_str,
-                Self::escape_copy_value(&metric.name),
+                escape_copy_value(&metric.name),
                 metric.value,
                 attributes_str,
-                now
+                collected_at
             );
             copy.send(row.as_bytes()).await?;
         }
@@ -214,14 +231,6 @@ impl Metric {
         Ok(())
     }
 
-    fn escape_copy_value(value: &str) -> String {
-        if value.is_empty() {
-            return "\\N".to_string();
-        }
-
-        value.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n").replace('\r', "\\r")
-    }
-
     pub async fn update_name_on_metrics(
         pool: &PgPool,
         machine_id: Uuid,
@@ -0,0 +1,54 @@
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+
+use crate::{
+    alerts::node::alert_handler::EIGEN_PERFORMANCE_HEALTHY_THRESHOLD, error::DatabaseError,
+};
+
+/// Performance score below which the EigenDA nodes of an organization raise a LowPerformanceScore
+/// alert. A threshold that is not set falls back to [`EIGEN_PERFORMANCE_HEALTHY_THRESHOLD`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize, ToSchema)]
+pub struct PerformanceSettings {
+    pub healthy_threshold: Option<f64>,
+}
+
+impl PerformanceSettings {
+    pub fn healthy_threshold(&self) -> f64 {
+        self.healthy_threshold.unwrap_or(EIGEN_PERFORMANCE_HEALTHY_THRESHOLD)
+    }
+
+    pub async fn get(pool: &PgPool, organization_id: i64) -> Result<Self, DatabaseError> {
+        let healthy_threshold = sqlx::query_scalar!(
+            "SELECT healthy_threshold FROM performance_settings WHERE organization_id = $1",
+            organization_id
+        )
+        .fetch_optional(pool)
+        .await?
+        .flatten();
+        Ok(Self { healthy_threshold })
+    }
+
+    pub async fn set(&self, pool: &PgPool, organization_id: i64) -> Result<(), DatabaseError> {
+        if let Some(threshold) = self.healthy_threshold.filter(|t| !(0.0..=100.0).contains(t)) {
+            return Err(DatabaseError::InvalidInput(format!(
+                "Invalid performance threshold {threshold}"
+            )));
+        }
+
+        sqlx::query!(
+            r#"INSERT INTO
+                performance_settings (organization_id, healthy_threshold, updated_at)
+            VALUES
+                ($1, $2, NOW())
+            ON CONFLICT (organization_id)
+            DO UPDATE SET
+                healthy_threshold = EXCLUDED.healthy_threshold, updated_at = EXCLUDED.updated_at"#,
+            organization_id,
+            self.healthy_threshold
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,64 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::error::DatabaseError;
+
+/// Sequence numbers machines used for their typed signatures, kept in the database so every
+/// ingress replica rejects replays of messages seen by another one or before a restart.
+pub struct SignatureSequence;
+
+impl SignatureSequence {
+    /// Records a sequence number of `machine_id`. Returns false if it was used before, or is
+    /// `window` or more behind the highest one of the machine, which is as far back as used
+    /// numbers are kept. Numbers that fell behind the window are pruned on the way.
+    pub async fn accept(
+        pool: &PgPool,
+        machine_id: Uuid,
+        sequence: i64,
+        window: i64,
+    ) -> Result<bool, DatabaseError> {
+        let inserted = sqlx::query!(
+            r#"WITH highest AS (
+                   SELECT COALESCE(MAX(sequence), 0) AS sequence
+                   FROM signature_sequence
+                   WHERE machine_id = $1
+               ), pruned AS (
+                   DELETE FROM signature_sequence
+                   WHERE machine_id = $1 AND sequence <= (SELECT sequence FROM highest) - $3
+               )
+               INSERT INTO signature_sequence (machine_id, sequence)
+               SELECT $1, $2
+               WHERE $2 > (SELECT sequence FROM highest) - $3
+               ON CONFLICT DO NOTHING"#,
+            machine_id,
+            sequence,
+            window
+        )
+        .execute(pool)
+        .await?;
+        Ok(inserted.rows_affected() == 1)
+    }
+}
+
+#[cfg(test)]
+mod signature_sequence_tests {
+    use super::*;
+
+    const WINDOW: i64 = 1_000;
+
+    #[sqlx::test(migrations = "../migrations", fixtures("../fixtures/new_user_registration.sql"))]
+    #[ignore]
+    async fn test_accept_rejects_replays(pool: PgPool) {
+        let machine_id = Uuid::parse_str("dcbf22c7-9d96-47ac-bf06-62d6544e440d").unwrap();
+        let accept = |sequence| SignatureSequence::accept(&pool, machine_id, sequence, WINDOW);
+
+        assert!(accept(10_000).await.unwrap());
+        assert!(!accept(10_000).await.unwrap());
+        // Out of order but within the window
+        assert!(accept(9_500).await.unwrap());
+        assert!(!accept(9_500).await.unwrap());
+        assert!(accept(10_500).await.unwrap());
+        // Too far behind the highest sequence to tell whether it was seen
+        assert!(!accept(9_400).await.unwrap());
+    }
+}
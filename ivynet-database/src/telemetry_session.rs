@@ -0,0 +1,116 @@
+use chrono::{NaiveDateTime, Utc};
+use ivynet_error::ethers::types::Address;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::error::DatabaseError;
+
+/// Telemetry session a machine opened by proving it holds its key. Sessions are looked up by the
+/// hash of their token.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TelemetrySession {
+    pub machine_id: Uuid,
+    pub client_id: Address,
+    pub expires_at: NaiveDateTime,
+}
+
+struct DbTelemetrySession {
+    machine_id: Uuid,
+    client_id: Vec<u8>,
+    expires_at: NaiveDateTime,
+}
+
+impl From<DbTelemetrySession> for TelemetrySession {
+    fn from(value: DbTelemetrySession) -> Self {
+        Self {
+            machine_id: value.machine_id,
+            client_id: Address::from_slice(&value.client_id),
+            expires_at: value.expires_at,
+        }
+    }
+}
+
+impl TelemetrySession {
+    /// Stores the session under `token`, replacing any session the machine had before. Expired
+    /// sessions are removed on the way.
+    pub async fn open(
+        pool: &PgPool,
+        token: &str,
+        session: &TelemetrySession,
+    ) -> Result<(), DatabaseError> {
+        let mut tx = pool.begin().await?;
+        sqlx::query!(
+            "DELETE FROM telemetry_session WHERE machine_id = $1 OR expires_at <= $2",
+            session.machine_id,
+            Utc::now().naive_utc()
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            r#"INSERT INTO telemetry_session (token_hash, machine_id, client_id, expires_at)
+               VALUES ($1, $2, $3, $4)"#,
+            sha256::digest(token),
+            session.machine_id,
+            session.client_id.as_bytes(),
+            session.expires_at
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Session of `token`, unless it is unknown or expired.
+    pub async fn get(pool: &PgPool, token: &str) -> Result<Option<Self>, DatabaseError> {
+        let session = sqlx::query_as!(
+            DbTelemetrySession,
+            r#"SELECT machine_id, client_id, expires_at
+               FROM telemetry_session
+               WHERE token_hash = $1 AND expires_at > $2"#,
+            sha256::digest(token),
+            Utc::now().naive_utc()
+        )
+        .fetch_optional(pool)
+        .await?;
+        Ok(session.map(TelemetrySession::from))
+    }
+}
+
+#[cfg(test)]
+mod telemetry_session_tests {
+    use chrono::TimeDelta;
+
+    use super::*;
+
+    fn session(ttl: TimeDelta) -> TelemetrySession {
+        TelemetrySession {
+            machine_id: Uuid::parse_str("dcbf22c7-9d96-47ac-bf06-62d6544e440d").unwrap(),
+            client_id: Address::from_low_u64_be(1),
+            expires_at: Utc::now().naive_utc() + ttl,
+        }
+    }
+
+    #[sqlx::test(migrations = "../migrations", fixtures("../fixtures/new_user_registration.sql"))]
+    #[ignore]
+    async fn test_session_lifecycle(pool: PgPool) {
+        let session = session(TimeDelta::seconds(60));
+        assert!(TelemetrySession::get(&pool, "unknown").await.unwrap().is_none());
+
+        TelemetrySession::open(&pool, "token", &session).await.unwrap();
+        let stored = TelemetrySession::get(&pool, "token").await.unwrap().unwrap();
+        assert_eq!(stored.machine_id, session.machine_id);
+        assert_eq!(stored.client_id, session.client_id);
+
+        // A new session replaces the old one
+        TelemetrySession::open(&pool, "renewed", &session).await.unwrap();
+        assert!(TelemetrySession::get(&pool, "token").await.unwrap().is_none());
+        assert!(TelemetrySession::get(&pool, "renewed").await.unwrap().is_some());
+    }
+
+    #[sqlx::test(migrations = "../migrations", fixtures("../fixtures/new_user_registration.sql"))]
+    #[ignore]
+    async fn test_session_expiry(pool: PgPool) {
+        TelemetrySession::open(&pool, "token", &session(TimeDelta::zero())).await.unwrap();
+        assert!(TelemetrySession::get(&pool, "token").await.unwrap().is_none());
+    }
+}
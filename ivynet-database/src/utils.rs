@@ -9,3 +9,19 @@ pub fn try_parse_chain(chain: &str) -> Result<Chain, DatabaseError> {
 pub fn gb_to_bytes(gb: u64) -> u64 {
     gb * 10u64.pow(9)
 }
+
+/// Escapes a value for use in a text-format `COPY ... FROM STDIN` row. Empty values are written as
+/// NULL.
+pub fn escape_copy_value(value: &str) -> String {
+    if value.is_empty() {
+        return "\\N".to_string();
+    }
+
+    escape_copy_text(value)
+}
+
+/// Escapes a value for use in a text-format `COPY ... FROM STDIN` row. Empty values stay empty
+/// strings, NULL has to be written as `\N` by the caller.
+pub fn escape_copy_text(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n").replace('\r', "\\r")
+}
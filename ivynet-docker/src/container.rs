@@ -1,7 +1,8 @@
-use std::{fmt::Display, str::FromStr, time::Duration};
+use std::{collections::HashMap, fmt::Display, path::Path, str::FromStr, time::Duration};
 
 use crate::{
     dockerapi::DockerApi,
+    procfs::{self, PROC_ROOT},
     repodigest::RepoDigest,
     sidecar::{
         build_sidecar_image,
@@ -13,7 +14,7 @@ use crate::{
 use super::dockerapi::DockerClient;
 
 use bollard::{
-    container::{Config, CreateContainerOptions, LogOutput, LogsOptions},
+    container::{Config, CreateContainerOptions, InspectContainerOptions, LogOutput, LogsOptions},
     errors::Error,
     secret::{ContainerSummary, HostConfig, ImageInspect},
     Docker,
@@ -23,6 +24,19 @@ use serde::{Deserialize, Serialize};
 use tokio_stream::Stream;
 use tracing::debug;
 
+/// How the listening ports of a container in host network mode are discovered. Containers on a
+/// bridge network report their published ports directly and need neither.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PortDiscovery {
+    /// Read the container's sockets from /proc, falling back to the sidecar when /proc is not
+    /// available to the client.
+    #[default]
+    Procfs,
+    /// Build and run a one-shot `netstat` sidecar container in the target's namespaces.
+    Sidecar,
+}
+
 /// Type representing a docker image verison `repository:tag.` Primarily for tracking image version
 /// between container and image.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -126,6 +140,10 @@ impl Container {
         repo_digest.digest
     }
 
+    pub fn labels(&self) -> Option<&HashMap<String, String>> {
+        self.0.labels.as_ref()
+    }
+
     pub fn ports(&self) -> Option<&Vec<bollard::models::Port>> {
         self.0.ports.as_ref()
     }
@@ -134,9 +152,13 @@ impl Container {
         self.0.state.as_deref()
     }
 
-    pub async fn public_ports(&self, docker: &impl DockerApi) -> Vec<u16> {
+    pub async fn public_ports(
+        &self,
+        docker: &impl DockerApi,
+        discovery: PortDiscovery,
+    ) -> Vec<u16> {
         match self.is_network_mode_host() {
-            true => self.get_host_ports(docker).await.unwrap_or_default(),
+            true => self.get_host_ports(docker, discovery).await.unwrap_or_default(),
             false => self
                 .ports()
                 .map(|ports| ports.iter().filter_map(|port| port.public_port).collect())
@@ -144,8 +166,12 @@ impl Container {
         }
     }
 
-    pub async fn metrics_port(&self, docker: &DockerClient) -> Option<u16> {
-        let mut ports = self.public_ports(docker).await;
+    pub async fn metrics_port(
+        &self,
+        docker: &DockerClient,
+        discovery: PortDiscovery,
+    ) -> Option<u16> {
+        let mut ports = self.public_ports(docker, discovery).await;
         ports.sort();
         ports.dedup();
         for port in ports {
@@ -174,6 +200,24 @@ impl Container {
         docker.0.logs(self.id().unwrap(), Some(log_opts))
     }
 
+    /// Logs the container wrote between two timestamps, without following new output
+    pub fn logs_between(
+        &self,
+        docker: &DockerClient,
+        since: i64,
+        until: i64,
+    ) -> impl Stream<Item = Result<LogOutput, bollard::errors::Error>> {
+        let log_opts: LogsOptions<&str> = LogsOptions {
+            follow: false,
+            stdout: true,
+            stderr: true,
+            since,
+            until,
+            ..Default::default()
+        };
+        docker.0.logs(self.id().unwrap(), Some(log_opts))
+    }
+
     /// Stream logs for the container since current time
     pub fn stream_logs_latest(
         &self,
@@ -187,6 +231,49 @@ impl Container {
     pub async fn get_host_ports(
         &self,
         docker: &impl DockerApi,
+        discovery: PortDiscovery,
+    ) -> Result<Vec<u16>, ContainerError> {
+        match discovery {
+            PortDiscovery::Procfs => {
+                match self.get_procfs_ports(docker, Path::new(PROC_ROOT)).await {
+                    Err(ContainerError::ProcUnavailable(e)) => {
+                        debug!("Cannot read container ports from /proc, using sidecar: {}", e);
+                        self.get_sidecar_ports(docker).await
+                    }
+                    result => result,
+                }
+            }
+            PortDiscovery::Sidecar => self.get_sidecar_ports(docker).await,
+        }
+    }
+
+    /// Get the container's listening ports from the /proc entries of its main process.
+    pub async fn get_procfs_ports(
+        &self,
+        docker: &impl DockerApi,
+        proc_root: &Path,
+    ) -> Result<Vec<u16>, ContainerError> {
+        let container_id = self.id().ok_or(ContainerError::NoContainerId)?;
+        let inspect =
+            docker.inner().inspect_container(container_id, None::<InspectContainerOptions>).await?;
+        let pid = inspect
+            .state
+            .and_then(|state| state.pid)
+            .filter(|pid| *pid > 0)
+            .ok_or(ContainerError::NoContainerPid)?;
+
+        let proc_root = proc_root.to_path_buf();
+        tokio::task::spawn_blocking(move || procfs::listening_ports(&proc_root, pid as u32))
+            .await
+            .map_err(|e| ContainerError::ProcUnavailable(e.into()))?
+            .map_err(ContainerError::ProcUnavailable)
+    }
+
+    /// Get the container's listening ports from `netstat` run in a sidecar container sharing its
+    /// namespaces.
+    pub async fn get_sidecar_ports(
+        &self,
+        docker: &impl DockerApi,
     ) -> Result<Vec<u16>, ContainerError> {
         let sidecar_name = build_sidecar_image(&docker.inner()).await?;
         let sidecar_container = self.run_one_shot_sidecar(docker, &sidecar_name).await?;
@@ -288,6 +375,10 @@ pub enum ContainerError {
     NoContainerFound(String),
     #[error("Container is not in host network mode")]
     NotHostNetworkMode,
+    #[error("Container has no running process")]
+    NoContainerPid,
+    #[error("Cannot read container process from /proc: {0}")]
+    ProcUnavailable(std::io::Error),
     #[error(transparent)]
     DockerSidecarError(#[from] DockerSidecarError),
     #[error(transparent)]
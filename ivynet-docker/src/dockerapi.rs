@@ -2,7 +2,7 @@ use std::{collections::HashMap, pin::Pin};
 
 use async_trait::async_trait;
 use bollard::{
-    container::{LogOutput, LogsOptions},
+    container::{InspectContainerOptions, LogOutput, LogsOptions, Stats, StatsOptions},
     errors::Error,
     secret::{EventMessage, ImageSummary},
     Docker,
@@ -55,6 +55,23 @@ pub trait DockerApi: Clone + Sync + Send + 'static {
         since: i64,
     ) -> Pin<Box<dyn Stream<Item = Result<LogOutput, Error>> + Send + Unpin>>;
 
+    /// Stream resource usage samples for a container. The docker daemon emits one sample roughly
+    /// every second until the container stops.
+    async fn stream_stats(
+        &self,
+        container: Container,
+    ) -> Pin<Box<dyn Stream<Item = Result<Stats, Error>> + Send + Unpin>>;
+
+    /// Number of times the docker daemon has restarted a container under its restart policy.
+    async fn restart_count(&self, container: &Container) -> Option<u64> {
+        let inspect = self
+            .inner()
+            .inspect_container(container.id()?, None::<InspectContainerOptions>)
+            .await
+            .ok()?;
+        inspect.restart_count.map(|count| count as u64)
+    }
+
     async fn inspect(&self, image_name: &str) -> Option<Container> {
         let containers = self.list_containers().await;
         for container in containers {
@@ -252,6 +269,14 @@ impl DockerApi for DockerClient {
     ) -> Pin<Box<dyn Stream<Item = Result<EventMessage, Error>> + Send + Unpin>> {
         Box::pin(self.0.events::<&str>(None))
     }
+
+    async fn stream_stats(
+        &self,
+        container: Container,
+    ) -> Pin<Box<dyn Stream<Item = Result<Stats, Error>> + Send + Unpin>> {
+        let stats_opts = StatsOptions { stream: true, one_shot: false };
+        Box::pin(self.0.stats(container.id().unwrap(), Some(stats_opts)))
+    }
 }
 
 #[derive(Clone, Debug, thiserror::Error)]
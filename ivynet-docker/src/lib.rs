@@ -4,5 +4,7 @@ pub mod dockerapi;
 pub mod dockercmd;
 pub mod logs;
 pub mod mocks;
+pub mod procfs;
 pub mod repodigest;
 pub mod sidecar;
+pub mod stats;
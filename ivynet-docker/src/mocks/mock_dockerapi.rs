@@ -2,7 +2,7 @@ use std::{pin::Pin, sync::Arc};
 
 use async_trait::async_trait;
 use bollard::{
-    container::LogOutput,
+    container::{LogOutput, Stats},
     errors::Error,
     secret::{ContainerSummary, EventMessage, ImageSummary},
     Docker,
@@ -19,6 +19,7 @@ pub struct MockDockerClient {
     pub records: Vec<ContainerRecord>,
     pub events: Vec<EventMessage>,
     pub logs: Vec<LogOutput>,
+    pub stats: Vec<Stats>,
     pub images: Arc<Vec<ImageSummary>>,
 }
 
@@ -30,11 +31,18 @@ impl MockDockerClient {
             .flatten()
             .collect();
         let logs = mock_logs();
-        Self { records, events, logs, images: Arc::new(vec![]) }
+        let stats = vec![eigenda_stats()];
+        Self { records, events, logs, stats, images: Arc::new(vec![]) }
     }
 
     pub fn images_only(&self, images: Vec<ImageSummary>) -> MockDockerClient {
-        MockDockerClient { images: Arc::new(images), records: vec![], events: vec![], logs: vec![] }
+        MockDockerClient {
+            images: Arc::new(images),
+            records: vec![],
+            events: vec![],
+            logs: vec![],
+            stats: vec![],
+        }
     }
 }
 
@@ -75,6 +83,17 @@ impl DockerApi for MockDockerClient {
     ) -> Pin<Box<dyn Stream<Item = Result<EventMessage, Error>> + Send + Unpin>> {
         Box::pin(stream::iter(self.events.clone().into_iter().map(Ok)))
     }
+
+    async fn stream_stats(
+        &self,
+        _container: Container,
+    ) -> Pin<Box<dyn Stream<Item = Result<Stats, Error>> + Send + Unpin>> {
+        Box::pin(stream::iter(self.stats.clone().into_iter().map(Ok)))
+    }
+
+    async fn restart_count(&self, _container: &Container) -> Option<u64> {
+        Some(0)
+    }
 }
 
 // Extra scenarios for testing
@@ -173,6 +192,10 @@ fn eigenda_container_1() -> ContainerRecord {
     }
 }
 
+fn eigenda_stats() -> Stats {
+    serde_json::from_str(include_str!("./stats/eigenda_container_stats.json")).unwrap()
+}
+
 // streams
 fn eigenda_stream_start() -> Vec<EventMessage> {
     serde_json::from_str(include_str!("./eventstream/eigenda_container_start.json")).unwrap()
@@ -0,0 +1,129 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::Path,
+};
+
+pub const PROC_ROOT: &str = "/proc";
+
+/// TCP socket state for a listening socket in /proc/net/tcp{,6}.
+const TCP_LISTEN: &str = "0A";
+
+/// TCP ports listened on by the processes sharing `pid`'s PID namespace, read from the network
+/// namespace of `pid`. This is the same set `netstat -tlp` reports from inside the container, so
+/// for a container in host network mode only the container's own sockets are returned rather than
+/// every socket on the host.
+///
+/// Fails if the process' entries cannot be read, e.g. when /proc belongs to another PID namespace
+/// or file descriptors of other users are hidden from an unprivileged caller.
+pub fn listening_ports(proc_root: &Path, pid: u32) -> Result<Vec<u16>, io::Error> {
+    let process = proc_root.join(pid.to_string());
+    let listening: HashMap<u64, u16> = ["tcp", "tcp6"]
+        .iter()
+        .filter_map(|file| fs::read_to_string(process.join("net").join(file)).ok())
+        .flat_map(|contents| parse_listening_sockets(&contents))
+        .collect();
+
+    // Errors on the target process itself are what make /proc unusable for it
+    let namespace = fs::read_link(process.join("ns").join("pid"))?;
+    let mut inodes: HashSet<u64> = socket_inodes(proc_root, pid)?.into_iter().collect();
+
+    for entry in fs::read_dir(proc_root)?.flatten() {
+        let Some(other) = entry.file_name().to_str().and_then(|name| name.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        if other == pid ||
+            fs::read_link(entry.path().join("ns").join("pid")).ok().as_ref() != Some(&namespace)
+        {
+            continue;
+        }
+        inodes.extend(socket_inodes(proc_root, other).unwrap_or_default());
+    }
+
+    let mut ports: Vec<u16> =
+        inodes.iter().filter_map(|inode| listening.get(inode).copied()).collect();
+    ports.sort_unstable();
+    ports.dedup();
+    Ok(ports)
+}
+
+/// Inodes of the sockets held open by a process, from the links in /proc/<pid>/fd.
+pub fn socket_inodes(proc_root: &Path, pid: u32) -> Result<Vec<u64>, io::Error> {
+    let fds = fs::read_dir(proc_root.join(pid.to_string()).join("fd"))?;
+    Ok(fds
+        .flatten()
+        .filter_map(|fd| {
+            let target = fs::read_link(fd.path()).ok()?;
+            target.to_str()?.strip_prefix("socket:[")?.strip_suffix(']')?.parse::<u64>().ok()
+        })
+        .collect())
+}
+
+/// Parses the contents of /proc/net/tcp or /proc/net/tcp6 into (inode, port) pairs for sockets in
+/// the LISTEN state.
+pub fn parse_listening_sockets(contents: &str) -> Vec<(u64, u16)> {
+    contents
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.get(3) != Some(&TCP_LISTEN) {
+                return None;
+            }
+            let (_, port) = fields.get(1)?.rsplit_once(':')?;
+            let port = u16::from_str_radix(port, 16).ok()?;
+            let inode = fields.get(9)?.parse::<u64>().ok()?;
+            Some((inode, port))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod procfs_tests {
+    use std::{os::unix::fs::symlink, path::PathBuf};
+
+    use super::*;
+
+    const TCP: &str = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 00000000:2382 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 1001 1 0000000000000000 100 0 0 10 0
+   1: 0100007F:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 1002 1 0000000000000000 100 0 0 10 0
+   2: 00000000:0016 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 1003 1 0000000000000000 100 0 0 10 0
+   3: 0100007F:A1B2 0100007F:2382 01 00000000:00000000 00:00000000 00000000     0        0 1004 1 0000000000000000 20 4 30 10 -1
+";
+
+    #[test]
+    fn test_parse_listening_sockets() {
+        assert_eq!(parse_listening_sockets(TCP), vec![(1001, 9090), (1002, 8080), (1003, 22)]);
+    }
+
+    fn fake_process(root: &Path, pid: u32, namespace: &str, sockets: &[u64]) {
+        let dir = root.join(pid.to_string());
+        fs::create_dir_all(dir.join("fd")).unwrap();
+        fs::create_dir_all(dir.join("ns")).unwrap();
+        fs::create_dir_all(dir.join("net")).unwrap();
+        fs::write(dir.join("net").join("tcp"), TCP).unwrap();
+        symlink(namespace, dir.join("ns").join("pid")).unwrap();
+        for (fd, inode) in sockets.iter().enumerate() {
+            symlink(format!("socket:[{inode}]"), dir.join("fd").join(fd.to_string())).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_listening_ports_of_pid_namespace() {
+        let root = PathBuf::from("test_procfs_ports");
+        let _ = fs::remove_dir_all(&root);
+
+        // Container init and a worker in the same namespace, and sshd on the host
+        fake_process(&root, 4100, "pid:[4026532001]", &[1001]);
+        fake_process(&root, 4120, "pid:[4026532001]", &[1002]);
+        fake_process(&root, 300, "pid:[4026531836]", &[1003]);
+
+        let ports = listening_ports(&root, 4100);
+        let missing = listening_ports(&root, 9999);
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(ports.unwrap(), vec![8080, 9090]);
+        assert!(missing.is_err());
+    }
+}
@@ -0,0 +1,113 @@
+use bollard::container::{MemoryStatsStats, Stats};
+use serde::{Deserialize, Serialize};
+
+/// Resource usage of a single container, derived from one sample of the docker stats API. Values
+/// are computed the same way `docker stats` computes them, so they line up with what operators see
+/// on the command line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ContainerStats {
+    /// CPU usage since the previous sample, in percent of a single core. A container saturating
+    /// two cores reports 200.
+    pub cpu_percent: f64,
+    /// Memory in use, excluding the reclaimable page cache.
+    pub memory_usage: u64,
+    pub memory_limit: u64,
+    /// Cumulative bytes received and sent, summed across all of the container's networks.
+    pub network_rx_bytes: u64,
+    pub network_tx_bytes: u64,
+    /// Cumulative bytes read from and written to block devices.
+    pub block_read_bytes: u64,
+    pub block_write_bytes: u64,
+}
+
+impl From<&Stats> for ContainerStats {
+    fn from(stats: &Stats) -> Self {
+        let (network_rx_bytes, network_tx_bytes) = stats
+            .networks
+            .iter()
+            .flat_map(|networks| networks.values())
+            .chain(stats.network.iter())
+            .fold((0, 0), |(rx, tx), net| (rx + net.rx_bytes, tx + net.tx_bytes));
+
+        let block_bytes = |op: &str| {
+            stats
+                .blkio_stats
+                .io_service_bytes_recursive
+                .iter()
+                .flatten()
+                .filter(|entry| entry.op.eq_ignore_ascii_case(op))
+                .map(|entry| entry.value)
+                .sum()
+        };
+
+        Self {
+            cpu_percent: cpu_percent(stats),
+            memory_usage: memory_usage(stats),
+            memory_limit: stats.memory_stats.limit.unwrap_or_default(),
+            network_rx_bytes,
+            network_tx_bytes,
+            block_read_bytes: block_bytes("read"),
+            block_write_bytes: block_bytes("write"),
+        }
+    }
+}
+
+fn cpu_percent(stats: &Stats) -> f64 {
+    let cpu = &stats.cpu_stats;
+    let precpu = &stats.precpu_stats;
+    let cpu_delta = cpu.cpu_usage.total_usage.saturating_sub(precpu.cpu_usage.total_usage);
+    let system_delta = cpu
+        .system_cpu_usage
+        .unwrap_or_default()
+        .saturating_sub(precpu.system_cpu_usage.unwrap_or_default());
+    if cpu_delta == 0 || system_delta == 0 {
+        return 0.0;
+    }
+
+    // Older daemons do not report online_cpus, only per-cpu usage
+    let online_cpus = cpu
+        .online_cpus
+        .or_else(|| cpu.cpu_usage.percpu_usage.as_ref().map(|percpu| percpu.len() as u64))
+        .unwrap_or(1);
+    cpu_delta as f64 / system_delta as f64 * online_cpus as f64 * 100.0
+}
+
+fn memory_usage(stats: &Stats) -> u64 {
+    let usage = stats.memory_stats.usage.unwrap_or_default();
+    let cache = match &stats.memory_stats.stats {
+        Some(MemoryStatsStats::V1(v1)) => v1.total_inactive_file,
+        Some(MemoryStatsStats::V2(v2)) => v2.inactive_file,
+        None => 0,
+    };
+    usage.saturating_sub(cache)
+}
+
+#[cfg(test)]
+mod stats_tests {
+    use super::*;
+
+    fn stats_fixture() -> Stats {
+        serde_json::from_str(include_str!("./mocks/stats/eigenda_container_stats.json")).unwrap()
+    }
+
+    #[test]
+    fn test_container_stats_from_sample() {
+        let stats = ContainerStats::from(&stats_fixture());
+        assert_eq!(stats.cpu_percent, 50.0);
+        assert_eq!(stats.memory_usage, 90_000_000);
+        assert_eq!(stats.memory_limit, 1_000_000_000);
+        assert_eq!(stats.network_rx_bytes, 3000);
+        assert_eq!(stats.network_tx_bytes, 700);
+        assert_eq!(stats.block_read_bytes, 4096);
+        assert_eq!(stats.block_write_bytes, 8192);
+    }
+
+    #[test]
+    fn test_first_sample_has_no_cpu() {
+        let mut stats = stats_fixture();
+        stats.precpu_stats.system_cpu_usage = None;
+        stats.precpu_stats.cpu_usage.total_usage = 0;
+        stats.cpu_stats.system_cpu_usage = None;
+        assert_eq!(ContainerStats::from(&stats).cpu_percent, 0.0);
+    }
+}
@@ -0,0 +1,75 @@
+//! Protocol version and feature flags exchanged with the `Hello` RPC.
+
+use tonic::{metadata::MetadataValue, Request, Status};
+
+/// Version of the protocol between clients and the backend. Bumped whenever a change cannot be
+/// handled by peers of the previous version. Clients from before `Hello` speak version 1.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Oldest protocol version the backend accepts. Version 1 clients still send node data v1, which
+/// is no longer handled.
+pub const MIN_PROTOCOL_VERSION: u32 = 2;
+
+/// Metadata key carrying the protocol version a client negotiated with `Hello`, sent along with
+/// its telemetry.
+pub const PROTOCOL_METADATA_KEY: &str = "x-ivynet-protocol";
+
+/// Telemetry sent in a session opened with `OpenSession`
+pub const FEATURE_SESSIONS: &str = "sessions";
+/// Telemetry sent over the long-lived `TelemetryStream`
+pub const FEATURE_TELEMETRY_STREAM: &str = "telemetry_stream";
+/// Signed commands pushed over the telemetry stream
+pub const FEATURE_REMOTE_COMMANDS: &str = "remote_commands";
+/// Heartbeats sent to the `Heartbeat` service in a session
+pub const FEATURE_HEARTBEATS: &str = "heartbeats";
+
+/// Every feature of this protocol version.
+pub const FEATURES: &[&str] =
+    &[FEATURE_SESSIONS, FEATURE_TELEMETRY_STREAM, FEATURE_REMOTE_COMMANDS, FEATURE_HEARTBEATS];
+
+/// Marks `request` as sent by a client that negotiated [`PROTOCOL_VERSION`].
+pub fn declare_protocol<T>(request: &mut Request<T>) {
+    request.metadata_mut().insert(PROTOCOL_METADATA_KEY, MetadataValue::from(PROTOCOL_VERSION));
+}
+
+/// Checks that `request` comes from a client that negotiated a protocol version the backend still
+/// accepts. Clients that never negotiated predate `Hello` and speak version 1.
+pub fn check_protocol<T>(request: &Request<T>) -> Result<(), Status> {
+    let version = match request.metadata().get(PROTOCOL_METADATA_KEY) {
+        Some(version) => version
+            .to_str()
+            .ok()
+            .and_then(|version| version.parse::<u32>().ok())
+            .ok_or_else(|| Status::invalid_argument("Protocol version is invalid"))?,
+        None => 1,
+    };
+    if version < MIN_PROTOCOL_VERSION {
+        return Err(Status::failed_precondition(format!(
+            "Client speaks protocol version {version}, but the backend requires version \
+             {MIN_PROTOCOL_VERSION} or newer. Please update the client"
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod capabilities_tests {
+    use tonic::Code;
+
+    use super::*;
+
+    #[test]
+    fn test_check_protocol() {
+        let mut request = Request::new(());
+        declare_protocol(&mut request);
+        assert!(check_protocol(&request).is_ok());
+
+        // Clients that never negotiated speak version 1
+        let status = check_protocol(&Request::new(())).unwrap_err();
+        assert_eq!(status.code(), Code::FailedPrecondition);
+
+        let mut request = Request::new(());
+        request.metadata_mut().insert(PROTOCOL_METADATA_KEY, MetadataValue::from(1u32));
+        assert_eq!(check_protocol(&request).unwrap_err().code(), Code::FailedPrecondition);
+    }
+}
@@ -1,3 +1,4 @@
+pub mod capabilities;
 pub mod client;
 pub mod server;
 
@@ -27,6 +28,9 @@ use tonic::transport::Channel;
 
 pub use tonic::{self, async_trait, Response, Status};
 
+/// Metadata key carrying the token of a telemetry session opened with `OpenSession`.
+pub const SESSION_METADATA_KEY: &str = "x-ivynet-session";
+
 #[derive(Debug, Clone)]
 pub struct BackendClientMiddleware(backend::backend_client::BackendClient<Channel>);
 
@@ -43,11 +47,6 @@ pub trait BackendMiddleware: Clone + Send + Sync + 'static {
 
     async fn metrics(&mut self, request: messages::SignedMetrics) -> Result<Response<()>, Status>;
 
-    async fn node_data(
-        &mut self,
-        request: messages::SignedNodeData,
-    ) -> Result<Response<()>, Status>;
-
     async fn node_data_v2(
         &mut self,
         request: messages::SignedNodeDataV2,
@@ -60,6 +59,11 @@ pub trait BackendMiddleware: Clone + Send + Sync + 'static {
 
     async fn logs(&mut self, request: messages::SignedLog) -> Result<Response<()>, Status>;
 
+    async fn logs_batch(
+        &mut self,
+        request: messages::SignedLogBatch,
+    ) -> Result<Response<()>, Status>;
+
     async fn node_type_queries(
         &mut self,
         request: messages::NodeTypeQueries,
@@ -71,6 +75,13 @@ pub trait BackendMiddleware: Clone + Send + Sync + 'static {
     ) -> Result<Response<()>, Status>;
 }
 
+/// Telemetry request declaring the protocol version, without which the backend refuses it.
+fn telemetry_request<T>(message: T) -> tonic::Request<T> {
+    let mut request = tonic::Request::new(message);
+    capabilities::declare_protocol(&mut request);
+    request
+}
+
 impl From<BackendClient<Channel>> for BackendClientMiddleware {
     fn from(client: BackendClient<Channel>) -> Self {
         Self(client)
@@ -95,32 +106,32 @@ impl BackendMiddleware for BackendClientMiddleware {
     }
 
     async fn metrics(&mut self, request: messages::SignedMetrics) -> Result<Response<()>, Status> {
-        self.0.metrics(request).await
-    }
-
-    async fn node_data(
-        &mut self,
-        request: messages::SignedNodeData,
-    ) -> Result<Response<()>, Status> {
-        self.0.node_data(request).await
+        self.0.metrics(telemetry_request(request)).await
     }
 
     async fn node_data_v2(
         &mut self,
         request: messages::SignedNodeDataV2,
     ) -> Result<Response<()>, Status> {
-        self.0.node_data_v2(request).await
+        self.0.node_data_v2(telemetry_request(request)).await
     }
 
     async fn machine_data(
         &mut self,
         request: messages::SignedMachineData,
     ) -> Result<Response<()>, Status> {
-        self.0.machine_data(request).await
+        self.0.machine_data(telemetry_request(request)).await
     }
 
     async fn logs(&mut self, request: messages::SignedLog) -> Result<Response<()>, Status> {
-        self.0.logs(request).await
+        self.0.logs(telemetry_request(request)).await
+    }
+
+    async fn logs_batch(
+        &mut self,
+        request: messages::SignedLogBatch,
+    ) -> Result<Response<()>, Status> {
+        self.0.logs_batch(telemetry_request(request)).await
     }
 
     async fn node_type_queries(
@@ -170,13 +181,6 @@ impl BackendMiddleware for BackendClientMock {
         Ok(Response::new(()))
     }
 
-    async fn node_data(
-        &mut self,
-        _request: messages::SignedNodeData,
-    ) -> Result<Response<()>, Status> {
-        Ok(Response::new(()))
-    }
-
     async fn node_data_v2(
         &mut self,
         _request: messages::SignedNodeDataV2,
@@ -195,6 +199,13 @@ impl BackendMiddleware for BackendClientMock {
         Ok(Response::new(()))
     }
 
+    async fn logs_batch(
+        &mut self,
+        _request: messages::SignedLogBatch,
+    ) -> Result<Response<()>, Status> {
+        Ok(Response::new(()))
+    }
+
     async fn node_type_queries(
         &mut self,
         _request: messages::NodeTypeQueries,
@@ -213,3 +224,14 @@ impl BackendMiddleware for BackendClientMock {
         Ok(Response::new(()))
     }
 }
+
+#[cfg(test)]
+mod middleware_tests {
+    use super::*;
+
+    #[test]
+    fn test_telemetry_requests_declare_protocol() {
+        let request = telemetry_request(messages::SignedNodeDataV2::default());
+        assert!(capabilities::check_protocol(&request).is_ok());
+    }
+}
@@ -2,7 +2,11 @@ use std::sync::Arc;
 
 use chrono::{DateTime, Utc};
 use ivynet_alerts::AlertType;
-use ivynet_database::{error::DatabaseError, NotificationSettings};
+use ivynet_database::{
+    alerts::silence::{AlertSilence, SilenceTarget},
+    error::DatabaseError,
+    Avs, NotificationSettings,
+};
 use ivynet_notifications::{NotificationDispatcher, OrganizationDatabase};
 use sqlx::PgPool;
 
@@ -97,10 +101,17 @@ impl<D: OrganizationDatabase> HeartbeatEventHandler<D> {
             organization_id,
         };
         ClientHeartbeatAlert::insert(&self.db, alert.clone(), organization_id).await?;
+        let target = SilenceTarget {
+            alert_type: AlertType::NoClientHeartbeat,
+            machine_id: None,
+            node_name: None,
+            node_type: None,
+        };
         if settings
             .alert_flags
             .is_alert_enabled(&AlertType::NoClientHeartbeat)
-            .is_ok_and(|enabled| enabled)
+            .is_ok_and(|enabled| enabled) &&
+            !AlertSilence::is_silenced(&self.db, organization_id, &target).await?
         {
             let channels = settings.get_active_channels();
             self.notifier.notify(alert, channels).await?;
@@ -122,10 +133,17 @@ impl<D: OrganizationDatabase> HeartbeatEventHandler<D> {
             organization_id,
         };
         MachineHeartbeatAlert::insert(&self.db, alert.clone(), organization_id).await?;
+        let target = SilenceTarget {
+            alert_type: AlertType::NoMachineHeartbeat,
+            machine_id: Some(alert.machine_id.0),
+            node_name: None,
+            node_type: None,
+        };
         if settings
             .alert_flags
             .is_alert_enabled(&AlertType::NoMachineHeartbeat)
-            .is_ok_and(|enabled| enabled)
+            .is_ok_and(|enabled| enabled) &&
+            !AlertSilence::is_silenced(&self.db, organization_id, &target).await?
         {
             let channels = settings.get_active_channels();
             self.notifier.notify(alert, channels).await?;
@@ -148,10 +166,20 @@ impl<D: OrganizationDatabase> HeartbeatEventHandler<D> {
             organization_id,
         };
         NodeHeartbeatAlert::insert(&self.db, alert.clone(), organization_id).await?;
+        let node_type = Avs::get_machines_avs(&self.db, alert.node_id.machine, &alert.node_id.name)
+            .await?
+            .map(|avs| avs.avs_type.to_string());
+        let target = SilenceTarget {
+            alert_type: AlertType::NoNodeHeartbeat,
+            machine_id: Some(alert.node_id.machine),
+            node_name: Some(&alert.node_id.name),
+            node_type: node_type.as_deref(),
+        };
         if settings
             .alert_flags
             .is_alert_enabled(&AlertType::NoNodeHeartbeat)
-            .is_ok_and(|enabled| enabled)
+            .is_ok_and(|enabled| enabled) &&
+            !AlertSilence::is_silenced(&self.db, organization_id, &target).await?
         {
             let channels = settings.get_active_channels();
             self.notifier.notify(alert, channels).await?;
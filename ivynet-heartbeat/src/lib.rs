@@ -1,14 +1,11 @@
 use core::fmt;
 use std::{
-    collections::HashMap,
-    fmt::{Debug, Display, Formatter},
-    hash::Hash,
+    fmt::{Display, Formatter},
     str::FromStr,
-    sync::{Arc, RwLock},
-    time::Duration,
+    sync::Arc,
 };
 
-use chrono::{DateTime, TimeDelta, Utc};
+use chrono::{DateTime, Utc};
 use ethers::types::Address;
 use event::{HeartbeatEvent, HeartbeatEventHandler};
 use ivynet_database::error::DatabaseError;
@@ -24,21 +21,30 @@ use ivynet_notifications::{
 };
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
+use store::{HeartbeatKey, HeartbeatStore};
 use tracing::{error, warn};
+use ttl::{Expiring, Ttls};
 use utoipa::ToSchema;
 use uuid::Uuid;
 
 mod event;
+mod ttl;
 
 pub mod alerts;
 pub mod server;
+pub mod store;
 
-const FIFTEEN_MINUTES_SECS: u64 = 15 * 60;
-const ONE_MINUTE_SECS: u64 = 60;
+pub use ttl::HeartbeatConfig;
 
 #[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Serialize, Deserialize)]
 pub struct ClientId(Address);
 
+impl ClientId {
+    pub fn new(address: Address) -> Self {
+        Self(address)
+    }
+}
+
 impl TryFrom<ClientHeartbeatSrc> for ClientId {
     type Error = HeartbeatError;
 
@@ -122,57 +128,6 @@ impl FromStr for NodeId {
     }
 }
 
-/// A simplified map of heartbeats for a given type to a unix timestamp
-pub struct HeartbeatMap<T: Debug + Clone + Eq + Hash + Send + Sync + 'static> {
-    map: Arc<RwLock<HashMap<T, DateTime<Utc>>>>,
-}
-
-impl<T: Debug + Eq + Hash + Send + Sync + Clone + 'static> Default for HeartbeatMap<T> {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
-impl<T: Debug + Eq + Hash + Send + Sync + Clone + 'static> HeartbeatMap<T> {
-    pub fn new() -> Self {
-        let map: Arc<RwLock<HashMap<T, DateTime<Utc>>>> = Arc::new(RwLock::new(HashMap::new()));
-        Self { map }
-    }
-
-    pub fn insert(&self, key: T) -> Option<DateTime<Utc>> {
-        let now = Utc::now();
-        self.map.write().expect("Write lock failed").insert(key, now)
-    }
-
-    pub fn remove(&self, key: &T) -> Option<DateTime<Utc>> {
-        self.map.write().expect("Write lock failed").remove(key)
-    }
-
-    pub fn get(&self, key: &T) -> Option<DateTime<Utc>> {
-        self.map.read().expect("Read lock failed").get(key).copied()
-    }
-
-    pub fn get_all(&self) -> HashMap<T, DateTime<Utc>> {
-        self.map.read().expect("Read lock failed").clone()
-    }
-
-    pub fn remove_stale_entries(&self, ttl: TimeDelta) -> Vec<(T, DateTime<Utc>)> {
-        let now = Utc::now();
-        let mut map = self.map.write().expect("Write lock failed");
-        let stale_entries = map
-            .iter()
-            .filter(|(_, &time)| now - time > ttl)
-            .map(|(k, v)| (k.clone(), *v))
-            .collect::<Vec<_>>();
-
-        for (key, _) in &stale_entries {
-            map.remove(key);
-        }
-
-        stale_entries
-    }
-}
-
 #[derive(Debug, thiserror::Error)]
 pub enum HeartbeatError {
     #[error("invalid client address")]
@@ -194,55 +149,84 @@ impl From<HeartbeatError> for Status {
 }
 
 pub struct HeartbeatMonitor<D: OrganizationDatabase> {
-    client_map: Arc<HeartbeatMap<ClientId>>,
-    machine_map: Arc<HeartbeatMap<MachineId>>,
-    node_map: Arc<HeartbeatMap<NodeId>>,
+    store: Arc<dyn HeartbeatStore>,
     event_handler: Arc<HeartbeatEventHandler<D>>,
 }
 
-impl<D: OrganizationDatabase> HeartbeatMonitor<D> {
-    pub fn new(db: PgPool, notifier: Arc<NotificationDispatcher<D>>) -> Self {
-        let client_map = Arc::new(HeartbeatMap::new());
-        let machine_map = Arc::new(HeartbeatMap::new());
-        let node_map = Arc::new(HeartbeatMap::new());
-        let event_handler = Arc::new(HeartbeatEventHandler::new(db, notifier));
-
-        let monitor = Self { client_map, machine_map, node_map, event_handler };
+// Not derived, which would require `D: Clone`
+impl<D: OrganizationDatabase> Clone for HeartbeatMonitor<D> {
+    fn clone(&self) -> Self {
+        Self { store: Arc::clone(&self.store), event_handler: Arc::clone(&self.event_handler) }
+    }
+}
 
-        let client_map = Arc::clone(&monitor.client_map);
-        let machine_map = Arc::clone(&monitor.machine_map);
-        let node_map = Arc::clone(&monitor.node_map);
+impl<D: OrganizationDatabase> HeartbeatMonitor<D> {
+    /// Starts monitoring the heartbeats kept in `store`. Monitors sharing a store, e.g. in
+    /// several ingress replicas, each sweep it, but every stale entry is reported by one of them.
+    pub fn new(
+        db: PgPool,
+        notifier: Arc<NotificationDispatcher<D>>,
+        store: impl HeartbeatStore,
+        config: HeartbeatConfig,
+    ) -> Self {
+        let store: Arc<dyn HeartbeatStore> = Arc::new(store);
+        let event_handler = Arc::new(HeartbeatEventHandler::new(db.clone(), notifier));
+
+        let monitor = Self { store, event_handler };
+
+        let store = Arc::clone(&monitor.store);
         let event_handler = Arc::clone(&monitor.event_handler);
 
         tokio::spawn(async move {
-            let interval = Duration::from_secs(ONE_MINUTE_SECS);
-            let ttl = TimeDelta::seconds(FIFTEEN_MINUTES_SECS as i64);
-            let mut interval = tokio::time::interval(interval);
+            let mut interval = tokio::time::interval(config.sweep_interval);
             loop {
                 interval.tick().await;
-                let stale_clients = client_map.remove_stale_entries(ttl);
-                let stale_machines = machine_map.remove_stale_entries(ttl);
-                let stale_nodes = node_map.remove_stale_entries(ttl);
-
-                for (client_id, time) in stale_clients {
-                    let event = HeartbeatEvent::StaleClient { client_id, last_heartbeat: time };
-                    if let Err(e) = event_handler.handle_event(event).await {
-                        error!("Error handling stale client event: {}", e);
-                    };
+
+                // Settings are read once per sweep, so changes apply from the next one
+                let mut ttls = match Ttls::load(&db, config.ttl).await {
+                    Ok(ttls) => ttls,
+                    Err(e) => {
+                        error!("Failed to load heartbeat settings: {}", e);
+                        continue;
+                    }
+                };
+
+                match sweep::<ClientId>(store.as_ref(), &mut ttls).await {
+                    Ok(stale_clients) => {
+                        for (client_id, time) in stale_clients {
+                            let event =
+                                HeartbeatEvent::StaleClient { client_id, last_heartbeat: time };
+                            if let Err(e) = event_handler.handle_event(event).await {
+                                error!("Error handling stale client event: {}", e);
+                            };
+                        }
+                    }
+                    Err(e) => error!("Failed to sweep client heartbeats: {}", e),
                 }
 
-                for (machine_id, time) in stale_machines {
-                    let event = HeartbeatEvent::StaleMachine { machine_id, last_heartbeat: time };
-                    if let Err(e) = event_handler.handle_event(event).await {
-                        error!("Error handling stale machine event: {}", e);
-                    };
+                match sweep::<MachineId>(store.as_ref(), &mut ttls).await {
+                    Ok(stale_machines) => {
+                        for (machine_id, time) in stale_machines {
+                            let event =
+                                HeartbeatEvent::StaleMachine { machine_id, last_heartbeat: time };
+                            if let Err(e) = event_handler.handle_event(event).await {
+                                error!("Error handling stale machine event: {}", e);
+                            };
+                        }
+                    }
+                    Err(e) => error!("Failed to sweep machine heartbeats: {}", e),
                 }
 
-                for (node_id, time) in stale_nodes {
-                    let event = HeartbeatEvent::StaleNode { node_id, last_heartbeat: time };
-                    if let Err(e) = event_handler.handle_event(event).await {
-                        error!("Error handling stale node event: {}", e);
-                    };
+                match sweep::<NodeId>(store.as_ref(), &mut ttls).await {
+                    Ok(stale_nodes) => {
+                        for (node_id, time) in stale_nodes {
+                            let event = HeartbeatEvent::StaleNode { node_id, last_heartbeat: time };
+                            if let Err(e) = event_handler.handle_event(event).await {
+                                error!("Error handling stale node event: {}", e);
+                            };
+                        }
+                    }
+                    Err(e) => error!("Failed to sweep node heartbeats: {}", e),
                 }
             }
         });
@@ -250,19 +234,30 @@ impl<D: OrganizationDatabase> HeartbeatMonitor<D> {
         monitor
     }
 
+    /// Records a heartbeat, returning whether `id` had none yet.
+    async fn beat<K: HeartbeatKey>(&self, id: &K) -> Result<bool, HeartbeatError> {
+        self.store.beat(K::KIND, &id.to_key(), Utc::now()).await
+    }
+
     pub async fn post_client_heartbeat(&self, client_id: ClientId) -> Result<(), HeartbeatError> {
-        if self.client_map.insert(client_id).is_none() {
+        if self.beat(&client_id).await? {
             let event = HeartbeatEvent::NewClient(client_id);
             self.event_handler.handle_event(event).await?;
         }
         Ok(())
     }
 
+    /// Moves the heartbeat of a client whose key was rotated to the new key, so the old key is
+    /// not reported as stale.
+    pub async fn rotate_client(&self, old: ClientId, new: ClientId) -> Result<(), HeartbeatError> {
+        self.store.rename(ClientId::KIND, &old.to_key(), &new.to_key()).await
+    }
+
     pub async fn post_machine_heartbeat(
         &self,
         machine_id: MachineId,
     ) -> Result<(), HeartbeatError> {
-        if self.machine_map.insert(machine_id).is_none() {
+        if self.beat(&machine_id).await? {
             let event = HeartbeatEvent::NewMachine(machine_id);
             self.event_handler.handle_event(event).await?;
         }
@@ -270,7 +265,7 @@ impl<D: OrganizationDatabase> HeartbeatMonitor<D> {
     }
 
     pub async fn post_node_heartbeat(&self, node_id: NodeId) -> Result<(), HeartbeatError> {
-        if self.node_map.insert(node_id.clone()).is_none() {
+        if self.beat(&node_id).await? {
             let event = HeartbeatEvent::NewNode(node_id);
             self.event_handler.handle_event(event).await?;
         }
@@ -278,6 +273,37 @@ impl<D: OrganizationDatabase> HeartbeatMonitor<D> {
     }
 }
 
+/// Claims the entries of `K` without a heartbeat within their TTL. Entries that sent another
+/// heartbeat in the meantime, or were claimed by another monitor, are left out.
+async fn sweep<K: Expiring>(
+    store: &dyn HeartbeatStore,
+    ttls: &mut Ttls<'_>,
+) -> Result<Vec<(K, DateTime<Utc>)>, HeartbeatError> {
+    let now = Utc::now();
+    let mut claimed = Vec::new();
+    for (key, last_seen) in store.stale(K::KIND, now - ttls.shortest()).await? {
+        let id = match K::from_key(&key) {
+            Ok(id) => Some(id),
+            Err(e) => {
+                warn!("Dropping {} heartbeat with invalid key {}: {}", K::KIND, key, e);
+                None
+            }
+        };
+        if let Some(id) = &id {
+            if now - last_seen <= ttls.ttl(id).await {
+                continue;
+            }
+        }
+        if !store.claim(K::KIND, &key, last_seen).await? {
+            continue;
+        }
+        if let Some(id) = id {
+            claimed.push((id, last_seen));
+        }
+    }
+    Ok(claimed)
+}
+
 impl<D: OrganizationDatabase> Drop for HeartbeatMonitor<D> {
     fn drop(&mut self) {
         warn!("Shutting down heartbeat monitor");
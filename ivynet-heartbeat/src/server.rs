@@ -13,7 +13,7 @@ use ivynet_grpc::{
 use ivynet_notifications::{NotificationConfig, NotificationDispatcher, OrganizationDatabase};
 use sqlx::PgPool;
 
-use crate::HeartbeatMonitor;
+use crate::{store::MemoryHeartbeatStore, HeartbeatConfig, HeartbeatMonitor};
 
 #[ivynet_grpc::async_trait]
 impl<D: OrganizationDatabase> Heartbeat for HeartbeatMonitor<D> {
@@ -56,7 +56,12 @@ async fn serve(
 ) -> Result<(), Box<dyn std::error::Error>> {
     let alert_db = AlertDb::new(db.clone());
     let notifier = NotificationDispatcher::new(notification_config, alert_db);
-    let heartbeat_monitor = HeartbeatMonitor::new(db, Arc::new(notifier));
+    let heartbeat_monitor = HeartbeatMonitor::new(
+        db,
+        Arc::new(notifier),
+        MemoryHeartbeatStore::new(),
+        HeartbeatConfig::default(),
+    );
     let server = Server::new(HeartbeatServer::new(heartbeat_monitor), tls_cert, tls_key);
     let endpoint = Endpoint::Port(port);
     server.serve(endpoint).await?;
@@ -0,0 +1,452 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+    sync::RwLock,
+};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use ivynet_database::error::DatabaseError;
+use sqlx::PgPool;
+
+use crate::{ClientId, HeartbeatError, MachineId, NodeId};
+
+/// Kind of entity heartbeats are kept for
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub enum HeartbeatKind {
+    Client,
+    Machine,
+    Node,
+}
+
+impl HeartbeatKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HeartbeatKind::Client => "client",
+            HeartbeatKind::Machine => "machine",
+            HeartbeatKind::Node => "node",
+        }
+    }
+}
+
+impl Display for HeartbeatKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Identifier of an entity sending heartbeats, and how it is keyed in a [`HeartbeatStore`].
+pub trait HeartbeatKey: Sized {
+    const KIND: HeartbeatKind;
+
+    fn to_key(&self) -> String;
+
+    fn from_key(key: &str) -> Result<Self, HeartbeatError>;
+}
+
+impl HeartbeatKey for ClientId {
+    const KIND: HeartbeatKind = HeartbeatKind::Client;
+
+    // `Display` of an address is abbreviated
+    fn to_key(&self) -> String {
+        format!("{:?}", self.0)
+    }
+
+    fn from_key(key: &str) -> Result<Self, HeartbeatError> {
+        key.parse().map(ClientId).map_err(|_| HeartbeatError::InvalidClientAddress)
+    }
+}
+
+impl HeartbeatKey for MachineId {
+    const KIND: HeartbeatKind = HeartbeatKind::Machine;
+
+    fn to_key(&self) -> String {
+        self.0.to_string()
+    }
+
+    fn from_key(key: &str) -> Result<Self, HeartbeatError> {
+        key.parse().map(MachineId).map_err(|_| HeartbeatError::InvalidMachineAddress)
+    }
+}
+
+impl HeartbeatKey for NodeId {
+    const KIND: HeartbeatKind = HeartbeatKind::Node;
+
+    fn to_key(&self) -> String {
+        self.to_string()
+    }
+
+    fn from_key(key: &str) -> Result<Self, HeartbeatError> {
+        key.parse()
+    }
+}
+
+/// Last heartbeat of every client, machine and node.
+///
+/// Stale entries are swept in two steps: [`stale`](HeartbeatStore::stale) lists the candidates,
+/// and each one is [`claim`](HeartbeatStore::claim)ed before it is reported. A claim only
+/// succeeds for one of several monitors sharing the store, so every stale entry is reported
+/// exactly once.
+#[async_trait::async_trait]
+pub trait HeartbeatStore: Send + Sync + 'static {
+    /// Records a heartbeat sent at `at`. Returns whether the entity had no heartbeat yet, i.e. is
+    /// new or was swept as stale before.
+    async fn beat(
+        &self,
+        kind: HeartbeatKind,
+        key: &str,
+        at: DateTime<Utc>,
+    ) -> Result<bool, HeartbeatError>;
+
+    /// Moves the heartbeat of `old` to `new`, if there is one.
+    async fn rename(&self, kind: HeartbeatKind, old: &str, new: &str)
+        -> Result<(), HeartbeatError>;
+
+    /// Entries whose last heartbeat was sent before `before`, oldest first.
+    async fn stale(
+        &self,
+        kind: HeartbeatKind,
+        before: DateTime<Utc>,
+    ) -> Result<Vec<(String, DateTime<Utc>)>, HeartbeatError>;
+
+    /// Removes an entry if its last heartbeat is still `last_seen`. Returns whether it was
+    /// removed, which is false once it sent another heartbeat or was claimed by someone else.
+    async fn claim(
+        &self,
+        kind: HeartbeatKind,
+        key: &str,
+        last_seen: DateTime<Utc>,
+    ) -> Result<bool, HeartbeatError>;
+}
+
+/// Heartbeats kept in process memory. They are lost on restart and not shared between
+/// processes, which only suits tests and single instances.
+#[derive(Debug, Default)]
+pub struct MemoryHeartbeatStore {
+    map: RwLock<HashMap<(HeartbeatKind, String), DateTime<Utc>>>,
+}
+
+impl MemoryHeartbeatStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait::async_trait]
+impl HeartbeatStore for MemoryHeartbeatStore {
+    async fn beat(
+        &self,
+        kind: HeartbeatKind,
+        key: &str,
+        at: DateTime<Utc>,
+    ) -> Result<bool, HeartbeatError> {
+        let mut map = self.map.write().expect("Write lock failed");
+        match map.get_mut(&(kind, key.to_string())) {
+            Some(last_seen) => {
+                *last_seen = at.max(*last_seen);
+                Ok(false)
+            }
+            None => {
+                map.insert((kind, key.to_string()), at);
+                Ok(true)
+            }
+        }
+    }
+
+    async fn rename(
+        &self,
+        kind: HeartbeatKind,
+        old: &str,
+        new: &str,
+    ) -> Result<(), HeartbeatError> {
+        let mut map = self.map.write().expect("Write lock failed");
+        if map.remove(&(kind, old.to_string())).is_some() {
+            map.insert((kind, new.to_string()), Utc::now());
+        }
+        Ok(())
+    }
+
+    async fn stale(
+        &self,
+        kind: HeartbeatKind,
+        before: DateTime<Utc>,
+    ) -> Result<Vec<(String, DateTime<Utc>)>, HeartbeatError> {
+        let map = self.map.read().expect("Read lock failed");
+        let mut stale = map
+            .iter()
+            .filter(|((entry_kind, _), &last_seen)| *entry_kind == kind && last_seen < before)
+            .map(|((_, key), last_seen)| (key.clone(), *last_seen))
+            .collect::<Vec<_>>();
+        stale.sort_by_key(|(_, last_seen)| *last_seen);
+        Ok(stale)
+    }
+
+    async fn claim(
+        &self,
+        kind: HeartbeatKind,
+        key: &str,
+        last_seen: DateTime<Utc>,
+    ) -> Result<bool, HeartbeatError> {
+        let mut map = self.map.write().expect("Write lock failed");
+        let entry = (kind, key.to_string());
+        if map.get(&entry) == Some(&last_seen) {
+            map.remove(&entry);
+            return Ok(true);
+        }
+        Ok(false)
+    }
+}
+
+/// Heartbeats kept in Postgres, so they survive restarts and are shared by every ingress
+/// replica. Claims are settled by the database, which deletes each row for one replica only.
+#[derive(Debug, Clone)]
+pub struct PgHeartbeatStore {
+    pool: PgPool,
+}
+
+impl PgHeartbeatStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl HeartbeatStore for PgHeartbeatStore {
+    async fn beat(
+        &self,
+        kind: HeartbeatKind,
+        key: &str,
+        at: DateTime<Utc>,
+    ) -> Result<bool, HeartbeatError> {
+        // xmax is only set on rows that already existed
+        let inserted = sqlx::query_scalar!(
+            r#"INSERT INTO heartbeat (kind, key, last_seen) VALUES ($1, $2, $3)
+               ON CONFLICT (kind, key)
+               DO UPDATE SET last_seen = GREATEST(heartbeat.last_seen, EXCLUDED.last_seen)
+               RETURNING (xmax = 0) AS "inserted!""#,
+            kind.as_str(),
+            key,
+            at.naive_utc()
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(DatabaseError::from)?;
+        Ok(inserted)
+    }
+
+    async fn rename(
+        &self,
+        kind: HeartbeatKind,
+        old: &str,
+        new: &str,
+    ) -> Result<(), HeartbeatError> {
+        let removed =
+            sqlx::query!("DELETE FROM heartbeat WHERE kind = $1 AND key = $2", kind.as_str(), old)
+                .execute(&self.pool)
+                .await
+                .map_err(DatabaseError::from)?;
+        if removed.rows_affected() > 0 {
+            self.beat(kind, new, Utc::now()).await?;
+        }
+        Ok(())
+    }
+
+    async fn stale(
+        &self,
+        kind: HeartbeatKind,
+        before: DateTime<Utc>,
+    ) -> Result<Vec<(String, DateTime<Utc>)>, HeartbeatError> {
+        let rows = sqlx::query!(
+            "SELECT key, last_seen FROM heartbeat
+             WHERE kind = $1 AND last_seen < $2
+             ORDER BY last_seen",
+            kind.as_str(),
+            before.naive_utc()
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(DatabaseError::from)?;
+        Ok(rows.into_iter().map(|row| (row.key, row.last_seen.and_utc())).collect())
+    }
+
+    async fn claim(
+        &self,
+        kind: HeartbeatKind,
+        key: &str,
+        last_seen: DateTime<Utc>,
+    ) -> Result<bool, HeartbeatError> {
+        let removed = sqlx::query!(
+            "DELETE FROM heartbeat WHERE kind = $1 AND key = $2 AND last_seen = $3",
+            kind.as_str(),
+            key,
+            last_seen.naive_utc()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(DatabaseError::from)?;
+        Ok(removed.rows_affected() == 1)
+    }
+}
+
+/// Write-through cache in front of another store, which writes a heartbeat of the same entity at
+/// most once per `write_interval`. Spares the database the writes of frequent heartbeats, at the
+/// cost of the stored time lagging by up to `write_interval`, which has to stay well below the
+/// heartbeat TTL.
+pub struct CachedHeartbeatStore<S: HeartbeatStore> {
+    inner: S,
+    write_interval: TimeDelta,
+    written: RwLock<HashMap<(HeartbeatKind, String), DateTime<Utc>>>,
+}
+
+impl<S: HeartbeatStore> CachedHeartbeatStore<S> {
+    pub fn new(inner: S, write_interval: TimeDelta) -> Self {
+        Self { inner, write_interval, written: RwLock::new(HashMap::new()) }
+    }
+
+    fn forget(&self, kind: HeartbeatKind, key: &str) {
+        self.written.write().expect("Write lock failed").remove(&(kind, key.to_string()));
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: HeartbeatStore> HeartbeatStore for CachedHeartbeatStore<S> {
+    async fn beat(
+        &self,
+        kind: HeartbeatKind,
+        key: &str,
+        at: DateTime<Utc>,
+    ) -> Result<bool, HeartbeatError> {
+        let entry = (kind, key.to_string());
+        let written = self.written.read().expect("Read lock failed").get(&entry).copied();
+        if written.is_some_and(|written| at - written < self.write_interval) {
+            return Ok(false);
+        }
+        let new = self.inner.beat(kind, key, at).await?;
+        self.written.write().expect("Write lock failed").insert(entry, at);
+        Ok(new)
+    }
+
+    async fn rename(
+        &self,
+        kind: HeartbeatKind,
+        old: &str,
+        new: &str,
+    ) -> Result<(), HeartbeatError> {
+        self.forget(kind, old);
+        self.inner.rename(kind, old, new).await
+    }
+
+    async fn stale(
+        &self,
+        kind: HeartbeatKind,
+        before: DateTime<Utc>,
+    ) -> Result<Vec<(String, DateTime<Utc>)>, HeartbeatError> {
+        // Entries written that long ago are stale or were claimed, renamed or removed elsewhere,
+        // so they no longer save any writes
+        self.written
+            .write()
+            .expect("Write lock failed")
+            .retain(|(entry_kind, _), written| *entry_kind != kind || *written >= before);
+        self.inner.stale(kind, before).await
+    }
+
+    async fn claim(
+        &self,
+        kind: HeartbeatKind,
+        key: &str,
+        last_seen: DateTime<Utc>,
+    ) -> Result<bool, HeartbeatError> {
+        let claimed = self.inner.claim(kind, key, last_seen).await?;
+        if claimed {
+            // The next heartbeat has to be written to be reported as new
+            self.forget(kind, key);
+        }
+        Ok(claimed)
+    }
+}
+
+#[cfg(test)]
+mod store_tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_stale_entries_are_claimed_once() {
+        let store = MemoryHeartbeatStore::new();
+        let now = Utc::now();
+        let key = MachineId::new(Uuid::new_v4()).to_key();
+
+        assert!(store
+            .beat(HeartbeatKind::Machine, &key, now - TimeDelta::minutes(20))
+            .await
+            .unwrap());
+        // Kinds are kept apart
+        assert!(store.beat(HeartbeatKind::Node, &key, now).await.unwrap());
+
+        let stale =
+            store.stale(HeartbeatKind::Machine, now - TimeDelta::minutes(15)).await.unwrap();
+        assert_eq!(stale.len(), 1);
+        let (stale_key, last_seen) = stale[0].clone();
+        assert_eq!(stale_key, key);
+
+        assert!(store.claim(HeartbeatKind::Machine, &key, last_seen).await.unwrap());
+        assert!(!store.claim(HeartbeatKind::Machine, &key, last_seen).await.unwrap());
+
+        // Swept entries are new again on their next heartbeat
+        assert!(store.beat(HeartbeatKind::Machine, &key, now).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_renewed_entries_are_not_claimed() {
+        let store = MemoryHeartbeatStore::new();
+        let now = Utc::now();
+        let key = "0x0101010101010101010101010101010101010101";
+
+        store.beat(HeartbeatKind::Client, key, now - TimeDelta::minutes(20)).await.unwrap();
+        let stale = store.stale(HeartbeatKind::Client, now - TimeDelta::minutes(15)).await.unwrap();
+        assert!(!store.beat(HeartbeatKind::Client, key, now).await.unwrap());
+
+        assert!(!store.claim(HeartbeatKind::Client, key, stale[0].1).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_cached_store_writes_once_per_interval() {
+        let store = CachedHeartbeatStore::new(MemoryHeartbeatStore::new(), TimeDelta::seconds(30));
+        let now = Utc::now();
+        let key = "machine";
+
+        assert!(store.beat(HeartbeatKind::Machine, key, now).await.unwrap());
+        store.beat(HeartbeatKind::Machine, key, now + TimeDelta::seconds(10)).await.unwrap();
+        let stored = store.stale(HeartbeatKind::Machine, now + TimeDelta::hours(1)).await.unwrap();
+        assert_eq!(stored[0].1, now);
+
+        store.beat(HeartbeatKind::Machine, key, now + TimeDelta::seconds(40)).await.unwrap();
+        let stored = store.stale(HeartbeatKind::Machine, now + TimeDelta::hours(1)).await.unwrap();
+        assert_eq!(stored[0].1, now + TimeDelta::seconds(40));
+    }
+
+    #[tokio::test]
+    async fn test_cached_store_forgets_stale_entries() {
+        let store = CachedHeartbeatStore::new(MemoryHeartbeatStore::new(), TimeDelta::seconds(30));
+        let now = Utc::now();
+
+        store.beat(HeartbeatKind::Node, "old", now - TimeDelta::minutes(20)).await.unwrap();
+        store.beat(HeartbeatKind::Node, "recent", now).await.unwrap();
+        store.beat(HeartbeatKind::Machine, "old", now - TimeDelta::minutes(20)).await.unwrap();
+
+        store.stale(HeartbeatKind::Node, now - TimeDelta::minutes(15)).await.unwrap();
+        let written = store.written.read().unwrap();
+        assert!(!written.contains_key(&(HeartbeatKind::Node, "old".to_string())));
+        assert!(written.contains_key(&(HeartbeatKind::Node, "recent".to_string())));
+        assert!(written.contains_key(&(HeartbeatKind::Machine, "old".to_string())));
+    }
+
+    #[test]
+    fn test_keys_round_trip() {
+        let client = ClientId::new("0x0101010101010101010101010101010101010101".parse().unwrap());
+        assert_eq!(ClientId::from_key(&client.to_key()).unwrap(), client);
+
+        let node = NodeId::new(Uuid::new_v4(), "eigenda".to_string());
+        assert_eq!(NodeId::from_key(&node.to_key()).unwrap(), node);
+    }
+}
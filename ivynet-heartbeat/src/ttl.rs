@@ -0,0 +1,117 @@
+use std::{collections::HashMap, time::Duration};
+
+use chrono::TimeDelta;
+use ivynet_database::{error::DatabaseError, Avs, Client, HeartbeatSettings, Machine};
+use sqlx::PgPool;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{store::HeartbeatKey, ClientId, HeartbeatError, MachineId, NodeId};
+
+/// Settings of the heartbeat monitor. Organizations can override the TTL with their
+/// [`HeartbeatSettings`].
+#[derive(Clone, Copy, Debug)]
+pub struct HeartbeatConfig {
+    /// How long an entry may go without a heartbeat unless its organization set otherwise
+    pub ttl: TimeDelta,
+    /// How often entries without a heartbeat within their TTL are looked for
+    pub sweep_interval: Duration,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self { ttl: TimeDelta::minutes(15), sweep_interval: Duration::from_secs(60) }
+    }
+}
+
+/// TTLs of the entries of one sweep, resolved from the settings of their organization.
+pub(crate) struct Ttls<'a> {
+    db: &'a PgPool,
+    default: TimeDelta,
+    settings: HashMap<i64, HeartbeatSettings>,
+    machine_organizations: HashMap<Uuid, i64>,
+}
+
+impl<'a> Ttls<'a> {
+    pub async fn load(db: &'a PgPool, default: TimeDelta) -> Result<Self, HeartbeatError> {
+        let settings = HeartbeatSettings::get_all(db).await?;
+        Ok(Self { db, default, settings, machine_organizations: HashMap::new() })
+    }
+
+    /// The shortest TTL of any entry. Entries with a heartbeat within it are not stale.
+    pub fn shortest(&self) -> TimeDelta {
+        self.settings
+            .values()
+            .flat_map(HeartbeatSettings::ttls)
+            .map(TimeDelta::seconds)
+            .fold(self.default, TimeDelta::min)
+    }
+
+    /// TTL of `id`. Falls back to the default when the organization of `id` cannot be told, so
+    /// entries are rather reported late than never.
+    pub async fn ttl<K: Expiring>(&mut self, id: &K) -> TimeDelta {
+        match id.ttl_secs(self).await {
+            Ok(ttl) => ttl.map_or(self.default, TimeDelta::seconds),
+            Err(e) => {
+                warn!("Failed to look up heartbeat TTL of {} {}: {}", K::KIND, id.to_key(), e);
+                self.default
+            }
+        }
+    }
+
+    fn settings(&self, organization_id: i64) -> Option<&HeartbeatSettings> {
+        self.settings.get(&organization_id)
+    }
+
+    async fn machine_organization(&mut self, machine_id: Uuid) -> Result<i64, HeartbeatError> {
+        if let Some(organization_id) = self.machine_organizations.get(&machine_id) {
+            return Ok(*organization_id);
+        }
+        let organization_id =
+            Machine::get_organization_id(self.db, machine_id).await.map_err(DatabaseError::from)?;
+        self.machine_organizations.insert(machine_id, organization_id);
+        Ok(organization_id)
+    }
+}
+
+/// Entries whose TTL can be set by their organization.
+#[async_trait::async_trait]
+pub(crate) trait Expiring: HeartbeatKey + Send + Sync {
+    /// TTL set by the organization of the entry, if any.
+    async fn ttl_secs(&self, ttls: &mut Ttls<'_>) -> Result<Option<i64>, HeartbeatError>;
+}
+
+#[async_trait::async_trait]
+impl Expiring for ClientId {
+    async fn ttl_secs(&self, ttls: &mut Ttls<'_>) -> Result<Option<i64>, HeartbeatError> {
+        let Some(client) = Client::get(ttls.db, &self.0).await? else {
+            return Ok(None);
+        };
+        Ok(ttls.settings(client.organization_id).and_then(|settings| settings.client_ttl_secs))
+    }
+}
+
+#[async_trait::async_trait]
+impl Expiring for MachineId {
+    async fn ttl_secs(&self, ttls: &mut Ttls<'_>) -> Result<Option<i64>, HeartbeatError> {
+        let organization_id = ttls.machine_organization(self.0).await?;
+        Ok(ttls.settings(organization_id).and_then(|settings| settings.machine_ttl_secs))
+    }
+}
+
+#[async_trait::async_trait]
+impl Expiring for NodeId {
+    async fn ttl_secs(&self, ttls: &mut Ttls<'_>) -> Result<Option<i64>, HeartbeatError> {
+        let organization_id = ttls.machine_organization(self.machine).await?;
+        let Some(settings) = ttls.settings(organization_id) else {
+            return Ok(None);
+        };
+        if settings.node_type_ttl_secs.is_empty() {
+            return Ok(settings.node_ttl_secs);
+        }
+        Ok(match Avs::get_machines_avs(ttls.db, self.machine, &self.name).await? {
+            Some(avs) => settings.node_ttl_secs_for(&avs.avs_type.to_string()),
+            None => settings.node_ttl_secs,
+        })
+    }
+}
@@ -21,6 +21,7 @@ pub mod bls;
 pub mod keychain;
 pub mod keyfile;
 pub mod sign_utils;
+pub mod typed_data;
 
 // TODO: Make this a newtype strict and impl deref + derefmut to get signer stuff for free
 #[derive(Clone, Debug, PartialEq)]
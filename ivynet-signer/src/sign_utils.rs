@@ -3,7 +3,7 @@ use ethers::{
     types::{Address, Signature, H256, U256},
     utils::keccak256,
 };
-use ivynet_grpc::messages::{MachineData, Metrics, NodeData, NodeDataV2};
+use ivynet_grpc::messages::{MachineData, Metrics, NodeDataV2};
 
 use crate::IvyWallet;
 
@@ -30,32 +30,6 @@ pub fn recover_from_string(
     )
 }
 
-// --- NodeData ---
-#[deprecated = "Use sign_node_data_v2 instead"]
-pub fn sign_node_data(
-    node_data: &NodeData,
-    wallet: &IvyWallet,
-) -> Result<Signature, IvySigningError> {
-    sign_hash(hash_node_data(node_data)?, wallet)
-}
-
-fn hash_node_data(node_data: &NodeData) -> Result<H256, IvySigningError> {
-    let mut tokens = Vec::new();
-    let node_data = node_data.clone();
-    tokens.push(Token::String(node_data.name));
-    tokens.push(Token::String(node_data.node_type));
-    tokens.push(Token::String(node_data.manifest));
-    tokens.push(Token::Bool(node_data.metrics_alive));
-    Ok(H256::from(&keccak256(encode(&tokens))))
-}
-
-pub fn recover_node_data(
-    node_data: &NodeData,
-    signature: &Signature,
-) -> Result<Address, IvySigningError> {
-    recover_from_hash(hash_node_data(node_data)?, signature)
-}
-
 // --- NodeData V2 ---
 pub fn sign_node_data_v2(
     node_data: &NodeDataV2,
@@ -179,6 +153,31 @@ pub fn recover_log(log: &str, signature: &Signature) -> Result<Address, IvySigni
     recover_from_string(log, signature)
 }
 
+// --- LogBatch ---
+pub fn sign_log_batch(
+    avs_name: &str,
+    logs: &[String],
+    wallet: &IvyWallet,
+) -> Result<Signature, IvySigningError> {
+    sign_hash(hash_log_batch(avs_name, logs), wallet)
+}
+
+pub fn recover_log_batch(
+    avs_name: &str,
+    logs: &[String],
+    signature: &Signature,
+) -> Result<Address, IvySigningError> {
+    recover_from_hash(hash_log_batch(avs_name, logs), signature)
+}
+
+fn hash_log_batch(avs_name: &str, logs: &[String]) -> H256 {
+    let tokens = vec![
+        Token::String(avs_name.to_string()),
+        Token::Array(logs.iter().cloned().map(Token::String).collect()),
+    ];
+    H256::from(&keccak256(encode(&tokens)))
+}
+
 // --- ClientLog ---
 pub fn sign_client_log(log: &str, wallet: &IvyWallet) -> Result<Signature, IvySigningError> {
     sign_string(log, wallet)
@@ -188,6 +187,48 @@ pub fn recover_client_log(log: &str, signature: &Signature) -> Result<Address, I
     recover_from_string(log, signature)
 }
 
+// --- Ping ---
+pub fn sign_ping(timestamp: u64, wallet: &IvyWallet) -> Result<Signature, IvySigningError> {
+    sign_hash(hash_ping(timestamp), wallet)
+}
+
+pub fn recover_ping(timestamp: u64, signature: &Signature) -> Result<Address, IvySigningError> {
+    recover_from_hash(hash_ping(timestamp), signature)
+}
+
+fn hash_ping(timestamp: u64) -> H256 {
+    H256::from(&keccak256(encode(&[Token::Uint(U256::from(timestamp))])))
+}
+
+// --- Key Rotation ---
+pub fn sign_key_rotation(
+    machine_id: &[u8],
+    new_key: &Address,
+    timestamp: u64,
+    wallet: &IvyWallet,
+) -> Result<Signature, IvySigningError> {
+    sign_hash(hash_key_rotation(machine_id, new_key, timestamp), wallet)
+}
+
+pub fn recover_key_rotation(
+    machine_id: &[u8],
+    new_key: &Address,
+    timestamp: u64,
+    signature: &Signature,
+) -> Result<Address, IvySigningError> {
+    recover_from_hash(hash_key_rotation(machine_id, new_key, timestamp), signature)
+}
+
+fn hash_key_rotation(machine_id: &[u8], new_key: &Address, timestamp: u64) -> H256 {
+    let tokens = vec![
+        Token::String("rotate_key".to_string()),
+        Token::Bytes(machine_id.to_vec()),
+        Token::Address(*new_key),
+        Token::Uint(U256::from(timestamp)),
+    ];
+    H256::from(&keccak256(encode(&tokens)))
+}
+
 // --- Errors ---
 
 #[derive(Debug, thiserror::Error)]
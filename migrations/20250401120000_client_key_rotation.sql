@@ -0,0 +1,10 @@
+-- Record of identity key rotations, one row per retired client key
+CREATE TABLE IF NOT EXISTS client_key_rotation (
+    old_client_id       BYTEA        PRIMARY KEY,
+    new_client_id       BYTEA        NOT NULL,
+    organization_id     BIGINT       NOT NULL REFERENCES organization
+                                        ON DELETE CASCADE,
+    rotated_at          TIMESTAMP    NOT NULL
+);
+
+CREATE INDEX idx_client_key_rotation_new_client ON client_key_rotation (new_client_id);
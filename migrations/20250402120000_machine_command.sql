@@ -0,0 +1,16 @@
+-- Commands queued for machines, delivered over the telemetry stream
+CREATE TABLE IF NOT EXISTS machine_command (
+    id                  UUID         PRIMARY KEY,
+    machine_id          UUID         NOT NULL REFERENCES machine
+                                        ON DELETE CASCADE,
+    command             JSONB        NOT NULL,
+    created_at          TIMESTAMP    NOT NULL,
+    expires_at          TIMESTAMP    NOT NULL,
+    delivered_at        TIMESTAMP,
+    completed_at        TIMESTAMP,
+    success             BOOLEAN,
+    result              TEXT
+);
+
+CREATE INDEX idx_machine_command_pending ON machine_command (machine_id)
+    WHERE delivered_at IS NULL;
@@ -0,0 +1,10 @@
+-- Last heartbeat of every client, machine and node, shared by all ingress replicas
+CREATE TABLE IF NOT EXISTS heartbeat (
+    kind                TEXT         NOT NULL,
+    key                 TEXT         NOT NULL,
+    last_seen           TIMESTAMP    NOT NULL,
+
+    PRIMARY KEY (kind, key)
+);
+
+CREATE INDEX idx_heartbeat_last_seen ON heartbeat (kind, last_seen);
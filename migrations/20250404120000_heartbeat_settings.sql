@@ -0,0 +1,20 @@
+-- How long an organization's clients, machines and nodes may go without a heartbeat. TTLs left
+-- NULL fall back to the default of the ingress.
+CREATE TABLE IF NOT EXISTS heartbeat_settings (
+    organization_id     BIGINT       NOT NULL REFERENCES organization
+                            ON DELETE CASCADE PRIMARY KEY,
+    client_ttl_secs     BIGINT       CHECK (client_ttl_secs > 0),
+    machine_ttl_secs    BIGINT       CHECK (machine_ttl_secs > 0),
+    node_ttl_secs       BIGINT       CHECK (node_ttl_secs > 0),
+    updated_at          TIMESTAMP    NOT NULL DEFAULT NOW()
+);
+
+-- TTLs of the nodes of a node type, taking precedence over node_ttl_secs
+CREATE TABLE IF NOT EXISTS node_type_heartbeat_settings (
+    organization_id     BIGINT       NOT NULL REFERENCES organization
+                            ON DELETE CASCADE,
+    node_type           TEXT         NOT NULL,
+    ttl_secs            BIGINT       NOT NULL CHECK (ttl_secs > 0),
+
+    PRIMARY KEY (organization_id, node_type)
+);
@@ -0,0 +1,35 @@
+CREATE TYPE alert_rule_comparison AS ENUM ('gt', 'ge', 'lt', 'le', 'eq', 'ne');
+
+-- Alerts raised when a metric crosses a threshold for long enough, defined per organization
+CREATE TABLE IF NOT EXISTS alert_rule (
+    rule_id             UUID                    PRIMARY KEY,
+    organization_id     BIGINT                  NOT NULL REFERENCES organization
+                            ON DELETE CASCADE,
+    name                TEXT                    NOT NULL,
+    metric_name         TEXT                    NOT NULL,
+    label_matchers      JSONB                   NOT NULL DEFAULT '[]',
+    comparison          alert_rule_comparison   NOT NULL,
+    threshold           DOUBLE PRECISION        NOT NULL,
+    for_secs            BIGINT                  NOT NULL DEFAULT 0 CHECK (for_secs >= 0),
+    scope               JSONB                   NOT NULL DEFAULT '{}',
+    enabled             BOOL                    NOT NULL DEFAULT TRUE,
+    created_at          TIMESTAMP               NOT NULL,
+    updated_at          TIMESTAMP               NOT NULL
+);
+
+CREATE INDEX idx_alert_rule_org ON alert_rule (organization_id);
+
+-- Series of a node matching a rule whose condition holds, since when, and the alert raised once
+-- it held for long enough
+CREATE TABLE IF NOT EXISTS alert_rule_state (
+    rule_id             UUID         NOT NULL REFERENCES alert_rule ON DELETE CASCADE,
+    machine_id          UUID         NOT NULL REFERENCES machine ON DELETE CASCADE,
+    node_name           TEXT         NOT NULL,
+    series              TEXT         NOT NULL,
+    pending_since       TIMESTAMP    NOT NULL,
+    alert_id            UUID,
+
+    PRIMARY KEY (rule_id, machine_id, node_name, series)
+);
+
+CREATE INDEX idx_alert_rule_state_node ON alert_rule_state (machine_id, node_name);
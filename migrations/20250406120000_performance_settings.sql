@@ -0,0 +1,8 @@
+-- Performance score below which the EigenDA nodes of an organization raise a LowPerformanceScore
+-- alert. A NULL threshold falls back to the default of the ingress.
+CREATE TABLE IF NOT EXISTS performance_settings (
+    organization_id     BIGINT              NOT NULL REFERENCES organization
+                            ON DELETE CASCADE PRIMARY KEY,
+    healthy_threshold   DOUBLE PRECISION    CHECK (healthy_threshold BETWEEN 0 AND 100),
+    updated_at          TIMESTAMP           NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,20 @@
+-- Silences keep the alerts they match from being notified, while still recording them. Scopes
+-- left NULL match anything; a silence with a schedule is only in effect during its weekly window.
+CREATE TABLE IF NOT EXISTS alert_silence (
+    silence_id          UUID         NOT NULL PRIMARY KEY,
+    organization_id     BIGINT       NOT NULL REFERENCES organization
+                            ON DELETE CASCADE,
+    comment             TEXT         NOT NULL DEFAULT '',
+    machine_id          UUID,
+    node_name           TEXT,
+    node_type           TEXT,
+    alert_type          INTEGER,
+    starts_at           TIMESTAMP    NOT NULL,
+    ends_at             TIMESTAMP    CHECK (ends_at >= starts_at),
+    schedule            JSONB,
+    created_at          TIMESTAMP    NOT NULL DEFAULT NOW(),
+
+    CHECK (ends_at IS NOT NULL OR schedule IS NOT NULL)
+);
+
+CREATE INDEX IF NOT EXISTS idx_alert_silence_organization_id ON alert_silence (organization_id);
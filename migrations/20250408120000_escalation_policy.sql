@@ -0,0 +1,25 @@
+CREATE TYPE escalation_channel AS ENUM ('telegram', 'email', 'pagerduty');
+
+-- How the node and machine alerts of an organization escalate while nobody acknowledges them.
+-- With a repeat interval, every channel of the policy is notified again that often once all
+-- steps were taken, until the alert is acknowledged or resolved.
+CREATE TABLE IF NOT EXISTS escalation_policy (
+    organization_id         BIGINT       NOT NULL REFERENCES organization
+                                ON DELETE CASCADE PRIMARY KEY,
+    repeat_interval_secs    BIGINT       CHECK (repeat_interval_secs > 0),
+    updated_at              TIMESTAMP    NOT NULL DEFAULT NOW()
+);
+
+-- Each step notifies a channel once an alert went unacknowledged for after_secs
+CREATE TABLE IF NOT EXISTS escalation_step (
+    organization_id     BIGINT               NOT NULL REFERENCES escalation_policy
+                            ON DELETE CASCADE,
+    channel             escalation_channel   NOT NULL,
+    after_secs          BIGINT               NOT NULL CHECK (after_secs >= 0),
+
+    PRIMARY KEY (organization_id, channel)
+);
+
+-- When an alert was last notified by an escalation step or repeat
+ALTER TABLE node_alerts_active ADD COLUMN IF NOT EXISTS notified_at TIMESTAMP;
+ALTER TABLE machine_alerts_active ADD COLUMN IF NOT EXISTS notified_at TIMESTAMP;
@@ -0,0 +1,10 @@
+-- Sequence numbers of typed signatures that are recent enough to be replayed, shared by all
+-- ingress replicas. The highest sequence of a machine is its high-water mark, rows further
+-- behind it than the replay window are pruned as new ones come in.
+CREATE TABLE IF NOT EXISTS signature_sequence (
+    machine_id          UUID         NOT NULL REFERENCES machine
+                                        ON DELETE CASCADE,
+    sequence            BIGINT       NOT NULL,
+
+    PRIMARY KEY (machine_id, sequence)
+);
@@ -0,0 +1,13 @@
+-- Telemetry sessions of machines, shared by all ingress replicas. Only a hash of the token is
+-- stored, the token itself is only known to the machine.
+CREATE TABLE IF NOT EXISTS telemetry_session (
+    token_hash          TEXT         PRIMARY KEY,
+    machine_id          UUID         NOT NULL REFERENCES machine
+                                        ON DELETE CASCADE,
+    client_id           BYTEA        NOT NULL,
+    expires_at          TIMESTAMP    NOT NULL
+);
+
+CREATE INDEX idx_telemetry_session_machine ON telemetry_session (machine_id);
+CREATE INDEX idx_telemetry_session_client ON telemetry_session (client_id);
+CREATE INDEX idx_telemetry_session_expires_at ON telemetry_session (expires_at);